@@ -0,0 +1,61 @@
+//! Benchmarks of the calls status dashboards poll. They talk to the
+//! NetworkManager instance on the system bus, so results depend on the number
+//! of devices and visible access points of the host.
+
+#[macro_use]
+extern crate criterion;
+extern crate network_manager;
+
+use criterion::Criterion;
+
+use network_manager::NetworkManager;
+
+fn device_enumeration(c: &mut Criterion) {
+    let manager = NetworkManager::new();
+
+    c.bench_function("get_devices", |b| b.iter(|| manager.get_devices().unwrap()));
+}
+
+fn access_point_listing(c: &mut Criterion) {
+    let manager = NetworkManager::new();
+
+    let device = manager
+        .get_devices()
+        .unwrap()
+        .into_iter()
+        .find(|device| device.as_wifi_device().is_some());
+
+    let device = match device {
+        Some(device) => device,
+        None => return,
+    };
+
+    let wifi_device = device.as_wifi_device().unwrap();
+
+    c.bench_function("get_access_points", |b| {
+        b.iter(|| wifi_device.get_access_points().unwrap())
+    });
+}
+
+fn status_query(c: &mut Criterion) {
+    let manager = NetworkManager::new();
+
+    c.bench_function("fast_status", |b| b.iter(|| manager.fast_status().unwrap()));
+
+    c.bench_function("get_state_and_connectivity", |b| {
+        b.iter(|| {
+            (
+                manager.get_state().unwrap(),
+                manager.get_active_connections().unwrap(),
+            )
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    device_enumeration,
+    access_point_listing,
+    status_query
+);
+criterion_main!(benches);
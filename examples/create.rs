@@ -8,7 +8,9 @@ extern crate network_manager;
 use clap::{App, Arg};
 use std::io::Write;
 
-use network_manager::{AccessPoint, AccessPointCredentials, Device, DeviceType, NetworkManager};
+use network_manager::{
+    AccessPoint, AccessPointCredentials, Device, DeviceType, NetworkManager, SecretFlags,
+};
 
 mod errors {
     use network_manager;
@@ -72,7 +74,8 @@ fn run() -> Result<()> {
     let ap_index = find_access_point(&access_points, matches.value_of("SSID").unwrap())?;
 
     let credentials = AccessPointCredentials::Wpa {
-        passphrase: matches.value_of("PASSWORD").unwrap().to_string(),
+        passphrase: matches.value_of("PASSWORD").unwrap().into(),
+        psk_flags: SecretFlags::NONE,
     };
 
     wifi_device.connect(&access_points[ap_index], &credentials)?;
@@ -0,0 +1,9 @@
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate network_manager;
+
+fuzz_target!(|data: &[u8]| {
+    network_manager::fuzzing::variant_to(data);
+});
@@ -0,0 +1,239 @@
+use std::fmt;
+
+use connection::get_profile_with_secrets;
+use dbus_nm::DBusNetworkManager;
+use errors::*;
+use settings::ConnectionProfile;
+
+/// A single step needed to converge NetworkManager's profiles on a desired
+/// configuration.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlannedChange {
+    Create {
+        profile: ConnectionProfile,
+    },
+    Update {
+        path: String,
+        id: String,
+        /// The `setting.key` entries that differ.
+        changes: Vec<String>,
+        /// The existing profile with the desired settings applied.
+        profile: ConnectionProfile,
+    },
+    Delete {
+        path: String,
+        id: String,
+    },
+    Unchanged {
+        path: String,
+        id: String,
+    },
+}
+
+/// The changes [`NetworkManager::apply`] would perform. Its `Display` output
+/// serves as a dry-run report.
+///
+/// [`NetworkManager::apply`]: struct.NetworkManager.html#method.apply
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigPlan {
+    pub changes: Vec<PlannedChange>,
+}
+
+impl ConfigPlan {
+    /// Whether NetworkManager already matches the desired configuration.
+    pub fn is_converged(&self) -> bool {
+        self.changes
+            .iter()
+            .all(|change| matches!(*change, PlannedChange::Unchanged { .. }))
+    }
+}
+
+impl fmt::Display for ConfigPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.changes {
+            match *change {
+                PlannedChange::Create { ref profile } => {
+                    writeln!(f, "+ {}", profile.id().unwrap_or_default())?
+                }
+                PlannedChange::Update {
+                    ref id,
+                    ref changes,
+                    ..
+                } => writeln!(f, "~ {}: {}", id, changes.join(", "))?,
+                PlannedChange::Delete { ref id, .. } => writeln!(f, "- {}", id)?,
+                PlannedChange::Unchanged { ref id, .. } => writeln!(f, "= {}", id)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn plan_config(
+    dbus_manager: &DBusNetworkManager,
+    desired: &[ConnectionProfile],
+    prune: bool,
+) -> Result<ConfigPlan> {
+    for profile in desired {
+        if profile.id().is_none() && profile.uuid().is_none() {
+            bail!(ErrorKind::NetworkManager(
+                "Desired connection has neither an ID nor a UUID".into()
+            ));
+        }
+    }
+
+    let mut existing = Vec::new();
+
+    for path in dbus_manager.list_connections()? {
+        let profile = dbus_manager.get_connection_profile(&path)?;
+        existing.push((path, profile));
+    }
+
+    for profile in desired {
+        if let Some(index) = find_match(&existing, profile) {
+            let path = existing[index].0.clone();
+            existing[index].1 = get_profile_with_secrets(dbus_manager, &path, profile)?;
+        }
+    }
+
+    Ok(plan(&existing, desired, prune))
+}
+
+pub fn apply_config(dbus_manager: &DBusNetworkManager, plan: &ConfigPlan) -> Result<()> {
+    enter_span!("apply_config");
+
+    for change in &plan.changes {
+        match *change {
+            PlannedChange::Create { ref profile } => {
+                info!("Creating connection {:?}", profile.id());
+                dbus_manager.add_connection(profile)?;
+            }
+            PlannedChange::Update {
+                ref path,
+                ref profile,
+                ref changes,
+                ..
+            } => {
+                info!("Updating connection {}: {:?}", path, changes);
+                dbus_manager.update_connection(path, profile)?;
+            }
+            PlannedChange::Delete { ref path, .. } => {
+                info!("Deleting connection {}", path);
+                dbus_manager.delete_connection(path)?;
+            }
+            PlannedChange::Unchanged { .. } => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn plan(
+    existing: &[(String, ConnectionProfile)],
+    desired: &[ConnectionProfile],
+    prune: bool,
+) -> ConfigPlan {
+    let mut matched = vec![false; existing.len()];
+    let mut changes = Vec::new();
+
+    for profile in desired {
+        let index = match find_match(existing, profile) {
+            Some(index) => index,
+            None => {
+                changes.push(PlannedChange::Create {
+                    profile: profile.clone(),
+                });
+                continue;
+            }
+        };
+
+        matched[index] = true;
+
+        let (ref path, ref current) = existing[index];
+        let id = current.id().unwrap_or_default().to_string();
+        let differences = current.changes(profile);
+
+        if differences.is_empty() {
+            changes.push(PlannedChange::Unchanged {
+                path: path.clone(),
+                id,
+            });
+        } else {
+            let mut merged = current.clone();
+            merged.merge(profile);
+
+            changes.push(PlannedChange::Update {
+                path: path.clone(),
+                id,
+                changes: differences,
+                profile: merged,
+            });
+        }
+    }
+
+    if prune {
+        for (index, (path, current)) in existing.iter().enumerate() {
+            if !matched[index] {
+                changes.push(PlannedChange::Delete {
+                    path: path.clone(),
+                    id: current.id().unwrap_or_default().to_string(),
+                });
+            }
+        }
+    }
+
+    ConfigPlan { changes }
+}
+
+/// Matches by UUID, or by ID when the desired profile has no UUID.
+fn find_match(
+    existing: &[(String, ConnectionProfile)],
+    profile: &ConnectionProfile,
+) -> Option<usize> {
+    existing
+        .iter()
+        .position(|(_, current)| match profile.uuid() {
+            Some(uuid) => current.uuid() == Some(uuid),
+            None => current.id() == profile.id(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        let existing = vec![
+            (
+                "/c/1".to_string(),
+                ConnectionProfile::ethernet("uplink", "eth0").with("ipv4", "method", "auto"),
+            ),
+            (
+                "/c/2".to_string(),
+                ConnectionProfile::ethernet("office", "eth1"),
+            ),
+            (
+                "/c/3".to_string(),
+                ConnectionProfile::ethernet("old", "eth2"),
+            ),
+        ];
+        let desired = vec![
+            ConnectionProfile::ethernet("uplink", "eth0").with("ipv4", "method", "manual"),
+            ConnectionProfile::ethernet("office", "eth1"),
+            ConnectionProfile::ethernet("lab", "eth3"),
+        ];
+
+        let plan = plan(&existing, &desired, true);
+
+        assert!(!plan.is_converged());
+        assert_eq!(
+            plan.to_string(),
+            "~ uplink: ipv4.method\n= office\n+ lab\n- old\n"
+        );
+
+        let plan = super::plan(&existing, &desired[1..2], false);
+
+        assert!(plan.is_converged());
+    }
+}
@@ -0,0 +1,130 @@
+use std::fs;
+use std::path::PathBuf;
+
+use dbus_api::{name_owner, open_connection, Bus};
+use errors::*;
+
+const NM_CONFIG_FILE: &str = "/etc/NetworkManager/NetworkManager.conf";
+
+// Drop-in directories in increasing order of precedence
+const NM_CONFIG_DIRS: &[&str] = &[
+    "/usr/lib/NetworkManager/conf.d",
+    "/run/NetworkManager/conf.d",
+    "/etc/NetworkManager/conf.d",
+];
+
+const WPAS_SERVICE: &str = "fi.w1.wpa_supplicant1";
+const IWD_SERVICE: &str = "net.connman.iwd";
+
+/// The daemon NetworkManager drives Wi-Fi devices through.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum WifiBackend {
+    WpaSupplicant,
+    Iwd,
+}
+
+impl WifiBackend {
+    /// iwd does not support WPS through NetworkManager.
+    pub fn supports_wps(&self) -> bool {
+        *self == WifiBackend::WpaSupplicant
+    }
+
+    /// iwd only offers WPA2-Personal in AP mode.
+    pub fn supports_sae_hotspot(&self) -> bool {
+        *self == WifiBackend::WpaSupplicant
+    }
+
+    /// Whether the stations associated with a hotspot can be listed.
+    pub fn supports_hotspot_clients(&self) -> bool {
+        *self == WifiBackend::WpaSupplicant
+    }
+
+    /// Whether the regulatory domain can be set through the backend. iwd
+    /// leaves it to the kernel.
+    pub fn supports_country(&self) -> bool {
+        *self == WifiBackend::WpaSupplicant
+    }
+}
+
+/// Determines the backend from the `wifi.backend` setting in NetworkManager's
+/// configuration. Without one, iwd is assumed if it is running on `bus` and
+/// wpa_supplicant is not.
+pub fn get_wifi_backend(bus: Bus) -> Result<WifiBackend> {
+    if let Some(backend) = configured_backend() {
+        return match &backend as &str {
+            "iwd" => Ok(WifiBackend::Iwd),
+            "wpa_supplicant" => Ok(WifiBackend::WpaSupplicant),
+            _ => bail!(ErrorKind::NetworkManager(format!(
+                "Unknown Wi-Fi backend: {}",
+                backend
+            ))),
+        };
+    }
+
+    let connection = open_connection(bus)?;
+
+    let iwd = name_owner(&connection, IWD_SERVICE)?.is_some();
+    let supplicant = name_owner(&connection, WPAS_SERVICE)?.is_some();
+
+    if iwd && !supplicant {
+        Ok(WifiBackend::Iwd)
+    } else {
+        Ok(WifiBackend::WpaSupplicant)
+    }
+}
+
+fn configured_backend() -> Option<String> {
+    let mut files = vec![PathBuf::from(NM_CONFIG_FILE)];
+
+    for dir in NM_CONFIG_DIRS {
+        if let Ok(entries) = fs::read_dir(dir) {
+            let mut entries = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().and_then(|e| e.to_str()) == Some("conf"))
+                .collect::<Vec<_>>();
+            entries.sort();
+            files.extend(entries);
+        }
+    }
+
+    // The last file setting the backend wins
+    files
+        .iter()
+        .rev()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .find_map(|config| parse_backend(&config))
+}
+
+/// Reads `wifi.backend` from the `[device]` section of a configuration file.
+fn parse_backend(config: &str) -> Option<String> {
+    let mut in_device = false;
+    let mut backend = None;
+
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_device = line == "[device]";
+        } else if in_device {
+            let mut parts = line.splitn(2, '=');
+
+            if parts.next().map(str::trim) == Some("wifi.backend") {
+                backend = parts.next().map(|value| value.trim().to_string());
+            }
+        }
+    }
+
+    backend
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_backend() {
+        let config = "[main]\nplugins=keyfile\n\n[device]\nwifi.backend = iwd\n";
+
+        assert_eq!(parse_backend(config), Some("iwd".into()));
+        assert_eq!(parse_backend("[main]\nwifi.backend=iwd\n"), None);
+    }
+}
@@ -0,0 +1,546 @@
+use std::cell::RefCell;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use dbus_api::{Bus, CallInfo, CallObserver};
+use dbus_nm::DBusNetworkManager;
+use errors::*;
+
+/// Lists shorter than this are read on the calling thread, handing them to
+/// the pool does not pay off.
+const MIN_POOLED_READS: usize = 16;
+
+type Job<W> = Box<dyn FnOnce(&W) + Send>;
+
+/// What the workers of a pool are set up with, taken from the manager that
+/// started it.
+#[derive(Clone, Debug, PartialEq)]
+struct PoolSettings {
+    size: usize,
+    bus: Bus,
+    method_timeout: u64,
+    wait_interval: Duration,
+    wait_deadline: Duration,
+    /// The bus owner of NetworkManager as known to the manager, so that
+    /// workers report a restart it did not resync on as `DaemonRestarted`.
+    owner: Option<String>,
+}
+
+impl PoolSettings {
+    fn of(dbus_manager: &DBusNetworkManager) -> Self {
+        PoolSettings {
+            size: dbus_manager.batch_concurrency(),
+            bus: dbus_manager.bus(),
+            method_timeout: dbus_manager.method_timeout(),
+            wait_interval: dbus_manager.wait_interval(),
+            wait_deadline: dbus_manager.wait_deadline(),
+            owner: dbus_manager.daemon_owner(),
+        }
+    }
+}
+
+/// The manager of a worker thread, with the calls it made for the current
+/// item.
+pub struct Worker {
+    dbus_manager: Rc<DBusNetworkManager>,
+    calls: Rc<CallRecorder>,
+}
+
+/// A call made by a worker, reported to the call observer of the calling
+/// manager once the item it was made for is done.
+struct RecordedCall {
+    path: String,
+    interface: String,
+    method: String,
+    property: Option<String>,
+    duration: Duration,
+    error: Option<String>,
+}
+
+#[derive(Default)]
+struct CallRecorder {
+    calls: RefCell<Vec<RecordedCall>>,
+}
+
+impl CallRecorder {
+    fn take(&self) -> Vec<RecordedCall> {
+        mem::take(&mut *self.calls.borrow_mut())
+    }
+}
+
+impl CallObserver for CallRecorder {
+    fn after_call(
+        &self,
+        call: &CallInfo,
+        duration: Duration,
+        result: ::std::result::Result<(), &Error>,
+    ) {
+        self.calls.borrow_mut().push(RecordedCall {
+            path: call.path.to_string(),
+            interface: call.interface.to_string(),
+            method: call.method.to_string(),
+            property: call.property.map(|property| property.to_string()),
+            duration,
+            error: result.err().map(|e| e.to_string()),
+        });
+    }
+}
+
+fn replay_calls(observer: &dyn CallObserver, calls: Vec<RecordedCall>) {
+    for call in calls {
+        let info = CallInfo {
+            path: &call.path,
+            interface: &call.interface,
+            method: &call.method,
+            property: call.property.as_deref(),
+        };
+
+        let error = call
+            .error
+            .map(|message| Error::from(ErrorKind::DBusAPI(message)));
+
+        observer.before_call(&info);
+        observer.after_call(&info, call.duration, error.as_ref().map_or(Ok(()), Err));
+    }
+}
+
+fn connect_worker(settings: &PoolSettings) -> Result<Worker> {
+    let dbus_manager = DBusNetworkManager::with_bus(settings.bus, Some(settings.method_timeout))?;
+
+    dbus_manager.set_wait_interval(settings.wait_interval);
+    dbus_manager.set_wait_deadline(settings.wait_deadline);
+    dbus_manager.set_daemon_owner(settings.owner.clone());
+
+    // Workers read lists themselves instead of starting pools of their own
+    dbus_manager.set_batch_concurrency(1);
+
+    let calls = Rc::new(CallRecorder::default());
+    dbus_manager.set_call_observer(Some(Rc::clone(&calls) as Rc<dyn CallObserver>));
+
+    Ok(Worker {
+        dbus_manager: Rc::new(dbus_manager),
+        calls,
+    })
+}
+
+/// Threads with a bus connection each, kept by a manager between batches so
+/// that connecting does not add to the latency of every batch. The threads
+/// exit once the pool is dropped and they finished their current job.
+pub struct WorkerPool<W = Worker> {
+    settings: PoolSettings,
+    jobs: Sender<Job<W>>,
+}
+
+impl<W: 'static> WorkerPool<W> {
+    /// Starts `settings.size` threads, each setting itself up with `connect`.
+    /// Fails with the first error of `connect`, after which the threads that
+    /// did connect exit.
+    fn start<C>(settings: PoolSettings, connect: C) -> Result<Self>
+    where
+        C: Fn(&PoolSettings) -> Result<W> + Send + Sync + 'static,
+    {
+        let (jobs, receiver) = mpsc::channel::<Job<W>>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let connect = Arc::new(connect);
+        let (ready, connected) = mpsc::channel();
+
+        for index in 0..settings.size {
+            let receiver = Arc::clone(&receiver);
+            let connect = Arc::clone(&connect);
+            let ready = ready.clone();
+            let settings = settings.clone();
+
+            thread::Builder::new()
+                .name(format!("nm-worker-{}", index))
+                .spawn(move || match connect(&settings) {
+                    Ok(worker) => {
+                        let _ = ready.send(Ok(()));
+                        drop(ready);
+                        run_worker(&worker, &receiver);
+                    }
+                    Err(e) => {
+                        let _ = ready.send(Err(e));
+                    }
+                })
+                .chain_err(|| ErrorKind::NetworkManager("Unable to start batch workers".into()))?;
+        }
+
+        drop(ready);
+
+        for result in connected {
+            result
+                .chain_err(|| ErrorKind::NetworkManager("Unable to connect batch worker".into()))?;
+        }
+
+        debug!("Started {} batch workers", settings.size);
+
+        Ok(WorkerPool { settings, jobs })
+    }
+
+    /// Runs `operation` on every item and returns the outcomes in the order
+    /// of `items`, `None` for items whose operation panicked. Each outcome is
+    /// handed to `arrived` on the calling thread as soon as it is there.
+    fn run<T, R, F, A>(&self, items: Vec<T>, operation: F, mut arrived: A) -> Vec<Option<R>>
+    where
+        T: Send + 'static,
+        R: Send + 'static,
+        F: Fn(&W, T) -> R + Send + Sync + 'static,
+        A: FnMut(&mut R),
+    {
+        let count = items.len();
+        let operation = Arc::new(operation);
+        let (sender, receiver) = mpsc::channel();
+
+        for (index, item) in items.into_iter().enumerate() {
+            let operation = Arc::clone(&operation);
+            let sender = sender.clone();
+
+            let job: Job<W> = Box::new(move |worker| {
+                let _ = sender.send((index, operation(worker, item)));
+            });
+
+            if self.jobs.send(job).is_err() {
+                break;
+            }
+        }
+
+        drop(sender);
+
+        let mut outcomes = (0..count).map(|_| None).collect::<Vec<_>>();
+
+        for (index, mut outcome) in receiver {
+            arrived(&mut outcome);
+            outcomes[index] = Some(outcome);
+        }
+
+        outcomes
+    }
+}
+
+fn run_worker<W>(worker: &W, receiver: &Mutex<Receiver<Job<W>>>) {
+    loop {
+        let job = match receiver.lock().unwrap_or_else(|e| e.into_inner()).recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+
+        // A panicking operation fails its item, the worker stays available
+        if panic::catch_unwind(AssertUnwindSafe(|| job(worker))).is_err() {
+            warn!("Batch operation panicked");
+        }
+    }
+}
+
+/// The pool `current`, or a new one if there is none or it was started with
+/// other settings.
+fn pool_for<W, C>(
+    current: Option<Rc<WorkerPool<W>>>,
+    settings: PoolSettings,
+    connect: C,
+) -> Result<Rc<WorkerPool<W>>>
+where
+    W: 'static,
+    C: Fn(&PoolSettings) -> Result<W> + Send + Sync + 'static,
+{
+    match current {
+        Some(ref pool) if pool.settings == settings => Ok(Rc::clone(pool)),
+        _ => Ok(Rc::new(WorkerPool::start(settings, connect)?)),
+    }
+}
+
+/// Runs `operation` on every item and returns its results in the order of
+/// `items`, so that callers can tell which items failed.
+///
+/// Up to `batch_concurrency` items are processed at the same time by the
+/// worker pool of `dbus_manager`, each worker on a bus connection of its own
+/// with the method timeout and wait settings of `dbus_manager`. The pool is
+/// started on first use and again once these settings change. Read-only
+/// managers and a concurrency of one process the items one after another on
+/// `dbus_manager` itself.
+///
+/// The calls of a worker are reported to the call observer of `dbus_manager`
+/// once the item they were made for is done.
+pub fn run_batch<T, R, F>(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    items: Vec<T>,
+    operation: F,
+) -> Vec<Result<R>>
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(&Rc<DBusNetworkManager>, &T) -> Result<R> + Send + Sync + 'static,
+{
+    run_batch_admitted(dbus_manager, items, |_, _| Ok(()), operation)
+}
+
+/// Like `run_batch`, but items are handed to the workers only once `admit`
+/// let them through on the calling thread. Workers lack the state of
+/// `dbus_manager`, e.g. its activation limiter, which `admit` applies
+/// instead. Items processed on `dbus_manager` itself are not passed to
+/// `admit`.
+pub fn run_batch_admitted<T, R, A, F>(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    items: Vec<T>,
+    admit: A,
+    operation: F,
+) -> Vec<Result<R>>
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    A: Fn(&Rc<DBusNetworkManager>, &T) -> Result<()>,
+    F: Fn(&Rc<DBusNetworkManager>, &T) -> Result<R> + Send + Sync + 'static,
+{
+    if items.len() <= 1 || dbus_manager.batch_concurrency() <= 1 || dbus_manager.is_read_only() {
+        return items
+            .iter()
+            .map(|item| operation(dbus_manager, item))
+            .collect();
+    }
+
+    let settings = PoolSettings::of(dbus_manager);
+
+    // Not borrowed from the manager while the batch runs, so that the manager
+    // stays usable meanwhile
+    let pool = match pool_for(dbus_manager.worker_pool(), settings, connect_worker) {
+        Ok(pool) => pool,
+        Err(e) => {
+            dbus_manager.set_worker_pool(None);
+
+            let message = e
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join(": ");
+
+            return items
+                .iter()
+                .map(|_| Err(ErrorKind::NetworkManager(message.clone()).into()))
+                .collect();
+        }
+    };
+
+    dbus_manager.set_worker_pool(Some(Rc::clone(&pool)));
+
+    let observer = dbus_manager.call_observer();
+
+    let admitted = |item: &T| admit(dbus_manager, item);
+
+    run_admitted(items, admitted, |items| {
+        let outcomes = pool.run(
+            items,
+            move |worker: &Worker, item| {
+                // Left behind by an item that panicked
+                worker.calls.take();
+
+                let result = operation(&worker.dbus_manager, &item);
+                (result, worker.calls.take())
+            },
+            |&mut (_, ref mut calls)| {
+                if let Some(ref observer) = observer {
+                    replay_calls(&**observer, mem::take(calls));
+                }
+            },
+        );
+
+        outcomes
+            .into_iter()
+            .map(|outcome| match outcome {
+                Some((result, _)) => result,
+                None => Err(ErrorKind::NetworkManager("Batch operation panicked".into()).into()),
+            })
+            .collect()
+    })
+}
+
+/// Runs `run` on the items `admit` lets through, and returns its results
+/// together with the refusals of `admit` in the order of `items`.
+fn run_admitted<T, R, A, F>(items: Vec<T>, mut admit: A, run: F) -> Vec<Result<R>>
+where
+    A: FnMut(&T) -> Result<()>,
+    F: FnOnce(Vec<T>) -> Vec<Result<R>>,
+{
+    let mut refusals = Vec::with_capacity(items.len());
+    let mut admitted = Vec::with_capacity(items.len());
+
+    for item in items {
+        match admit(&item) {
+            Ok(()) => {
+                refusals.push(None);
+                admitted.push(item);
+            }
+            Err(e) => refusals.push(Some(e)),
+        }
+    }
+
+    let mut results = run(admitted).into_iter();
+
+    refusals
+        .into_iter()
+        .map(|refusal| match refusal {
+            Some(e) => Err(e),
+            None => results.next().unwrap_or_else(|| {
+                Err(ErrorKind::NetworkManager("Batch operation got lost".into()).into())
+            }),
+        })
+        .collect()
+}
+
+/// Reads many objects, e.g. the properties of all access points, with
+/// `read` on the worker pool as `run_batch` describes. Fails with the first
+/// error, like reading them one after another would.
+pub fn read_all<R, F>(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    paths: Vec<String>,
+    read: F,
+) -> Result<Vec<R>>
+where
+    R: Send + 'static,
+    F: Fn(&Rc<DBusNetworkManager>, &String) -> Result<R> + Send + Sync + 'static,
+{
+    if paths.len() < MIN_POOLED_READS {
+        return paths.iter().map(|path| read(dbus_manager, path)).collect();
+    }
+
+    debug!("Reading {} objects on the batch workers", paths.len());
+
+    run_batch(dbus_manager, paths, read).into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn settings(size: usize) -> PoolSettings {
+        PoolSettings {
+            size,
+            bus: Bus::System,
+            method_timeout: 15,
+            wait_interval: Duration::from_millis(100),
+            wait_deadline: Duration::from_secs(15),
+            owner: None,
+        }
+    }
+
+    #[test]
+    fn test_outcomes_keep_item_order() {
+        let pool = WorkerPool::start(settings(4), |_: &PoolSettings| Ok(())).unwrap();
+
+        // Later items finish first
+        let mut arrived = Vec::new();
+
+        let outcomes = pool.run(
+            (0..12u64).collect(),
+            |_: &(), item| {
+                thread::sleep(Duration::from_millis(12 - item));
+                item * 2
+            },
+            |outcome| arrived.push(*outcome),
+        );
+
+        assert_eq!(
+            outcomes,
+            (0..12).map(|item| Some(item * 2)).collect::<Vec<_>>()
+        );
+
+        arrived.sort();
+        assert_eq!(arrived, (0..12).map(|item| item * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_panicking_operation_fails_its_item() {
+        let pool = WorkerPool::start(settings(2), |_: &PoolSettings| Ok(())).unwrap();
+
+        let outcomes = pool.run(
+            vec![1, 2, 3],
+            |_: &(), item| {
+                if item == 2 {
+                    panic!("operation failed");
+                }
+                item
+            },
+            |_| {},
+        );
+
+        assert_eq!(outcomes, vec![Some(1), None, Some(3)]);
+    }
+
+    #[test]
+    fn test_pool_restarts_when_settings_change() {
+        let connects = Arc::new(AtomicUsize::new(0));
+
+        let connect = {
+            let connects = Arc::clone(&connects);
+            move |_: &PoolSettings| {
+                connects.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        };
+
+        let pool = pool_for(None, settings(2), connect.clone()).unwrap();
+        assert_eq!(connects.load(Ordering::SeqCst), 2);
+
+        let kept = pool_for(Some(Rc::clone(&pool)), settings(2), connect.clone()).unwrap();
+        assert!(Rc::ptr_eq(&pool, &kept));
+        assert_eq!(connects.load(Ordering::SeqCst), 2);
+
+        let mut changed = settings(2);
+        changed.method_timeout = 30;
+
+        let restarted = pool_for(Some(Rc::clone(&pool)), changed, connect).unwrap();
+        assert!(!Rc::ptr_eq(&pool, &restarted));
+        assert_eq!(connects.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_worker_connect_failure() {
+        let result = WorkerPool::<()>::start(settings(3), |_: &PoolSettings| {
+            bail!(ErrorKind::DBusAPI("No such bus".into()))
+        });
+
+        let error = match result {
+            Ok(_) => panic!("pool started without workers"),
+            Err(e) => e,
+        };
+
+        assert!(error.to_string().contains("Unable to connect batch worker"));
+        assert!(error.iter().any(|e| e.to_string().contains("No such bus")));
+    }
+
+    #[test]
+    fn test_refused_items_are_not_run() {
+        let results = run_admitted(
+            vec![1, 2, 3, 4],
+            |item| match item % 2 {
+                0 => bail!(ErrorKind::ActivationThrottled(
+                    item.to_string(),
+                    Duration::from_secs(2)
+                )),
+                _ => Ok(()),
+            },
+            |items| {
+                assert_eq!(items, vec![1, 3]);
+                items.into_iter().map(|item| Ok(item * 10)).collect()
+            },
+        );
+
+        let results = results
+            .into_iter()
+            .map(|result| result.map_err(|e| e.to_string()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(results[0], Ok(10));
+        assert!(results[1]
+            .as_ref()
+            .unwrap_err()
+            .contains("attempted too often"));
+        assert_eq!(results[2], Ok(30));
+        assert!(results[3].is_err());
+    }
+}
@@ -0,0 +1,341 @@
+//! Command line front end to the library, built with the `cli` feature.
+
+#[macro_use]
+extern crate error_chain;
+
+#[macro_use]
+extern crate clap;
+extern crate network_manager;
+#[macro_use]
+extern crate serde_json;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::fs::File;
+use std::io::Write;
+
+use network_manager::report::{self, ToJson};
+use network_manager::{
+    AccessPoint, AccessPointCredentials, ConnectionProfile, Device, DeviceType, NetworkManager,
+    SecretExport, SecretFlags, Security, WiFiDevice,
+};
+
+mod errors {
+    use network_manager;
+    use serde_json;
+    use std::io;
+
+    error_chain! {
+        links {
+            NetworkManager(network_manager::errors::Error, network_manager::errors::ErrorKind);
+        }
+
+        foreign_links {
+            Io(io::Error);
+            Json(serde_json::Error);
+        }
+
+        errors {
+            Runtime(info: String) {
+                description("Runtime error")
+                display("{}", info)
+            }
+        }
+    }
+}
+
+use errors::*;
+
+fn main() {
+    if let Err(ref e) = run() {
+        let stderr = &mut ::std::io::stderr();
+        let errmsg = "Error writing to stderr";
+
+        writeln!(stderr, "{}", e).expect(errmsg);
+
+        for e in e.iter().skip(1) {
+            writeln!(stderr, "  caused by: {}", e).expect(errmsg);
+        }
+
+        ::std::process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
+    let json = Arg::with_name("JSON")
+        .long("json")
+        .help("Print JSON output");
+
+    let interface = Arg::with_name("INTERFACE")
+        .short("i")
+        .takes_value(true)
+        .help("Network interface");
+
+    let matches = App::new("nm-rs")
+        .version(crate_version!())
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("status").about("Show daemon, device and connection state"),
+        )
+        .subcommand(
+            SubCommand::with_name("scan")
+                .about("List access points in range")
+                .arg(interface.clone())
+                .arg(json),
+        )
+        .subcommand(
+            SubCommand::with_name("connect")
+                .about("Connect to a Wi-Fi network")
+                .arg(interface.clone())
+                .arg(Arg::with_name("SSID").required(true).help("Network SSID"))
+                .arg(Arg::with_name("PASSWORD").help("Network password")),
+        )
+        .subcommand(
+            SubCommand::with_name("hotspot")
+                .about("Start an access point")
+                .arg(interface)
+                .arg(Arg::with_name("SSID").required(true).help("Hotspot SSID"))
+                .arg(Arg::with_name("PASSWORD").help("Hotspot password")),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Print a connection profile as JSON")
+                .arg(Arg::with_name("ID").required(true).help("Connection ID"))
+                .arg(
+                    Arg::with_name("SECRETS")
+                        .long("secrets")
+                        .takes_value(true)
+                        .possible_values(&["include", "strip", "placeholder"])
+                        .default_value("strip")
+                        .help("How to export passwords and keys"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Create or update connection profiles from a JSON file")
+                .arg(
+                    Arg::with_name("FILE")
+                        .required(true)
+                        .help("A profile or an array of profiles"),
+                )
+                .arg(
+                    Arg::with_name("DRY_RUN")
+                        .long("dry-run")
+                        .help("Only print the planned changes"),
+                ),
+        )
+        .get_matches();
+
+    let manager = NetworkManager::new();
+
+    match matches.subcommand() {
+        ("status", Some(matches)) => status(&manager, matches),
+        ("scan", Some(matches)) => scan(&manager, matches),
+        ("connect", Some(matches)) => connect(&manager, matches),
+        ("hotspot", Some(matches)) => hotspot(&manager, matches),
+        ("export", Some(matches)) => export(&manager, matches),
+        ("import", Some(matches)) => import(&manager, matches),
+        _ => unreachable!(),
+    }
+}
+
+fn status(manager: &NetworkManager, matches: &ArgMatches) -> Result<()> {
+    if matches.is_present("JSON") {
+        let status = json!({
+            "state": format!("{:?}", manager.get_state()?),
+            "connectivity": format!("{:?}", manager.get_connectivity()?),
+            "devices": report::devices(manager)?,
+            "active_connections": report::active_connections(manager)?,
+        });
+
+        println!("{}", status.to_json()?);
+
+        return Ok(());
+    }
+
+    println!("state: {:?}", manager.get_state()?);
+    println!("connectivity: {:?}", manager.get_connectivity()?);
+
+    println!("devices:");
+    for device in manager.get_devices()? {
+        println!(
+            "  {} {:?} {:?}",
+            device.interface(),
+            device.device_type(),
+            device.get_state()?
+        );
+    }
+
+    println!("active connections:");
+    for connection in manager.get_active_connections()? {
+        let settings = connection.settings();
+        println!(
+            "  {} ({}) {:?}",
+            settings.id,
+            settings.kind,
+            connection.get_state()?
+        );
+    }
+
+    Ok(())
+}
+
+fn scan(manager: &NetworkManager, matches: &ArgMatches) -> Result<()> {
+    let device = find_device(manager, matches.value_of("INTERFACE"))?;
+    let wifi_device = device.as_wifi_device().unwrap();
+
+    let access_points = wifi_device.get_access_points()?;
+
+    if matches.is_present("JSON") {
+        println!("{}", report::access_points(&access_points).to_json()?);
+
+        return Ok(());
+    }
+
+    for access_point in access_points {
+        println!(
+            "{:>3}% {} {} {:?}",
+            access_point.strength,
+            access_point.bssid,
+            access_point.ssid(),
+            access_point.security_summary()
+        );
+    }
+
+    Ok(())
+}
+
+fn connect(manager: &NetworkManager, matches: &ArgMatches) -> Result<()> {
+    let device = find_device(manager, matches.value_of("INTERFACE"))?;
+    let wifi_device = device.as_wifi_device().unwrap();
+
+    let access_point = find_access_point(&wifi_device, matches.value_of("SSID").unwrap())?;
+
+    let credentials = match matches.value_of("PASSWORD") {
+        None => AccessPointCredentials::None,
+        Some(password)
+            if access_point
+                .security_summary()
+                .intersects(Security::WPA | Security::WPA2) =>
+        {
+            AccessPointCredentials::Wpa {
+                passphrase: password.into(),
+                psk_flags: SecretFlags::NONE,
+            }
+        }
+        Some(password) => AccessPointCredentials::Wep {
+            passphrase: password.into(),
+        },
+    };
+
+    let (connection, state) = wifi_device.connect(&access_point, &credentials)?;
+
+    println!("{}: {:?}", connection.settings().id, state);
+
+    Ok(())
+}
+
+fn hotspot(manager: &NetworkManager, matches: &ArgMatches) -> Result<()> {
+    let device = find_device(manager, matches.value_of("INTERFACE"))?;
+    let wifi_device = device.as_wifi_device().unwrap();
+
+    let (connection, state) = wifi_device.create_hotspot(
+        matches.value_of("SSID").unwrap(),
+        matches.value_of("PASSWORD"),
+        None,
+    )?;
+
+    println!("{}: {:?}", connection.settings().id, state);
+
+    Ok(())
+}
+
+fn export(manager: &NetworkManager, matches: &ArgMatches) -> Result<()> {
+    let id = matches.value_of("ID").unwrap();
+
+    let connection = manager
+        .get_connections()?
+        .into_iter()
+        .find(|connection| connection.settings().id == id)
+        .ok_or_else(|| ErrorKind::Runtime(format!("Connection {} not found", id)))?;
+
+    let secrets = match matches.value_of("SECRETS").unwrap() {
+        "include" => SecretExport::Include,
+        "placeholder" => SecretExport::Placeholder,
+        _ => SecretExport::Strip,
+    };
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&connection.export(secrets)?)?
+    );
+
+    Ok(())
+}
+
+fn import(manager: &NetworkManager, matches: &ArgMatches) -> Result<()> {
+    let file = File::open(matches.value_of("FILE").unwrap())?;
+
+    let profiles = match serde_json::from_reader(file)? {
+        serde_json::Value::Array(values) => values
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<::std::result::Result<Vec<ConnectionProfile>, _>>(
+        )?,
+        value => vec![serde_json::from_value(value)?],
+    };
+
+    if profiles
+        .iter()
+        .any(ConnectionProfile::has_secret_placeholders)
+    {
+        bail!(ErrorKind::Runtime(
+            "Profiles contain placeholder secrets, fill in the real ones first".into()
+        ));
+    }
+
+    let plan = manager.plan(&profiles, false)?;
+
+    print!("{}", plan);
+
+    if !matches.is_present("DRY_RUN") {
+        manager.apply(&plan)?;
+    }
+
+    Ok(())
+}
+
+fn find_device(manager: &NetworkManager, interface: Option<&str>) -> Result<Device> {
+    if let Some(interface) = interface {
+        let device = manager.get_device_by_interface(interface)?;
+
+        if *device.device_type() == DeviceType::WiFi {
+            Ok(device)
+        } else {
+            bail!(ErrorKind::Runtime(format!(
+                "{} is not a WiFi device",
+                interface
+            )))
+        }
+    } else {
+        let devices = manager.get_devices()?;
+
+        let index = devices
+            .iter()
+            .position(|d| *d.device_type() == DeviceType::WiFi);
+
+        if let Some(index) = index {
+            Ok(devices[index].clone())
+        } else {
+            bail!(ErrorKind::Runtime("Cannot find a WiFi device".into()))
+        }
+    }
+}
+
+fn find_access_point(wifi_device: &WiFiDevice, ssid: &str) -> Result<AccessPoint> {
+    let access_points = wifi_device.get_access_points()?;
+
+    access_points
+        .into_iter()
+        .find(|ap| ap.ssid().as_str().ok() == Some(ssid))
+        .ok_or_else(|| ErrorKind::Runtime("Access point not found".into()).into())
+}
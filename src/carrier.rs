@@ -0,0 +1,189 @@
+use std::time::{Duration, Instant};
+
+use dbus::arg::{Dict, Iter, Variant};
+use dbus::ConnectionItem;
+
+use dbus_api::open_connection;
+use dbus_nm::DBusNetworkManager;
+use errors::*;
+
+const NM_BUS_NAME: &str = "org.freedesktop.NetworkManager";
+const NM_WIRED_INTERFACE: &str = "org.freedesktop.NetworkManager.Device.Wired";
+
+/// How long to block for signals while no carrier change is pending.
+const IDLE_TIMEOUT_MS: u32 = 1000;
+
+/// Link changes of an Ethernet device, e.g. on dock or undock.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum CarrierEvent {
+    Up,
+    Down,
+}
+
+impl CarrierEvent {
+    fn from_carrier(carrier: bool) -> Self {
+        if carrier {
+            CarrierEvent::Up
+        } else {
+            CarrierEvent::Down
+        }
+    }
+}
+
+/// Blocks watching `PropertiesChanged` of the wired device at `path`,
+/// invoking `callback` with each carrier change until it returns `false`.
+///
+/// A change is only reported once the carrier stayed in its new state for
+/// `debounce`, so that a flapping link settling back into its previous state
+/// is not reported at all. A zero `debounce` reports every change.
+pub fn watch_carrier<F>(
+    dbus_manager: &DBusNetworkManager,
+    path: &str,
+    debounce: Duration,
+    mut callback: F,
+) -> Result<()>
+where
+    F: FnMut(CarrierEvent) -> bool,
+{
+    let connection = open_connection(dbus_manager.bus())?;
+
+    connection.add_match(&format!(
+        "type='signal',sender='{}',path='{}',interface='org.freedesktop.DBus.Properties',\
+         member='PropertiesChanged',arg0='{}'",
+        NM_BUS_NAME, path, NM_WIRED_INTERFACE
+    ))?;
+
+    // Read once subscribed, so that a change in between is not lost
+    let mut debouncer = Debouncer::new(dbus_manager.get_device_carrier(path)?, debounce);
+
+    loop {
+        let timeout = debouncer.timeout_ms(Instant::now());
+
+        if let Some(ConnectionItem::Signal(message)) = connection.iter(timeout as i32).next() {
+            let changed = message.get2::<&str, Dict<&str, Variant<Iter>, _>>();
+
+            if let (Some(NM_WIRED_INTERFACE), Some(properties)) = changed {
+                for (name, mut value) in properties {
+                    if name == "Carrier" {
+                        if let Some(carrier) = value.0.get::<bool>() {
+                            debug!("Carrier of {} changed: {}", path, carrier);
+                            debouncer.changed(carrier, Instant::now());
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(event) = debouncer.poll(Instant::now()) {
+            if !callback(event) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Holds back carrier changes until the carrier stayed in its new state for
+/// the debounce time.
+struct Debouncer {
+    debounce: Duration,
+    reported: bool,
+    pending: Option<(bool, Instant)>,
+}
+
+impl Debouncer {
+    fn new(carrier: bool, debounce: Duration) -> Self {
+        Debouncer {
+            debounce,
+            reported: carrier,
+            pending: None,
+        }
+    }
+
+    fn changed(&mut self, carrier: bool, now: Instant) {
+        self.pending = Some((carrier, now));
+    }
+
+    /// The change to report once it settled, if it differs from the last one.
+    fn poll(&mut self, now: Instant) -> Option<CarrierEvent> {
+        let (carrier, since) = self.pending?;
+
+        if now.duration_since(since) < self.debounce {
+            return None;
+        }
+
+        self.pending = None;
+
+        if carrier == self.reported {
+            return None;
+        }
+
+        self.reported = carrier;
+
+        Some(CarrierEvent::from_carrier(carrier))
+    }
+
+    /// How long to block for signals before the pending change settles.
+    fn timeout_ms(&self, now: Instant) -> u32 {
+        match self.pending {
+            Some((_, since)) => remaining_ms(self.debounce, now.duration_since(since)),
+            None => IDLE_TIMEOUT_MS,
+        }
+    }
+}
+
+fn remaining_ms(debounce: Duration, elapsed: Duration) -> u32 {
+    let remaining = debounce.checked_sub(elapsed).unwrap_or_default();
+
+    remaining.as_millis().min(u128::from(IDLE_TIMEOUT_MS)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debounce_flap() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(true, Duration::from_millis(500));
+
+        assert_eq!(debouncer.timeout_ms(start), IDLE_TIMEOUT_MS);
+
+        debouncer.changed(false, start);
+        assert_eq!(debouncer.timeout_ms(start), 500);
+        assert_eq!(debouncer.poll(start + Duration::from_millis(200)), None);
+
+        // back up before settling, nothing to report
+        debouncer.changed(true, start + Duration::from_millis(300));
+        assert_eq!(debouncer.poll(start + Duration::from_millis(900)), None);
+        assert_eq!(debouncer.timeout_ms(start), IDLE_TIMEOUT_MS);
+    }
+
+    #[test]
+    fn test_debounce_settled() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(true, Duration::from_millis(500));
+
+        debouncer.changed(false, start);
+        assert_eq!(
+            debouncer.timeout_ms(start + Duration::from_millis(400)),
+            100
+        );
+        assert_eq!(debouncer.poll(start + Duration::from_millis(499)), None);
+        assert_eq!(
+            debouncer.poll(start + Duration::from_millis(500)),
+            Some(CarrierEvent::Down)
+        );
+        assert_eq!(debouncer.poll(start + Duration::from_millis(600)), None);
+    }
+
+    #[test]
+    fn test_no_debounce() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(false, Duration::from_millis(0));
+
+        debouncer.changed(true, start);
+        assert_eq!(debouncer.timeout_ms(start), 0);
+        assert_eq!(debouncer.poll(start), Some(CarrierEvent::Up));
+    }
+}
@@ -1,13 +1,33 @@
+use std::cmp::Reverse;
 use std::fmt;
-use std::net::Ipv4Addr;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "hotspot")]
+use backend::{get_wifi_backend, WifiBackend};
+use batch::{read_all, run_batch, run_batch_admitted};
 use dbus_nm::DBusNetworkManager;
 use errors::*;
+#[cfg(feature = "wifi")]
+use network_manager_types::Value;
 
-use device::{get_active_connection_devices, Device};
-use ssid::{AsSsidSlice, Ssid};
-use wifi::{AccessPoint, AccessPointCredentials};
+use device::{get_active_connection_devices, wait_for_device, Device, PathGetter};
+#[cfg(feature = "hotspot")]
+use hotspot::{remove_dnsmasq_config, write_dnsmasq_config, HotspotConfig, HotspotSecurity};
+#[cfg(feature = "wifi")]
+use manager::Connectivity;
+#[cfg(feature = "wifi")]
+use network_manager_types::AsSsidSlice;
+use network_manager_types::SecretExport;
+use network_manager_types::Ssid;
+use settings::{new_uuid, verify_interface_name, ConnectionProfile};
+use signal_wait::{properties_changed_rule, signal_rule, wait_for_signal};
+#[cfg(feature = "wifi")]
+use wifi::psk::verify_psk;
+#[cfg(feature = "wifi")]
+use wifi::{AccessPoint, AccessPointCredentials, WifiPowerSave};
+#[cfg(feature = "wwan")]
+use wwan::check_sim_unlocked;
 
 #[derive(Clone)]
 pub struct Connection {
@@ -31,6 +51,59 @@ impl Connection {
         &self.settings
     }
 
+    pub fn get_flags(&self) -> Result<ConnectionFlags> {
+        Ok(ConnectionFlags::from_bits_truncate(
+            self.dbus_manager.get_connection_flags(&self.path)?,
+        ))
+    }
+
+    /// Reads the full settings of the connection. Secrets are not included.
+    pub fn get_profile(&self) -> Result<ConnectionProfile> {
+        self.dbus_manager.get_connection_profile(&self.path)
+    }
+
+    /// Reads the full settings of the connection for a backup or bug report,
+    /// with secrets stored by NetworkManager included, left out or replaced
+    /// by a placeholder. Secrets owned by agents are never included.
+    pub fn export(&self, secrets: SecretExport) -> Result<ConnectionProfile> {
+        let mut profile = self.get_profile()?;
+
+        if secrets == SecretExport::Strip {
+            return Ok(profile);
+        }
+
+        let names = profile.settings().keys().cloned().collect::<Vec<_>>();
+
+        for name in names {
+            match self.dbus_manager.get_connection_secrets(&self.path, &name) {
+                Ok(setting) => {
+                    for (key, value) in setting {
+                        profile.set(&name, &key, value);
+                    }
+                }
+                Err(e) => debug!("Unable to read {} secrets of {}: {}", name, self.path, e),
+            }
+        }
+
+        profile.export_secrets(secrets);
+
+        Ok(profile)
+    }
+
+    /// Add a copy of the profile named `id` with a new UUID, including the
+    /// secrets stored by NetworkManager, e.g. to derive per-site variants of
+    /// a template profile. The copy is not activated.
+    pub fn duplicate(&self, id: &str) -> Result<Connection> {
+        let mut profile = self.export(SecretExport::Include)?;
+
+        profile
+            .set("connection", "id", id)
+            .set("connection", "uuid", new_uuid()?);
+        profile.remove("connection", "timestamp");
+
+        add_connection(&self.dbus_manager, &profile, true)
+    }
+
     pub fn get_state(&self) -> Result<ConnectionState> {
         let active_path_option = get_connection_active_path(&self.dbus_manager, &self.path)?;
 
@@ -43,12 +116,50 @@ impl Connection {
         }
     }
 
+    /// Merge the keys set in `changes` into the stored profile. Active
+    /// connections pick the changes up on their next activation.
+    pub fn update(&self, changes: &ConnectionProfile) -> Result<()> {
+        let mut current = get_profile_with_secrets(&self.dbus_manager, &self.path, changes)?;
+
+        current.merge(changes);
+
+        self.dbus_manager.update_connection(&self.path, &current)
+    }
+
     pub fn delete(&self) -> Result<()> {
         self.dbus_manager.delete_connection(&self.path)
     }
 
+    /// Get the Wi-Fi power save mode of the stored profile. `None` for
+    /// connections other than Wi-Fi ones.
+    #[cfg(feature = "wifi")]
+    pub fn get_wifi_powersave(&self) -> Result<Option<WifiPowerSave>> {
+        let profile = self.get_profile()?;
+
+        if profile.setting("802-11-wireless").is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            profile.wifi_powersave().unwrap_or(WifiPowerSave::Default),
+        ))
+    }
+
+    /// Set the Wi-Fi power save mode of the stored profile, e.g. to force it
+    /// off for latency-sensitive traffic. Takes effect on the next
+    /// activation.
+    #[cfg(feature = "wifi")]
+    pub fn set_wifi_powersave(&self, powersave: WifiPowerSave) -> Result<()> {
+        let mut changes = ConnectionProfile::default();
+        changes.set_wifi_powersave(powersave);
+
+        self.update(&changes)
+    }
+
     /// Activate a Network Manager connection.
     pub fn activate(&self) -> Result<ConnectionState> {
+        enter_span!("activate_connection", path = %self.path);
+
         let state = self.get_state()?;
 
         match state {
@@ -56,25 +167,63 @@ impl Connection {
             ConnectionState::Activating => wait(
                 self,
                 &ConnectionState::Activated,
-                self.dbus_manager.method_timeout(),
+                self.dbus_manager.wait_deadline(),
             ),
             ConnectionState::Unknown => bail!(ErrorKind::NetworkManager(
                 "Unable to get connection state".into()
             )),
             _ => {
-                self.dbus_manager.activate_connection(&self.path)?;
+                #[cfg(feature = "wwan")]
+                if self.settings.kind == "gsm" {
+                    check_sim_unlocked(&self.dbus_manager, &self.path, None)?;
+                }
 
-                wait(
-                    self,
-                    &ConnectionState::Activated,
-                    self.dbus_manager.method_timeout(),
-                )
+                let active_path = self.dbus_manager.activate_connection(&self.path)?;
+
+                wait_active(self, &active_path, self.dbus_manager.wait_deadline())
             }
         }
     }
 
+    /// Activate the connection on `device` rather than letting
+    /// NetworkManager pick one.
+    pub fn activate_on(&self, device: &Device) -> Result<ConnectionState> {
+        self.activate_with(device.path(), "/")
+    }
+
+    /// Activate a Wi-Fi connection on `device` associated with
+    /// `access_point`, forcing that BSS instead of letting NetworkManager
+    /// choose among the access points of the network.
+    #[cfg(feature = "wifi")]
+    pub fn activate_on_access_point(
+        &self,
+        device: &Device,
+        access_point: &AccessPoint,
+    ) -> Result<ConnectionState> {
+        self.activate_with(device.path(), &access_point.path)
+    }
+
+    /// Activates the connection even if it is already active, e.g. on
+    /// another device or access point, and waits until it is activated.
+    fn activate_with(&self, device_path: &str, specific_object: &str) -> Result<ConnectionState> {
+        enter_span!("activate_connection", path = %self.path, device = %device_path);
+
+        #[cfg(feature = "wwan")]
+        if self.settings.kind == "gsm" {
+            check_sim_unlocked(&self.dbus_manager, &self.path, Some(device_path))?;
+        }
+
+        let active_path =
+            self.dbus_manager
+                .activate_connection_on(&self.path, device_path, specific_object)?;
+
+        wait_active(self, &active_path, self.dbus_manager.wait_deadline())
+    }
+
     /// Deactivates a Network Manager connection.
     pub fn deactivate(&self) -> Result<ConnectionState> {
+        enter_span!("deactivate_connection", path = %self.path);
+
         let state = self.get_state()?;
 
         match state {
@@ -82,7 +231,7 @@ impl Connection {
             ConnectionState::Deactivating => wait(
                 self,
                 &ConnectionState::Deactivated,
-                self.dbus_manager.method_timeout(),
+                self.dbus_manager.wait_deadline(),
             ),
             ConnectionState::Unknown => bail!(ErrorKind::NetworkManager(
                 "Unable to get connection state".into()
@@ -97,7 +246,7 @@ impl Connection {
                     wait(
                         self,
                         &ConnectionState::Deactivated,
-                        self.dbus_manager.method_timeout(),
+                        self.dbus_manager.wait_deadline(),
                     )
                 } else {
                     Ok(ConnectionState::Deactivated)
@@ -137,6 +286,12 @@ impl PartialEq for Connection {
 
 impl Eq for Connection {}
 
+impl PathGetter for Connection {
+    fn path(&self) -> &str {
+        &self.path
+    }
+}
+
 impl fmt::Debug for Connection {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -147,7 +302,7 @@ impl fmt::Debug for Connection {
     }
 }
 
-impl<'a> From<&'a Connection> for i32 {
+impl From<&Connection> for i32 {
     fn from(val: &Connection) -> i32 {
         val.clone()
             .path
@@ -159,16 +314,38 @@ impl<'a> From<&'a Connection> for i32 {
     }
 }
 
+bitflags! {
+    /// How a profile came to be, NetworkManager's `NMSettingsConnectionFlags`.
+    pub struct ConnectionFlags: u32 {
+        const NONE         = 0x0;
+        // the profile is not saved to disk, or has changes that are not
+        const UNSAVED      = 0x1;
+        // NetworkManager created the profile, e.g. "Wired connection 1"
+        const NM_GENERATED = 0x2;
+        // the profile is deleted once it is deactivated
+        const VOLATILE     = 0x4;
+        // the profile represents configuration made outside of NetworkManager
+        const EXTERNAL     = 0x8;
+    }
+}
+
 #[derive(Default, Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct ConnectionSettings {
     pub kind: String, // `type` is a reserved word, so we are using `kind` instead
     pub id: String,
     pub uuid: String,
     pub ssid: Ssid,
     pub mode: String,
+    /// `connection.timestamp`: when the profile was last activated
+    /// successfully, in seconds since the Unix epoch. NetworkManager also
+    /// refreshes it periodically while the profile stays active. `None` if it
+    /// never was.
+    pub timestamp: Option<u64>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum ConnectionState {
     Unknown = 0,
     Activating = 1,
@@ -193,20 +370,70 @@ impl From<i64> for ConnectionState {
     }
 }
 
+#[cfg(feature = "hotspot")]
+pub fn get_connection_by_path(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    path: &str,
+) -> Result<Connection> {
+    Connection::init(dbus_manager, path)
+}
+
 pub fn get_connections(dbus_manager: &Rc<DBusNetworkManager>) -> Result<Vec<Connection>> {
     let paths = dbus_manager.list_connections()?;
 
-    let mut connections = Vec::with_capacity(paths.len());
+    let settings = read_all(dbus_manager, paths.clone(), |dbus_manager, path| {
+        dbus_manager.get_connection_settings(path)
+    })?;
 
-    for path in &paths {
-        connections.push(Connection::init(dbus_manager, path)?)
-    }
+    let mut connections = paths
+        .into_iter()
+        .zip(settings)
+        .map(|(path, settings)| Connection {
+            dbus_manager: Rc::clone(dbus_manager),
+            path,
+            settings,
+        })
+        .collect::<Vec<_>>();
 
     connections.sort();
 
     Ok(connections)
 }
 
+/// The profiles having none of `flags`, sorted by path.
+pub fn get_connections_without(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    flags: ConnectionFlags,
+) -> Result<Vec<Connection>> {
+    let mut connections = Vec::new();
+
+    for connection in get_connections(dbus_manager)? {
+        if !connection.get_flags()?.intersects(flags) {
+            connections.push(connection);
+        }
+    }
+
+    Ok(connections)
+}
+
+/// Wi-Fi profiles that were connected before, most recently used first, at
+/// most `count` of them.
+pub fn get_recently_used_networks(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    count: usize,
+) -> Result<Vec<Connection>> {
+    let mut connections = get_connections(dbus_manager)?;
+
+    connections.retain(|connection| {
+        connection.settings.kind == "802-11-wireless" && connection.settings.timestamp.is_some()
+    });
+
+    connections.sort_by_key(|connection| Reverse(connection.settings.timestamp));
+    connections.truncate(count);
+
+    Ok(connections)
+}
+
 pub fn get_active_connections(dbus_manager: &Rc<DBusNetworkManager>) -> Result<Vec<Connection>> {
     let active_paths = dbus_manager.get_active_connections()?;
 
@@ -223,12 +450,31 @@ pub fn get_active_connections(dbus_manager: &Rc<DBusNetworkManager>) -> Result<V
     Ok(connections)
 }
 
+/// Get the connection active on the device at `device_path`, if any.
+pub fn get_device_connection(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    device_path: &str,
+) -> Result<Option<Connection>> {
+    let active_path = match dbus_manager.get_device_active_connection(device_path) {
+        Some(active_path) => active_path,
+        None => return Ok(None),
+    };
+
+    match dbus_manager.get_active_connection_path(&active_path) {
+        Some(path) => Ok(Some(Connection::init(dbus_manager, &path)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(feature = "wifi")]
 pub fn connect_to_access_point(
     dbus_manager: &Rc<DBusNetworkManager>,
     device_path: &str,
     access_point: &AccessPoint,
     credentials: &AccessPointCredentials,
 ) -> Result<(Connection, ConnectionState)> {
+    enter_span!("connect_to_access_point", device = %device_path);
+
     let (path, _) = dbus_manager.connect_to_access_point(device_path, access_point, credentials)?;
 
     let connection = Connection::init(dbus_manager, &path)?;
@@ -236,36 +482,846 @@ pub fn connect_to_access_point(
     let state = wait(
         &connection,
         &ConnectionState::Activated,
-        dbus_manager.method_timeout(),
+        dbus_manager.wait_deadline(),
     )?;
 
     Ok((connection, state))
 }
 
-pub fn create_hotspot<S>(
+#[cfg(feature = "wifi")]
+pub fn activate_on_access_point(
     dbus_manager: &Rc<DBusNetworkManager>,
+    path: &str,
     device_path: &str,
-    interface: &str,
-    ssid: &S,
-    password: Option<&str>,
-    address: Option<Ipv4Addr>,
-) -> Result<(Connection, ConnectionState)>
+    access_point: &AccessPoint,
+) -> Result<(Connection, ConnectionState)> {
+    let connection = Connection::init(dbus_manager, path)?;
+
+    let state = connection.activate_with(device_path, &access_point.path)?;
+
+    Ok((connection, state))
+}
+
+/// Adds a profile without activating it. Unsaved profiles are lost when
+/// NetworkManager restarts. See `staged_profile` for autoconnect.
+pub fn add_connection(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    profile: &ConnectionProfile,
+    save: bool,
+) -> Result<Connection> {
+    enter_span!("add_connection", id = ?profile.id(), save = save);
+
+    let profile = staged_profile(profile);
+
+    let path = if save {
+        dbus_manager.add_connection(&profile)?
+    } else {
+        dbus_manager.add_connection_unsaved(&profile)?
+    };
+
+    Connection::init(dbus_manager, &path)
+}
+
+/// `profile` with `connection.autoconnect` turned off unless it is set
+/// explicitly, as NetworkManager would otherwise bring the profile up as soon
+/// as a matching device is available.
+fn staged_profile(profile: &ConnectionProfile) -> ConnectionProfile {
+    let mut profile = profile.clone();
+
+    if profile.get("connection", "autoconnect").is_none() {
+        profile.set_autoconnect(false);
+    }
+
+    profile
+}
+
+/// Delete the profiles with the given UUIDs, concurrently as `run_batch`
+/// describes. The results are in the order of `uuids`.
+pub fn delete_connections(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    uuids: &[&str],
+) -> Vec<Result<()>> {
+    enter_span!("delete_connections", count = uuids.len());
+
+    let uuids = uuids.iter().map(|uuid| uuid.to_string()).collect();
+
+    run_batch(dbus_manager, uuids, |dbus_manager, uuid| {
+        let path = dbus_manager.get_connection_by_uuid(uuid)?;
+
+        dbus_manager.delete_connection(&path)
+    })
+}
+
+/// Activate `connections` concurrently as `run_batch` describes, each on
+/// the device NetworkManager picks. The results are in the order of
+/// `connections`. The activation limiter applies as to `Connection::activate`.
+pub fn activate_many(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    connections: &[Connection],
+) -> Vec<Result<ConnectionState>> {
+    enter_span!("activate_many", count = connections.len());
+
+    // Connections cannot be shared between threads, so the workers rebuild
+    // them on their own bus connections
+    let items = connections
+        .iter()
+        .map(|connection| (connection.path.clone(), connection.settings.clone()))
+        .collect::<Vec<_>>();
+
+    let results = run_batch_admitted(
+        dbus_manager,
+        items,
+        |dbus_manager, item| dbus_manager.check_activation(&item.1.uuid, "/"),
+        |dbus_manager, item| {
+            let (ref path, ref settings) = *item;
+
+            let connection = Connection {
+                dbus_manager: Rc::clone(dbus_manager),
+                path: path.clone(),
+                settings: settings.clone(),
+            };
+
+            connection.activate()
+        },
+    );
+
+    // The limiter of the workers is not the one of `dbus_manager`
+    for (connection, result) in connections.iter().zip(&results) {
+        if let Ok(ref state) = *result {
+            activation_finished(connection, state);
+        }
+    }
+
+    results
+}
+
+/// Add `profiles` without activating them, concurrently as `run_batch`
+/// describes. Profiles with secret placeholders fail. The results are in
+/// the order of `profiles`.
+pub fn import_many(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    profiles: &[ConnectionProfile],
+) -> Vec<Result<Connection>> {
+    enter_span!("import_many", count = profiles.len());
+
+    let paths = run_batch(dbus_manager, profiles.to_vec(), |dbus_manager, profile| {
+        if profile.has_secret_placeholders() {
+            bail!(ErrorKind::NetworkManager(format!(
+                "Profile {:?} contains placeholder secrets",
+                profile.id()
+            )));
+        }
+
+        dbus_manager.add_connection(profile)
+    });
+
+    paths
+        .into_iter()
+        .map(|path| Connection::init(dbus_manager, &path?))
+        .collect()
+}
+
+pub fn create_connection(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    profile: &ConnectionProfile,
+    device_path: Option<&str>,
+) -> Result<(Connection, ConnectionState)> {
+    enter_span!("create_connection", id = ?profile.id());
+
+    let (path, _) =
+        dbus_manager.add_and_activate_connection(profile, device_path.unwrap_or("/"), "/")?;
+
+    let connection = Connection::init(dbus_manager, &path)?;
+
+    let state = wait(
+        &connection,
+        &ConnectionState::Activated,
+        dbus_manager.wait_deadline(),
+    )?;
+
+    Ok((connection, state))
+}
+
+/// Add and activate the profile of a virtual device, e.g. a bridge, and
+/// return once NetworkManager created the device named by its
+/// `connection.interface-name`. The activation itself may still be in
+/// progress, a bridge e.g. waits for its ports.
+pub fn create_virtual_device(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    profile: &ConnectionProfile,
+    timeout: Duration,
+) -> Result<(Connection, Device)> {
+    enter_span!("create_virtual_device", id = ?profile.id());
+
+    let interface = match profile.interface_name() {
+        Some(interface) => interface,
+        None => bail!(ErrorKind::NetworkManager(format!(
+            "Profile {:?} has no interface name",
+            profile.id()
+        ))),
+    };
+
+    verify_interface_name(interface)?;
+
+    let (path, _) = dbus_manager.add_and_activate_connection(profile, "/", "/")?;
+
+    let connection = Connection::init(dbus_manager, &path)?;
+
+    let device = wait_for_device(dbus_manager, interface, false, timeout)?;
+
+    Ok((connection, device))
+}
+
+/// What to do with a profile added by
+/// [`NetworkManager::create_connection_with`](struct.NetworkManager.html#method.create_connection_with)
+/// when it fails to activate within the method timeout.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OnFailure {
+    /// Leave the profile in place.
+    Keep,
+    /// Delete the added profile.
+    Delete,
+    /// Roll the device, or all devices if none was given, back to a checkpoint
+    /// taken before the profile was added. This also undoes any changes
+    /// NetworkManager made to other profiles meanwhile. Falls back to `Delete`
+    /// on NetworkManager builds without checkpoint support.
+    Rollback,
+}
+
+/// Seconds on top of the wait deadline after which NetworkManager rolls back
+/// a checkpoint on its own, in case we never get to destroy it.
+const CHECKPOINT_GRACE: u64 = 30;
+
+const NM_SERVICE_INTERFACE: &str = "org.freedesktop.NetworkManager";
+const NM_ACTIVE_INTERFACE: &str = "org.freedesktop.NetworkManager.Connection.Active";
+
+pub fn create_connection_with(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    profile: &ConnectionProfile,
+    device_path: Option<&str>,
+    on_failure: &OnFailure,
+) -> Result<Connection> {
+    let on_failure = if *on_failure == OnFailure::Rollback
+        && !dbus_manager.supports_checkpoints()?
+    {
+        warn!("NetworkManager has no checkpoint support, deleting the profile on failure instead");
+        &OnFailure::Delete
+    } else {
+        on_failure
+    };
+
+    let checkpoint = if *on_failure == OnFailure::Rollback {
+        let devices: Vec<&str> = device_path.into_iter().collect();
+        let timeout = dbus_manager.wait_deadline().as_secs() + CHECKPOINT_GRACE;
+
+        Some(dbus_manager.create_checkpoint(&devices, timeout as u32)?)
+    } else {
+        None
+    };
+
+    let mut added = None;
+
+    let result = dbus_manager
+        .add_and_activate_connection(profile, device_path.unwrap_or("/"), "/")
+        .and_then(|(path, _)| {
+            added = Some(path.clone());
+
+            let connection = Connection::init(dbus_manager, &path)?;
+
+            let state = wait(
+                &connection,
+                &ConnectionState::Activated,
+                dbus_manager.wait_deadline(),
+            )?;
+
+            if state == ConnectionState::Activated {
+                Ok(connection)
+            } else {
+                bail!(ErrorKind::NetworkManager(format!(
+                    "Connection {:?} not activated: {:?}",
+                    profile.id(),
+                    state
+                )))
+            }
+        });
+
+    let cleanup = match (result.is_ok(), checkpoint) {
+        (true, Some(checkpoint)) => dbus_manager.destroy_checkpoint(&checkpoint),
+        (false, Some(checkpoint)) => {
+            warn!(
+                "Rolling back checkpoint {} after failed activation",
+                checkpoint
+            );
+            dbus_manager.rollback_checkpoint(&checkpoint)
+        }
+        (false, None) if *on_failure == OnFailure::Delete => match added {
+            Some(path) => {
+                warn!("Deleting connection {} after failed activation", path);
+                dbus_manager.delete_connection(&path)
+            }
+            None => Ok(()),
+        },
+        _ => Ok(()),
+    };
+
+    chain_cleanup(result, cleanup)
+}
+
+/// Combines the result of an operation with that of cleaning up after it. A
+/// failed cleanup is reported as the cause of the failed operation rather
+/// than masking it.
+fn chain_cleanup<T>(result: Result<T>, cleanup: Result<()>) -> Result<T> {
+    match (result, cleanup) {
+        (Ok(value), Ok(())) => Ok(value),
+        (Ok(_), Err(cleanup_error)) => Err(cleanup_error),
+        (Err(e), Ok(())) => Err(e),
+        (Err(e), Err(cleanup_error)) => Err(e.with_cleanup_error(cleanup_error)),
+    }
+}
+
+pub fn switch_connection(
+    from: &Connection,
+    to: &Connection,
+    delete_old: bool,
+    on_failure: &OnFailure,
+) -> Result<()> {
+    enter_span!("switch_connection", from = ?from.settings.id, to = ?to.settings.id);
+
+    let dbus_manager = &from.dbus_manager;
+
+    let devices = from.get_devices()?;
+    let was_active = !devices.is_empty();
+
+    let checkpoint = if *on_failure == OnFailure::Rollback && dbus_manager.supports_checkpoints()? {
+        let device_paths: Vec<&str> = devices.iter().map(PathGetter::path).collect();
+        let timeout = dbus_manager.wait_deadline().as_secs() + CHECKPOINT_GRACE;
+
+        Some(dbus_manager.create_checkpoint(&device_paths, timeout as u32)?)
+    } else {
+        None
+    };
+
+    let result = to.activate().and_then(|state| {
+        if state == ConnectionState::Activated {
+            Ok(())
+        } else {
+            bail!(ErrorKind::NetworkManager(format!(
+                "Connection {:?} not activated: {:?}",
+                to.settings.id, state
+            )))
+        }
+    });
+
+    if let Err(e) = result {
+        // `to` existed before the switch, so it is kept whatever `on_failure`
+        // says, only `from` is brought back
+        let restored = match checkpoint {
+            Some(checkpoint) => {
+                warn!("Rolling back checkpoint {} after failed switch", checkpoint);
+                dbus_manager.rollback_checkpoint(&checkpoint)
+            }
+            None if was_active => {
+                warn!(
+                    "Reactivating connection {:?} after failed switch",
+                    from.settings.id
+                );
+                from.activate().map(|_| ())
+            }
+            None => Ok(()),
+        };
+
+        return chain_cleanup(Err(e), restored);
+    }
+
+    if let Some(checkpoint) = checkpoint {
+        dbus_manager.destroy_checkpoint(&checkpoint)?;
+    }
+
+    // Activating on the same device already took the old connection down
+    from.deactivate()?;
+
+    if delete_old {
+        from.delete()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "wifi")]
+pub fn rotate_psk<T>(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    ssid: &T,
+    new_psk: &str,
+) -> Result<Vec<Connection>>
 where
-    S: AsSsidSlice + ?Sized,
+    T: AsSsidSlice + ?Sized,
 {
-    let (path, _) = dbus_manager.create_hotspot(device_path, interface, ssid, password, address)?;
+    let ssid = ssid.as_ssid_slice()?;
+
+    verify_psk(new_psk)?;
+
+    let connections: Vec<Connection> = get_connections(dbus_manager)?
+        .into_iter()
+        .filter(|connection| {
+            connection.settings.kind == "802-11-wireless"
+                && connection.settings.mode != "ap"
+                && connection.settings.ssid.as_bytes() == ssid.as_bytes()
+        })
+        .collect();
+
+    if connections.is_empty() {
+        bail!(ErrorKind::NetworkManager(format!(
+            "No connection for SSID {:?}",
+            ssid
+        )));
+    }
+
+    let changes = ConnectionProfile::default().with("802-11-wireless-security", "psk", new_psk);
+
+    let mut rotated = Vec::new();
+
+    for connection in &connections {
+        if let Err(e) = rotate_connection_psk(connection, &changes, &mut rotated) {
+            return Err(match restore_psks(&rotated) {
+                Ok(()) => e,
+                Err(restore_error) => e.with_cleanup_error(restore_error),
+            });
+        }
+    }
+
+    Ok(connections)
+}
+
+/// A connection whose pre-shared key was changed by `rotate_psk`.
+#[cfg(feature = "wifi")]
+struct RotatedPsk<'a> {
+    connection: &'a Connection,
+    old_psk: Value,
+    active: bool,
+}
+
+#[cfg(feature = "wifi")]
+fn rotate_connection_psk<'a>(
+    connection: &'a Connection,
+    changes: &ConnectionProfile,
+    rotated: &mut Vec<RotatedPsk<'a>>,
+) -> Result<()> {
+    enter_span!("rotate_psk", id = ?connection.settings.id);
+
+    let current = get_profile_with_secrets(&connection.dbus_manager, &connection.path, changes)?;
+
+    let old_psk = match current.get("802-11-wireless-security", "psk") {
+        Some(psk) => psk.clone(),
+        None => bail!(ErrorKind::NetworkManager(format!(
+            "Connection {:?} has no readable pre-shared key",
+            connection.settings.id
+        ))),
+    };
+
+    let active = connection.get_state()? == ConnectionState::Activated;
+
+    connection.update(changes)?;
+
+    rotated.push(RotatedPsk {
+        connection,
+        old_psk,
+        active,
+    });
+
+    if !active {
+        return Ok(());
+    }
+
+    // Changed credentials can not be reapplied, only take effect on a new
+    // activation
+    if reactivate(connection)? != ConnectionState::Activated {
+        bail!(ErrorKind::PreSharedKey(format!(
+            "Connection {:?} not activated with the new pre-shared key",
+            connection.settings.id
+        )));
+    }
+
+    // `Unknown` means that NetworkManager does not check connectivity
+    match connection.dbus_manager.check_connectivity()? {
+        Connectivity::Full | Connectivity::Unknown => Ok(()),
+        connectivity => bail!(ErrorKind::PreSharedKey(format!(
+            "Connection {:?} has {:?} connectivity with the new pre-shared key",
+            connection.settings.id, connectivity
+        ))),
+    }
+}
+
+/// Puts the old pre-shared keys back, newest first, and reactivates the
+/// connections that were active. Restores as many as possible and returns
+/// the first failure.
+#[cfg(feature = "wifi")]
+fn restore_psks(rotated: &[RotatedPsk]) -> Result<()> {
+    let mut result = Ok(());
+
+    for rotated in rotated.iter().rev() {
+        let connection = rotated.connection;
+
+        warn!(
+            "Rolling back the pre-shared key of connection {:?}",
+            connection.settings.id
+        );
+
+        let restored = connection
+            .update(&ConnectionProfile::default().with(
+                "802-11-wireless-security",
+                "psk",
+                rotated.old_psk.clone(),
+            ))
+            .and_then(|()| {
+                if rotated.active {
+                    reactivate(connection).map(|_| ())
+                } else {
+                    Ok(())
+                }
+            });
+
+        if let Err(e) = restored {
+            warn!(
+                "Unable to roll back connection {:?}: {}",
+                connection.settings.id, e
+            );
+
+            if result.is_ok() {
+                result = Err(e);
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(feature = "wifi")]
+fn reactivate(connection: &Connection) -> Result<ConnectionState> {
+    connection
+        .dbus_manager
+        .activate_connection(&connection.path)?;
+
+    wait(
+        connection,
+        &ConnectionState::Activated,
+        connection.dbus_manager.wait_deadline(),
+    )
+}
+
+/// Outcome of [`NetworkManager::activate_group`](struct.NetworkManager.html#method.activate_group).
+#[derive(Debug)]
+pub struct GroupActivation {
+    /// State of the master after its slaves were activated.
+    pub master: ConnectionState,
+    pub activated: Vec<Connection>,
+    /// Slaves that failed to activate, with the reason.
+    pub failed: Vec<(Connection, Error)>,
+}
+
+impl GroupActivation {
+    pub fn is_complete(&self) -> bool {
+        self.master == ConnectionState::Activated && self.failed.is_empty()
+    }
+}
+
+pub fn activate_group(master: &Connection, slaves: &[Connection]) -> Result<GroupActivation> {
+    enter_span!("activate_group", master = ?master.settings.id);
+
+    let timeout = master.dbus_manager.wait_deadline();
+
+    match master.get_state()? {
+        ConnectionState::Activated => {}
+        ConnectionState::Activating => wait_for_master(master, timeout)?,
+        _ => {
+            master.dbus_manager.activate_connection(&master.path)?;
+
+            wait_for_master(master, timeout)?;
+        }
+    }
+
+    let mut activated = Vec::new();
+    let mut failed = Vec::new();
+
+    for slave in slaves {
+        match slave.activate() {
+            Ok(ConnectionState::Activated) => activated.push(slave.clone()),
+            Ok(state) => {
+                let error = Error::from(ErrorKind::NetworkManager(format!(
+                    "Connection {:?} not activated: {:?}",
+                    slave.settings.id, state
+                )));
+                failed.push((slave.clone(), error));
+            }
+            Err(e) => failed.push((slave.clone(), e)),
+        }
+    }
+
+    for (slave, e) in &failed {
+        warn!(
+            "Slave {:?} of {:?} failed: {}",
+            slave.settings.id, master.settings.id, e
+        );
+    }
+
+    let master_state = wait(master, &ConnectionState::Activated, timeout)?;
+
+    Ok(GroupActivation {
+        master: master_state,
+        activated,
+        failed,
+    })
+}
+
+/// Waits for the device of a master to come up. Masters usually do not
+/// complete their activation without slaves, e.g. a bridge with DHCP waits
+/// for a port with carrier, so waiting for `Activated` would only time out.
+fn wait_for_master(master: &Connection, timeout: Duration) -> Result<()> {
+    let up = wait_for_signal(
+        master.dbus_manager.bus(),
+        &connection_state_rules(),
+        timeout,
+        master.dbus_manager.wait_interval(),
+        || match master.get_state()? {
+            ConnectionState::Activated => Ok(Some(())),
+            ConnectionState::Activating if !master.get_devices()?.is_empty() => Ok(Some(())),
+            ConnectionState::Activating | ConnectionState::Unknown => Ok(None),
+            state => bail!(ErrorKind::NetworkManager(format!(
+                "Master {:?} not activated: {:?}",
+                master.settings.id, state
+            ))),
+        },
+    )?;
+
+    match up {
+        Some(()) => Ok(()),
+        None => bail!(ErrorKind::NetworkManager(format!(
+            "Master {:?} not activated within {:?}",
+            master.settings.id, timeout
+        ))),
+    }
+}
+
+/// Outcome of [`NetworkManager::ensure`](struct.NetworkManager.html#method.ensure).
+#[derive(Debug)]
+pub struct EnsureReport {
+    pub connection: Connection,
+    /// Whether the profile did not exist and was added.
+    pub created: bool,
+    /// The `setting.key` entries that were added or modified.
+    pub changes: Vec<String>,
+    /// Whether the connection had to be activated.
+    pub activated: bool,
+    pub state: ConnectionState,
+}
+
+pub fn ensure_connection(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    profile: &ConnectionProfile,
+) -> Result<EnsureReport> {
+    enter_span!("ensure_connection", id = ?profile.id());
+
+    let path = match find_connection_path(dbus_manager, profile)? {
+        Some(path) => path,
+        None => {
+            let (connection, state) = create_connection(dbus_manager, profile, None)?;
+
+            return Ok(EnsureReport {
+                connection,
+                created: true,
+                changes: ConnectionProfile::default().changes(profile),
+                activated: true,
+                state,
+            });
+        }
+    };
+
+    let mut current = get_profile_with_secrets(dbus_manager, &path, profile)?;
+
+    let changes = current.changes(profile);
+
+    if !changes.is_empty() {
+        info!("Updating connection {}: {:?}", path, changes);
+
+        current.merge(profile);
+        dbus_manager.update_connection(&path, &current)?;
+    }
+
+    let connection = Connection::init(dbus_manager, &path)?;
+
+    let activated = connection.get_state()? != ConnectionState::Activated;
+
+    let state = if activated {
+        connection.activate()?
+    } else {
+        ConnectionState::Activated
+    };
+
+    Ok(EnsureReport {
+        connection,
+        created: false,
+        changes,
+        activated,
+        state,
+    })
+}
+
+/// Reads the settings of the profile at `path`, together with the secrets of
+/// the settings `desired` touches. GetSettings leaves out secrets, which would
+/// otherwise always compare as changed.
+pub fn get_profile_with_secrets(
+    dbus_manager: &DBusNetworkManager,
+    path: &str,
+    desired: &ConnectionProfile,
+) -> Result<ConnectionProfile> {
+    let mut current = dbus_manager.get_connection_profile(path)?;
+
+    for (name, setting) in desired.settings() {
+        let missing = match current.setting(name) {
+            Some(current) => setting.keys().any(|key| !current.contains_key(key)),
+            None => false,
+        };
+
+        if missing {
+            match dbus_manager.get_connection_secrets(path, name) {
+                Ok(secrets) => {
+                    for (key, value) in secrets {
+                        current.set(name, &key, value);
+                    }
+                }
+                Err(e) => debug!("Unable to read {} secrets of {}: {}", name, path, e),
+            }
+        }
+    }
+
+    Ok(current)
+}
+
+/// Finds an existing profile by UUID, or by ID when the profile has no UUID.
+fn find_connection_path(
+    dbus_manager: &DBusNetworkManager,
+    profile: &ConnectionProfile,
+) -> Result<Option<String>> {
+    for path in dbus_manager.list_connections()? {
+        let settings = dbus_manager.get_connection_settings(&path)?;
+
+        let matches = match profile.uuid() {
+            Some(uuid) => settings.uuid == uuid,
+            None => Some(&settings.id as &str) == profile.id(),
+        };
+
+        if matches {
+            return Ok(Some(path));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(feature = "hotspot")]
+pub fn create_hotspot(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    device_path: &str,
+    interface: &str,
+    config: &HotspotConfig,
+) -> Result<(Connection, ConnectionState)> {
+    enter_span!("create_hotspot", device = %device_path);
+
+    config.verify()?;
+
+    verify_ap_mode(dbus_manager, device_path, interface)?;
+
+    if let Some(country) = config.get_country() {
+        if get_wifi_backend(dbus_manager.bus())?.supports_country() {
+            dbus_manager.supplicant().set_country(interface, country)?;
+        } else {
+            warn!("Ignoring hotspot country {}: not supported by iwd", country);
+        }
+    }
+
+    if config.get_security() == HotspotSecurity::Sae {
+        let supported = get_wifi_backend(dbus_manager.bus())?.supports_sae_hotspot()
+            && dbus_manager
+                .supplicant()
+                .supports_key_mgmt(interface, "sae")?;
+
+        if !supported {
+            bail!(ErrorKind::NetworkManager(format!(
+                "{} does not support WPA3-SAE hotspots",
+                interface
+            )));
+        }
+    }
+
+    if dbus_manager.is_read_only() {
+        return activate_hotspot(dbus_manager, device_path, interface, config);
+    }
+
+    write_dnsmasq_config(interface, &config.dnsmasq_options())?;
+
+    let result = activate_hotspot(dbus_manager, device_path, interface, config);
+
+    // The options would apply to every later shared connection otherwise
+    match result {
+        Ok((_, ConnectionState::Activated)) => {}
+        _ => {
+            if let Err(e) = remove_dnsmasq_config(interface) {
+                warn!("Unable to remove hotspot options of {}: {}", interface, e);
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(feature = "hotspot")]
+fn activate_hotspot(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    device_path: &str,
+    interface: &str,
+    config: &HotspotConfig,
+) -> Result<(Connection, ConnectionState)> {
+    let (path, _) = dbus_manager.create_hotspot(device_path, interface, config)?;
 
     let connection = Connection::init(dbus_manager, &path)?;
 
     let state = wait(
         &connection,
         &ConnectionState::Activated,
-        dbus_manager.method_timeout(),
+        dbus_manager.wait_deadline(),
     )?;
 
     Ok((connection, state))
 }
 
+/// Fail early on adapters that can not do AP mode, activating a hotspot on
+/// them only fails once the activation times out.
+#[cfg(feature = "hotspot")]
+pub fn verify_ap_mode(
+    dbus_manager: &DBusNetworkManager,
+    device_path: &str,
+    interface: &str,
+) -> Result<()> {
+    let mut supported = dbus_manager.is_device_ap_capable(device_path)?;
+
+    // wpa_supplicant knows the interface modes of the driver, as listed by
+    // `iw phy`, and may rule out AP mode NetworkManager reports
+    if supported && get_wifi_backend(dbus_manager.bus())? == WifiBackend::WpaSupplicant {
+        match dbus_manager.supplicant().supports_mode(interface, "ap") {
+            Ok(ap) => supported = ap,
+            Err(e) => debug!(
+                "Unable to get modes of {} from wpa_supplicant: {}",
+                interface, e
+            ),
+        }
+    }
+
+    if !supported {
+        bail!(ErrorKind::ApModeUnsupported(interface.to_string()));
+    }
+
+    Ok(())
+}
+
 fn get_connection_active_path(
     dbus_manager: &DBusNetworkManager,
     connection_path: &str,
@@ -286,42 +1342,191 @@ fn get_connection_active_path(
 fn wait(
     connection: &Connection,
     target_state: &ConnectionState,
-    timeout: u64,
+    timeout: Duration,
+) -> Result<ConnectionState> {
+    let state = wait_for_state(connection, target_state, timeout, false, || {
+        connection.get_state()
+    })?;
+
+    activation_finished(connection, &state);
+
+    Ok(state)
+}
+
+/// Waits for the active connection at `active_path` to be activated. Unlike
+/// `wait` this follows the activation NetworkManager started for us rather
+/// than whichever active connection of the profile it finds first, e.g. the
+/// one still deactivating on another device, and gives up once it failed.
+fn wait_active(
+    connection: &Connection,
+    active_path: &str,
+    timeout: Duration,
 ) -> Result<ConnectionState> {
-    if timeout == 0 {
-        return connection.get_state();
+    let state = wait_for_state(
+        connection,
+        &ConnectionState::Activated,
+        timeout,
+        true,
+        || get_active_state(&connection.dbus_manager, active_path),
+    )?;
+
+    activation_finished(connection, &state);
+
+    Ok(state)
+}
+
+fn activation_finished(connection: &Connection, state: &ConnectionState) {
+    if *state == ConnectionState::Activated {
+        let dbus_manager = &connection.dbus_manager;
+
+        dbus_manager.activation_succeeded(&connection.settings.uuid);
+        if !connection.settings.ssid.as_bytes().is_empty() {
+            dbus_manager.activation_succeeded(&connection.settings.ssid.to_string_lossy());
+        }
     }
+}
 
-    debug!("Waiting for connection state: {:?}", target_state);
+/// The state of the active connection at `active_path`, `Deactivated` once
+/// NetworkManager removed it.
+fn get_active_state(
+    dbus_manager: &DBusNetworkManager,
+    active_path: &str,
+) -> Result<ConnectionState> {
+    let state = dbus_manager.get_connection_state(active_path)?;
 
-    let mut total_time = 0;
+    if state == ConnectionState::Unknown
+        && !dbus_manager
+            .get_active_connections()?
+            .iter()
+            .any(|path| path == active_path)
+    {
+        return Ok(ConnectionState::Deactivated);
+    }
 
-    loop {
-        ::std::thread::sleep(::std::time::Duration::from_secs(1));
+    Ok(state)
+}
+
+/// Waits until `get_state` returns `target_state`, or `Deactivated` if
+/// `until_deactivated` is set, and returns the last state read.
+fn wait_for_state<F>(
+    connection: &Connection,
+    target_state: &ConnectionState,
+    timeout: Duration,
+    until_deactivated: bool,
+    get_state: F,
+) -> Result<ConnectionState>
+where
+    F: Fn() -> Result<ConnectionState>,
+{
+    if timeout == Duration::from_secs(0) {
+        return get_state();
+    }
 
-        let state = connection.get_state()?;
+    debug!("Waiting for connection state: {:?}", target_state);
 
-        total_time += 1;
+    let start = Instant::now();
 
-        if state == *target_state {
-            debug!(
-                "Connection target state reached: {:?} / {}s elapsed",
-                state, total_time
-            );
+    let reached = wait_for_signal(
+        connection.dbus_manager.bus(),
+        &connection_state_rules(),
+        timeout,
+        connection.dbus_manager.wait_interval(),
+        || {
+            let state = get_state()?;
+
+            if state == *target_state || until_deactivated && state == ConnectionState::Deactivated
+            {
+                return Ok(Some(state));
+            }
 
-            return Ok(state);
-        } else if total_time >= timeout {
             debug!(
-                "Timeout reached in waiting for connection state ({:?}): {:?} / {}s elapsed",
-                target_state, state, total_time
+                "Still waiting for connection state ({:?}): {:?} / {:?} elapsed",
+                target_state,
+                state,
+                start.elapsed()
             );
 
-            return Ok(state);
-        }
+            Ok(None)
+        },
+    )?;
 
+    if let Some(state) = reached {
         debug!(
-            "Still waiting for connection state ({:?}): {:?} / {}s elapsed",
-            target_state, state, total_time
+            "Connection target state reached: {:?} / {:?} elapsed",
+            state,
+            start.elapsed()
         );
+
+        return Ok(state);
+    }
+
+    let state = get_state()?;
+
+    debug!(
+        "Timeout reached in waiting for connection state ({:?}): {:?} / {:?} elapsed",
+        target_state,
+        state,
+        start.elapsed()
+    );
+
+    Ok(state)
+}
+
+/// Signals on which the state of a connection may have changed: an active
+/// connection changing state or devices, or being added or removed.
+fn connection_state_rules() -> Vec<String> {
+    vec![
+        signal_rule(NM_ACTIVE_INTERFACE, "StateChanged", None),
+        properties_changed_rule(NM_ACTIVE_INTERFACE),
+        properties_changed_rule(NM_SERVICE_INTERFACE),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::error::Error as StdError;
+
+    use network_manager_types::Value;
+
+    fn failure(message: &str) -> Error {
+        ErrorKind::NetworkManager(message.into()).into()
+    }
+
+    #[test]
+    fn test_chain_cleanup() {
+        assert_eq!(chain_cleanup(Ok(1), Ok(())).unwrap(), 1);
+
+        let error = chain_cleanup::<()>(Ok(()), Err(failure("destroy failed"))).unwrap_err();
+        assert_eq!(error.to_string(), "NetworkManager failure: destroy failed");
+
+        let error = chain_cleanup::<()>(Err(failure("not activated")), Ok(())).unwrap_err();
+        assert_eq!(error.to_string(), "NetworkManager failure: not activated");
+        assert!(error.source().is_none());
+
+        let error = chain_cleanup::<()>(
+            Err(failure("not activated")),
+            Err(failure("rollback failed")),
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "NetworkManager failure: not activated");
+        assert_eq!(
+            error.source().map(|cause| cause.to_string()),
+            Some("NetworkManager failure: rollback failed".to_string())
+        );
+    }
+
+    #[test]
+    fn test_staged_profile_autoconnect() {
+        let profile = ConnectionProfile::ethernet("Site A", "eth0");
+        assert_eq!(
+            staged_profile(&profile).get("connection", "autoconnect"),
+            Some(&Value::Bool(false))
+        );
+
+        let mut profile = profile;
+        profile.set_autoconnect(true);
+        assert_eq!(staged_profile(&profile), profile);
     }
 }
@@ -0,0 +1,301 @@
+//! Checks of what NetworkManager's connectivity state does not tell apart,
+//! such as a connection that is up while its DNS servers are not answering.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, UdpSocket};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use errors::*;
+
+use connection::Connection;
+use ip_config::IpConfig;
+
+#[cfg(feature = "probe")]
+mod probe;
+
+#[cfg(feature = "probe")]
+pub use self::probe::{probe_via, ProbeTarget};
+
+const DNS_PORT: u16 = 53;
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(3);
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+/// Results of resolving a hostname through each DNS server of a connection.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ResolveCheck {
+    pub hostname: String,
+    pub results: Vec<NameserverResult>,
+}
+
+impl ResolveCheck {
+    /// Whether at least one DNS server resolved the hostname.
+    pub fn is_resolving(&self) -> bool {
+        self.results
+            .iter()
+            .any(|result| matches!(result.outcome, ResolveOutcome::Resolved(_)))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct NameserverResult {
+    pub nameserver: IpAddr,
+    pub outcome: ResolveOutcome,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum ResolveOutcome {
+    /// IPv4 and IPv6 addresses of the hostname, empty if it has none.
+    Resolved(Vec<IpAddr>),
+    /// The server answered with an error code, e.g. 3 for a non-existent
+    /// name or 2 for a server failure.
+    ServerError(u8),
+    /// The server did not answer in time.
+    TimedOut,
+    /// The query could not be sent or the answer was malformed.
+    Failed(String),
+}
+
+/// Resolve `hostname` through each DNS server of the active `connection`,
+/// bypassing the system resolver. Queries are sent from the address of the
+/// connection's device, so that they leave through it on multi-homed systems.
+///
+/// `results` is empty if the connection is not active or has no DNS servers.
+pub fn resolve_check(connection: &Connection, hostname: &str) -> Result<ResolveCheck> {
+    let queries = [
+        encode_query(query_id(), hostname, TYPE_A)?,
+        encode_query(query_id().wrapping_add(1), hostname, TYPE_AAAA)?,
+    ];
+
+    let mut results = Vec::new();
+
+    for device in connection.get_devices()? {
+        let ifindex = device.ifindex()?;
+
+        let configs = [device.get_ip4_config()?, device.get_ip6_config()?];
+
+        for config in configs.iter().flatten() {
+            for &nameserver in &config.nameservers {
+                let outcome = match query_nameserver(nameserver, config, ifindex, &queries) {
+                    Ok(outcome) => outcome,
+                    Err(e) => ResolveOutcome::Failed(e.to_string()),
+                };
+
+                debug!("Resolving {} via {}: {:?}", hostname, nameserver, outcome);
+
+                results.push(NameserverResult {
+                    nameserver,
+                    outcome,
+                });
+            }
+        }
+    }
+
+    Ok(ResolveCheck {
+        hostname: hostname.to_string(),
+        results,
+    })
+}
+
+fn query_nameserver(
+    nameserver: IpAddr,
+    config: &IpConfig,
+    ifindex: u32,
+    queries: &[Vec<u8>],
+) -> ::std::io::Result<ResolveOutcome> {
+    let source = config
+        .addresses
+        .iter()
+        .map(|address| address.address)
+        .find(|address| address.is_ipv4() == nameserver.is_ipv4())
+        .unwrap_or_else(|| unspecified(nameserver));
+
+    let socket = UdpSocket::bind(SocketAddr::new(source, 0))?;
+    socket.set_read_timeout(Some(RESOLVE_TIMEOUT))?;
+
+    let target = match nameserver {
+        // Link-local servers are only reachable through the given interface
+        IpAddr::V6(address) if address.segments()[0] & 0xffc0 == 0xfe80 => {
+            SocketAddr::V6(SocketAddrV6::new(address, DNS_PORT, 0, ifindex))
+        }
+        _ => SocketAddr::new(nameserver, DNS_PORT),
+    };
+
+    let mut addresses = Vec::new();
+    let mut buffer = [0; 512];
+
+    for query in queries {
+        socket.send_to(query, target)?;
+
+        let length = match socket.recv(&mut buffer) {
+            Ok(length) => length,
+            Err(ref e)
+                if e.kind() == ::std::io::ErrorKind::WouldBlock
+                    || e.kind() == ::std::io::ErrorKind::TimedOut =>
+            {
+                return Ok(ResolveOutcome::TimedOut)
+            }
+            Err(e) => return Err(e),
+        };
+
+        match decode_response(query, &buffer[..length]) {
+            Some(Ok(mut answers)) => addresses.append(&mut answers),
+            Some(Err(rcode)) => return Ok(ResolveOutcome::ServerError(rcode)),
+            None => return Ok(ResolveOutcome::Failed("Malformed DNS response".into())),
+        }
+    }
+
+    Ok(ResolveOutcome::Resolved(addresses))
+}
+
+fn unspecified(address: IpAddr) -> IpAddr {
+    match address {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    }
+}
+
+fn query_id() -> u16 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos() as u16)
+        .unwrap_or_default()
+}
+
+fn encode_query(id: u16, hostname: &str, record_type: u16) -> Result<Vec<u8>> {
+    let hostname = hostname.trim_end_matches('.');
+
+    let valid = !hostname.is_empty()
+        && hostname.len() <= 253
+        && hostname
+            .split('.')
+            .all(|label| !label.is_empty() && label.len() <= 63);
+
+    if !valid {
+        bail!(ErrorKind::NetworkManager(format!(
+            "Invalid hostname: {:?}",
+            hostname
+        )));
+    }
+
+    let mut query = Vec::with_capacity(hostname.len() + 18);
+
+    // Header with recursion desired and one question
+    query.extend_from_slice(&id.to_be_bytes());
+    query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+
+    for label in hostname.split('.') {
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+
+    query.extend_from_slice(&record_type.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    Ok(query)
+}
+
+/// Extracts the addresses from the answer to `query`, or the response code if
+/// the server reported an error. `None` if the response is malformed.
+fn decode_response(
+    query: &[u8],
+    response: &[u8],
+) -> Option<::std::result::Result<Vec<IpAddr>, u8>> {
+    let u16_at = |offset: usize| -> Option<u16> {
+        Some(u16::from_be_bytes([
+            *response.get(offset)?,
+            *response.get(offset + 1)?,
+        ]))
+    };
+
+    // Same ID and a response
+    if response.get(..2)? != &query[..2] || response.get(2)? & 0x80 == 0 {
+        return None;
+    }
+
+    let rcode = response.get(3)? & 0x0f;
+    if rcode != 0 {
+        return Some(Err(rcode));
+    }
+
+    let questions = u16_at(4)?;
+    let answers = u16_at(6)?;
+
+    let mut offset = 12;
+
+    for _ in 0..questions {
+        offset = skip_name(response, offset)? + 4;
+    }
+
+    let mut addresses = Vec::new();
+
+    for _ in 0..answers {
+        offset = skip_name(response, offset)?;
+
+        let record_type = u16_at(offset)?;
+        let length = usize::from(u16_at(offset + 8)?);
+        let data = response.get(offset + 10..offset + 10 + length)?;
+        offset += 10 + length;
+
+        match (record_type, data.len()) {
+            (TYPE_A, 4) => addresses.push(IpAddr::from([data[0], data[1], data[2], data[3]])),
+            (TYPE_AAAA, 16) => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(data);
+                addresses.push(IpAddr::from(octets));
+            }
+            // CNAME records preceding the addresses
+            _ => {}
+        }
+    }
+
+    Some(Ok(addresses))
+}
+
+/// Returns the offset after the name starting at `offset`.
+fn skip_name(message: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let length = *message.get(offset)?;
+
+        match length {
+            0 => return Some(offset + 1),
+            // Compression pointer, which always ends the name
+            _ if length & 0xc0 == 0xc0 => return Some(offset + 2),
+            _ => offset += 1 + usize::from(length),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_response() {
+        let query = encode_query(0x1234, "example.com.", TYPE_A).unwrap();
+        assert_eq!(query.len(), 12 + 13 + 4);
+
+        let mut response = query.clone();
+        response[2] |= 0x80;
+        response[7] = 2;
+        // CNAME pointing back at the question, then an A record
+        response.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, 2, 0xc0, 12]);
+        response.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 93, 184, 216, 34]);
+
+        assert_eq!(
+            decode_response(&query, &response),
+            Some(Ok(vec![IpAddr::from([93, 184, 216, 34])]))
+        );
+
+        assert_eq!(decode_response(&query, &response[..20]), None);
+
+        response[3] = 3;
+        assert_eq!(decode_response(&query, &response), Some(Err(3)));
+        assert!(encode_query(1, "bad..name", TYPE_A).is_err());
+    }
+}
@@ -0,0 +1,380 @@
+//! Reachability probes bound to a single device, for telling apart which
+//! uplink of a multi-homed system is broken.
+
+use std::io;
+use std::mem;
+use std::net::{IpAddr, SocketAddr, TcpStream, UdpSocket};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::time::{Duration, Instant};
+
+use libc;
+
+use errors::*;
+
+use connectivity::query_id;
+use device::Device;
+
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+
+/// What `probe_via` tries to reach.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum ProbeTarget {
+    /// Open a TCP connection. A refused connection still counts as reachable.
+    Tcp(SocketAddr),
+    /// Send an ICMP echo request. Relies on unprivileged ping sockets, which
+    /// the `net.ipv4.ping_group_range` sysctl has to allow for the caller.
+    Icmp(IpAddr),
+}
+
+impl ProbeTarget {
+    fn ip(&self) -> IpAddr {
+        match *self {
+            ProbeTarget::Tcp(address) => address.ip(),
+            ProbeTarget::Icmp(address) => address,
+        }
+    }
+}
+
+/// Check whether `target` is reachable through `device`, returning the round
+/// trip time. The probe socket is bound to the device with `SO_BINDTODEVICE`
+/// and to its address, so that each uplink of a multi-homed system can be
+/// verified independently. Without `CAP_NET_RAW`, which kernels before 5.7
+/// require for binding to a device, only the address is bound and policy
+/// routing has to send the probe out by source address.
+pub fn probe_via(device: &Device, target: &ProbeTarget, timeout: Duration) -> Result<Duration> {
+    if timeout == Duration::from_secs(0) {
+        bail!(ErrorKind::NetworkManager(
+            "Probe timeout should not be zero".into()
+        ))
+    }
+
+    let config = if target.ip().is_ipv4() {
+        device.get_ip4_config()?
+    } else {
+        device.get_ip6_config()?
+    };
+
+    // Link-local addresses would need a scope to be bound to
+    let source = config.and_then(|config| {
+        config
+            .addresses
+            .into_iter()
+            .map(|address| address.address)
+            .find(|address| match *address {
+                IpAddr::V4(_) => true,
+                IpAddr::V6(address) => address.segments()[0] & 0xffc0 != 0xfe80,
+            })
+    });
+
+    let source = match source {
+        Some(source) => source,
+        None => bail!(ErrorKind::NetworkManager(format!(
+            "{} has no address to probe {} from",
+            device.interface(),
+            target.ip()
+        ))),
+    };
+
+    let interface = match device.get_ip_interface()? {
+        ref interface if interface.is_empty() => device.interface().to_string(),
+        interface => interface,
+    };
+
+    let start = Instant::now();
+
+    let reached = match *target {
+        ProbeTarget::Tcp(address) => probe_tcp(source, &interface, address, timeout),
+        ProbeTarget::Icmp(address) => probe_icmp(source, &interface, address, timeout),
+    };
+
+    match reached {
+        Ok(true) => Ok(start.elapsed()),
+        Ok(false) => bail!(ErrorKind::NetworkManager(format!(
+            "{:?} not reachable via {} within {:?}",
+            target,
+            device.interface(),
+            timeout
+        ))),
+        Err(e) => Err(e).chain_err(|| {
+            ErrorKind::NetworkManager(format!(
+                "Unable to probe {:?} via {}",
+                target,
+                device.interface()
+            ))
+        }),
+    }
+}
+
+fn probe_tcp(
+    source: IpAddr,
+    interface: &str,
+    target: SocketAddr,
+    timeout: Duration,
+) -> io::Result<bool> {
+    let socket = bound_socket(source, interface, libc::SOCK_STREAM, 0)?;
+    let socket = unsafe { TcpStream::from_raw_fd(socket) };
+
+    // Linux applies the send timeout to `connect`
+    let timeval = send_timeout(timeout);
+    check(unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_SNDTIMEO,
+            &timeval as *const _ as *const libc::c_void,
+            mem::size_of::<libc::timeval>() as libc::socklen_t,
+        )
+    })?;
+
+    let (address, length) = sockaddr(target);
+    let connected = check(unsafe {
+        libc::connect(
+            socket.as_raw_fd(),
+            &address as *const _ as *const libc::sockaddr,
+            length,
+        )
+    });
+
+    match connected {
+        Ok(()) => Ok(true),
+        Err(ref e) if e.raw_os_error() == Some(libc::ECONNREFUSED) => Ok(true),
+        Err(ref e)
+            if e.raw_os_error() == Some(libc::EINPROGRESS)
+                || e.raw_os_error() == Some(libc::ETIMEDOUT) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn probe_icmp(
+    source: IpAddr,
+    interface: &str,
+    target: IpAddr,
+    timeout: Duration,
+) -> io::Result<bool> {
+    let (protocol, request, reply) = match target {
+        IpAddr::V4(_) => (libc::IPPROTO_ICMP, ICMP_ECHO_REQUEST, ICMP_ECHO_REPLY),
+        IpAddr::V6(_) => (libc::IPPROTO_ICMPV6, ICMPV6_ECHO_REQUEST, ICMPV6_ECHO_REPLY),
+    };
+
+    // Ping sockets are datagram sockets, the kernel fills in the identifier
+    let socket = bound_socket(source, interface, libc::SOCK_DGRAM, protocol)?;
+    let socket = unsafe { UdpSocket::from_raw_fd(socket) };
+
+    let sequence = query_id();
+    let mut packet = [request, 0, 0, 0, 0, 0, 0, 0];
+    packet[6..].copy_from_slice(&sequence.to_be_bytes());
+
+    // ICMPv6 checksums cover the addresses and are left to the kernel
+    if target.is_ipv4() {
+        let checksum = icmp_checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+
+    socket.send_to(&packet, SocketAddr::new(target, 0))?;
+
+    let deadline = Instant::now() + timeout;
+    let mut buffer = [0; 64];
+
+    loop {
+        let remaining = match deadline.checked_duration_since(Instant::now()) {
+            Some(remaining) if remaining > Duration::from_millis(0) => remaining,
+            _ => return Ok(false),
+        };
+        socket.set_read_timeout(Some(remaining))?;
+
+        let length = match socket.recv(&mut buffer) {
+            Ok(length) => length,
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                return Ok(false)
+            }
+            Err(e) => return Err(e),
+        };
+
+        if length >= 8 && buffer[0] == reply && buffer[6..8] == sequence.to_be_bytes() {
+            return Ok(true);
+        }
+    }
+}
+
+/// `timeout` as `SO_SNDTIMEO`, at least a microsecond, as zero would block
+/// for the full SYN retries of the kernel.
+fn send_timeout(timeout: Duration) -> libc::timeval {
+    let timeout = timeout.max(Duration::from_micros(1));
+
+    libc::timeval {
+        tv_sec: timeout.as_secs() as libc::time_t,
+        tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+    }
+}
+
+fn icmp_checksum(packet: &[u8]) -> u16 {
+    let mut sum: u32 = packet
+        .chunks(2)
+        .map(|chunk| u32::from(chunk[0]) << 8 | u32::from(*chunk.get(1).unwrap_or(&0)))
+        .sum();
+
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+fn bound_socket(
+    source: IpAddr,
+    interface: &str,
+    kind: libc::c_int,
+    protocol: libc::c_int,
+) -> io::Result<RawFd> {
+    let domain = match source {
+        IpAddr::V4(_) => libc::AF_INET,
+        IpAddr::V6(_) => libc::AF_INET6,
+    };
+
+    let fd = unsafe { libc::socket(domain, kind | libc::SOCK_CLOEXEC, protocol) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let bound = bind_to_device(fd, interface).and_then(|()| {
+        let (address, length) = sockaddr(SocketAddr::new(source, 0));
+        check(unsafe { libc::bind(fd, &address as *const _ as *const libc::sockaddr, length) })
+    });
+
+    if let Err(e) = bound {
+        unsafe { libc::close(fd) };
+        return Err(e);
+    }
+
+    Ok(fd)
+}
+
+fn bind_to_device(fd: RawFd, interface: &str) -> io::Result<()> {
+    let bound = check(unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            interface.as_ptr() as *const libc::c_void,
+            interface.len() as libc::socklen_t,
+        )
+    });
+
+    match bound {
+        Err(ref e) if e.raw_os_error() == Some(libc::EPERM) => {
+            debug!(
+                "Not permitted to bind to {}, probing by source address only",
+                interface
+            );
+            Ok(())
+        }
+        bound => bound,
+    }
+}
+
+fn sockaddr(address: SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+
+    let length = match address {
+        SocketAddr::V4(address) => {
+            let sockaddr = libc::sockaddr_in {
+                sin_family: libc::AF_INET as libc::sa_family_t,
+                sin_port: address.port().to_be(),
+                sin_addr: libc::in_addr {
+                    s_addr: u32::from_ne_bytes(address.ip().octets()),
+                },
+                sin_zero: [0; 8],
+            };
+            unsafe { (&mut storage as *mut _ as *mut libc::sockaddr_in).write(sockaddr) };
+            mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(address) => {
+            let sockaddr = libc::sockaddr_in6 {
+                sin6_family: libc::AF_INET6 as libc::sa_family_t,
+                sin6_port: address.port().to_be(),
+                sin6_flowinfo: address.flowinfo(),
+                sin6_addr: libc::in6_addr {
+                    s6_addr: address.ip().octets(),
+                },
+                sin6_scope_id: address.scope_id(),
+            };
+            unsafe { (&mut storage as *mut _ as *mut libc::sockaddr_in6).write(sockaddr) };
+            mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+
+    (storage, length as libc::socklen_t)
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_icmp_checksum() {
+        // RFC 1071, section 3
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(icmp_checksum(&data), !0xddf2);
+
+        // An odd trailing byte is padded with zero
+        assert_eq!(icmp_checksum(&[0x01]), !0x0100);
+        assert_eq!(
+            icmp_checksum(&data[..7]),
+            icmp_checksum(&[&data[..7], &[0][..]].concat())
+        );
+
+        // A packet carrying its checksum sums up to zero
+        let mut packet = [ICMP_ECHO_REQUEST, 0, 0, 0, 0x12, 0x34, 0x00, 0x01];
+        let checksum = icmp_checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+        assert_eq!(icmp_checksum(&packet), 0);
+    }
+
+    #[test]
+    fn test_sockaddr_byte_order() {
+        let (storage, length) = sockaddr("192.0.2.1:80".parse().unwrap());
+        let address = unsafe { *(&storage as *const _ as *const libc::sockaddr_in) };
+
+        assert_eq!(length as usize, mem::size_of::<libc::sockaddr_in>());
+        assert_eq!(i32::from(address.sin_family), libc::AF_INET);
+        assert_eq!(address.sin_port.to_ne_bytes(), [0, 80]);
+        assert_eq!(address.sin_addr.s_addr.to_ne_bytes(), [192, 0, 2, 1]);
+
+        let (storage, length) = sockaddr("[2001:db8::1]:443".parse().unwrap());
+        let address = unsafe { *(&storage as *const _ as *const libc::sockaddr_in6) };
+
+        assert_eq!(length as usize, mem::size_of::<libc::sockaddr_in6>());
+        assert_eq!(i32::from(address.sin6_family), libc::AF_INET6);
+        assert_eq!(address.sin6_port.to_ne_bytes(), [0x01, 0xbb]);
+        assert_eq!(
+            address.sin6_addr.s6_addr,
+            [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]
+        );
+    }
+
+    #[test]
+    fn test_send_timeout() {
+        let timeval = send_timeout(Duration::from_nanos(10));
+        assert_eq!((timeval.tv_sec, timeval.tv_usec), (0, 1));
+
+        let timeval = send_timeout(Duration::from_millis(2500));
+        assert_eq!((timeval.tv_sec, timeval.tv_usec), (2, 500_000));
+    }
+}
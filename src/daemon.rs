@@ -0,0 +1,82 @@
+use dbus::ConnectionItem;
+
+use dbus_api::{name_owner, open_connection, Bus};
+use errors::*;
+
+const NM_BUS_NAME: &str = "org.freedesktop.NetworkManager";
+
+/// Changes in the availability of the NetworkManager daemon on the bus.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum DaemonEvent {
+    /// The daemon appeared on the bus for the first time since watching
+    /// started.
+    Started,
+    /// The daemon left the bus.
+    Stopped,
+    /// The daemon came back after having been seen before. Devices,
+    /// connections and access points obtained earlier refer to stale object
+    /// paths and need to be fetched again.
+    Restarted,
+}
+
+/// Blocks watching `NameOwnerChanged` for NetworkManager on `bus`, invoking
+/// `callback` with each event until it returns `false`.
+pub fn watch_daemon<F>(bus: Bus, mut callback: F) -> Result<()>
+where
+    F: FnMut(DaemonEvent) -> bool,
+{
+    let connection = open_connection(bus)?;
+
+    connection.add_match(&format!(
+        "type='signal',sender='org.freedesktop.DBus',interface='org.freedesktop.DBus',\
+         member='NameOwnerChanged',arg0='{}'",
+        NM_BUS_NAME
+    ))?;
+
+    let mut seen = name_owner(&connection, NM_BUS_NAME)?.is_some();
+
+    for item in connection.iter(1000) {
+        let message = match item {
+            ConnectionItem::Signal(message) => message,
+            _ => continue,
+        };
+
+        let (name, old_owner, new_owner) = message.get3::<&str, &str, &str>();
+
+        if name != Some(NM_BUS_NAME) {
+            continue;
+        }
+
+        let old_owner = old_owner.unwrap_or_default();
+        let new_owner = new_owner.unwrap_or_default();
+
+        debug!(
+            "NetworkManager bus owner changed: {:?} -> {:?}",
+            old_owner, new_owner
+        );
+
+        let mut events = Vec::new();
+
+        if !old_owner.is_empty() && new_owner.is_empty() {
+            events.push(DaemonEvent::Stopped);
+        }
+
+        if !new_owner.is_empty() {
+            events.push(if seen || !old_owner.is_empty() {
+                DaemonEvent::Restarted
+            } else {
+                DaemonEvent::Started
+            });
+            seen = true;
+        }
+
+        for event in events {
+            if !callback(event) {
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
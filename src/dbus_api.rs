@@ -1,19 +1,183 @@
-use dbus::arg::{ArgType, Array, Get, Iter, RefArg, Variant};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+use dbus::arg::{cast, ArgType, Array, Get, Iter, IterAppend, RefArg, Variant};
 use dbus::stdintf::OrgFreedesktopDBusProperties;
 use dbus::Connection as DBusConnection;
 use dbus::{BusType, ConnPath, Message, Path};
-use std::any::Any;
+use network_manager_types::Value;
 
 use errors::*;
+use redact::Keyed;
+
+const DBUS_SERVICE: &str = "org.freedesktop.DBus";
+const DBUS_PATH: &str = "/org/freedesktop/DBus";
+const DBUS_PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+const DBUS_INTROSPECTABLE_INTERFACE: &str = "org.freedesktop.DBus.Introspectable";
+const DBUS_NAME_HAS_NO_OWNER: &str = "org.freedesktop.DBus.Error.NameHasNoOwner";
+/// Errors of reading a property an object does not have. GDBus based services
+/// such as NetworkManager report `InvalidArgs`.
+const DBUS_UNKNOWN_PROPERTY_ERRORS: &[&str] = &[
+    "org.freedesktop.DBus.Error.UnknownProperty",
+    "org.freedesktop.DBus.Error.InvalidArgs",
+];
+
+/// Methods that leave the state of the daemon alone, by interface, the only
+/// ones sent in read-only mode.
+const READ_ONLY_METHODS: &[(&str, &str)] = &[
+    ("fi.w1.wpa_supplicant1", "GetInterface"),
+    ("org.freedesktop.DBus.Introspectable", "Introspect"),
+    ("org.freedesktop.DBus.Properties", "Get"),
+    ("org.freedesktop.DBus.Properties", "GetAll"),
+    ("org.freedesktop.NetworkManager", "CheckConnectivity"),
+    ("org.freedesktop.NetworkManager", "GetDeviceByIpIface"),
+    ("org.freedesktop.NetworkManager", "state"),
+    (
+        "org.freedesktop.NetworkManager.Device",
+        "GetAppliedConnection",
+    ),
+    (
+        "org.freedesktop.NetworkManager.Settings",
+        "GetConnectionByUuid",
+    ),
+    ("org.freedesktop.NetworkManager.Settings", "ListConnections"),
+    (
+        "org.freedesktop.NetworkManager.Settings.Connection",
+        "GetSecrets",
+    ),
+    (
+        "org.freedesktop.NetworkManager.Settings.Connection",
+        "GetSettings",
+    ),
+];
 
 const DEFAULT_TIMEOUT: u64 = 15;
 const RETRIES_ALLOWED: usize = 10;
 
+/// A D-Bus method call or property read, as reported to a [`CallObserver`].
+#[derive(Clone, Copy, Debug)]
+pub struct CallInfo<'a> {
+    pub path: &'a str,
+    pub interface: &'a str,
+    /// The method name, `Get` for property reads.
+    pub method: &'a str,
+    /// The property name for property reads.
+    pub property: Option<&'a str>,
+}
+
+/// Instrumentation hooks invoked around every D-Bus call made to
+/// NetworkManager, e.g. to open `tracing` spans or record latencies.
+pub trait CallObserver {
+    fn before_call(&self, _call: &CallInfo) {}
+
+    /// `duration` includes any retries of the call.
+    fn after_call(
+        &self,
+        call: &CallInfo,
+        duration: Duration,
+        result: ::std::result::Result<(), &Error>,
+    );
+}
+
+/// The message bus NetworkManager is reached on.
+///
+/// There is no variant for an explicit address such as `tcp:host=10.0.0.2`,
+/// as the `Connection` of dbus 0.5 can only be opened on a well-known bus.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bus {
+    /// The system bus. libdbus connects to `DBUS_SYSTEM_BUS_ADDRESS` if set,
+    /// e.g. for a host bus socket mounted into a container at a non-standard
+    /// path.
+    System,
+    /// The session bus at `DBUS_SESSION_BUS_ADDRESS`, e.g. for a
+    /// NetworkManager instance run for testing.
+    Session,
+}
+
+impl Bus {
+    fn bus_type(self) -> BusType {
+        match self {
+            Bus::System => BusType::System,
+            Bus::Session => BusType::Session,
+        }
+    }
+}
+
+fn is_read_only_method(interface: &str, method: &str) -> bool {
+    READ_ONLY_METHODS.contains(&(interface, method))
+}
+
+/// Stands in for the reply of a call skipped in read-only mode: the unsent
+/// call, which has no arguments.
+fn dry_run_reply(destination: &str, path: &str, interface: &str, method: &str) -> Result<Message> {
+    Message::new_method_call(destination, path, interface, method)
+        .map_err(|e| ErrorKind::DBusAPI(e).into())
+}
+
+fn dry_run_error(planned: &[String]) -> Error {
+    ErrorKind::DryRun(planned.join(", then ")).into()
+}
+
+/// Opens a new private connection to `bus`, e.g. to receive signals on.
+pub fn open_connection(bus: Bus) -> Result<DBusConnection> {
+    Ok(DBusConnection::get_private(bus.bus_type())?)
+}
+
+thread_local! {
+    static SHARED_CONNECTIONS: RefCell<Vec<(Bus, Weak<DBusConnection>)>> =
+        const { RefCell::new(Vec::new()) };
+}
+
+/// Returns the connection to `bus` shared by API objects of the current
+/// thread, opening a new one if the last user dropped it.
+///
+/// `dbus::Connection` wraps a libdbus connection that is neither `Send` nor
+/// `Sync`, so it cannot be put behind an `Arc` and shared across threads;
+/// each thread gets its own instead, kept alive by its users through `Rc`.
+///
+/// Waits for signals, which only need a wakeup, run on the shared connection
+/// too. Watchers that read the signals themselves open a private connection
+/// with `open_connection`, as a connection has a single incoming queue and a
+/// watcher on a shared one would consume the signals matched for others.
+pub fn shared_connection(bus: Bus) -> Result<Rc<DBusConnection>> {
+    SHARED_CONNECTIONS.with(|shared| {
+        let mut shared = shared.borrow_mut();
+
+        shared.retain(|(_, connection)| connection.upgrade().is_some());
+
+        let existing = shared
+            .iter()
+            .find(|(shared_bus, _)| *shared_bus == bus)
+            .and_then(|(_, connection)| connection.upgrade());
+
+        if let Some(connection) = existing {
+            return Ok(connection);
+        }
+
+        let connection = Rc::new(open_connection(bus)?);
+        shared.push((bus, Rc::downgrade(&connection)));
+
+        Ok(connection)
+    })
+}
+
+/// The calls a read-only API object skipped, in order. Shared by the clients
+/// created from one manager, so that they add up to a single plan.
+pub type DryRunPlan = Rc<RefCell<Vec<String>>>;
+
 pub struct DBusApi {
-    connection: DBusConnection,
+    connection: Rc<DBusConnection>,
     method_timeout: u64,
     base: &'static str,
     method_retry_error_names: &'static [&'static str],
+    observer: RefCell<Option<Rc<dyn CallObserver>>>,
+    owner: RefCell<Option<String>>,
+    dry_run: Option<DryRunPlan>,
+    bus: Bus,
 }
 
 impl DBusApi {
@@ -22,18 +186,172 @@ impl DBusApi {
         method_retry_error_names: &'static [&'static str],
         method_timeout: Option<u64>,
     ) -> Self {
-        let connection = DBusConnection::get_private(BusType::System).unwrap();
+        DBusApi::with_bus(Bus::System, base, method_retry_error_names, method_timeout).unwrap()
+    }
+
+    /// Creates an API object on a new private connection to `bus`.
+    pub fn with_bus(
+        bus: Bus,
+        base: &'static str,
+        method_retry_error_names: &'static [&'static str],
+        method_timeout: Option<u64>,
+    ) -> Result<Self> {
+        let connection = open_connection(bus)?;
 
+        Ok(DBusApi::with_connection(
+            Rc::new(connection),
+            bus,
+            base,
+            method_retry_error_names,
+            method_timeout,
+        ))
+    }
+
+    /// Creates an API object on an existing, possibly shared, connection to
+    /// `bus`.
+    pub fn with_connection(
+        connection: Rc<DBusConnection>,
+        bus: Bus,
+        base: &'static str,
+        method_retry_error_names: &'static [&'static str],
+        method_timeout: Option<u64>,
+    ) -> Self {
         let method_timeout = method_timeout.unwrap_or(DEFAULT_TIMEOUT);
 
+        let owner = name_owner(&connection, base).unwrap_or(None);
+
         DBusApi {
             connection,
             method_timeout,
             base,
             method_retry_error_names,
+            observer: RefCell::new(None),
+            owner: RefCell::new(owner),
+            dry_run: None,
+            bus,
+        }
+    }
+
+    /// In read-only mode calls that would change state are not sent, they
+    /// are added to the plan returned by `take_planned` instead. Their reply
+    /// is empty, so that extracting a result from it fails with `DryRun`.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.dry_run = if read_only {
+            Some(DryRunPlan::default())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Read-only mode adding to the plan of another API object, `None` to
+    /// send every call.
+    #[cfg(any(feature = "wifi", feature = "wwan"))]
+    pub fn dry_run(mut self, plan: Option<DryRunPlan>) -> Self {
+        self.dry_run = plan;
+        self
+    }
+
+    #[cfg(any(feature = "wifi", feature = "wwan"))]
+    pub fn dry_run_plan(&self) -> Option<DryRunPlan> {
+        self.dry_run.clone()
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.dry_run.is_some()
+    }
+
+    /// The calls skipped in read-only mode since the last call.
+    pub fn take_planned(&self) -> Vec<String> {
+        match self.dry_run {
+            Some(ref plan) => plan.borrow_mut().drain(..).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn bus(&self) -> Bus {
+        self.bus
+    }
+
+    /// Forgets about a daemon restart, after which failing calls are no longer
+    /// reported as `DaemonRestarted`.
+    pub fn resync(&self) -> Result<()> {
+        *self.owner.borrow_mut() = name_owner(&self.connection, self.base)?;
+        Ok(())
+    }
+
+    /// Whether the service got a new bus owner, i.e. was restarted, since we
+    /// first talked to it.
+    fn restarted(&self) -> bool {
+        let current = match name_owner(&self.connection, self.base) {
+            Ok(Some(current)) => current,
+            _ => return false,
+        };
+
+        let mut owner = self.owner.borrow_mut();
+
+        match *owner {
+            Some(ref owner) => *owner != current,
+            None => {
+                *owner = Some(current);
+                false
+            }
         }
     }
 
+    fn check_restarted(&self, e: Error) -> Error {
+        if self.restarted() {
+            warn!("{} was restarted, object paths are stale", self.base);
+            e.chain_err(|| ErrorKind::DaemonRestarted)
+        } else {
+            e
+        }
+    }
+
+    /// The bus owner of the service when we first talked to it, against which
+    /// restarts are detected.
+    pub fn owner(&self) -> Option<String> {
+        self.owner.borrow().clone()
+    }
+
+    pub fn set_owner(&self, owner: Option<String>) {
+        *self.owner.borrow_mut() = owner;
+    }
+
+    pub fn set_observer(&self, observer: Option<Rc<dyn CallObserver>>) {
+        *self.observer.borrow_mut() = observer;
+    }
+
+    pub fn observer(&self) -> Option<Rc<dyn CallObserver>> {
+        self.observer.borrow().clone()
+    }
+
+    fn observe<T, F>(&self, call: &CallInfo, f: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T>,
+    {
+        let observer = self.observer.borrow().clone();
+
+        let observer = match observer {
+            Some(observer) => observer,
+            None => return f(),
+        };
+
+        observer.before_call(call);
+
+        let start = Instant::now();
+        let result = f();
+
+        observer.after_call(call, start.elapsed(), result.as_ref().map(|_| ()));
+
+        result
+    }
+
+    #[cfg(any(feature = "wifi", feature = "wwan"))]
+    pub fn connection(&self) -> &Rc<DBusConnection> {
+        &self.connection
+    }
+
     pub fn method_timeout(&self) -> u64 {
         self.method_timeout
     }
@@ -49,12 +367,30 @@ impl DBusApi {
         method: &str,
         args: &[&dyn RefArg],
     ) -> Result<Message> {
-        self.call_with_args_retry(path, interface, method, args)
-            .map_err(|e| {
-                let message = format!("{}::{} method call failed on {}", interface, method, path);
-                error!("{}", message);
-                e.chain_err(|| ErrorKind::DBusAPI(message))
-            })
+        if let Some(ref plan) = self.dry_run {
+            if !is_read_only_method(interface, method) {
+                plan.borrow_mut()
+                    .push(format!("call {}.{} on {}", interface, method, path));
+
+                return dry_run_reply(self.base, path, interface, method);
+            }
+        }
+
+        let call = CallInfo {
+            path,
+            interface,
+            method,
+            property: None,
+        };
+
+        self.observe(&call, || {
+            self.call_with_args_retry(path, interface, method, args)
+        })
+        .map_err(|e| {
+            let message = format!("{}::{} method call failed on {}", interface, method, path);
+            error!("{}", message);
+            self.check_restarted(e.chain_err(|| ErrorKind::DBusAPI(message)))
+        })
     }
 
     fn call_with_args_retry(
@@ -134,6 +470,81 @@ impl DBusApi {
 pub fn property<T>(&self, path: &str, interface: &str, name: &str) -> Result<T>
     where
         DBusApi: VariantTo<T>,
+    {
+        self.property_ref(path, interface, name, DBusApi::variant_to)
+    }
+
+    pub fn set_property<T>(&self, path: &str, interface: &str, name: &str, value: T) -> Result<()>
+    where
+        DBusApi: VariantFrom<T>,
+    {
+        if let Some(ref plan) = self.dry_run {
+            plan.borrow_mut()
+                .push(format!("set {}.{} on {}", interface, name, path));
+
+            return Ok(());
+        }
+
+        let value = DBusApi::variant_from(value);
+
+        self.call_with_args(
+            path,
+            DBUS_PROPERTIES_INTERFACE,
+            "Set",
+            &[
+                &interface.to_string() as &dyn RefArg,
+                &name.to_string() as &dyn RefArg,
+                &value as &dyn RefArg,
+            ],
+        )
+        .chain_err(|| {
+            ErrorKind::DBusAPI(format!(
+                "Set {}::{} property failed on {}",
+                interface, name, path
+            ))
+        })?;
+
+        Ok(())
+    }
+
+    /// Reads a property and hands it to `f` without converting it to an owned
+    /// type first, for callers that only need to inspect or borrow from it.
+    pub fn property_ref<R, F>(&self, path: &str, interface: &str, name: &str, f: F) -> Result<R>
+    where
+        F: FnOnce(&Variant<Box<dyn RefArg>>) -> Option<R>,
+    {
+        let call = CallInfo {
+            path,
+            interface,
+            method: "Get",
+            property: Some(name),
+        };
+
+        self.observe(&call, || self.get_property(path, interface, name, f))
+            .map_err(|e| self.check_restarted(e))
+    }
+
+    /// Fetches all properties of an interface in a single call. Properties of
+    /// types not representable by `Value` are left out.
+    pub fn all_properties(&self, path: &str, interface: &str) -> Result<HashMap<String, Value>> {
+        let response = self.call_with_args(
+            path,
+            DBUS_PROPERTIES_INTERFACE,
+            "GetAll",
+            &[&interface.to_string() as &dyn RefArg],
+        )?;
+
+        let properties: HashMap<String, Variant<Box<dyn RefArg>>> = self.extract(&response)?;
+
+        Ok(properties
+            .into_iter()
+            .filter_map(|(name, value)| ref_arg_to_value(&*value.0).map(|value| (name, value)))
+            .collect())
+    }
+
+    fn get_property<R, F>(&self, path: &str, interface: &str, name: &str, f: F) -> Result<R>
+    where
+        F: FnOnce(&Variant<Box<dyn RefArg>>) -> Option<R>,
     {
         let property_error = |details: &str, err: bool| {
             let message = format!(
@@ -156,19 +567,23 @@ pub fn property<T>(&self, path: &str, interface: &str, name: &str) -> Result<T>
                     "Got D-Bus variant for {}::{}: {:?} (type: {})",
                     interface,
                     name,
-                    variant,
-                    std::any::type_name::<T>()
+                    Keyed(name, &variant),
+                    std::any::type_name::<R>()
                 );
                 
-                match DBusApi::variant_to(&variant) {
+                match f(&variant) {
                     Some(data) => Ok(data),
                     None => {
                         error!(
                             "Failed to convert variant {:?} to {}",
-                            variant,
-                            std::any::type_name::<T>()
+                            Keyed(name, &variant),
+                            std::any::type_name::<R>()
                         );
-                        bail!(property_error("wrong property type", false))
+                        bail!(ErrorKind::PropertyType(
+                            format!("{}::{} on {}", interface, name, path.path),
+                            variant.0.signature().to_string(),
+                            std::any::type_name::<R>().to_string()
+                        ))
                     }
                 }
             },
@@ -182,13 +597,18 @@ pub fn property<T>(&self, path: &str, interface: &str, name: &str) -> Result<T>
         }
     }
 
+    /// Fetches the introspection XML of the object at `path`.
+    pub fn introspect(&self, path: &str) -> Result<String> {
+        let response = self.call(path, DBUS_INTROSPECTABLE_INTERFACE, "Introspect")?;
+
+        self.extract::<&str>(&response).map(|xml| xml.to_string())
+    }
+
     pub fn extract<'a, T>(&self, response: &'a Message) -> Result<T>
     where
         T: Get<'a>,
     {
-        response
-            .get1()
-            .ok_or_else(|| ErrorKind::DBusAPI("Wrong response type".into()).into())
+        response.get1().ok_or_else(|| self.wrong_response(response))
     }
 
     pub fn extract_two<'a, T1, T2>(&self, response: &'a Message) -> Result<(T1, T2)>
@@ -204,10 +624,19 @@ pub fn property<T>(&self, path: &str, interface: &str, name: &str) -> Result<T>
             }
         }
 
-        bail!(ErrorKind::DBusAPI("Wrong response type".into()))
+        Err(self.wrong_response(response))
     }
 
-    fn with_path<'a, P: Into<Path<'a>>>(&'a self, path: P) -> ConnPath<&'a DBusConnection> {
+    /// A reply without the expected arguments is the placeholder for a call
+    /// skipped in read-only mode, which the caller cannot go on without.
+    fn wrong_response(&self, response: &Message) -> Error {
+        match self.dry_run {
+            Some(ref plan) if response.get_items().is_empty() => dry_run_error(&plan.borrow()),
+            _ => ErrorKind::DBusAPI("Wrong response type".into()).into(),
+        }
+    }
+
+    fn with_path<'a, P: Into<Path<'a>>>(&'a self, path: P) -> ConnPath<'a, &'a DBusConnection> {
         self.connection
             .with_path(self.base, path, self.method_timeout as i32 * 1000)
     }
@@ -223,138 +652,390 @@ impl VariantTo<String> for DBusApi {
     }
 }
 
-impl VariantTo<i64> for DBusApi {
-    fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<i64> {
-        value.0.as_i64()
+/// Reads an integer of any D-Bus integer type, looking through variants.
+/// Booleans and doubles are not accepted.
+fn variant_integer(value: &Variant<Box<dyn RefArg>>) -> Option<i128> {
+    match cast::<u64>(&*value.0) {
+        Some(&number) => Some(number.into()),
+        None => ref_arg_integer(&*value.0),
+    }
+}
+
+// Dispatches on `arg_type` rather than `signature`, which allocates
+fn ref_arg_integer(arg: &dyn RefArg) -> Option<i128> {
+    match arg.arg_type() {
+        ArgType::Variant => ref_arg_integer(arg.as_iter()?.next()?),
+        ArgType::Byte
+        | ArgType::Int16
+        | ArgType::UInt16
+        | ArgType::Int32
+        | ArgType::UInt32
+        | ArgType::Int64 => arg.as_i64().map(i128::from),
+        ArgType::UInt64 => arg.as_u64().map(i128::from),
+        _ => None,
     }
 }
 
-// Remove the unused import
-// use std::any::Any;
+/// The `u64` and `f64` accessors of `RefArg`, which dbus 0.5 lacks. Values
+/// nested in variants and containers are only reachable as non-`'static`
+/// trait objects, which can't be downcast, so they are appended to a scratch
+/// message and read back with their D-Bus type.
+trait RefArgNumber {
+    fn as_u64(&self) -> Option<u64>;
+    fn as_f64(&self) -> Option<f64>;
+}
 
-impl VariantTo<u32> for DBusApi {
-    fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<u32> {
-        debug!("VariantTo<u32> called with value: {:?}", value);
-        
-        // Handle iterator case first
-        if let Some(iter) = value.0.as_iter() {
-            debug!("Value is an iterator");
-            let vec: Vec<_> = iter.collect();
-            debug!("Iterator contents: {:?}", vec);
-            
-            if let Some(first) = vec.first() {
-                debug!("First element: {:?}", first);
-                debug!("First element type: {:?}", first.arg_type());
-                
-                match first.arg_type() {
-                    ArgType::UInt32 => {
-                        // Special handling for UInt32 iterator
-                        if let Some(num) = first.as_i64() {
-                            debug!("Converting UInt32 iterator value {} to u32", num);
-                            if num >= 0 && num <= u32::MAX as i64 {
-                                return Some(num as u32);
-                            }
-                        }
-                        // Try to get the base type directly
-                        if let Some(mut array) = value.0.as_iter() {
-                            if let Some(first) = array.next() {
-                                debug!("Trying direct array element conversion");
-                                if let Some(num) = first.as_i64() {
-                                    if num >= 0 && num <= u32::MAX as i64 {
-                                        return Some(num as u32);
-                                    }
-                                }
-                            }
-                        }
-                    },
-                    _ => {
-                        // Try regular i64 conversion
-                        if let Some(num) = first.as_i64() {
-                            debug!("Converting i64 {} to u32", num);
-                            if num >= 0 && num <= u32::MAX as i64 {
-                                return Some(num as u32);
-                            }
+impl<'a> RefArgNumber for dyn RefArg + 'a {
+    fn as_u64(&self) -> Option<u64> {
+        match self.arg_type() {
+            ArgType::UInt64 => scratch_message(self)?.get1(),
+            _ => None,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self.arg_type() {
+            ArgType::Double => scratch_message(self)?.get1(),
+            _ => None,
+        }
+    }
+}
+
+fn scratch_message(arg: &dyn RefArg) -> Option<Message> {
+    let mut message = Message::new_signal(DBUS_PATH, DBUS_SERVICE, "Value").ok()?;
+    arg.append(&mut IterAppend::new(&mut message));
+    Some(message)
+}
+
+macro_rules! variant_to_integer {
+    ($($t:ty),* $(,)*) => {
+        $(
+            impl VariantTo<$t> for DBusApi {
+                fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<$t> {
+                    let number = variant_integer(value)?;
+
+                    match <$t>::try_from(number) {
+                        Ok(number) => Some(number),
+                        Err(_) => {
+                            debug!("{} out of range for {}", number, stringify!($t));
+                            None
                         }
                     }
                 }
             }
-            debug!("Failed to convert iterator element to number");
-            return None;
-        }
-        
-        // Handle direct value case
-        debug!("Value is not an iterator, trying direct conversion");
-        debug!("Direct value arg type: {:?}", value.0.arg_type());
-        
-        match value.0.arg_type() {
-            ArgType::UInt32 | ArgType::Byte => {
-                if let Some(num) = value.0.as_i64() {
-                    debug!("Direct numeric conversion: {}", num);
-                    if num >= 0 && num <= u32::MAX as i64 {
-                        return Some(num as u32);
-                    }
-                }
-            },
-            _ => {
-                // Try regular i64 conversion as fallback
-                if let Some(num) = value.0.as_i64() {
-                    debug!("Direct i64 conversion: {}", num);
-                    if num >= 0 && num <= u32::MAX as i64 {
-                        return Some(num as u32);
-                    }
-                }
+        )*
+    };
+}
+
+variant_to_integer!(u8, i32, u32, i64, u64);
+
+impl VariantTo<f64> for DBusApi {
+    fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<f64> {
+        fn double(arg: &dyn RefArg) -> Option<f64> {
+            match arg.arg_type() {
+                ArgType::Variant => double(arg.as_iter()?.next()?),
+                ArgType::Double => arg.as_f64(),
+                _ => None,
             }
         }
-        
-        debug!("All conversion attempts failed");
-        None
+
+        match cast::<f64>(&*value.0) {
+            Some(&number) => Some(number),
+            None => double(&*value.0),
+        }
     }
 }
 
 impl VariantTo<bool> for DBusApi {
     fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<bool> {
-        value.0.as_i64().map(|v| v == 0)
+        fn boolean(arg: &dyn RefArg) -> Option<bool> {
+            match arg.arg_type() {
+                ArgType::Variant => boolean(arg.as_iter()?.next()?),
+                ArgType::Boolean => arg.as_i64().map(|v| v != 0),
+                _ => None,
+            }
+        }
+
+        boolean(&*value.0)
     }
 }
 
 impl VariantTo<Vec<String>> for DBusApi {
     fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<Vec<String>> {
-        let mut result = Vec::new();
-
-        if let Some(list) = value.0.as_iter() {
-            for element in list {
-                if let Some(string) = element.as_str() {
-                    result.push(string.to_string());
-                } else {
-                    return None;
-                }
-            }
-
-            Some(result)
-        } else {
-            None
+        if let Some(strings) = cast::<Vec<String>>(&*value.0) {
+            return Some(strings.clone());
         }
+
+        value
+            .0
+            .as_iter()?
+            .map(|element| element.as_str().map(|string| string.to_string()))
+            .collect()
     }
 }
 
 impl VariantTo<Vec<u8>> for DBusApi {
     fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<Vec<u8>> {
-        let mut result = Vec::new();
-
-        if let Some(list) = value.0.as_iter() {
-            for element in list {
-                if let Some(value) = element.as_i64() {
-                    result.push(value as u8);
-                } else {
-                    return None;
+        ref_arg_bytes(&*value.0).map(Cow::into_owned)
+    }
+}
+
+impl VariantTo<Path<'static>> for DBusApi {
+    fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<Path<'static>> {
+        if value.0.arg_type() != ArgType::ObjectPath {
+            return None;
+        }
+
+        Path::new(value.0.as_str()?.to_string()).ok()
+    }
+}
+
+impl VariantTo<Vec<Path<'static>>> for DBusApi {
+    fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<Vec<Path<'static>>> {
+        if let Some(paths) = cast::<Vec<Path<'static>>>(&*value.0) {
+            return Some(paths.clone());
+        }
+
+        if &*value.0.signature() != "ao" {
+            return None;
+        }
+
+        value
+            .0
+            .as_iter()?
+            .map(|path| Path::new(path.as_str()?.to_string()).ok())
+            .collect()
+    }
+}
+
+impl VariantTo<Value> for DBusApi {
+    fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<Value> {
+        ref_arg_to_value(&*value.0)
+    }
+}
+
+impl VariantTo<HashMap<String, Value>> for DBusApi {
+    fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<HashMap<String, Value>> {
+        ref_arg_to_dict(&*value.0).map(|dict| dict.into_iter().collect())
+    }
+}
+
+impl VariantTo<Vec<HashMap<String, Value>>> for DBusApi {
+    fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<Vec<HashMap<String, Value>>> {
+        value
+            .0
+            .as_iter()?
+            .map(|entry| ref_arg_to_dict(entry).map(|dict| dict.into_iter().collect()))
+            .collect()
+    }
+}
+
+/// Borrows the content of a byte array, copying it only when it is not
+/// stored contiguously, e.g. when nested in a variant.
+pub fn ref_arg_bytes<'a>(arg: &'a (dyn RefArg + 'static)) -> Option<Cow<'a, [u8]>> {
+    if let Some(bytes) = cast::<Vec<u8>>(arg) {
+        return Some(Cow::Borrowed(bytes));
+    }
+
+    fn collect(arg: &dyn RefArg) -> Option<Vec<u8>> {
+        match arg.arg_type() {
+            ArgType::Variant => collect(arg.as_iter()?.next()?),
+            ArgType::Array => arg
+                .as_iter()?
+                .map(|element| match element.arg_type() {
+                    ArgType::Byte => element.as_i64().map(|byte| byte as u8),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+
+    collect(arg).map(Cow::Owned)
+}
+
+/// Returns the unique bus name currently owning `name`, if any.
+pub fn name_owner(connection: &DBusConnection, name: &str) -> Result<Option<String>> {
+    let message = Message::new_method_call(DBUS_SERVICE, DBUS_PATH, DBUS_SERVICE, "GetNameOwner")
+        .map_err(ErrorKind::DBusAPI)?
+        .append1(name);
+
+    match connection.send_with_reply_and_block(message, DEFAULT_TIMEOUT as i32 * 1000) {
+        Ok(response) => Ok(response.get1::<String>()),
+        Err(ref e) if e.name() == Some(DBUS_NAME_HAS_NO_OWNER) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Whether `error` is a property read failing as the object has no such
+/// property, e.g. one added in a later version of the service.
+pub fn is_unknown_property(error: &Error) -> bool {
+    let mut cause = ::std::error::Error::source(error);
+
+    while let Some(e) = cause {
+        if let Some(e) = e.downcast_ref::<::dbus::Error>() {
+            return match e.name() {
+                Some(name) => DBUS_UNKNOWN_PROPERTY_ERRORS.contains(&name),
+                None => false,
+            };
+        }
+
+        cause = e.source();
+    }
+
+    false
+}
+
+/// Looks up `key` in a dictionary argument such as `a{sv}`.
+pub fn dict_get<'a>(dict: &'a dyn RefArg, key: &str) -> Option<&'a dyn RefArg> {
+    let mut iter = dict.as_iter()?;
+
+    loop {
+        let k = iter.next()?;
+        let v = iter.next()?;
+
+        if k.as_str() == Some(key) {
+            return Some(v);
+        }
+    }
+}
+
+pub trait VariantFrom<T> {
+    fn variant_from(value: T) -> Variant<Box<dyn RefArg>>;
+}
+
+macro_rules! variant_from {
+    ($($t:ty),* $(,)*) => {
+        $(
+            impl VariantFrom<$t> for DBusApi {
+                fn variant_from(value: $t) -> Variant<Box<dyn RefArg>> {
+                    Variant(Box::new(value))
                 }
             }
+        )*
+    };
+}
 
-            Some(result)
-        } else {
-            None
+variant_from!(
+    bool,
+    u8,
+    i32,
+    u32,
+    i64,
+    u64,
+    f64,
+    String,
+    Vec<String>,
+    Vec<u8>,
+);
+
+impl<'a> VariantFrom<&'a str> for DBusApi {
+    fn variant_from(value: &'a str) -> Variant<Box<dyn RefArg>> {
+        Variant(Box::new(value.to_string()))
+    }
+}
+
+impl VariantFrom<Value> for DBusApi {
+    fn variant_from(value: Value) -> Variant<Box<dyn RefArg>> {
+        Variant(value_to_ref_arg(&value))
+    }
+}
+
+/// Converts an owned value into an argument ready to be appended to a
+/// message.
+pub fn value_to_ref_arg(value: &Value) -> Box<dyn RefArg> {
+    match *value {
+        Value::Bool(v) => Box::new(v),
+        Value::Byte(v) => Box::new(v),
+        Value::Int32(v) => Box::new(v),
+        Value::UInt32(v) => Box::new(v),
+        Value::Int64(v) => Box::new(v),
+        Value::UInt64(v) => Box::new(v),
+        Value::Double(v) => Box::new(v),
+        Value::String(ref v) => Box::new(v.clone()),
+        Value::Bytes(ref v) => Box::new(v.clone()),
+        Value::Strings(ref v) => Box::new(v.clone()),
+        Value::UInt32s(ref v) => Box::new(v.clone()),
+        Value::ByteArrays(ref v) => Box::new(v.clone()),
+        Value::Dict(ref v) => Box::new(dict_to_variant_map(v)),
+        Value::Dicts(ref v) => Box::new(v.iter().map(dict_to_variant_map).collect::<Vec<_>>()),
+    }
+}
+
+pub fn dict_to_variant_map(
+    dict: &BTreeMap<String, Value>,
+) -> HashMap<String, Variant<Box<dyn RefArg>>> {
+    dict.iter()
+        .map(|(k, v)| (k.clone(), Variant(value_to_ref_arg(v))))
+        .collect()
+}
+
+/// Converts a received argument into an owned value. Returns `None` for
+/// argument types not representable by `Value`.
+pub fn ref_arg_to_value(arg: &dyn RefArg) -> Option<Value> {
+    let signature = arg.signature();
+
+    let value = match &*signature {
+        "v" => return ref_arg_to_value(arg.as_iter()?.next()?),
+        "b" => Value::Bool(arg.as_i64()? != 0),
+        "y" => Value::Byte(arg.as_i64()? as u8),
+        "n" | "i" => Value::Int32(arg.as_i64()? as i32),
+        "q" | "u" => Value::UInt32(arg.as_i64()? as u32),
+        "x" => Value::Int64(arg.as_i64()?),
+        "t" => Value::UInt64(arg.as_u64()?),
+        "d" => Value::Double(arg.as_f64()?),
+        "s" | "o" | "g" => Value::String(arg.as_str()?.to_string()),
+        "ay" => Value::Bytes(
+            arg.as_iter()?
+                .map(|v| v.as_i64().map(|v| v as u8))
+                .collect::<Option<_>>()?,
+        ),
+        "as" | "ao" => Value::Strings(
+            arg.as_iter()?
+                .map(|v| v.as_str().map(|v| v.to_string()))
+                .collect::<Option<_>>()?,
+        ),
+        "au" => Value::UInt32s(
+            arg.as_iter()?
+                .map(|v| v.as_i64().map(|v| v as u32))
+                .collect::<Option<_>>()?,
+        ),
+        "aay" => Value::ByteArrays(
+            arg.as_iter()?
+                .map(|v| match ref_arg_to_value(v) {
+                    Some(Value::Bytes(bytes)) => Some(bytes),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?,
+        ),
+        "a{sv}" => Value::Dict(ref_arg_to_dict(arg)?),
+        "aa{sv}" => Value::Dicts(arg.as_iter()?.map(ref_arg_to_dict).collect::<Option<_>>()?),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+pub fn ref_arg_to_dict(arg: &dyn RefArg) -> Option<BTreeMap<String, Value>> {
+    let mut iter = arg.as_iter()?;
+    let mut dict = BTreeMap::new();
+
+    while let Some(key) = iter.next() {
+        let value = iter.next()?;
+
+        match ref_arg_to_value(value) {
+            Some(value) => {
+                dict.insert(key.as_str()?.to_string(), value);
+            }
+            None => debug!(
+                "Skipping unsupported value of {:?}: {}",
+                key.as_str(),
+                &*value.signature()
+            ),
         }
     }
+
+    Some(dict)
 }
 
 pub fn extract<'a, T>(var: &mut Variant<Iter<'a>>) -> Result<T>
@@ -378,3 +1059,162 @@ pub fn variant_iter_to_vec_u8(var: &mut Variant<Iter>) -> Result<Vec<u8>> {
         )))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_unknown_property() {
+        let read = |name: &str| -> Error {
+            let e = ::dbus::Error::new_custom(name, "No such property 'Flags'");
+
+            Err::<(), _>(e)
+                .chain_err(|| ErrorKind::DBusAPI("Get property failed".into()))
+                .unwrap_err()
+        };
+
+        assert!(is_unknown_property(&read(
+            "org.freedesktop.DBus.Error.InvalidArgs"
+        )));
+        assert!(is_unknown_property(&read(
+            "org.freedesktop.DBus.Error.UnknownProperty"
+        )));
+        assert!(!is_unknown_property(&read(
+            "org.freedesktop.DBus.Error.UnknownObject"
+        )));
+        assert!(!is_unknown_property(&"Timeout".into()));
+    }
+
+    #[test]
+    fn test_read_only_methods() {
+        assert!(is_read_only_method(DBUS_PROPERTIES_INTERFACE, "Get"));
+        assert!(is_read_only_method(
+            "org.freedesktop.NetworkManager.Settings.Connection",
+            "GetSettings"
+        ));
+
+        assert!(!is_read_only_method(DBUS_PROPERTIES_INTERFACE, "Set"));
+        // only the Properties interface has a harmless `Get`
+        assert!(!is_read_only_method(
+            "org.freedesktop.NetworkManager",
+            "Get"
+        ));
+        assert!(!is_read_only_method(
+            "org.freedesktop.NetworkManager.Settings.Connection",
+            "Delete"
+        ));
+    }
+
+    #[test]
+    fn test_dry_run_reply() {
+        let reply = dry_run_reply(
+            "org.freedesktop.NetworkManager",
+            "/org/freedesktop/NetworkManager",
+            "org.freedesktop.NetworkManager",
+            "ActivateConnection",
+        )
+        .unwrap();
+        assert!(reply.get_items().is_empty());
+
+        let error = dry_run_error(&[
+            "call org.freedesktop.NetworkManager.Settings.Connection.Update on /1".to_string(),
+            "call org.freedesktop.NetworkManager.ActivateConnection on /".to_string(),
+        ]);
+        assert_eq!(
+            error.to_string(),
+            "Dry run, would call org.freedesktop.NetworkManager.Settings.Connection.Update on /1, \
+             then call org.freedesktop.NetworkManager.ActivateConnection on /"
+        );
+    }
+
+    fn variant<T: RefArg + 'static>(value: T) -> Variant<Box<dyn RefArg>> {
+        Variant(Box::new(value))
+    }
+
+    fn to<T>(value: Variant<Box<dyn RefArg>>) -> Option<T>
+    where
+        DBusApi: VariantTo<T>,
+    {
+        DBusApi::variant_to(&value)
+    }
+
+    #[test]
+    fn test_variant_to_bool() {
+        assert_eq!(to(variant(true)), Some(true));
+        assert_eq!(to(variant(false)), Some(false));
+        assert_eq!(to::<bool>(variant(1u32)), None);
+        assert_eq!(to::<bool>(variant("true".to_string())), None);
+    }
+
+    #[test]
+    fn test_boolean_property() {
+        // Boolean properties such as `FirmwareMissing` arrive as a variant
+        // holding a `b`, which only decodes as `bool`
+        let property = variant(variant(true));
+        assert_eq!(to(property), Some(true));
+        assert_eq!(to::<i64>(variant(variant(true))), None);
+    }
+
+    #[test]
+    fn test_variant_to_integers() {
+        assert_eq!(to(variant(7u8)), Some(7u32));
+        assert_eq!(to(variant(-7i32)), Some(-7i64));
+        assert_eq!(to(variant(u64::MAX)), Some(u64::MAX));
+        assert_eq!(to(variant(u32::MAX)), Some(u32::MAX));
+        assert_eq!(to(variant(variant(42u32))), Some(42i32));
+
+        assert_eq!(to::<u32>(variant(-1i32)), None);
+        assert_eq!(to::<u32>(variant(1u64 << 32)), None);
+        assert_eq!(to::<i32>(variant(u32::MAX)), None);
+        assert_eq!(to::<i64>(variant(u64::MAX)), None);
+        assert_eq!(to::<u8>(variant(256u32)), None);
+        assert_eq!(to::<u32>(variant(true)), None);
+        assert_eq!(to::<u32>(variant(1.0f64)), None);
+        assert_eq!(to::<u32>(variant("1".to_string())), None);
+    }
+
+    #[test]
+    fn test_variant_to_object_paths() {
+        let path = Path::new("/org/freedesktop/NetworkManager/Devices/1").unwrap();
+
+        assert_eq!(to(variant(path.clone())), Some(path.clone()));
+        assert_eq!(to(variant(vec![path.clone()])), Some(vec![path]));
+        assert_eq!(to::<Path>(variant("/".to_string())), None);
+        assert_eq!(to::<Vec<Path>>(variant(vec!["/".to_string()])), None);
+    }
+
+    #[test]
+    fn test_ref_arg_bytes() {
+        let bytes = variant(vec![1u8, 2, 3]);
+        assert!(match ref_arg_bytes(&*bytes.0) {
+            Some(Cow::Borrowed(bytes)) => bytes == [1, 2, 3],
+            _ => false,
+        });
+
+        let nested = variant(variant(vec![1u8, 2, 3]));
+        assert_eq!(to(nested), Some(vec![1u8, 2, 3]));
+
+        assert_eq!(to::<Vec<u8>>(variant(vec![1u32])), None);
+    }
+
+    #[test]
+    fn test_variant_to_double() {
+        assert_eq!(to(variant(1.5f64)), Some(1.5f64));
+        assert_eq!(to(variant(-0.25f64)), Some(-0.25f64));
+        assert_eq!(to::<f64>(variant(1u32)), None);
+        assert_eq!(to(variant(variant(0.1f64))), Some(0.1f64));
+    }
+
+    #[test]
+    fn test_ref_arg_to_value_numbers() {
+        let large = variant(variant(u64::MAX));
+        assert_eq!(ref_arg_to_value(&*large.0), Some(Value::UInt64(u64::MAX)));
+        assert_eq!(to(large), Some(u64::MAX));
+
+        let double = variant(variant(-2.75f64));
+        assert_eq!(ref_arg_to_value(&*double.0), Some(Value::Double(-2.75)));
+
+        assert_eq!(ref_arg_to_value(&*variant(7u32).0), Some(Value::UInt32(7)));
+    }
+}
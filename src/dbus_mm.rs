@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use dbus::arg::RefArg;
+use dbus::Connection as DBusConnection;
+use dbus::Path;
+
+use dbus_api::{Bus, DBusApi, DryRunPlan};
+use errors::*;
+
+const MM_SERVICE: &str = "org.freedesktop.ModemManager1";
+
+const MM_MODEM_INTERFACE: &str = "org.freedesktop.ModemManager1.Modem";
+const MM_SIM_INTERFACE: &str = "org.freedesktop.ModemManager1.Sim";
+
+const METHOD_RETRY_ERROR_NAMES: &[&str; 0] = &[];
+
+/// Client of ModemManager, which NetworkManager drives mobile broadband
+/// devices through. Modems are addressed by the `Udi` of their
+/// NetworkManager device.
+pub struct DBusModemManager {
+    dbus: DBusApi,
+}
+
+impl DBusModemManager {
+    pub fn with_connection(
+        connection: Rc<DBusConnection>,
+        bus: Bus,
+        dry_run: Option<DryRunPlan>,
+    ) -> Self {
+        DBusModemManager {
+            dbus: DBusApi::with_connection(
+                connection,
+                bus,
+                MM_SERVICE,
+                METHOD_RETRY_ERROR_NAMES,
+                None,
+            )
+            .dry_run(dry_run),
+        }
+    }
+
+    /// The `MMModemLock` the modem is waiting to be unlocked with.
+    pub fn get_unlock_required(&self, modem: &str) -> Result<u32> {
+        self.dbus
+            .property(modem, MM_MODEM_INTERFACE, "UnlockRequired")
+    }
+
+    /// Remaining attempts by `MMModemLock`.
+    pub fn get_unlock_retries(&self, modem: &str) -> Result<HashMap<u32, u32>> {
+        self.dbus
+            .property_ref(modem, MM_MODEM_INTERFACE, "UnlockRetries", |variant| {
+                let mut iter = variant.0.as_iter()?;
+                let mut retries = HashMap::new();
+
+                while let Some(lock) = iter.next() {
+                    let count = iter.next()?;
+                    retries.insert(lock.as_i64()? as u32, count.as_i64()? as u32);
+                }
+
+                Some(retries)
+            })
+    }
+
+    /// The active SIM of the modem, if one is inserted.
+    pub fn get_sim(&self, modem: &str) -> Result<Option<String>> {
+        let sim: Path = self.dbus.property(modem, MM_MODEM_INTERFACE, "Sim")?;
+
+        Ok(if &*sim == "/" {
+            None
+        } else {
+            Some(sim.to_string())
+        })
+    }
+
+    /// The SIMs in the slots of the modem, `/` for empty slots. Empty if the
+    /// modem has a single slot.
+    pub fn get_sim_slots(&self, modem: &str) -> Result<Vec<Path<'static>>> {
+        self.dbus.property(modem, MM_MODEM_INTERFACE, "SimSlots")
+    }
+
+    /// The 1-based slot of the active SIM, 0 if the modem has a single slot.
+    pub fn get_primary_sim_slot(&self, modem: &str) -> Result<u32> {
+        self.dbus
+            .property(modem, MM_MODEM_INTERFACE, "PrimarySimSlot")
+    }
+
+    pub fn set_primary_sim_slot(&self, modem: &str, slot: u32) -> Result<()> {
+        self.dbus.call_with_args(
+            modem,
+            MM_MODEM_INTERFACE,
+            "SetPrimarySimSlot",
+            &[&slot as &dyn RefArg],
+        )?;
+
+        Ok(())
+    }
+
+    /// The ICCID of the SIM, which `gsm.sim-id` of a profile is matched
+    /// against.
+    pub fn get_sim_identifier(&self, sim: &str) -> Result<String> {
+        self.dbus.property(sim, MM_SIM_INTERFACE, "SimIdentifier")
+    }
+
+    /// MCC and MNC of the operator that issued the SIM, empty if unknown.
+    pub fn get_sim_operator_identifier(&self, sim: &str) -> Result<String> {
+        self.dbus
+            .property(sim, MM_SIM_INTERFACE, "OperatorIdentifier")
+    }
+
+    pub fn send_pin(&self, sim: &str, pin: &str) -> Result<()> {
+        self.dbus.call_with_args(
+            sim,
+            MM_SIM_INTERFACE,
+            "SendPin",
+            &[&pin.to_string() as &dyn RefArg],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn send_puk(&self, sim: &str, puk: &str, pin: &str) -> Result<()> {
+        self.dbus.call_with_args(
+            sim,
+            MM_SIM_INTERFACE,
+            "SendPuk",
+            &[
+                &puk.to_string() as &dyn RefArg,
+                &pin.to_string() as &dyn RefArg,
+            ],
+        )?;
+
+        Ok(())
+    }
+}
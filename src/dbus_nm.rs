@@ -1,18 +1,47 @@
-use std::collections::HashMap;
-use std::net::Ipv4Addr;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv6Addr};
+use std::rc::Rc;
+use std::time::Duration;
 
 use dbus::arg::{Array, Dict, Iter, RefArg, Variant};
+use dbus::Connection as DBusConnection;
 use dbus::Path;
+use network_manager_types::{DeviceType, Setting, Ssid, Value};
+#[cfg(feature = "wifi")]
+use network_manager_types::{NM80211ApFlags, NM80211ApSecurityFlags};
 
-use ascii::AsciiStr;
-
+use batch::WorkerPool;
 use connection::{ConnectionSettings, ConnectionState};
-use dbus_api::{extract, variant_iter_to_vec_u8, DBusApi, VariantTo};
-use device::{DeviceState, DeviceType};
+#[cfg(feature = "wifi")]
+use dbus_api::ref_arg_bytes;
+use dbus_api::{
+    dict_get, dict_to_variant_map, extract, is_unknown_property, ref_arg_to_value,
+    variant_iter_to_vec_u8, Bus, CallObserver, DBusApi, VariantTo,
+};
+#[cfg(feature = "wwan")]
+use dbus_mm::DBusModemManager;
+#[cfg(feature = "wifi")]
+use dbus_supplicant::DBusSupplicant;
+use device::DeviceState;
 use errors::*;
+#[cfg(feature = "hotspot")]
+use hotspot::{HotspotConfig, HotspotSecurity};
+use introspect::{parse_introspection, Introspection};
+use ip_config::{IpAddress, IpConfig};
+use lldp::{LldpNeighbor, LldpVlan};
 use manager::{Connectivity, NetworkManagerState};
-use ssid::{AsSsidSlice, Ssid};
-use wifi::{AccessPoint, AccessPointCredentials, NM80211ApFlags, NM80211ApSecurityFlags};
+use rate_limit::ActivationLimiter;
+use settings::ConnectionProfile;
+#[cfg(feature = "hotspot")]
+use wifi::channels::verify_band_setting;
+#[cfg(feature = "wifi")]
+use wifi::psk::{verify_psk, verify_wep_key, verify_wep_passphrase};
+#[cfg(feature = "hotspot")]
+use wifi::Band;
+#[cfg(feature = "wifi")]
+use wifi::{AccessPoint, AccessPointCredentials};
 
 type VariantMap = HashMap<String, Variant<Box<dyn RefArg>>>;
 
@@ -27,29 +56,257 @@ const NM_CONNECTION_INTERFACE: &str = "org.freedesktop.NetworkManager.Settings.\
                                        Connection";
 const NM_ACTIVE_INTERFACE: &str = "org.freedesktop.NetworkManager.Connection.Active";
 const NM_DEVICE_INTERFACE: &str = "org.freedesktop.NetworkManager.Device";
+const NM_WIRED_INTERFACE: &str = "org.freedesktop.NetworkManager.Device.Wired";
+#[cfg(feature = "wifi")]
 const NM_WIRELESS_INTERFACE: &str = "org.freedesktop.NetworkManager.Device.Wireless";
+#[cfg(feature = "wifi")]
 const NM_ACCESS_POINT_INTERFACE: &str = "org.freedesktop.NetworkManager.AccessPoint";
-
+const NM_STATISTICS_INTERFACE: &str = "org.freedesktop.NetworkManager.Device.Statistics";
+#[cfg(feature = "wwan")]
+const NM_MODEM_INTERFACE: &str = "org.freedesktop.NetworkManager.Device.Modem";
+const NM_IP4_CONFIG_INTERFACE: &str = "org.freedesktop.NetworkManager.IP4Config";
+const NM_IP6_CONFIG_INTERFACE: &str = "org.freedesktop.NetworkManager.IP6Config";
+const NM_DHCP4_CONFIG_INTERFACE: &str = "org.freedesktop.NetworkManager.DHCP4Config";
+const NM_DHCP6_CONFIG_INTERFACE: &str = "org.freedesktop.NetworkManager.DHCP6Config";
+
+#[cfg(feature = "wifi")]
+const NM_WEP_KEY_TYPE_KEY: u32 = 1;
+#[cfg(feature = "wifi")]
 const NM_WEP_KEY_TYPE_PASSPHRASE: u32 = 2;
 
+#[cfg(feature = "hotspot")]
+const NM_SETTING_WIRELESS_SECURITY_PMF_REQUIRED: i32 = 3;
+
+#[cfg(feature = "wifi")]
+#[cfg(feature = "hotspot")]
+const NM_WIFI_DEVICE_CAP_AP: u32 = 0x40;
+
 const UNKNOWN_CONNECTION: &str = "org.freedesktop.NetworkManager.UnknownConnection";
 const METHOD_RETRY_ERROR_NAMES: &[&str; 1] = &[UNKNOWN_CONNECTION];
 
+/// Longest time to block on signals in `wait_*` helpers before checking the
+/// awaited state again.
+const DEFAULT_WAIT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shortest wait interval, below which the `wait_*` helpers would spin on
+/// the connection rather than block on it.
+const MIN_WAIT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How many operations of a batch run at the same time unless set otherwise.
+const DEFAULT_BATCH_CONCURRENCY: usize = 4;
+
 pub struct DBusNetworkManager {
     dbus: DBusApi,
+    wait_interval: Cell<Duration>,
+    wait_deadline: Cell<Option<Duration>>,
+    activation_limiter: RefCell<Option<Rc<ActivationLimiter>>>,
+    batch_concurrency: Cell<usize>,
+    worker_pool: RefCell<Option<Rc<WorkerPool>>>,
 }
 
 impl DBusNetworkManager {
     pub fn new(method_timeout: Option<u64>) -> Self {
+        DBusNetworkManager::with_api(DBusApi::new(
+            NM_SERVICE_MANAGER,
+            METHOD_RETRY_ERROR_NAMES,
+            method_timeout,
+        ))
+    }
+
+    pub fn with_connection(
+        connection: Rc<DBusConnection>,
+        bus: Bus,
+        method_timeout: Option<u64>,
+    ) -> Self {
+        DBusNetworkManager::with_api(DBusApi::with_connection(
+            connection,
+            bus,
+            NM_SERVICE_MANAGER,
+            METHOD_RETRY_ERROR_NAMES,
+            method_timeout,
+        ))
+    }
+
+    pub fn with_bus(bus: Bus, method_timeout: Option<u64>) -> Result<Self> {
+        Ok(DBusNetworkManager::with_api(DBusApi::with_bus(
+            bus,
+            NM_SERVICE_MANAGER,
+            METHOD_RETRY_ERROR_NAMES,
+            method_timeout,
+        )?))
+    }
+
+    /// Sends no calls that would change state, see `DBusApi::read_only`.
+    pub fn read_only(method_timeout: Option<u64>) -> Self {
+        DBusNetworkManager::with_api(
+            DBusApi::new(NM_SERVICE_MANAGER, METHOD_RETRY_ERROR_NAMES, method_timeout)
+                .read_only(true),
+        )
+    }
+
+    fn with_api(dbus: DBusApi) -> Self {
         DBusNetworkManager {
-            dbus: DBusApi::new(NM_SERVICE_MANAGER, METHOD_RETRY_ERROR_NAMES, method_timeout),
+            dbus,
+            wait_interval: Cell::new(DEFAULT_WAIT_INTERVAL),
+            wait_deadline: Cell::new(None),
+            activation_limiter: RefCell::new(None),
+            batch_concurrency: Cell::new(DEFAULT_BATCH_CONCURRENCY),
+            worker_pool: RefCell::new(None),
         }
     }
 
+    /// A wpa_supplicant client multiplexed on the same bus connection.
+    #[cfg(feature = "wifi")]
+    pub fn supplicant(&self) -> DBusSupplicant {
+        DBusSupplicant::with_connection(
+            self.dbus.connection().clone(),
+            self.dbus.bus(),
+            self.dbus.dry_run_plan(),
+        )
+    }
+
+    /// A ModemManager client multiplexed on the same bus connection.
+    #[cfg(feature = "wwan")]
+    pub fn modem_manager(&self) -> DBusModemManager {
+        DBusModemManager::with_connection(
+            self.dbus.connection().clone(),
+            self.dbus.bus(),
+            self.dbus.dry_run_plan(),
+        )
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.dbus.is_read_only()
+    }
+
+    /// The calls skipped in read-only mode since the last call, see
+    /// `DBusApi::read_only`.
+    pub fn take_planned(&self) -> Vec<String> {
+        self.dbus.take_planned()
+    }
+
+    pub fn bus(&self) -> Bus {
+        self.dbus.bus()
+    }
+
     pub fn method_timeout(&self) -> u64 {
         self.dbus.method_timeout()
     }
 
+    pub fn wait_interval(&self) -> Duration {
+        self.wait_interval.get()
+    }
+
+    pub fn set_wait_interval(&self, interval: Duration) {
+        self.wait_interval.set(interval.max(MIN_WAIT_INTERVAL))
+    }
+
+    /// How long `wait_*` helpers wait for a state, the method timeout unless
+    /// set otherwise.
+    /// Zero in read-only mode, where nothing changes that could be waited
+    /// for, so that waits return the current state at once.
+    pub fn wait_deadline(&self) -> Duration {
+        if self.is_read_only() {
+            return Duration::from_secs(0);
+        }
+
+        self.wait_deadline
+            .get()
+            .unwrap_or_else(|| Duration::from_secs(self.method_timeout()))
+    }
+
+    pub fn set_wait_deadline(&self, deadline: Duration) {
+        self.wait_deadline.set(Some(deadline))
+    }
+
+    /// How many operations of a batch run at the same time. Lists of many
+    /// objects, e.g. access points, are read on as many threads too, a
+    /// concurrency of one keeps all calls on the calling thread.
+    pub fn batch_concurrency(&self) -> usize {
+        self.batch_concurrency.get()
+    }
+
+    pub fn set_batch_concurrency(&self, concurrency: usize) {
+        self.batch_concurrency.set(concurrency.max(1))
+    }
+
+    /// The pool running batches, `None` until the first one.
+    pub fn worker_pool(&self) -> Option<Rc<WorkerPool>> {
+        self.worker_pool.borrow().clone()
+    }
+
+    pub fn set_worker_pool(&self, pool: Option<Rc<WorkerPool>>) {
+        *self.worker_pool.borrow_mut() = pool;
+    }
+
+    pub fn set_activation_limiter(&self, limiter: Option<ActivationLimiter>) {
+        *self.activation_limiter.borrow_mut() = limiter.map(Rc::new);
+    }
+
+    pub fn reset_activation_limiter(&self) {
+        if let Some(limiter) = self.activation_limiter() {
+            limiter.reset();
+        }
+    }
+
+    /// Lets the next activation of `connection` through the limiter right
+    /// away, see `ActivationLimiter::succeeded`.
+    pub fn activation_succeeded(&self, connection: &str) {
+        if let Some(limiter) = self.activation_limiter() {
+            limiter.succeeded(connection);
+        }
+    }
+
+    // Cloned out of the cell so that the observer of the limiter may replace it
+    fn activation_limiter(&self) -> Option<Rc<ActivationLimiter>> {
+        self.activation_limiter.borrow().clone()
+    }
+
+    /// Records an attempt to activate `connection` on `device` with the
+    /// activation limiter, if there is one.
+    pub fn check_activation(&self, connection: &str, device: &str) -> Result<()> {
+        match self.activation_limiter() {
+            Some(limiter) => limiter.check(connection, device),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks the activation of the saved profile at `path`, which the
+    /// limiter knows by its UUID.
+    fn check_profile_activation(&self, path: &str, device: &str) -> Result<()> {
+        match self.activation_limiter() {
+            Some(limiter) => limiter.check(&self.get_connection_settings(path)?.uuid, device),
+            None => Ok(()),
+        }
+    }
+
+    pub fn resync(&self) -> Result<()> {
+        self.dbus.resync()
+    }
+
+    pub fn set_call_observer(&self, observer: Option<Rc<dyn CallObserver>>) {
+        self.dbus.set_observer(observer)
+    }
+
+    pub fn call_observer(&self) -> Option<Rc<dyn CallObserver>> {
+        self.dbus.observer()
+    }
+
+    /// The bus owner of NetworkManager when we first talked to it, see
+    /// `DBusApi::owner`.
+    pub fn daemon_owner(&self) -> Option<String> {
+        self.dbus.owner()
+    }
+
+    pub fn set_daemon_owner(&self, owner: Option<String>) {
+        self.dbus.set_owner(owner)
+    }
+
+    pub fn get_version(&self) -> Result<String> {
+        self.dbus
+            .property(NM_SERVICE_PATH, NM_SERVICE_INTERFACE, "Version")
+    }
+
     pub fn get_state(&self) -> Result<NetworkManagerState> {
         let response = self
             .dbus
@@ -60,6 +317,15 @@ impl DBusNetworkManager {
         Ok(NetworkManagerState::from(state))
     }
 
+    /// The connectivity found by the last check, without starting one.
+    pub fn get_connectivity(&self) -> Result<Connectivity> {
+        let connectivity: u32 =
+            self.dbus
+                .property(NM_SERVICE_PATH, NM_SERVICE_INTERFACE, "Connectivity")?;
+
+        Ok(Connectivity::from(connectivity))
+    }
+
     pub fn check_connectivity(&self) -> Result<Connectivity> {
         let response =
             self.dbus
@@ -75,6 +341,50 @@ impl DBusNetworkManager {
             .property(NM_SERVICE_PATH, NM_SERVICE_INTERFACE, "WirelessEnabled")
     }
 
+    pub fn is_wireless_hardware_enabled(&self) -> Result<bool> {
+        self.dbus.property(
+            NM_SERVICE_PATH,
+            NM_SERVICE_INTERFACE,
+            "WirelessHardwareEnabled",
+        )
+    }
+
+    pub fn set_wireless_enabled(&self, enabled: bool) -> Result<()> {
+        self.dbus.set_property(
+            NM_SERVICE_PATH,
+            NM_SERVICE_INTERFACE,
+            "WirelessEnabled",
+            enabled,
+        )
+    }
+
+    pub fn get_global_dns_configuration(&self) -> Result<HashMap<String, Value>> {
+        self.dbus.property(
+            NM_SERVICE_PATH,
+            NM_SERVICE_INTERFACE,
+            "GlobalDnsConfiguration",
+        )
+    }
+
+    pub fn get_manager_properties(&self) -> Result<HashMap<String, Value>> {
+        self.dbus
+            .all_properties(NM_SERVICE_PATH, NM_SERVICE_INTERFACE)
+    }
+
+    pub fn introspect(&self, path: &str) -> Result<Introspection> {
+        parse_introspection(&self.dbus.introspect(path)?)
+    }
+
+    /// Checkpoints were added in NetworkManager 1.4 and can be compiled out.
+    pub fn supports_checkpoints(&self) -> Result<bool> {
+        let introspection = self.introspect(NM_SERVICE_PATH)?;
+
+        match introspection.interface(NM_SERVICE_INTERFACE) {
+            Some(interface) => Ok(interface.has_method("CheckpointCreate")),
+            None => Ok(false),
+        }
+    }
+
     pub fn is_networking_enabled(&self) -> Result<bool> {
         self.dbus
             .property(NM_SERVICE_PATH, NM_SERVICE_INTERFACE, "NetworkingEnabled")
@@ -90,15 +400,34 @@ impl DBusNetworkManager {
         Ok(array.map(|e| e.to_string()).collect())
     }
 
+    pub fn get_connection_by_uuid(&self, uuid: &str) -> Result<String> {
+        let response = self.dbus.call_with_args(
+            NM_SETTINGS_PATH,
+            NM_SETTINGS_INTERFACE,
+            "GetConnectionByUuid",
+            &[&uuid.to_string() as &dyn RefArg],
+        )?;
+
+        let path: Path = self.dbus.extract(&response)?;
+
+        Ok(path.to_string())
+    }
+
     pub fn get_active_connections(&self) -> Result<Vec<String>> {
         self.dbus
             .property(NM_SERVICE_PATH, NM_SERVICE_INTERFACE, "ActiveConnections")
+            .map(path_strings)
     }
 
     pub fn get_active_connection_path(&self, path: &str) -> Option<String> {
         self.dbus
-            .property(path, NM_ACTIVE_INTERFACE, "Connection")
+            .property::<Path>(path, NM_ACTIVE_INTERFACE, "Connection")
             .ok()
+            .map(|path| path.to_string())
+    }
+
+    pub fn get_active_connection_properties(&self, path: &str) -> Result<HashMap<String, Value>> {
+        self.dbus.all_properties(path, NM_ACTIVE_INTERFACE)
     }
 
     pub fn get_connection_state(&self, path: &str) -> Result<ConnectionState> {
@@ -110,53 +439,65 @@ impl DBusNetworkManager {
         Ok(ConnectionState::from(state))
     }
 
+    /// `Flags` is missing before NetworkManager 1.12, where profiles are
+    /// reported without any.
+    pub fn get_connection_flags(&self, path: &str) -> Result<u32> {
+        match self.dbus.property(path, NM_CONNECTION_INTERFACE, "Flags") {
+            Err(ref e) if is_unknown_property(e) => Ok(0),
+            result => result,
+        }
+    }
+
     pub fn get_connection_settings(&self, path: &str) -> Result<ConnectionSettings> {
         let response = self
             .dbus
             .call(path, NM_CONNECTION_INTERFACE, "GetSettings")?;
 
-        let dict: Dict<&str, Dict<&str, Variant<Iter>, _>, _> = self.dbus.extract(&response)?;
+        connection_settings_from_dict(self.dbus.extract(&response)?)
+    }
 
-        let mut kind = String::new();
-        let mut id = String::new();
-        let mut uuid = String::new();
-        let mut ssid = Ssid::new();
-        let mut mode = String::new();
+    pub fn get_connection_profile(&self, path: &str) -> Result<ConnectionProfile> {
+        let response = self
+            .dbus
+            .call(path, NM_CONNECTION_INTERFACE, "GetSettings")?;
 
-        for (_, v1) in dict {
-            for (k2, mut v2) in v1 {
-                match k2 {
-                    "id" => {
-                        id = extract::<String>(&mut v2)?;
-                    }
-                    "uuid" => {
-                        uuid = extract::<String>(&mut v2)?;
-                    }
-                    "type" => {
-                        kind = extract::<String>(&mut v2)?;
-                    }
-                    "ssid" => {
-                        ssid = Ssid::from_bytes(variant_iter_to_vec_u8(&mut v2)?)?;
-                    }
-                    "mode" => {
-                        mode = extract::<String>(&mut v2)?;
-                    }
-                    _ => {}
-                }
-            }
-        }
+        let dict: HashMap<String, HashMap<String, Variant<Box<dyn RefArg>>>> =
+            self.dbus.extract(&response)?;
 
-        Ok(ConnectionSettings {
-            kind,
-            id,
-            uuid,
-            ssid,
-            mode,
-        })
+        Ok(ConnectionProfile::from(settings_from_dict(dict)))
+    }
+
+    pub fn get_connection_secrets(&self, path: &str, setting: &str) -> Result<Setting> {
+        let response = self.dbus.call_with_args(
+            path,
+            NM_CONNECTION_INTERFACE,
+            "GetSecrets",
+            &[&setting.to_string() as &dyn RefArg],
+        )?;
+
+        let dict: HashMap<String, HashMap<String, Variant<Box<dyn RefArg>>>> =
+            self.dbus.extract(&response)?;
+
+        Ok(settings_from_dict(dict).remove(setting).unwrap_or_default())
+    }
+
+    pub fn update_connection(&self, path: &str, profile: &ConnectionProfile) -> Result<()> {
+        let settings = profile_to_settings(profile);
+
+        self.dbus.call_with_args(
+            path,
+            NM_CONNECTION_INTERFACE,
+            "Update",
+            &[&settings as &dyn RefArg],
+        )?;
+
+        Ok(())
     }
 
     pub fn get_active_connection_devices(&self, path: &str) -> Result<Vec<String>> {
-        self.dbus.property(path, NM_ACTIVE_INTERFACE, "Devices")
+        self.dbus
+            .property(path, NM_ACTIVE_INTERFACE, "Devices")
+            .map(path_strings)
     }
 
     pub fn delete_connection(&self, path: &str) -> Result<()> {
@@ -165,19 +506,34 @@ impl DBusNetworkManager {
         Ok(())
     }
 
-    pub fn activate_connection(&self, path: &str) -> Result<()> {
-        self.dbus.call_with_args(
+    /// Returns the path of the active connection created for `path`.
+    pub fn activate_connection(&self, path: &str) -> Result<String> {
+        self.activate_connection_on(path, "/", "/")
+    }
+
+    /// Returns the path of the active connection created for `path`.
+    pub fn activate_connection_on(
+        &self,
+        path: &str,
+        device_path: &str,
+        specific_object: &str,
+    ) -> Result<String> {
+        self.check_profile_activation(path, device_path)?;
+
+        let response = self.dbus.call_with_args(
             NM_SERVICE_PATH,
             NM_SERVICE_INTERFACE,
             "ActivateConnection",
             &[
                 &Path::new(path)? as &dyn RefArg,
-                &Path::new("/")? as &dyn RefArg,
-                &Path::new("/")? as &dyn RefArg,
+                &Path::new(device_path)? as &dyn RefArg,
+                &Path::new(specific_object)? as &dyn RefArg,
             ],
         )?;
 
-        Ok(())
+        let active_path: Path = self.dbus.extract(&response)?;
+
+        Ok(active_path.to_string())
     }
 
     pub fn deactivate_connection(&self, path: &str) -> Result<()> {
@@ -191,12 +547,15 @@ impl DBusNetworkManager {
         Ok(())
     }
 
+    #[cfg(feature = "wifi")]
     pub fn connect_to_access_point(
         &self,
         device_path: &str,
         access_point: &AccessPoint,
         credentials: &AccessPointCredentials,
     ) -> Result<(String, String)> {
+        self.check_activation(&access_point.ssid().to_string_lossy(), device_path)?;
+
         let mut settings: HashMap<String, VariantMap> = HashMap::new();
 
         let mut wireless: VariantMap = HashMap::new();
@@ -209,6 +568,11 @@ impl DBusNetworkManager {
 
         match *credentials {
             AccessPointCredentials::Wep { ref passphrase } => {
+                warn!(
+                    "Connecting to {:?} with deprecated WEP security",
+                    access_point.ssid()
+                );
+
                 let mut security_settings: VariantMap = HashMap::new();
 
                 add_val(
@@ -219,39 +583,93 @@ impl DBusNetworkManager {
                 add_str(
                     &mut security_settings,
                     "wep-key0",
-                    verify_ascii_password(passphrase)?,
+                    verify_wep_passphrase(passphrase.expose())?,
                 );
 
                 settings.insert("802-11-wireless-security".to_string(), security_settings);
             }
-            AccessPointCredentials::Wpa { ref passphrase } => {
+            AccessPointCredentials::WepKey { ref key } => {
+                warn!(
+                    "Connecting to {:?} with deprecated WEP security",
+                    access_point.ssid()
+                );
+
                 let mut security_settings: VariantMap = HashMap::new();
 
-                add_str(&mut security_settings, "key-mgmt", "wpa-psk");
+                add_str(&mut security_settings, "key-mgmt", "none");
+                add_val(&mut security_settings, "wep-key-type", NM_WEP_KEY_TYPE_KEY);
                 add_str(
                     &mut security_settings,
-                    "psk",
-                    verify_ascii_password(passphrase)?,
+                    "wep-key0",
+                    verify_wep_key(key.expose())?,
+                );
+
+                settings.insert("802-11-wireless-security".to_string(), security_settings);
+            }
+            AccessPointCredentials::DynamicWep { ref config } => {
+                warn!(
+                    "Connecting to {:?} with deprecated dynamic WEP security",
+                    access_point.ssid()
                 );
 
+                let mut security_settings: VariantMap = HashMap::new();
+
+                add_str(&mut security_settings, "key-mgmt", "ieee8021x");
+
                 settings.insert("802-11-wireless-security".to_string(), security_settings);
+                settings.insert(
+                    "802-1x".to_string(),
+                    dict_to_variant_map(&config.to_setting()?),
+                );
             }
-            AccessPointCredentials::Enterprise {
-                ref identity,
+            AccessPointCredentials::Wpa {
                 ref passphrase,
+                psk_flags,
             } => {
                 let mut security_settings: VariantMap = HashMap::new();
 
+                add_str(&mut security_settings, "key-mgmt", "wpa-psk");
+                add_str(
+                    &mut security_settings,
+                    "psk",
+                    verify_psk(passphrase.expose())?,
+                );
+                if !psk_flags.is_empty() {
+                    add_val(&mut security_settings, "psk-flags", psk_flags.bits());
+                }
+
+                settings.insert("802-11-wireless-security".to_string(), security_settings);
+            }
+            AccessPointCredentials::Enterprise { ref config } => {
+                let mut security_settings: VariantMap = HashMap::new();
+
                 add_str(&mut security_settings, "key-mgmt", "wpa-eap");
 
-                let mut eap: VariantMap = HashMap::new();
-                add_val(&mut eap, "eap", vec!["peap".to_string()]);
-                add_str(&mut eap, "identity", identity as &str);
-                add_str(&mut eap, "password", passphrase as &str);
-                add_str(&mut eap, "phase2-auth", "mschapv2");
+                settings.insert("802-11-wireless-security".to_string(), security_settings);
+                settings.insert(
+                    "802-1x".to_string(),
+                    dict_to_variant_map(&config.to_setting()?),
+                );
+            }
+            AccessPointCredentials::Wps { ref method } => {
+                if !access_point.supports_wps(method) {
+                    bail!(ErrorKind::NetworkManager(format!(
+                        "Access point {:?} does not support WPS {:?}",
+                        access_point.ssid(),
+                        method
+                    )));
+                }
+
+                let mut security_settings: VariantMap = HashMap::new();
+
+                add_str(&mut security_settings, "key-mgmt", "wpa-psk");
+                add_val(
+                    &mut security_settings,
+                    "wps-method",
+                    u32::from(method.clone()),
+                );
 
                 settings.insert("802-11-wireless-security".to_string(), security_settings);
-                settings.insert("802-1x".to_string(), eap);
             }
             AccessPointCredentials::None => {}
         };
@@ -272,23 +690,25 @@ impl DBusNetworkManager {
         Ok((conn_path.to_string(), active_connection.to_string()))
     }
 
-    pub fn create_hotspot<T>(
+    #[cfg(feature = "hotspot")]
+    pub fn create_hotspot(
         &self,
         device_path: &str,
         interface: &str,
-        ssid: &T,
-        password: Option<&str>,
-        address: Option<Ipv4Addr>,
-    ) -> Result<(String, String)>
-    where
-        T: AsSsidSlice + ?Sized,
-    {
-        let ssid = ssid.as_ssid_slice()?;
+        config: &HotspotConfig,
+    ) -> Result<(String, String)> {
+        let ssid = config.ssid();
+        self.check_activation(&ssid.to_string_lossy(), device_path)?;
+
         let ssid_vec = ssid.as_bytes().to_vec();
 
         let mut wireless: VariantMap = HashMap::new();
         add_val(&mut wireless, "ssid", ssid_vec);
-        add_str(&mut wireless, "band", "bg");
+        let band = config.get_band().unwrap_or(Band::TwoGhz);
+        add_str(&mut wireless, "band", verify_band_setting(band)?);
+        if let Some(channel) = config.get_channel() {
+            add_val(&mut wireless, "channel", channel);
+        }
         add_val(&mut wireless, "hidden", false);
         add_str(&mut wireless, "mode", "ap");
 
@@ -301,7 +721,7 @@ impl DBusNetworkManager {
         add_str(&mut connection, "type", "802-11-wireless");
 
         let mut ipv4: VariantMap = HashMap::new();
-        if let Some(address) = address {
+        if let Some(address) = config.get_address() {
             add_str(&mut ipv4, "method", "manual");
 
             let mut addr_map: VariantMap = HashMap::new();
@@ -311,16 +731,62 @@ impl DBusNetworkManager {
             add_val(&mut ipv4, "address-data", vec![addr_map]);
         } else {
             add_str(&mut ipv4, "method", "shared");
+
+            if let Some((address, prefix)) = config.get_subnet() {
+                let mut addr_map: VariantMap = HashMap::new();
+                add_str(&mut addr_map, "address", format!("{}", address));
+                add_val(&mut addr_map, "prefix", prefix);
+
+                add_val(&mut ipv4, "address-data", vec![addr_map]);
+            }
+
+            if let Some((start, end)) = config.get_dhcp_range() {
+                add_str(&mut ipv4, "shared-dhcp-range", format!("{},{}", start, end));
+            }
+
+            if let Some(lease_time) = config.get_dhcp_lease_time() {
+                let lease_time = i32::try_from(lease_time).unwrap_or(i32::MAX);
+                add_val(&mut ipv4, "shared-dhcp-lease-time", lease_time);
+            }
         }
 
         let mut settings: HashMap<String, VariantMap> = HashMap::new();
 
-        if let Some(password) = password {
+        let key_mgmt = match (config.get_security(), config.get_password()) {
+            (HotspotSecurity::Open, None) => None,
+            (HotspotSecurity::WpaPsk, Some(password)) => Some(("wpa-psk", verify_psk(password)?)),
+            (HotspotSecurity::Sae, Some(password)) if !password.is_empty() => {
+                Some(("sae", password))
+            }
+            (HotspotSecurity::Open, Some(_)) => bail!(ErrorKind::PreSharedKey(
+                "Open hotspots do not take a password".into()
+            )),
+            (security, _) => bail!(ErrorKind::PreSharedKey(format!(
+                "{:?} hotspots need a password",
+                security
+            ))),
+        };
+
+        if let Some((key_mgmt, password)) = key_mgmt {
             add_str(&mut wireless, "security", "802-11-wireless-security");
 
             let mut security: VariantMap = HashMap::new();
-            add_str(&mut security, "key-mgmt", "wpa-psk");
-            add_str(&mut security, "psk", verify_ascii_password(password)?);
+            add_str(&mut security, "key-mgmt", key_mgmt);
+            add_str(&mut security, "psk", password);
+
+            // SAE mandates management frame protection
+            if key_mgmt == "sae" {
+                add_val(
+                    &mut security,
+                    "pmf",
+                    NM_SETTING_WIRELESS_SECURITY_PMF_REQUIRED,
+                );
+            }
+
+            let flags = config.get_password_flags();
+            if !flags.is_empty() {
+                add_val(&mut security, "psk-flags", flags.bits());
+            }
 
             settings.insert("802-11-wireless-security".to_string(), security);
         }
@@ -345,9 +811,116 @@ impl DBusNetworkManager {
         Ok((conn_path.to_string(), active_connection.to_string()))
     }
 
+    pub fn add_connection(&self, profile: &ConnectionProfile) -> Result<String> {
+        let settings = profile_to_settings(profile);
+
+        let response = self.dbus.call_with_args(
+            NM_SETTINGS_PATH,
+            NM_SETTINGS_INTERFACE,
+            "AddConnection",
+            &[&settings as &dyn RefArg],
+        )?;
+
+        let path: Path = self.dbus.extract(&response)?;
+
+        Ok(path.to_string())
+    }
+
+    /// Like `add_connection`, but the profile is kept in memory only and
+    /// lost when NetworkManager restarts, unless saved later.
+    pub fn add_connection_unsaved(&self, profile: &ConnectionProfile) -> Result<String> {
+        let settings = profile_to_settings(profile);
+
+        let response = self.dbus.call_with_args(
+            NM_SETTINGS_PATH,
+            NM_SETTINGS_INTERFACE,
+            "AddConnectionUnsaved",
+            &[&settings as &dyn RefArg],
+        )?;
+
+        let path: Path = self.dbus.extract(&response)?;
+
+        Ok(path.to_string())
+    }
+
+    pub fn add_and_activate_connection(
+        &self,
+        profile: &ConnectionProfile,
+        device_path: &str,
+        specific_object: &str,
+    ) -> Result<(String, String)> {
+        // NetworkManager generates the UUID of a profile naming neither, so
+        // there is nothing to count its attempts by
+        if let Some(connection) = profile.uuid().or_else(|| profile.id()) {
+            self.check_activation(connection, device_path)?;
+        }
+
+        let settings = profile_to_settings(profile);
+
+        let response = self.dbus.call_with_args(
+            NM_SERVICE_PATH,
+            NM_SERVICE_INTERFACE,
+            "AddAndActivateConnection",
+            &[
+                &settings as &dyn RefArg,
+                &Path::new(device_path)? as &dyn RefArg,
+                &Path::new(specific_object)? as &dyn RefArg,
+            ],
+        )?;
+
+        let (conn_path, active_connection): (Path, Path) = self.dbus.extract_two(&response)?;
+
+        Ok((conn_path.to_string(), active_connection.to_string()))
+    }
+
+    pub fn create_checkpoint(&self, devices: &[&str], rollback_timeout: u32) -> Result<String> {
+        let devices = devices
+            .iter()
+            .map(|device| Path::new(*device))
+            .collect::<::std::result::Result<Vec<_>, _>>()?;
+
+        let response = self.dbus.call_with_args(
+            NM_SERVICE_PATH,
+            NM_SERVICE_INTERFACE,
+            "CheckpointCreate",
+            &[
+                &devices as &dyn RefArg,
+                &rollback_timeout as &dyn RefArg,
+                &0_u32 as &dyn RefArg,
+            ],
+        )?;
+
+        let path: Path = self.dbus.extract(&response)?;
+
+        Ok(path.to_string())
+    }
+
+    pub fn rollback_checkpoint(&self, path: &str) -> Result<()> {
+        self.dbus.call_with_args(
+            NM_SERVICE_PATH,
+            NM_SERVICE_INTERFACE,
+            "CheckpointRollback",
+            &[&Path::new(path)? as &dyn RefArg],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn destroy_checkpoint(&self, path: &str) -> Result<()> {
+        self.dbus.call_with_args(
+            NM_SERVICE_PATH,
+            NM_SERVICE_INTERFACE,
+            "CheckpointDestroy",
+            &[&Path::new(path)? as &dyn RefArg],
+        )?;
+
+        Ok(())
+    }
+
     pub fn get_devices(&self) -> Result<Vec<String>> {
         self.dbus
             .property(NM_SERVICE_PATH, NM_SERVICE_INTERFACE, "Devices")
+            .map(path_strings)
     }
 
     pub fn get_device_by_interface(&self, interface: &str) -> Result<String> {
@@ -367,6 +940,55 @@ impl DBusNetworkManager {
         self.dbus.property(path, NM_DEVICE_INTERFACE, "Interface")
     }
 
+    /// The settings applied to the device by its active connection, which may
+    /// differ from the stored profile after a reapply, and their version.
+    pub fn get_device_applied_connection(&self, path: &str) -> Result<(ConnectionProfile, u64)> {
+        let response = self.dbus.call_with_args(
+            path,
+            NM_DEVICE_INTERFACE,
+            "GetAppliedConnection",
+            &[&0u32 as &dyn RefArg],
+        )?;
+
+        let (dict, version): (HashMap<String, VariantMap>, u64) =
+            self.dbus.extract_two(&response)?;
+
+        Ok((ConnectionProfile::from(settings_from_dict(dict)), version))
+    }
+
+    /// Apply `profile` to the active connection of the device without
+    /// reactivating it or changing the stored profile. Fails if the applied
+    /// connection changed since `version` was read.
+    pub fn reapply_device(
+        &self,
+        path: &str,
+        profile: &ConnectionProfile,
+        version: u64,
+    ) -> Result<()> {
+        let settings = profile_to_settings(profile);
+
+        self.dbus.call_with_args(
+            path,
+            NM_DEVICE_INTERFACE,
+            "Reapply",
+            &[
+                &settings as &dyn RefArg,
+                &version as &dyn RefArg,
+                &0u32 as &dyn RefArg,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_device_ifindex(&self, path: &str) -> Result<u32> {
+        self.dbus.property(path, NM_DEVICE_INTERFACE, "Ifindex")
+    }
+
+    pub fn get_device_udi(&self, path: &str) -> Result<String> {
+        self.dbus.property(path, NM_DEVICE_INTERFACE, "Udi")
+    }
+
     pub fn get_device_type(&self, path: &str) -> Result<DeviceType> {
         self.dbus.property(path, NM_DEVICE_INTERFACE, "DeviceType")
     }
@@ -375,7 +997,213 @@ impl DBusNetworkManager {
         self.dbus.property(path, NM_DEVICE_INTERFACE, "State")
     }
 
+    pub fn get_device_driver(&self, path: &str) -> Result<String> {
+        self.dbus.property(path, NM_DEVICE_INTERFACE, "Driver")
+    }
+
+    pub fn get_device_ip_interface(&self, path: &str) -> Result<String> {
+        self.dbus.property(path, NM_DEVICE_INTERFACE, "IpInterface")
+    }
+
+    pub fn get_device_driver_version(&self, path: &str) -> Result<String> {
+        self.dbus
+            .property(path, NM_DEVICE_INTERFACE, "DriverVersion")
+    }
+
+    pub fn get_device_firmware_version(&self, path: &str) -> Result<String> {
+        self.dbus
+            .property(path, NM_DEVICE_INTERFACE, "FirmwareVersion")
+    }
+
+    pub fn is_device_firmware_missing(&self, path: &str) -> Result<bool> {
+        self.dbus
+            .property(path, NM_DEVICE_INTERFACE, "FirmwareMissing")
+    }
+
+    pub fn is_device_plugin_missing(&self, path: &str) -> Result<bool> {
+        self.dbus
+            .property(path, NM_DEVICE_INTERFACE, "NmPluginMissing")
+    }
+
+    pub fn is_device_managed(&self, path: &str) -> Result<bool> {
+        self.dbus.property(path, NM_DEVICE_INTERFACE, "Managed")
+    }
+
+    /// The reason code of the last device state change.
+    pub fn get_device_state_reason(&self, path: &str) -> Result<u32> {
+        // `StateReason` is a `(state, reason)` struct
+        self.dbus
+            .property_ref(path, NM_DEVICE_INTERFACE, "StateReason", |value| {
+                value
+                    .0
+                    .as_iter()?
+                    .nth(1)?
+                    .as_i64()
+                    .and_then(|reason| u32::try_from(reason).ok())
+            })
+    }
+
+    pub fn get_device_carrier(&self, path: &str) -> Result<bool> {
+        self.dbus.property(path, NM_WIRED_INTERFACE, "Carrier")
+    }
+
+    pub fn get_device_autoconnect(&self, path: &str) -> Result<bool> {
+        self.dbus.property(path, NM_DEVICE_INTERFACE, "Autoconnect")
+    }
+
+    pub fn set_device_autoconnect(&self, path: &str, autoconnect: bool) -> Result<()> {
+        self.dbus
+            .set_property(path, NM_DEVICE_INTERFACE, "Autoconnect", autoconnect)
+    }
+
+    pub fn set_device_managed(&self, path: &str, managed: bool) -> Result<()> {
+        self.dbus
+            .set_property(path, NM_DEVICE_INTERFACE, "Managed", managed)
+    }
+
+    pub fn get_device_statistics_refresh_rate(&self, path: &str) -> Result<u32> {
+        self.dbus
+            .property(path, NM_STATISTICS_INTERFACE, "RefreshRateMs")
+    }
+
+    pub fn get_device_tx_bytes(&self, path: &str) -> Result<u64> {
+        self.dbus.property(path, NM_STATISTICS_INTERFACE, "TxBytes")
+    }
+
+    pub fn get_device_rx_bytes(&self, path: &str) -> Result<u64> {
+        self.dbus.property(path, NM_STATISTICS_INTERFACE, "RxBytes")
+    }
+
+    pub fn set_device_statistics_refresh_rate(&self, path: &str, rate_ms: u32) -> Result<()> {
+        self.dbus
+            .set_property(path, NM_STATISTICS_INTERFACE, "RefreshRateMs", rate_ms)
+    }
+
+    /// The identifier `gsm.device-id` of a profile is matched against.
+    #[cfg(feature = "wwan")]
+    pub fn get_modem_device_id(&self, path: &str) -> Result<String> {
+        self.dbus.property(path, NM_MODEM_INTERFACE, "DeviceId")
+    }
+
+    pub fn get_device_active_connection(&self, path: &str) -> Option<String> {
+        self.dbus
+            .property(path, NM_DEVICE_INTERFACE, "ActiveConnection")
+            .ok()
+            .and_then(non_empty_path)
+    }
+
+    /// The path of the IPv4 or IPv6 configuration object of a device, `None`
+    /// while it has none.
+    pub fn get_device_ip_config_path(&self, path: &str, ipv6: bool) -> Option<String> {
+        let property = if ipv6 { "Ip6Config" } else { "Ip4Config" };
+
+        self.dbus
+            .property(path, NM_DEVICE_INTERFACE, property)
+            .ok()
+            .and_then(non_empty_path)
+    }
+
+    pub fn get_device_ip4_config(&self, path: &str) -> Result<Option<IpConfig>> {
+        match self.get_device_ip_config_path(path, false) {
+            Some(config_path) => self.get_ip4_config(&config_path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_ip4_config(&self, config_path: &str) -> Result<IpConfig> {
+        let addresses: Vec<IpAddress> =
+            self.dbus
+                .property(config_path, NM_IP4_CONFIG_INTERFACE, "AddressData")?;
+
+        let gateway: Option<IpAddr> =
+            self.dbus
+                .property_ref(config_path, NM_IP4_CONFIG_INTERFACE, "Gateway", |value| {
+                    Some(value.0.as_str()?.parse().ok())
+                })?;
+
+        let nameservers: Vec<IpAddr> =
+            self.dbus
+                .property(config_path, NM_IP4_CONFIG_INTERFACE, "NameserverData")?;
+
+        Ok(IpConfig {
+            addresses,
+            gateway,
+            nameservers,
+        })
+    }
+
+    pub fn get_ip_config_addresses(&self, config_path: &str, ipv6: bool) -> Result<Vec<IpAddress>> {
+        let interface = if ipv6 {
+            NM_IP6_CONFIG_INTERFACE
+        } else {
+            NM_IP4_CONFIG_INTERFACE
+        };
+
+        self.dbus.property(config_path, interface, "AddressData")
+    }
+
+    pub fn get_device_ip6_config(&self, path: &str) -> Result<Option<IpConfig>> {
+        match self.get_device_ip_config_path(path, true) {
+            Some(config_path) => self.get_ip6_config(&config_path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_ip6_config(&self, config_path: &str) -> Result<IpConfig> {
+        let addresses: Vec<IpAddress> =
+            self.dbus
+                .property(config_path, NM_IP6_CONFIG_INTERFACE, "AddressData")?;
+
+        let gateway: Option<IpAddr> =
+            self.dbus
+                .property_ref(config_path, NM_IP6_CONFIG_INTERFACE, "Gateway", |value| {
+                    Some(value.0.as_str()?.parse().ok())
+                })?;
+
+        let nameservers: Vec<IpAddr> =
+            self.dbus
+                .property(config_path, NM_IP6_CONFIG_INTERFACE, "Nameservers")?;
+
+        Ok(IpConfig {
+            addresses,
+            gateway,
+            nameservers,
+        })
+    }
+
+    pub fn get_device_dhcp4_options(&self, path: &str) -> Result<HashMap<String, Value>> {
+        self.get_device_dhcp_options(path, "Dhcp4Config", NM_DHCP4_CONFIG_INTERFACE)
+    }
+
+    pub fn get_device_dhcp6_options(&self, path: &str) -> Result<HashMap<String, Value>> {
+        self.get_device_dhcp_options(path, "Dhcp6Config", NM_DHCP6_CONFIG_INTERFACE)
+    }
+
+    fn get_device_dhcp_options(
+        &self,
+        path: &str,
+        property: &str,
+        interface: &str,
+    ) -> Result<HashMap<String, Value>> {
+        match self
+            .dbus
+            .property(path, NM_DEVICE_INTERFACE, property)
+            .ok()
+            .and_then(non_empty_path)
+        {
+            Some(config_path) => self.dbus.property(&config_path, interface, "Options"),
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    pub fn get_device_lldp_neighbors(&self, path: &str) -> Result<Vec<LldpNeighbor>> {
+        self.dbus
+            .property(path, NM_DEVICE_INTERFACE, "LldpNeighbors")
+    }
+
     pub fn connect_device(&self, path: &str) -> Result<()> {
+        self.check_activation(path, path)?;
+
         self.dbus.call_with_args(
             NM_SERVICE_PATH,
             NM_SERVICE_INTERFACE,
@@ -396,6 +1224,7 @@ impl DBusNetworkManager {
         Ok(())
     }
 
+    #[cfg(feature = "wifi")]
     pub fn request_access_point_scan(&self, path: &str) -> Result<()> {
         let options: VariantMap = HashMap::new();
         self.dbus.call_with_args(
@@ -408,36 +1237,82 @@ impl DBusNetworkManager {
         Ok(())
     }
 
+    /// Whether the driver of the Wi-Fi device supports AP mode.
+    #[cfg(feature = "wifi")]
+    #[cfg(feature = "hotspot")]
+    pub fn is_device_ap_capable(&self, path: &str) -> Result<bool> {
+        let capabilities: u32 =
+            self.dbus
+                .property(path, NM_WIRELESS_INTERFACE, "WirelessCapabilities")?;
+
+        Ok(capabilities & NM_WIFI_DEVICE_CAP_AP != 0)
+    }
+
+    #[cfg(feature = "wifi")]
     pub fn get_device_access_points(&self, path: &str) -> Result<Vec<String>> {
         self.dbus
             .property(path, NM_WIRELESS_INTERFACE, "AccessPoints")
+            .map(path_strings)
+    }
+
+    #[cfg(feature = "wifi")]
+    pub fn get_device_active_access_point(&self, path: &str) -> Option<String> {
+        self.dbus
+            .property(path, NM_WIRELESS_INTERFACE, "ActiveAccessPoint")
+            .ok()
+            .and_then(non_empty_path)
     }
 
+    #[cfg(feature = "wifi")]
     pub fn get_access_point_ssid(&self, path: &str) -> Option<Ssid> {
-        if let Ok(ssid_vec) = self
-            .dbus
-            .property::<Vec<u8>>(path, NM_ACCESS_POINT_INTERFACE, "Ssid")
-        {
-            Ssid::from_bytes(ssid_vec).ok()
-        } else {
-            None
-        }
+        // Invalid SSIDs are rejected before the bytes are copied
+        self.dbus
+            .property_ref(path, NM_ACCESS_POINT_INTERFACE, "Ssid", |value| {
+                Ssid::from_bytes(ref_arg_bytes(&*value.0)?).ok()
+            })
+            .ok()
     }
 
+    #[cfg(feature = "wifi")]
     pub fn get_access_point_strength(&self, path: &str) -> Result<u32> {
         self.dbus
             .property(path, NM_ACCESS_POINT_INTERFACE, "Strength")
     }
 
+    #[cfg(feature = "wifi")]
+    pub fn get_access_point_hw_address(&self, path: &str) -> Result<String> {
+        self.dbus
+            .property(path, NM_ACCESS_POINT_INTERFACE, "HwAddress")
+    }
+
+    /// `LastSeen` is -1 for access points never seen and missing before
+    /// NetworkManager 1.2.
+    #[cfg(feature = "wifi")]
+    pub fn get_access_point_last_seen(&self, path: &str) -> Option<u32> {
+        self.dbus
+            .property::<i32>(path, NM_ACCESS_POINT_INTERFACE, "LastSeen")
+            .ok()
+            .and_then(|last_seen| u32::try_from(last_seen).ok())
+    }
+
+    #[cfg(feature = "wifi")]
+    pub fn get_access_point_frequency(&self, path: &str) -> Result<u32> {
+        self.dbus
+            .property(path, NM_ACCESS_POINT_INTERFACE, "Frequency")
+    }
+
+    #[cfg(feature = "wifi")]
     pub fn get_access_point_flags(&self, path: &str) -> Result<NM80211ApFlags> {
         self.dbus.property(path, NM_ACCESS_POINT_INTERFACE, "Flags")
     }
 
+    #[cfg(feature = "wifi")]
     pub fn get_access_point_wpa_flags(&self, path: &str) -> Result<NM80211ApSecurityFlags> {
         self.dbus
             .property(path, NM_ACCESS_POINT_INTERFACE, "WpaFlags")
     }
 
+    #[cfg(feature = "wifi")]
     pub fn get_access_point_rsn_flags(&self, path: &str) -> Result<NM80211ApSecurityFlags> {
         self.dbus
             .property(path, NM_ACCESS_POINT_INTERFACE, "RsnFlags")
@@ -446,7 +1321,13 @@ impl DBusNetworkManager {
 
 impl VariantTo<DeviceType> for DBusApi {
     fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<DeviceType> {
-        value.0.as_i64().map(DeviceType::from)
+        let device_type = DeviceType::from(value.0.as_i64()?);
+
+        if let DeviceType::Unknown(device_type @ 1..=u32::MAX) = device_type {
+            warn!("Undefined device type: {}", device_type);
+        }
+
+        Some(device_type)
     }
 }
 
@@ -456,6 +1337,7 @@ impl VariantTo<DeviceState> for DBusApi {
     }
 }
 
+#[cfg(feature = "wifi")]
 impl VariantTo<NM80211ApFlags> for DBusApi {
     fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<NM80211ApFlags> {
         value
@@ -465,6 +1347,7 @@ impl VariantTo<NM80211ApFlags> for DBusApi {
     }
 }
 
+#[cfg(feature = "wifi")]
 impl VariantTo<NM80211ApSecurityFlags> for DBusApi {
     fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<NM80211ApSecurityFlags> {
         value
@@ -474,6 +1357,96 @@ impl VariantTo<NM80211ApSecurityFlags> for DBusApi {
     }
 }
 
+impl VariantTo<Vec<IpAddress>> for DBusApi {
+    fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<Vec<IpAddress>> {
+        let mut result = Vec::new();
+
+        for entry in value.0.as_iter()? {
+            let address = dict_get(entry, "address")?.as_str()?.parse().ok()?;
+            let prefix = dict_get(entry, "prefix")?.as_i64()? as u32;
+
+            result.push(IpAddress { address, prefix });
+        }
+
+        Some(result)
+    }
+}
+
+/// Decodes both the `aa{sv}` IPv4 `NameserverData` and the `aay` IPv6
+/// `Nameservers` representations.
+impl VariantTo<Vec<IpAddr>> for DBusApi {
+    fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<Vec<IpAddr>> {
+        let mut result = Vec::new();
+
+        for entry in value.0.as_iter()? {
+            if let Some(address) = dict_get(entry, "address") {
+                result.push(address.as_str()?.parse().ok()?);
+            } else {
+                let mut octets = [0_u8; 16];
+                let mut len = 0;
+
+                for octet in entry.as_iter()? {
+                    if len == octets.len() {
+                        return None;
+                    }
+                    octets[len] = octet.as_i64()? as u8;
+                    len += 1;
+                }
+
+                if len != octets.len() {
+                    return None;
+                }
+
+                result.push(IpAddr::V6(Ipv6Addr::from(octets)));
+            }
+        }
+
+        Some(result)
+    }
+}
+
+impl VariantTo<Vec<LldpNeighbor>> for DBusApi {
+    fn variant_to(value: &Variant<Box<dyn RefArg>>) -> Option<Vec<LldpNeighbor>> {
+        let string = |entry: &dyn RefArg, key: &str| -> Option<String> {
+            dict_get(entry, key).and_then(|v| v.as_str().map(|s| s.to_string()))
+        };
+
+        let number = |entry: &dyn RefArg, key: &str| -> Option<u32> {
+            dict_get(entry, key).and_then(|v| v.as_i64().map(|n| n as u32))
+        };
+
+        let mut result = Vec::new();
+
+        for entry in value.0.as_iter()? {
+            let mut vlans = Vec::new();
+
+            if let Some(list) = dict_get(entry, "ieee-802-1-vlans").and_then(|v| v.as_iter()) {
+                for vlan in list {
+                    vlans.push(LldpVlan {
+                        vid: number(vlan, "vid")?,
+                        name: string(vlan, "name").unwrap_or_default(),
+                    });
+                }
+            }
+
+            result.push(LldpNeighbor {
+                chassis_id: string(entry, "chassis-id")?,
+                chassis_id_type: number(entry, "chassis-id-type")?,
+                port_id: string(entry, "port-id")?,
+                port_id_type: number(entry, "port-id-type")?,
+                port_description: string(entry, "port-description"),
+                system_name: string(entry, "system-name"),
+                system_description: string(entry, "system-description"),
+                pvid: number(entry, "ieee-802-1-pvid"),
+                vlans,
+            });
+        }
+
+        Some(result)
+    }
+}
+
+#[cfg(feature = "wifi")]
 pub fn add_val<K, V>(map: &mut VariantMap, key: K, value: V)
 where
     K: Into<String>,
@@ -482,6 +1455,7 @@ where
     map.insert(key.into(), Variant(Box::new(value)));
 }
 
+#[cfg(feature = "wifi")]
 pub fn add_str<K, V>(map: &mut VariantMap, key: K, value: V)
 where
     K: Into<String>,
@@ -490,23 +1464,151 @@ where
     map.insert(key.into(), Variant(Box::new(value.into())));
 }
 
-fn verify_ascii_password(password: &str) -> Result<&str> {
-    match AsciiStr::from_ascii(password) {
-        Err(e) => Err(e).chain_err(|| ErrorKind::PreSharedKey("Not an ASCII password".into())),
-        Ok(p) => {
-            if p.len() < 8 {
-                bail!(ErrorKind::PreSharedKey(format!(
-                    "Password length should be at least 8 characters: {} len",
-                    p.len()
-                )))
-            } else if p.len() > 64 {
-                bail!(ErrorKind::PreSharedKey(format!(
-                    "Password length should not exceed 64: {} len",
-                    p.len()
-                )))
-            } else {
-                Ok(password)
+pub fn settings_from_dict(
+    dict: HashMap<String, HashMap<String, Variant<Box<dyn RefArg>>>>,
+) -> BTreeMap<String, Setting> {
+    let mut settings = BTreeMap::new();
+
+    for (name, setting) in dict {
+        let mut values = Setting::new();
+
+        for (key, value) in setting {
+            match ref_arg_to_value(&value) {
+                Some(value) => {
+                    values.insert(key, value);
+                }
+                None => debug!("Skipping unsupported setting {}.{}", name, key),
             }
         }
+
+        settings.insert(name, values);
+    }
+
+    settings
+}
+
+pub fn profile_to_settings(profile: &ConnectionProfile) -> HashMap<String, VariantMap> {
+    profile
+        .settings()
+        .iter()
+        .map(|(name, setting)| (name.clone(), dict_to_variant_map(setting)))
+        .collect()
+}
+
+/// The `a{sa{sv}}` settings of a connection as read by `GetSettings`.
+type SettingsDict<'a> = Dict<'a, &'a str, Dict<'a, &'a str, Variant<Iter<'a>>, Iter<'a>>, Iter<'a>>;
+
+fn connection_settings_from_dict(dict: SettingsDict) -> Result<ConnectionSettings> {
+    let mut kind = String::new();
+    let mut id = String::new();
+    let mut uuid = String::new();
+    let mut ssid = Ssid::new();
+    let mut mode = String::new();
+    let mut timestamp = None;
+
+    for (_, v1) in dict {
+        for (k2, mut v2) in v1 {
+            match k2 {
+                "id" => {
+                    id = extract::<String>(&mut v2)?;
+                }
+                "uuid" => {
+                    uuid = extract::<String>(&mut v2)?;
+                }
+                "type" => {
+                    kind = extract::<String>(&mut v2)?;
+                }
+                "ssid" => {
+                    ssid = Ssid::from_bytes(variant_iter_to_vec_u8(&mut v2)?)?;
+                }
+                "mode" => {
+                    mode = extract::<String>(&mut v2)?;
+                }
+                "timestamp" => {
+                    timestamp = parse_timestamp(&mut v2);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(ConnectionSettings {
+        kind,
+        id,
+        uuid,
+        ssid,
+        mode,
+        timestamp,
+    })
+}
+
+/// `connection.timestamp` is informational, so a malformed one is left out
+/// rather than failing the whole profile.
+fn parse_timestamp(value: &mut Variant<Iter>) -> Option<u64> {
+    match value.0.get::<u64>() {
+        Some(0) => None,
+        Some(secs) => Some(secs),
+        None => {
+            debug!("Ignoring malformed connection timestamp: {:?}", value);
+            None
+        }
+    }
+}
+
+fn non_empty_path(path: Path) -> Option<String> {
+    if &*path == "/" {
+        None
+    } else {
+        Some(path.to_string())
+    }
+}
+
+fn path_strings(paths: Vec<Path>) -> Vec<String> {
+    paths.iter().map(|path| path.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use dbus::Message;
+
+    fn settings_message(timestamp: Box<dyn RefArg>) -> Message {
+        let mut connection: HashMap<&str, Variant<Box<dyn RefArg>>> = HashMap::new();
+        connection.insert("id", Variant(Box::new("office".to_string())));
+        connection.insert("type", Variant(Box::new("802-3-ethernet".to_string())));
+        connection.insert("timestamp", Variant(timestamp));
+
+        let mut settings = HashMap::new();
+        settings.insert("connection", connection);
+
+        Message::new_signal("/", NM_CONNECTION_INTERFACE, "Settings")
+            .unwrap()
+            .append1(settings)
+    }
+
+    fn parse(message: &Message) -> Result<ConnectionSettings> {
+        connection_settings_from_dict(message.get1().unwrap())
+    }
+
+    #[test]
+    fn test_connection_settings_timestamp() {
+        let settings = parse(&settings_message(Box::new(1_700_000_000u64))).unwrap();
+        assert_eq!(settings.id, "office");
+        assert_eq!(settings.kind, "802-3-ethernet");
+        assert_eq!(settings.timestamp, Some(1_700_000_000));
+
+        let never = parse(&settings_message(Box::new(0u64))).unwrap();
+        assert_eq!(never.timestamp, None);
+    }
+
+    #[test]
+    fn test_connection_settings_malformed_timestamp() {
+        let settings = parse(&settings_message(Box::new("yesterday".to_string()))).unwrap();
+        assert_eq!(settings.id, "office");
+        assert_eq!(settings.timestamp, None);
+
+        let settings = parse(&settings_message(Box::new(-1i64))).unwrap();
+        assert_eq!(settings.timestamp, None);
     }
 }
@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use dbus::arg::RefArg;
+use dbus::Connection as DBusConnection;
+use dbus::Path;
+
+use network_manager_types::Value;
+
+#[cfg(feature = "wifi")]
+use dbus_api::dict_to_variant_map;
+use dbus_api::{Bus, DBusApi, DryRunPlan};
+use errors::*;
+#[cfg(feature = "hotspot")]
+use hotspot::HotspotClient;
+
+const WPAS_SERVICE: &str = "fi.w1.wpa_supplicant1";
+
+const WPAS_PATH: &str = "/fi/w1/wpa_supplicant1";
+
+const WPAS_INTERFACE: &str = "fi.w1.wpa_supplicant1";
+const WPAS_IFACE_INTERFACE: &str = "fi.w1.wpa_supplicant1.Interface";
+const WPAS_NETWORK_INTERFACE: &str = "fi.w1.wpa_supplicant1.Network";
+#[cfg(feature = "wifi")]
+const WPAS_WPS_INTERFACE: &str = "fi.w1.wpa_supplicant1.Interface.WPS";
+#[cfg(feature = "hotspot")]
+const WPAS_STA_INTERFACE: &str = "fi.w1.wpa_supplicant1.Station";
+
+const METHOD_RETRY_ERROR_NAMES: &[&str; 0] = &[];
+
+pub struct DBusSupplicant {
+    dbus: DBusApi,
+}
+
+impl DBusSupplicant {
+    pub fn with_connection(
+        connection: Rc<DBusConnection>,
+        bus: Bus,
+        dry_run: Option<DryRunPlan>,
+    ) -> Self {
+        DBusSupplicant {
+            dbus: DBusApi::with_connection(
+                connection,
+                bus,
+                WPAS_SERVICE,
+                METHOD_RETRY_ERROR_NAMES,
+                None,
+            )
+            .dry_run(dry_run),
+        }
+    }
+
+    pub fn bus(&self) -> Bus {
+        self.dbus.bus()
+    }
+
+    pub fn get_interface(&self, ifname: &str) -> Result<String> {
+        let response = self.dbus.call_with_args(
+            WPAS_PATH,
+            WPAS_INTERFACE,
+            "GetInterface",
+            &[&ifname.to_string() as &dyn RefArg],
+        )?;
+
+        let path: Path = self.dbus.extract(&response)?;
+
+        Ok(path.to_string())
+    }
+
+    pub fn get_country(&self, ifname: &str) -> Result<String> {
+        let path = self.get_interface(ifname)?;
+
+        self.dbus.property(&path, WPAS_IFACE_INTERFACE, "Country")
+    }
+
+    /// Whether the interface supports the given key management, e.g. `sae`,
+    /// according to its `Capabilities`.
+    #[cfg(feature = "hotspot")]
+    pub fn supports_key_mgmt(&self, ifname: &str, key_mgmt: &str) -> Result<bool> {
+        self.has_capability(ifname, "KeyMgmt", key_mgmt)
+    }
+
+    /// Whether the interface supports the given mode, e.g. `ap`, according to
+    /// its `Capabilities`.
+    #[cfg(feature = "hotspot")]
+    pub fn supports_mode(&self, ifname: &str, mode: &str) -> Result<bool> {
+        self.has_capability(ifname, "Modes", mode)
+    }
+
+    #[cfg(feature = "hotspot")]
+    fn has_capability(&self, ifname: &str, name: &str, value: &str) -> Result<bool> {
+        let path = self.get_interface(ifname)?;
+
+        let capabilities: HashMap<String, Value> =
+            self.dbus
+                .property(&path, WPAS_IFACE_INTERFACE, "Capabilities")?;
+
+        Ok(match capabilities.get(name) {
+            Some(Value::Strings(values)) => values.iter().any(|v| v == value),
+            _ => false,
+        })
+    }
+
+    /// The stations associated with the interface while it is in AP mode.
+    /// Needs wpa_supplicant 2.10 or later, earlier versions do not export
+    /// stations.
+    #[cfg(feature = "hotspot")]
+    pub fn get_stations(&self, ifname: &str) -> Result<Vec<HotspotClient>> {
+        let path = self.get_interface(ifname)?;
+
+        let stations: Vec<Path> = self
+            .dbus
+            .property(&path, WPAS_IFACE_INTERFACE, "Stations")?;
+
+        let mut clients = Vec::new();
+
+        for station in stations {
+            let address: Vec<u8> = self
+                .dbus
+                .property(&station, WPAS_STA_INTERFACE, "Address")?;
+
+            let counter = |name| self.dbus.property(&station, WPAS_STA_INTERFACE, name).ok();
+
+            clients.push(HotspotClient {
+                mac: address
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<Vec<_>>()
+                    .join(":"),
+                address: None,
+                hostname: None,
+                rx_bytes: counter("RxBytes"),
+                rx_packets: counter("RxPackets"),
+                tx_bytes: counter("TxBytes"),
+                tx_packets: counter("TxPackets"),
+            });
+        }
+
+        Ok(clients)
+    }
+
+    /// The network the interface is associated with, if any.
+    pub fn get_current_network(&self, ifname: &str) -> Result<Option<String>> {
+        let path = self.get_interface(ifname)?;
+
+        let network: Path = self
+            .dbus
+            .property(&path, WPAS_IFACE_INTERFACE, "CurrentNetwork")?;
+
+        Ok(if &*network == "/" {
+            None
+        } else {
+            Some(network.to_string())
+        })
+    }
+
+    /// A setting of a configured network, e.g. `bgscan`, without the quotes
+    /// of string settings.
+    pub fn get_network_setting(&self, network: &str, key: &str) -> Result<Option<String>> {
+        let properties: HashMap<String, Value> =
+            self.dbus
+                .property(network, WPAS_NETWORK_INTERFACE, "Properties")?;
+
+        Ok(properties
+            .get(key)
+            .and_then(Value::as_str)
+            .map(|value| value.trim_matches('"').to_string()))
+    }
+
+    pub fn set_network_setting(&self, network: &str, key: &str, value: &str) -> Result<()> {
+        let mut properties = BTreeMap::new();
+        properties.insert(key.to_string(), Value::String(value.to_string()));
+
+        self.dbus.set_property(
+            network,
+            WPAS_NETWORK_INTERFACE,
+            "Properties",
+            Value::Dict(properties),
+        )
+    }
+
+    /// The log level of wpa_supplicant, shared by all its interfaces.
+    pub fn get_debug_level(&self) -> Result<String> {
+        self.dbus.property(WPAS_PATH, WPAS_INTERFACE, "DebugLevel")
+    }
+
+    pub fn set_debug_level(&self, level: &str) -> Result<()> {
+        self.dbus
+            .set_property(WPAS_PATH, WPAS_INTERFACE, "DebugLevel", level)
+    }
+
+    pub fn set_country(&self, ifname: &str, country: &str) -> Result<()> {
+        let path = self.get_interface(ifname)?;
+
+        self.dbus
+            .set_property(&path, WPAS_IFACE_INTERFACE, "Country", country)
+    }
+
+    /// Starts WPS enrollment of the interface at `path` with the access point
+    /// `bssid`, using `pin`, which has to be entered on the access point.
+    #[cfg(feature = "wifi")]
+    pub fn start_wps_pin(&self, path: &str, bssid: &[u8], pin: &str) -> Result<()> {
+        let mut args = BTreeMap::new();
+        args.insert("Role".to_string(), Value::String("enrollee".into()));
+        args.insert("Type".to_string(), Value::String("pin".into()));
+        args.insert("Pin".to_string(), Value::String(pin.into()));
+        args.insert("Bssid".to_string(), Value::Bytes(bssid.to_vec()));
+
+        self.dbus.call_with_args(
+            path,
+            WPAS_WPS_INTERFACE,
+            "Start",
+            &[&dict_to_variant_map(&args) as &dyn RefArg],
+        )?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "wifi")]
+    pub fn cancel_wps(&self, path: &str) -> Result<()> {
+        self.dbus.call(path, WPAS_WPS_INTERFACE, "Cancel")?;
+
+        Ok(())
+    }
+}
@@ -1,10 +1,29 @@
+use std::collections::HashMap;
 use std::fmt;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
+use network_manager_types::{DeviceType, Value};
+
+#[cfg(feature = "events")]
+use carrier::{watch_carrier, CarrierEvent};
 use dbus_nm::DBusNetworkManager;
+use device_watch::wait_for_device_path;
 use errors::*;
-
+use ip_config::IpConfig;
+#[cfg(feature = "events")]
+use ip_watch::{watch_ip_config, IpConfigEvent};
+use lldp::LldpNeighbor;
+use restore::{Restore, Undo};
+use settings::ConnectionProfile;
+use signal_wait::{signal_rule, wait_for_signal};
+
+#[cfg(feature = "wifi")]
 use wifi::{new_wifi_device, WiFiDevice};
+#[cfg(feature = "wwan")]
+use wwan::{new_modem_device, ModemDevice};
+
+const NM_DEVICE_INTERFACE: &str = "org.freedesktop.NetworkManager.Device";
 
 #[derive(Clone)]
 pub struct Device {
@@ -36,11 +55,92 @@ impl Device {
         &self.interface
     }
 
+    /// The kernel interface index, as used by netlink. `0` for devices
+    /// without a kernel interface, such as modems before they connect.
+    pub fn ifindex(&self) -> Result<u32> {
+        self.dbus_manager.get_device_ifindex(&self.path)
+    }
+
+    /// The unique device identifier, usually the sysfs path for devices
+    /// backed by hardware, e.g. `/sys/devices/pci0000:00/.../net/eth0`.
+    pub fn udi(&self) -> Result<String> {
+        self.dbus_manager.get_device_udi(&self.path)
+    }
+
     pub fn get_state(&self) -> Result<DeviceState> {
         self.dbus_manager.get_device_state(&self.path)
     }
 
-    pub fn as_wifi_device(&self) -> Option<WiFiDevice> {
+    /// The kernel interface carrying IP traffic, which differs from
+    /// `interface` for e.g. modems, and is empty while there is none.
+    pub fn get_ip_interface(&self) -> Result<String> {
+        self.dbus_manager.get_device_ip_interface(&self.path)
+    }
+
+    pub fn get_ip4_config(&self) -> Result<Option<IpConfig>> {
+        self.dbus_manager.get_device_ip4_config(&self.path)
+    }
+
+    pub fn get_ip6_config(&self) -> Result<Option<IpConfig>> {
+        self.dbus_manager.get_device_ip6_config(&self.path)
+    }
+
+    /// Block watching the IPv4 and IPv6 configuration of the device,
+    /// invoking `callback` on each change until it returns `false`, e.g. to
+    /// rebind sockets after a new DHCP lease.
+    #[cfg(feature = "events")]
+    pub fn watch_ip_config<F>(&self, callback: F) -> Result<()>
+    where
+        F: FnMut(IpConfigEvent) -> bool,
+    {
+        watch_ip_config(&self.dbus_manager, &self.path, callback)
+    }
+
+    /// Get the settings applied by the active connection, along with the
+    /// version `reapply` expects. Secrets are not included.
+    pub fn get_applied_connection(&self) -> Result<(ConnectionProfile, u64)> {
+        self.dbus_manager.get_device_applied_connection(&self.path)
+    }
+
+    /// Apply changed settings, e.g. a route metric, to the active connection
+    /// without reactivating it. The stored profile is left alone, so the
+    /// change lasts until the next activation. Not all settings can be
+    /// changed this way.
+    pub fn reapply(&self, profile: &ConnectionProfile, version: u64) -> Result<()> {
+        self.dbus_manager
+            .reapply_device(&self.path, profile, version)
+    }
+
+    /// Get the options received from the DHCPv4 server, empty if the device
+    /// was not configured by DHCP.
+    pub fn get_dhcp4_options(&self) -> Result<HashMap<String, Value>> {
+        self.dbus_manager.get_device_dhcp4_options(&self.path)
+    }
+
+    /// Get the options received from the DHCPv6 server, empty if the device
+    /// was not configured by DHCP.
+    pub fn get_dhcp6_options(&self) -> Result<HashMap<String, Value>> {
+        self.dbus_manager.get_device_dhcp6_options(&self.path)
+    }
+
+    /// Get the neighbors discovered through LLDP. LLDP must be enabled on the
+    /// active connection (`connection.lldp`) for neighbors to be reported.
+    pub fn get_lldp_neighbors(&self) -> Result<Vec<LldpNeighbor>> {
+        self.dbus_manager.get_device_lldp_neighbors(&self.path)
+    }
+
+    /// Get the kernel driver and firmware details of the device.
+    pub fn get_driver_info(&self) -> Result<DriverInfo> {
+        Ok(DriverInfo {
+            driver: self.dbus_manager.get_device_driver(&self.path)?,
+            driver_version: self.dbus_manager.get_device_driver_version(&self.path)?,
+            firmware_version: self.dbus_manager.get_device_firmware_version(&self.path)?,
+            firmware_missing: self.dbus_manager.is_device_firmware_missing(&self.path)?,
+        })
+    }
+
+    #[cfg(feature = "wifi")]
+    pub fn as_wifi_device(&self) -> Option<WiFiDevice<'_>> {
         if self.device_type == DeviceType::WiFi {
             Some(new_wifi_device(&self.dbus_manager, self))
         } else {
@@ -48,6 +148,15 @@ impl Device {
         }
     }
 
+    #[cfg(feature = "wwan")]
+    pub fn as_modem_device(&self) -> Option<ModemDevice<'_>> {
+        if self.device_type == DeviceType::Modem {
+            Some(new_modem_device(&self.dbus_manager, self))
+        } else {
+            None
+        }
+    }
+
     /// Connects a Network Manager device.
     pub fn connect(&self) -> Result<DeviceState> {
         let state = self.get_state()?;
@@ -57,13 +166,132 @@ impl Device {
             _ => {
                 self.dbus_manager.connect_device(&self.path)?;
 
-                wait(
+                let state = wait(
                     self,
                     &DeviceState::Activated,
-                    self.dbus_manager.method_timeout(),
-                )
+                    self.dbus_manager.wait_deadline(),
+                )?;
+
+                if state == DeviceState::Activated {
+                    self.dbus_manager.activation_succeeded(&self.path);
+                }
+
+                Ok(state)
+            }
+        }
+    }
+
+    /// Check whether the device can activate connections and if not, why.
+    pub fn availability(&self) -> Result<Availability> {
+        let reason = match self.get_state()? {
+            DeviceState::Unmanaged => UnavailableReason::Unmanaged,
+            DeviceState::Unknown | DeviceState::Unavailable => self.unavailable_reason()?,
+            _ => return Ok(Availability::Available),
+        };
+
+        Ok(Availability::Unavailable(reason))
+    }
+
+    fn unavailable_reason(&self) -> Result<UnavailableReason> {
+        let manager = &self.dbus_manager;
+
+        if manager.is_device_firmware_missing(&self.path)? {
+            return Ok(UnavailableReason::FirmwareMissing);
+        }
+
+        if manager.is_device_plugin_missing(&self.path)? {
+            return Ok(UnavailableReason::PluginMissing);
+        }
+
+        if !manager.is_device_managed(&self.path)? {
+            return Ok(UnavailableReason::Unmanaged);
+        }
+
+        match self.device_type {
+            DeviceType::WiFi => {
+                if !manager.is_wireless_hardware_enabled()? {
+                    return Ok(UnavailableReason::Rfkill { hardware: true });
+                }
+
+                if !manager.is_wireless_enabled()? {
+                    return Ok(UnavailableReason::Rfkill { hardware: false });
+                }
             }
+            DeviceType::Ethernet if !manager.get_device_carrier(&self.path)? => {
+                return Ok(UnavailableReason::NoCarrier);
+            }
+            _ => {}
+        }
+
+        Ok(UnavailableReason::Other(
+            manager.get_device_state_reason(&self.path)?,
+        ))
+    }
+
+    /// Block watching the link of an Ethernet device, invoking `callback` on
+    /// each carrier change until it returns `false`. Changes are reported
+    /// once the link stayed up or down for `debounce`.
+    #[cfg(feature = "events")]
+    pub fn watch_carrier<F>(&self, debounce: Duration, callback: F) -> Result<()>
+    where
+        F: FnMut(CarrierEvent) -> bool,
+    {
+        if self.device_type != DeviceType::Ethernet {
+            bail!(ErrorKind::NetworkManager(format!(
+                "{} is not an Ethernet device",
+                self.interface
+            )));
         }
+
+        watch_carrier(&self.dbus_manager, &self.path, debounce, callback)
+    }
+
+    /// Whether the device may activate connections on its own.
+    pub fn get_autoconnect(&self) -> Result<bool> {
+        self.dbus_manager.get_device_autoconnect(&self.path)
+    }
+
+    /// Allow or prevent the device from activating connections on its own.
+    pub fn set_autoconnect(&self, autoconnect: bool) -> Result<()> {
+        self.dbus_manager
+            .set_device_autoconnect(&self.path, autoconnect)
+    }
+
+    /// Keep the device from activating connections on its own until the
+    /// returned inhibitor is dropped, see `AutoconnectInhibitor`.
+    pub fn inhibit_autoconnect(&self) -> Result<AutoconnectInhibitor> {
+        AutoconnectInhibitor::new(self)
+    }
+
+    /// Hand the device to or take it away from Network Manager.
+    pub fn set_managed(&self, managed: bool) -> Result<()> {
+        self.dbus_manager.set_device_managed(&self.path, managed)
+    }
+
+    /// Set how often the device traffic counters are refreshed, 0 disables
+    /// them.
+    pub fn set_statistics_refresh_rate(&self, rate_ms: u32) -> Result<()> {
+        self.dbus_manager
+            .set_device_statistics_refresh_rate(&self.path, rate_ms)
+    }
+
+    /// Turn the traffic counters of the device on with `refresh_rate_ms` if
+    /// they are off, until the returned guard is dropped, see
+    /// `StatisticsGuard`.
+    pub fn enable_statistics(&self, refresh_rate_ms: u32) -> Result<StatisticsGuard> {
+        StatisticsGuard::new(self, refresh_rate_ms)
+    }
+
+    /// Get the traffic counters of the device. They are only updated while
+    /// a statistics refresh rate is set.
+    pub fn get_statistics(&self) -> Result<DeviceStatistics> {
+        Ok(DeviceStatistics {
+            refresh_rate_ms: self
+                .dbus_manager
+                .get_device_statistics_refresh_rate(&self.path)?,
+            tx_bytes: self.dbus_manager.get_device_tx_bytes(&self.path)?,
+            rx_bytes: self.dbus_manager.get_device_rx_bytes(&self.path)?,
+        })
     }
 
     /// Disconnect a Network Manager device.
@@ -78,7 +306,7 @@ impl Device {
                 wait(
                     self,
                     &DeviceState::Disconnected,
-                    self.dbus_manager.method_timeout(),
+                    self.dbus_manager.wait_deadline(),
                 )
             }
         }
@@ -95,98 +323,187 @@ impl fmt::Debug for Device {
     }
 }
 
-pub trait PathGetter {
-    fn path(&self) -> &str;
+/// Keeps a device from activating connections on its own, e.g. while a
+/// script reconfigures it, so that NetworkManager does not bring up another
+/// connection in between. The previous setting is restored when the
+/// inhibitor is dropped.
+///
+/// Connections activated explicitly, e.g. with `Connection::activate_on`,
+/// are not affected.
+pub struct AutoconnectInhibitor {
+    device: Device,
+    restore: Restore<AutoconnectChange>,
 }
 
-impl PathGetter for Device {
-    fn path(&self) -> &str {
-        &self.path
+impl AutoconnectInhibitor {
+    pub fn new(device: &Device) -> Result<Self> {
+        let autoconnect = device.get_autoconnect()?;
+
+        device.set_autoconnect(false)?;
+
+        debug!(
+            "Autoconnect of {} inhibited, was {}",
+            device.interface, autoconnect
+        );
+
+        Ok(AutoconnectInhibitor {
+            device: device.clone(),
+            restore: Restore::new(AutoconnectChange {
+                device: device.clone(),
+                autoconnect,
+            }),
+        })
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Restores the previous setting, reporting failures that are only
+    /// logged when the inhibitor is dropped.
+    pub fn release(self) -> Result<()> {
+        self.restore.release()
     }
 }
 
+#[derive(Debug)]
+struct AutoconnectChange {
+    device: Device,
+    autoconnect: bool,
+}
+
+impl Undo for AutoconnectChange {
+    fn description(&self) -> String {
+        format!("restore autoconnect of {}", self.device.interface)
+    }
+
+    fn undo(self) -> Result<()> {
+        self.device.set_autoconnect(self.autoconnect)
+    }
+}
+
+/// Keeps the traffic counters of a device on while they are sampled. Counters
+/// that were off are turned off again when the guard is dropped, counters
+/// someone else turned on are left alone.
+#[derive(Debug)]
+pub struct StatisticsGuard {
+    device: Device,
+    restore: Restore<StatisticsChange>,
+}
+
+impl StatisticsGuard {
+    pub fn new(device: &Device, refresh_rate_ms: u32) -> Result<Self> {
+        let enabled = device
+            .dbus_manager
+            .get_device_statistics_refresh_rate(&device.path)?
+            == 0;
+
+        let restore = if enabled {
+            debug!("Enabling traffic counters of {}", device.interface);
+            device.set_statistics_refresh_rate(refresh_rate_ms)?;
+
+            Restore::new(StatisticsChange {
+                device: device.clone(),
+            })
+        } else {
+            Restore::unchanged()
+        };
+
+        Ok(StatisticsGuard {
+            device: device.clone(),
+            restore,
+        })
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Turns the counters off again if they were off, reporting failures
+    /// that are only logged when the guard is dropped.
+    pub fn release(self) -> Result<()> {
+        self.restore.release()
+    }
+}
+
+/// Traffic counters that were turned on.
+#[derive(Debug)]
+struct StatisticsChange {
+    device: Device,
+}
+
+impl Undo for StatisticsChange {
+    fn description(&self) -> String {
+        format!("disable traffic counters of {}", self.device.interface)
+    }
+
+    fn undo(self) -> Result<()> {
+        debug!("Disabling traffic counters of {}", self.device.interface);
+        self.device.set_statistics_refresh_rate(0)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DriverInfo {
+    pub driver: String,
+    pub driver_version: String,
+    pub firmware_version: String,
+    pub firmware_missing: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DeviceStatistics {
+    /// How often the counters are refreshed, 0 if they are disabled.
+    pub refresh_rate_ms: u32,
+    pub tx_bytes: u64,
+    pub rx_bytes: u64,
+}
+
 #[derive(Clone, Debug, PartialEq)]
-pub enum DeviceType {
-    Unknown,
-    Ethernet,
-    WiFi,
-    Unused1,
-    Unused2,
-    Bt,
-    OlpcMesh,
-    Wimax,
-    Modem,
-    Infiniband,
-    Bond,
-    Vlan,
-    Adsl,
-    Bridge,
-    Generic,
-    Team,
-    Tun,
-    IpTunnel,
-    Macvlan,
-    Vxlan,
-    Veth,
-    Macsec,
-    Dummy,
-    Ppp,
-    OvsInterface,
-    OvsPort,
-    OvsBridge,
-    Wpan,
-    Lowpan,
-    Wireguard,
-    WifiP2p,
-    Vrf,
-    LoopBack,
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Availability {
+    Available,
+    Unavailable(UnavailableReason),
 }
 
-impl From<i64> for DeviceType {
-    fn from(device_type: i64) -> Self {
-        match device_type {
-            0 => DeviceType::Unknown,
-            1 => DeviceType::Ethernet,
-            2 => DeviceType::WiFi,
-            3 => DeviceType::Unused1,
-            4 => DeviceType::Unused2,
-            5 => DeviceType::Bt,
-            6 => DeviceType::OlpcMesh,
-            7 => DeviceType::Wimax,
-            8 => DeviceType::Modem,
-            9 => DeviceType::Infiniband,
-            10 => DeviceType::Bond,
-            11 => DeviceType::Vlan,
-            12 => DeviceType::Adsl,
-            13 => DeviceType::Bridge,
-            14 => DeviceType::Generic,
-            15 => DeviceType::Team,
-            16 => DeviceType::Tun,
-            17 => DeviceType::IpTunnel,
-            18 => DeviceType::Macvlan,
-            19 => DeviceType::Vxlan,
-            20 => DeviceType::Veth,
-            21 => DeviceType::Macsec,
-            22 => DeviceType::Dummy,
-            23 => DeviceType::Ppp,
-            24 => DeviceType::OvsInterface,
-            25 => DeviceType::OvsPort,
-            26 => DeviceType::OvsBridge,
-            27 => DeviceType::Wpan,
-            28 => DeviceType::Lowpan,
-            29 => DeviceType::Wireguard,
-            30 => DeviceType::WifiP2p,
-            31 => DeviceType::Vrf,
-            32 => DeviceType::LoopBack,  // Add LoopBack device type
-            _ => {
-                warn!("Undefined device type: {}", device_type);
-                DeviceType::Unknown
-            }
-        }
+impl Availability {
+    pub fn is_available(&self) -> bool {
+        *self == Availability::Available
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum UnavailableReason {
+    /// NetworkManager does not manage the device.
+    Unmanaged,
+    /// The radio is blocked, by a hardware switch or in software, e.g. with
+    /// `set_wireless_enabled(false)`.
+    Rfkill { hardware: bool },
+    /// No cable is plugged in.
+    NoCarrier,
+    /// The driver needs firmware that is not installed.
+    FirmwareMissing,
+    /// The NetworkManager plugin for the device type is not installed.
+    PluginMissing,
+    /// Any other cause, given as NetworkManager's `NMDeviceStateReason` code.
+    Other(u32),
+}
+
+pub trait PathGetter {
+    fn path(&self) -> &str;
+}
+
+impl PathGetter for Device {
+    fn path(&self) -> &str {
+        &self.path
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum DeviceState {
     Unknown,
     Unmanaged,
@@ -250,6 +567,40 @@ pub fn get_device_by_interface(
     Device::init(dbus_manager, &path)
 }
 
+pub fn get_device_by_path(dbus_manager: &Rc<DBusNetworkManager>, path: &str) -> Result<Device> {
+    Device::init(dbus_manager, path)
+}
+
+pub fn get_device_by_ifindex(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    ifindex: u32,
+) -> Result<Device> {
+    for path in dbus_manager.get_devices()? {
+        if dbus_manager.get_device_ifindex(&path)? == ifindex {
+            return Device::init(dbus_manager, &path);
+        }
+    }
+
+    bail!(ErrorKind::NetworkManager(format!(
+        "No device with interface index {}",
+        ifindex
+    )))
+}
+
+/// Wait for NetworkManager to add a device for `interface`, e.g. a virtual
+/// device created on activation of its connection, and with `managed` set
+/// also to manage it.
+pub fn wait_for_device(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    interface: &str,
+    managed: bool,
+    timeout: Duration,
+) -> Result<Device> {
+    let path = wait_for_device_path(dbus_manager, interface, managed, timeout)?;
+
+    Device::init(dbus_manager, &path)
+}
+
 pub fn get_active_connection_devices(
     dbus_manager: &Rc<DBusNetworkManager>,
     active_path: &str,
@@ -267,41 +618,62 @@ pub fn get_active_connection_devices(
     Ok(result)
 }
 
-fn wait(device: &Device, target_state: &DeviceState, timeout: u64) -> Result<DeviceState> {
-    if timeout == 0 {
+fn wait(device: &Device, target_state: &DeviceState, timeout: Duration) -> Result<DeviceState> {
+    if timeout == Duration::from_secs(0) {
         return device.get_state();
     }
 
     debug!("Waiting for device state: {:?}", target_state);
 
-    let mut total_time = 0;
-
-    loop {
-        ::std::thread::sleep(::std::time::Duration::from_secs(1));
+    let start = Instant::now();
 
-        let state = device.get_state()?;
+    let rules = [signal_rule(
+        NM_DEVICE_INTERFACE,
+        "StateChanged",
+        Some(&device.path),
+    )];
 
-        total_time += 1;
+    let reached = wait_for_signal(
+        device.dbus_manager.bus(),
+        &rules,
+        timeout,
+        device.dbus_manager.wait_interval(),
+        || {
+            let state = device.get_state()?;
 
-        if state == *target_state {
-            debug!(
-                "Device target state reached: {:?} / {}s elapsed",
-                state, total_time
-            );
+            if state == *target_state {
+                return Ok(Some(state));
+            }
 
-            return Ok(state);
-        } else if total_time >= timeout {
             debug!(
-                "Timeout reached in waiting for device state ({:?}): {:?} / {}s elapsed",
-                target_state, state, total_time
+                "Still waiting for device state ({:?}): {:?} / {:?} elapsed",
+                target_state,
+                state,
+                start.elapsed()
             );
 
-            return Ok(state);
-        }
+            Ok(None)
+        },
+    )?;
 
+    if let Some(state) = reached {
         debug!(
-            "Still waiting for device state ({:?}): {:?} / {}s elapsed",
-            target_state, state, total_time
+            "Device target state reached: {:?} / {:?} elapsed",
+            state,
+            start.elapsed()
         );
+
+        return Ok(state);
     }
+
+    let state = device.get_state()?;
+
+    debug!(
+        "Timeout reached in waiting for device state ({:?}): {:?} / {:?} elapsed",
+        target_state,
+        state,
+        start.elapsed()
+    );
+
+    Ok(state)
 }
@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use dbus_nm::DBusNetworkManager;
+use errors::*;
+
+use device::DeviceState;
+use signal_wait::{signal_rule, wait_for_signal};
+
+const NM_SERVICE_PATH: &str = "/org/freedesktop/NetworkManager";
+const NM_SERVICE_INTERFACE: &str = "org.freedesktop.NetworkManager";
+const NM_DEVICE_INTERFACE: &str = "org.freedesktop.NetworkManager.Device";
+
+/// Blocks until NetworkManager has a device for `interface`, returning its
+/// path, or fails once `timeout` elapsed. With `managed` set, the device also
+/// has to be managed, which NetworkManager holds off on until udev finished
+/// setting up the interface.
+///
+/// The device is looked up again whenever a device is added or changes state.
+pub fn wait_for_device_path(
+    dbus_manager: &DBusNetworkManager,
+    interface: &str,
+    managed: bool,
+    timeout: Duration,
+) -> Result<String> {
+    let mut rules = vec![signal_rule(
+        NM_SERVICE_INTERFACE,
+        "DeviceAdded",
+        Some(NM_SERVICE_PATH),
+    )];
+
+    if managed {
+        rules.push(signal_rule(NM_DEVICE_INTERFACE, "StateChanged", None));
+    }
+
+    let path = wait_for_signal(
+        dbus_manager.bus(),
+        &rules,
+        timeout,
+        dbus_manager.wait_interval(),
+        || Ok(find_device(dbus_manager, interface, managed)),
+    )?;
+
+    match path {
+        Some(path) => Ok(path),
+        None => bail!(ErrorKind::NetworkManager(format!(
+            "Device {} not {} within {:?}",
+            interface,
+            if managed { "managed" } else { "present" },
+            timeout
+        ))),
+    }
+}
+
+fn find_device(
+    dbus_manager: &DBusNetworkManager,
+    interface: &str,
+    managed: bool,
+) -> Option<String> {
+    let path = dbus_manager.get_device_by_interface(interface).ok()?;
+
+    if managed {
+        match dbus_manager.get_device_state(&path) {
+            Ok(DeviceState::Unmanaged) | Ok(DeviceState::Unknown) | Err(_) => return None,
+            Ok(_) => {}
+        }
+    }
+
+    Some(path)
+}
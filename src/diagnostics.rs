@@ -0,0 +1,193 @@
+use std::rc::Rc;
+
+#[cfg(feature = "wifi")]
+use backend::{get_wifi_backend, WifiBackend};
+use dbus_nm::DBusNetworkManager;
+use errors::*;
+use network_manager_types::DeviceType;
+
+use connection::{get_active_connections, ConnectionSettings, ConnectionState};
+use device::{get_devices, DeviceState, DriverInfo};
+use ip_config::IpConfig;
+use manager::{Connectivity, NetworkManagerState};
+#[cfg(feature = "wifi")]
+use wifi::{AccessPoint, SupplicantLogLevel};
+
+/// A point-in-time report of the NetworkManager state, intended to be attached
+/// to support requests.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Diagnostics {
+    pub version: String,
+    pub state: NetworkManagerState,
+    /// The result of the last connectivity check. No new check is started.
+    pub connectivity: Connectivity,
+    pub networking_enabled: bool,
+    pub wireless_enabled: bool,
+    pub devices: Vec<DeviceDiagnostics>,
+    pub active_connections: Vec<ActiveConnectionDiagnostics>,
+    /// The log level of wpa_supplicant, `None` with the iwd backend or if it
+    /// could not be read.
+    #[cfg(feature = "wifi")]
+    pub supplicant_log_level: Option<SupplicantLogLevel>,
+    /// What could not be read, e.g. of a device removed meanwhile. The rest
+    /// of the report is still collected.
+    pub errors: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DeviceDiagnostics {
+    pub interface: String,
+    pub device_type: DeviceType,
+    pub state: DeviceState,
+    /// `None` if it could not be read, see `Diagnostics::errors`.
+    pub driver: Option<DriverInfo>,
+    pub ip4_config: Option<IpConfig>,
+    pub ip6_config: Option<IpConfig>,
+    /// The most recent scan results of Wi-Fi devices. No new scan is requested.
+    #[cfg(feature = "wifi")]
+    pub access_points: Vec<AccessPoint>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ActiveConnectionDiagnostics {
+    pub settings: ConnectionSettings,
+    pub state: ConnectionState,
+    pub interfaces: Vec<String>,
+}
+
+pub fn get_diagnostics(dbus_manager: &Rc<DBusNetworkManager>) -> Result<Diagnostics> {
+    let mut errors = Vec::new();
+
+    let mut devices = Vec::new();
+
+    for device in get_devices(dbus_manager)? {
+        let interface = device.interface().to_string();
+
+        #[cfg(feature = "wifi")]
+        let access_points = match device.as_wifi_device() {
+            Some(wifi_device) => record(
+                &mut errors,
+                &interface,
+                "access points",
+                wifi_device.get_access_points(),
+            ),
+            None => None,
+        };
+
+        let state = record(&mut errors, &interface, "state", device.get_state());
+        let driver = record(&mut errors, &interface, "driver", device.get_driver_info());
+        let ip4_config = record(
+            &mut errors,
+            &interface,
+            "IPv4 configuration",
+            device.get_ip4_config(),
+        );
+        let ip6_config = record(
+            &mut errors,
+            &interface,
+            "IPv6 configuration",
+            device.get_ip6_config(),
+        );
+
+        devices.push(DeviceDiagnostics {
+            interface,
+            device_type: device.device_type().clone(),
+            state: state.unwrap_or(DeviceState::Unknown),
+            driver,
+            ip4_config: ip4_config.flatten(),
+            ip6_config: ip6_config.flatten(),
+            #[cfg(feature = "wifi")]
+            access_points: access_points.unwrap_or_default(),
+        });
+    }
+
+    let mut active_connections = Vec::new();
+
+    for connection in get_active_connections(dbus_manager)? {
+        let id = connection.settings().id.clone();
+
+        let interfaces = record(&mut errors, &id, "devices", connection.get_devices())
+            .unwrap_or_default()
+            .iter()
+            .map(|d| d.interface().to_string())
+            .collect();
+
+        let state = record(&mut errors, &id, "state", connection.get_state());
+
+        active_connections.push(ActiveConnectionDiagnostics {
+            settings: connection.settings().clone(),
+            state: state.unwrap_or(ConnectionState::Unknown),
+            interfaces,
+        });
+    }
+
+    #[cfg(feature = "wifi")]
+    let supplicant_log_level = match get_wifi_backend(dbus_manager.bus()) {
+        Ok(WifiBackend::WpaSupplicant) => record(
+            &mut errors,
+            "wpa_supplicant",
+            "log level",
+            dbus_manager
+                .supplicant()
+                .get_debug_level()
+                .and_then(|level| level.parse()),
+        ),
+        Ok(WifiBackend::Iwd) => None,
+        Err(e) => record(&mut errors, "NetworkManager", "Wi-Fi backend", Err(e)),
+    };
+
+    Ok(Diagnostics {
+        version: dbus_manager.get_version()?,
+        state: dbus_manager.get_state()?,
+        connectivity: dbus_manager.get_connectivity()?,
+        networking_enabled: dbus_manager.is_networking_enabled()?,
+        wireless_enabled: dbus_manager.is_wireless_enabled()?,
+        devices,
+        active_connections,
+        #[cfg(feature = "wifi")]
+        supplicant_log_level,
+        errors,
+    })
+}
+
+/// Adds the error of reading `what` of `object` to `errors`.
+fn record<T>(errors: &mut Vec<String>, object: &str, what: &str, result: Result<T>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            let chain = e
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join(": ");
+
+            errors.push(format!("{}: unable to read {}: {}", object, what, chain));
+
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let mut errors = Vec::new();
+
+        assert_eq!(record(&mut errors, "eth0", "state", Ok(20)), Some(20));
+        assert!(errors.is_empty());
+
+        let failed: Result<u32> =
+            Err(Error::from("Device removed")).chain_err(|| "Property read failed");
+        assert_eq!(record(&mut errors, "wlan0", "driver", failed), None);
+        assert_eq!(
+            errors,
+            vec!["wlan0: unable to read driver: Property read failed: Device removed".to_string()]
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use std::fmt;
+
+use errors::*;
+
+use network_manager_types::Ssid;
+use secret::SecretString;
+use settings::{Certificate, ConnectionProfile, Ieee8021xConfig};
+
+/// Common enterprise Wi-Fi setups, e.g. of eduroam and most campus networks.
+///
+/// Templates always verify the authentication server against a CA
+/// certificate and pin its certificate to the server domain, as leaving out
+/// either lets any access point with the same SSID collect the credentials.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnterpriseTemplate {
+    /// PEAP with MSCHAPv2 inner authentication, the usual choice of Active
+    /// Directory backed networks.
+    PeapMschapv2,
+    /// TTLS with PAP inner authentication, common where passwords are checked
+    /// against LDAP.
+    TtlsPap,
+}
+
+/// What users have to provide for an enterprise network.
+#[derive(Clone)]
+pub struct EnterpriseInputs {
+    pub ssid: String,
+    /// Inner identity, usually `user@realm`.
+    pub identity: String,
+    pub password: SecretString,
+    /// Outer identity, sent in the clear. `anonymous@realm` with the realm of
+    /// `identity` if not given.
+    pub anonymous_identity: Option<String>,
+    /// CA certificate the server certificate has to be issued by.
+    pub ca_cert: Certificate,
+    /// Name the server certificate has to be issued for exactly, e.g.
+    /// `radius.example.org`. Certificates of other hosts, including
+    /// subdomains, are rejected even if issued by `ca_cert`.
+    pub server_domain: String,
+}
+
+impl EnterpriseTemplate {
+    /// Builds a Wi-Fi profile named `id` for the network described by
+    /// `inputs`.
+    pub fn profile(&self, id: &str, inputs: &EnterpriseInputs) -> Result<ConnectionProfile> {
+        let ssid = Ssid::from_bytes(inputs.ssid.as_bytes())?;
+
+        let required = [
+            ("SSID", inputs.ssid.as_str()),
+            ("identity", inputs.identity.as_str()),
+            ("password", inputs.password.expose()),
+            ("server domain", inputs.server_domain.as_str()),
+        ];
+
+        for &(name, value) in &required {
+            if value.is_empty() {
+                bail!(ErrorKind::NetworkManager(format!(
+                    "Enterprise profile {:?} is missing the {}",
+                    id, name
+                )));
+            }
+        }
+
+        let config = match *self {
+            EnterpriseTemplate::PeapMschapv2 => {
+                Ieee8021xConfig::peap(&inputs.identity, inputs.password.expose())
+            }
+            EnterpriseTemplate::TtlsPap => {
+                Ieee8021xConfig::ttls(&inputs.identity, inputs.password.expose(), "pap")
+            }
+        };
+
+        let mut config = config
+            .ca_cert(inputs.ca_cert.clone())
+            .domain_match(&inputs.server_domain);
+
+        if let Some(anonymous_identity) = anonymous_identity(inputs) {
+            config = config.anonymous_identity(&anonymous_identity);
+        }
+
+        let mut profile = ConnectionProfile::new("802-11-wireless", id)
+            .with("802-11-wireless", "ssid", ssid.into_bytes())
+            .with("802-11-wireless", "mode", "infrastructure")
+            .with("802-11-wireless-security", "key-mgmt", "wpa-eap");
+
+        profile.set_8021x(&config)?;
+
+        Ok(profile)
+    }
+}
+
+fn anonymous_identity(inputs: &EnterpriseInputs) -> Option<String> {
+    if let Some(ref anonymous_identity) = inputs.anonymous_identity {
+        return Some(anonymous_identity.clone());
+    }
+
+    inputs
+        .identity
+        .rfind('@')
+        .map(|at| format!("anonymous{}", &inputs.identity[at..]))
+}
+
+impl fmt::Debug for EnterpriseInputs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EnterpriseInputs")
+            .field("ssid", &self.ssid)
+            .field("identity", &self.identity)
+            .field("password", &self.password)
+            .field("anonymous_identity", &self.anonymous_identity)
+            .field("ca_cert", &self.ca_cert)
+            .field("server_domain", &self.server_domain)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use network_manager_types::Value;
+
+    use super::*;
+
+    fn inputs() -> EnterpriseInputs {
+        EnterpriseInputs {
+            ssid: "eduroam".into(),
+            identity: "alice@example.org".into(),
+            password: "secret".into(),
+            anonymous_identity: None,
+            ca_cert: "/etc/ssl/certs/example-ca.pem".into(),
+            server_domain: "radius.example.org".into(),
+        }
+    }
+
+    #[test]
+    fn test_ttls_pap_template() {
+        let profile = EnterpriseTemplate::TtlsPap
+            .profile("eduroam", &inputs())
+            .unwrap();
+
+        assert_eq!(
+            profile.get("802-11-wireless-security", "key-mgmt"),
+            Some(&Value::String("wpa-eap".into()))
+        );
+        assert_eq!(
+            profile.get("802-1x", "eap"),
+            Some(&Value::Strings(vec!["ttls".into()]))
+        );
+        assert_eq!(
+            profile.get("802-1x", "phase2-auth"),
+            Some(&Value::String("pap".into()))
+        );
+        assert_eq!(
+            profile.get("802-1x", "anonymous-identity"),
+            Some(&Value::String("anonymous@example.org".into()))
+        );
+        assert_eq!(
+            profile.get("802-1x", "domain-match"),
+            Some(&Value::String("radius.example.org".into()))
+        );
+    }
+
+    #[test]
+    fn test_peap_mschapv2_template() {
+        let mut inputs = inputs();
+        inputs.anonymous_identity = Some("outer@example.org".into());
+
+        let profile = EnterpriseTemplate::PeapMschapv2
+            .profile("eduroam", &inputs)
+            .unwrap();
+
+        assert_eq!(
+            profile.get("802-1x", "eap"),
+            Some(&Value::Strings(vec!["peap".into()]))
+        );
+        assert_eq!(
+            profile.get("802-1x", "phase2-auth"),
+            Some(&Value::String("mschapv2".into()))
+        );
+        assert_eq!(
+            profile.get("802-1x", "anonymous-identity"),
+            Some(&Value::String("outer@example.org".into()))
+        );
+        assert_eq!(
+            profile.get("802-1x", "domain-match"),
+            Some(&Value::String("radius.example.org".into()))
+        );
+        assert_eq!(profile.get("802-1x", "domain-suffix-match"), None);
+        assert_eq!(
+            profile.get("802-1x", "ca-cert"),
+            Some(&Value::Bytes(
+                b"file:///etc/ssl/certs/example-ca.pem\0".to_vec()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_template_requires_server_domain() {
+        let mut inputs = inputs();
+        inputs.server_domain.clear();
+
+        assert!(EnterpriseTemplate::PeapMschapv2
+            .profile("eduroam", &inputs)
+            .is_err());
+    }
+}
@@ -26,6 +26,76 @@ error_chain! {
             display("D-Bus failure: {}", info)
         }
 
+        PropertyType(property: String, signature: String, expected: String) {
+            description("Unexpected D-Bus property type")
+            display("D-Bus property {} has type {}, expected {}", property, signature, expected)
+        }
+
+        ApModeUnsupported(interface: String) {
+            description("AP mode not supported")
+            display("{} does not support access point mode", interface)
+        }
+
+        DryRun(action: String) {
+            description("Not executed in read-only mode")
+            display("Dry run, would {}", action)
+        }
+
+        ActivationThrottled(connection: String, retry_after: ::std::time::Duration) {
+            description("Activation attempted too often")
+            display("Activation of {} attempted too often, retry in {:?}", connection, retry_after)
+        }
+
+        SimLocked(interface: String, lock: String) {
+            description("SIM locked")
+            display("SIM of {} is locked, unlock it with {}", interface, lock)
+        }
+
         Service
+
+        DaemonRestarted {
+            description("NetworkManager restarted")
+            display("NetworkManager was restarted, devices and connections need to be fetched again")
+        }
+    }
+}
+
+impl Error {
+    /// Attaches the failure to clean up after this error, e.g. to roll back
+    /// a checkpoint, as its cause. The kind is kept, so that callers still
+    /// see what went wrong in the first place.
+    pub fn with_cleanup_error(self, cleanup: Error) -> Error {
+        Error::with_chain(cleanup, self.0)
+    }
+}
+
+impl From<::network_manager_types::SsidError> for Error {
+    fn from(e: ::network_manager_types::SsidError) -> Self {
+        match e {
+            ::network_manager_types::SsidError::Utf8(e) => e.into(),
+            e => ErrorKind::SSID(e.to_string()).into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::error::Error as StdError;
+
+    #[test]
+    fn test_with_cleanup_error() {
+        let error = Error::from_kind(ErrorKind::NetworkManager("activation failed".into()))
+            .with_cleanup_error(ErrorKind::NetworkManager("rollback failed".into()).into());
+
+        assert_eq!(
+            error.to_string(),
+            "NetworkManager failure: activation failed"
+        );
+        assert_eq!(
+            error.source().map(|cause| cause.to_string()),
+            Some("NetworkManager failure: rollback failed".to_string())
+        );
     }
 }
@@ -0,0 +1,344 @@
+use std::thread;
+use std::time::Duration;
+
+use network_manager_types::Value;
+
+use errors::*;
+
+use connection::{Connection, ConnectionState};
+use connectivity::{probe_via, ProbeTarget};
+
+const DEFAULT_PROBE_TIMEOUT: u64 = 3;
+const DEFAULT_FAIL_THRESHOLD: u32 = 3;
+const DEFAULT_RECOVER_THRESHOLD: u32 = 5;
+
+/// Route metric given to a failing primary that stays active, high enough to
+/// put its routes behind any other uplink.
+const DEFAULT_FAILOVER_METRIC: i64 = 20000;
+
+/// Switches between a primary and a secondary uplink based on reachability,
+/// e.g. from Ethernet to LTE.
+///
+/// The primary is probed through its own device on every evaluation. After
+/// `fail_threshold` failed probes in a row the secondary is activated and,
+/// if the primary is still up, the routes of the primary are moved behind it
+/// by raising their metric. After `recover_threshold` successful probes in a
+/// row the primary's metric is restored and the secondary deactivated again.
+/// The differing thresholds keep a flaky primary from flapping.
+///
+/// Metrics are changed through a reapply, the stored profiles are left
+/// alone, so a reactivation of the primary resets them.
+pub struct FailoverManager {
+    primary: Connection,
+    secondary: Connection,
+    target: ProbeTarget,
+    probe_timeout: Duration,
+    failover_metric: i64,
+    deactivate_secondary: bool,
+    hysteresis: Hysteresis,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FailoverAction {
+    /// The primary is reachable and in use.
+    Healthy,
+    /// Probes through the primary failed this many times in a row.
+    Degraded(u32),
+    /// Traffic was moved to the secondary.
+    FailedOver,
+    /// Failed over, and probes through the primary succeeded this many times
+    /// in a row.
+    Recovering(u32),
+    /// Failed over, and the primary is still not reachable.
+    OnSecondary,
+    /// Traffic was moved back to the primary.
+    FailedBack,
+}
+
+impl FailoverManager {
+    pub fn new(primary: Connection, secondary: Connection, target: ProbeTarget) -> Self {
+        FailoverManager {
+            primary,
+            secondary,
+            target,
+            probe_timeout: Duration::from_secs(DEFAULT_PROBE_TIMEOUT),
+            failover_metric: DEFAULT_FAILOVER_METRIC,
+            deactivate_secondary: true,
+            hysteresis: Hysteresis::new(DEFAULT_FAIL_THRESHOLD, DEFAULT_RECOVER_THRESHOLD),
+        }
+    }
+
+    pub fn with_probe_timeout(mut self, timeout: Duration) -> Self {
+        self.probe_timeout = timeout;
+        self
+    }
+
+    /// Failed probes in a row before failing over and successful probes in a
+    /// row before failing back.
+    pub fn with_thresholds(mut self, fail_threshold: u32, recover_threshold: u32) -> Self {
+        self.hysteresis = Hysteresis::new(fail_threshold, recover_threshold);
+        self
+    }
+
+    /// Route metric applied to the primary while failed over. It has to be
+    /// higher than the metric of the secondary.
+    pub fn with_failover_metric(mut self, metric: i64) -> Self {
+        self.failover_metric = metric;
+        self
+    }
+
+    /// Whether to deactivate the secondary after failing back, on by default
+    /// as secondaries are often metered.
+    pub fn with_deactivate_secondary(mut self, deactivate_secondary: bool) -> Self {
+        self.deactivate_secondary = deactivate_secondary;
+        self
+    }
+
+    pub fn is_failed_over(&self) -> bool {
+        self.hysteresis.failed_over
+    }
+
+    /// Probes the primary once and fails over or back if a threshold was
+    /// reached.
+    pub fn evaluate(&mut self) -> Result<FailoverAction> {
+        let healthy = self.check_primary()?;
+        let action = self.hysteresis.record(healthy);
+
+        // A failed switch is tried again on the next evaluation
+        match action {
+            FailoverAction::FailedOver => {
+                self.fail_over()?;
+                self.hysteresis.switched(true);
+            }
+            FailoverAction::FailedBack => {
+                self.fail_back()?;
+                self.hysteresis.switched(false);
+            }
+            _ => {}
+        }
+
+        Ok(action)
+    }
+
+    /// Evaluates every `interval` until `callback` returns `false`.
+    pub fn watch<F>(&mut self, interval: Duration, mut callback: F) -> Result<()>
+    where
+        F: FnMut(&FailoverAction) -> bool,
+    {
+        loop {
+            let action = self.evaluate()?;
+
+            if !callback(&action) {
+                return Ok(());
+            }
+
+            thread::sleep(interval);
+        }
+    }
+
+    fn check_primary(&self) -> Result<bool> {
+        if self.primary.get_state()? != ConnectionState::Activated {
+            return Ok(false);
+        }
+
+        for device in self.primary.get_devices()? {
+            match probe_via(&device, &self.target, self.probe_timeout) {
+                Ok(_) => return Ok(true),
+                Err(e) => debug!("Failover probe via {} failed: {}", device.interface(), e),
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn fail_over(&mut self) -> Result<()> {
+        info!(
+            "Failing over from {:?} to {:?}",
+            self.primary.settings().id,
+            self.secondary.settings().id
+        );
+
+        if self.secondary.get_state()? != ConnectionState::Activated {
+            self.secondary.activate()?;
+        }
+
+        let metric = Value::from(self.failover_metric);
+        self.set_primary_route_metric(|_| Some(metric.clone()))
+    }
+
+    fn fail_back(&mut self) -> Result<()> {
+        info!(
+            "Failing back from {:?} to {:?}",
+            self.secondary.settings().id,
+            self.primary.settings().id
+        );
+
+        let profile = self.primary.get_profile()?;
+        self.set_primary_route_metric(|setting| profile.get(setting, "route-metric").cloned())?;
+
+        if self.deactivate_secondary {
+            self.secondary.deactivate()?;
+        }
+
+        Ok(())
+    }
+
+    /// Reapplies the primary with the IPv4 and IPv6 route metrics given by
+    /// `metric`, `None` restoring NetworkManager's default.
+    fn set_primary_route_metric<F>(&self, metric: F) -> Result<()>
+    where
+        F: Fn(&str) -> Option<Value>,
+    {
+        for device in self.primary.get_devices()? {
+            let (mut applied, version) = device.get_applied_connection()?;
+
+            for setting in &["ipv4", "ipv6"] {
+                if applied.setting(setting).is_some() {
+                    let value = metric(setting).unwrap_or(Value::Int64(-1));
+                    applied.set(setting, "route-metric", value);
+                }
+            }
+
+            device.reapply(&applied, version)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Counts probe results in a row and decides when to fail over or back.
+#[derive(Clone, Debug, PartialEq)]
+struct Hysteresis {
+    fail_threshold: u32,
+    recover_threshold: u32,
+    failures: u32,
+    successes: u32,
+    failed_over: bool,
+}
+
+impl Hysteresis {
+    fn new(fail_threshold: u32, recover_threshold: u32) -> Self {
+        Hysteresis {
+            fail_threshold: fail_threshold.max(1),
+            recover_threshold: recover_threshold.max(1),
+            failures: 0,
+            successes: 0,
+            failed_over: false,
+        }
+    }
+
+    /// Counts a probe of the primary. `FailedOver` and `FailedBack` are only
+    /// returned, `switched` records that they were carried out.
+    fn record(&mut self, healthy: bool) -> FailoverAction {
+        if healthy {
+            self.failures = 0;
+            self.successes = self.successes.saturating_add(1);
+        } else {
+            self.successes = 0;
+            self.failures = self.failures.saturating_add(1);
+        }
+
+        match (self.failed_over, healthy) {
+            (false, true) => FailoverAction::Healthy,
+            (false, false) if self.failures < self.fail_threshold => {
+                FailoverAction::Degraded(self.failures)
+            }
+            (false, false) => FailoverAction::FailedOver,
+            (true, false) => FailoverAction::OnSecondary,
+            (true, true) if self.successes < self.recover_threshold => {
+                FailoverAction::Recovering(self.successes)
+            }
+            (true, true) => FailoverAction::FailedBack,
+        }
+    }
+
+    fn switched(&mut self, failed_over: bool) {
+        self.failed_over = failed_over;
+        self.failures = 0;
+        self.successes = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(hysteresis: &mut Hysteresis, probes: &[bool]) -> Vec<FailoverAction> {
+        probes
+            .iter()
+            .map(|&healthy| {
+                let action = hysteresis.record(healthy);
+
+                match action {
+                    FailoverAction::FailedOver => hysteresis.switched(true),
+                    FailoverAction::FailedBack => hysteresis.switched(false),
+                    _ => {}
+                }
+
+                action
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fail_over_and_back() {
+        let mut hysteresis = Hysteresis::new(2, 3);
+
+        assert_eq!(
+            run(
+                &mut hysteresis,
+                &[true, false, false, false, true, true, true, true]
+            ),
+            vec![
+                FailoverAction::Healthy,
+                FailoverAction::Degraded(1),
+                FailoverAction::FailedOver,
+                FailoverAction::OnSecondary,
+                FailoverAction::Recovering(1),
+                FailoverAction::Recovering(2),
+                FailoverAction::FailedBack,
+                FailoverAction::Healthy,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flapping_primary_stays_failed_over() {
+        let mut hysteresis = Hysteresis::new(1, 2);
+
+        assert_eq!(
+            run(&mut hysteresis, &[false, true, false, true, false]),
+            vec![
+                FailoverAction::FailedOver,
+                FailoverAction::Recovering(1),
+                FailoverAction::OnSecondary,
+                FailoverAction::Recovering(1),
+                FailoverAction::OnSecondary,
+            ]
+        );
+        assert!(hysteresis.failed_over);
+    }
+
+    #[test]
+    fn test_failed_switch_is_retried() {
+        let mut hysteresis = Hysteresis::new(2, 2);
+
+        assert_eq!(hysteresis.record(false), FailoverAction::Degraded(1));
+        assert_eq!(hysteresis.record(false), FailoverAction::FailedOver);
+
+        // The switch failed, so `switched` was not called
+        assert_eq!(hysteresis.record(false), FailoverAction::FailedOver);
+        hysteresis.switched(true);
+
+        assert_eq!(hysteresis.record(false), FailoverAction::OnSecondary);
+    }
+
+    #[test]
+    fn test_thresholds_at_least_one() {
+        let mut hysteresis = Hysteresis::new(0, 0);
+
+        assert_eq!(hysteresis.record(false), FailoverAction::FailedOver);
+        hysteresis.switched(true);
+        assert_eq!(hysteresis.record(true), FailoverAction::FailedBack);
+    }
+}
@@ -0,0 +1,230 @@
+//! Entry points for the `cargo-fuzz` targets in `fuzz/`, enabled with the
+//! `fuzzing` feature. Not covered by semver.
+//!
+//! Run with `cargo +nightly fuzz run variant_to` or `settings`.
+//!
+//! The fuzz input is decoded into D-Bus arguments rather than parsed as a
+//! wire message, so that every input reaches the conversions instead of
+//! being rejected by libdbus.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use dbus::arg::{RefArg, Variant};
+use dbus::Path;
+use network_manager_types::{DeviceType, Value};
+#[cfg(feature = "wifi")]
+use network_manager_types::{NM80211ApFlags, NM80211ApSecurityFlags};
+
+use dbus_api::{ref_arg_to_value, value_to_ref_arg, DBusApi, VariantTo};
+use dbus_nm::{profile_to_settings, settings_from_dict};
+use device::DeviceState;
+use ip_config::IpAddress;
+use lldp::LldpNeighbor;
+use settings::ConnectionProfile;
+
+/// Nesting limit of variants, arrays and dictionaries.
+const MAX_DEPTH: u32 = 4;
+
+/// Element limit of arrays and dictionaries.
+const MAX_LEN: u8 = 8;
+
+/// Runs every `VariantTo` conversion on a variant decoded from `data`.
+/// Conversions may reject the variant, but must not panic.
+pub fn variant_to(data: &[u8]) {
+    let mut input = Input(data);
+
+    let value = match input.ref_arg(MAX_DEPTH) {
+        Some(arg) => Variant(arg),
+        None => return,
+    };
+
+    convert::<String>(&value);
+    convert::<u8>(&value);
+    convert::<i32>(&value);
+    convert::<u32>(&value);
+    convert::<i64>(&value);
+    convert::<u64>(&value);
+    convert::<f64>(&value);
+    convert::<bool>(&value);
+    convert::<Vec<String>>(&value);
+    convert::<Vec<u8>>(&value);
+    convert::<Path<'static>>(&value);
+    convert::<Vec<Path<'static>>>(&value);
+    convert::<HashMap<String, Value>>(&value);
+    convert::<Vec<HashMap<String, Value>>>(&value);
+    convert::<DeviceType>(&value);
+    convert::<DeviceState>(&value);
+    #[cfg(feature = "wifi")]
+    convert::<NM80211ApFlags>(&value);
+    #[cfg(feature = "wifi")]
+    convert::<NM80211ApSecurityFlags>(&value);
+    convert::<Vec<IpAddress>>(&value);
+    convert::<Vec<IpAddr>>(&value);
+    convert::<Vec<LldpNeighbor>>(&value);
+
+    if let Some(converted) = convert::<Value>(&value) {
+        assert_round_trip(&converted);
+    }
+}
+
+/// Builds a connection profile from `a{sa{sv}}` settings decoded from `data`,
+/// as received from `GetSettings`, and converts it back. See
+/// `assert_round_trip` for the comparison.
+pub fn settings(data: &[u8]) {
+    let mut input = Input(data);
+    let mut dict = HashMap::new();
+
+    for _ in 0..input.len() {
+        let name = match input.string() {
+            Some(name) => name,
+            None => break,
+        };
+
+        let mut setting = HashMap::new();
+
+        for _ in 0..input.len() {
+            match (input.string(), input.ref_arg(MAX_DEPTH)) {
+                (Some(key), Some(arg)) => {
+                    setting.insert(key, Variant(arg));
+                }
+                _ => break,
+            }
+        }
+
+        dict.insert(name, setting);
+    }
+
+    let profile = ConnectionProfile::from(settings_from_dict(dict));
+
+    let _ = (
+        profile.id(),
+        profile.uuid(),
+        profile.kind(),
+        profile.interface_name(),
+    );
+
+    for (name, setting) in profile_to_settings(&profile) {
+        for (key, value) in setting {
+            assert_eq!(
+                format!("{:?}", ref_arg_to_value(&value).as_ref()),
+                format!("{:?}", profile.get(&name, &key)),
+                "{}.{} changed in the round trip",
+                name,
+                key
+            );
+        }
+    }
+}
+
+fn convert<T>(value: &Variant<Box<dyn RefArg>>) -> Option<T>
+where
+    DBusApi: VariantTo<T>,
+{
+    DBusApi::variant_to(value)
+}
+
+/// Values have to survive being sent back to NetworkManager unchanged. Debug
+/// output is compared, as `NaN` is not equal to itself.
+fn assert_round_trip(value: &Value) {
+    let sent = ref_arg_to_value(&value_to_ref_arg(value));
+
+    assert_eq!(format!("{:?}", sent), format!("{:?}", Some(value)));
+}
+
+struct Input<'a>(&'a [u8]);
+
+impl<'a> Input<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let (&first, rest) = self.0.split_first()?;
+        self.0 = rest;
+        Some(first)
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        if self.0.len() < N {
+            return None;
+        }
+
+        let (bytes, rest) = self.0.split_at(N);
+        self.0 = rest;
+
+        let mut array = [0; N];
+        array.copy_from_slice(bytes);
+        Some(array)
+    }
+
+    fn len(&mut self) -> u8 {
+        self.byte().map_or(0, |len| len % (MAX_LEN + 1))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = usize::from(self.byte()? % 32).min(self.0.len());
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    fn ref_arg(&mut self, depth: u32) -> Option<Box<dyn RefArg>> {
+        let tag = self.byte()?;
+
+        // Containers are only built while the depth allows it
+        let tag = if depth == 0 { tag % 11 } else { tag % 17 };
+
+        let arg: Box<dyn RefArg> = match tag {
+            0 => Box::new(self.byte()? & 1 != 0),
+            1 => Box::new(self.byte()?),
+            2 => Box::new(i16::from_le_bytes(self.bytes()?)),
+            3 => Box::new(u16::from_le_bytes(self.bytes()?)),
+            4 => Box::new(i32::from_le_bytes(self.bytes()?)),
+            5 => Box::new(u32::from_le_bytes(self.bytes()?)),
+            6 => Box::new(i64::from_le_bytes(self.bytes()?)),
+            7 => Box::new(u64::from_le_bytes(self.bytes()?)),
+            8 => Box::new(f64::from_le_bytes(self.bytes()?)),
+            9 => Box::new(self.string()?),
+            10 => Box::new(self.path()?),
+            11 => Box::new(Variant(self.ref_arg(depth - 1)?)),
+            12 => Box::new(self.array(|input| input.byte())),
+            13 => Box::new(self.array(|input| input.string())),
+            14 => Box::new(self.array(|input| input.path())),
+            15 => Box::new(self.array(|input| Some(Variant(input.ref_arg(depth - 1)?)))),
+            _ => Box::new(self.dict(depth - 1)),
+        };
+
+        Some(arg)
+    }
+
+    fn path(&mut self) -> Option<Path<'static>> {
+        let mut path = String::new();
+
+        for _ in 0..self.byte()? % 4 {
+            path.push('/');
+            path.push_str(&format!("{}", self.byte()?));
+        }
+
+        Path::new(if path.is_empty() { "/".into() } else { path }).ok()
+    }
+
+    fn array<T, F>(&mut self, mut element: F) -> Vec<T>
+    where
+        F: FnMut(&mut Self) -> Option<T>,
+    {
+        (0..self.len()).map_while(|_| element(self)).collect()
+    }
+
+    fn dict(&mut self, depth: u32) -> HashMap<String, Variant<Box<dyn RefArg>>> {
+        let mut dict = HashMap::new();
+
+        for _ in 0..self.len() {
+            match (self.string(), self.ref_arg(depth)) {
+                (Some(key), Some(arg)) => {
+                    dict.insert(key, Variant(arg));
+                }
+                _ => break,
+            }
+        }
+
+        dict
+    }
+}
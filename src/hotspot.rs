@@ -0,0 +1,945 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use dbus_api::Bus;
+use dbus_nm::DBusNetworkManager;
+use errors::*;
+use secret::SecretString;
+
+use connection::{get_connection_by_path, Connection};
+use device::{get_device_by_path, Device, PathGetter};
+use network_manager_types::SecretFlags;
+
+use network_manager_types::{AsSsidSlice, Ssid, SsidSlice};
+use wifi::channels::{verify_band_setting, verify_channel, verify_country_code, Band};
+
+/// Extra configuration read by the dnsmasq instances NetworkManager runs for
+/// connections in shared mode.
+const DNSMASQ_SHARED_DIR: &str = "/etc/NetworkManager/dnsmasq-shared.d";
+
+/// Leases handed out by the dnsmasq instance of a shared connection are kept
+/// in `dnsmasq-<interface>.leases` here.
+const DNSMASQ_LEASES_DIR: &str = "/var/lib/NetworkManager";
+
+/// The subnet of NetworkManager's shared mode unless set otherwise.
+const DEFAULT_SHARED_SUBNET: (Ipv4Addr, u32) = (Ipv4Addr::new(10, 42, 0, 1), 24);
+
+/// How clients authenticate to a hotspot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum HotspotSecurity {
+    /// No password, traffic is not encrypted.
+    Open,
+    /// WPA2-Personal with a passphrase of 8 to 63 characters or a raw key
+    /// of 64 hex digits.
+    WpaPsk,
+    /// WPA3-Personal. Requires a driver and wpa_supplicant supporting SAE
+    /// in AP mode, and clients supporting WPA3.
+    Sae,
+}
+
+/// Settings for creating an access point (hotspot) connection.
+#[derive(Clone)]
+pub struct HotspotConfig {
+    ssid: Ssid,
+    password: Option<SecretString>,
+    password_flags: SecretFlags,
+    security: Option<HotspotSecurity>,
+    address: Option<Ipv4Addr>,
+    country: Option<String>,
+    band: Option<Band>,
+    channel: Option<u32>,
+    subnet: Option<(Ipv4Addr, u32)>,
+    dhcp_range: Option<(Ipv4Addr, Ipv4Addr)>,
+    dhcp_lease_time: Option<u32>,
+    static_leases: Vec<(String, Ipv4Addr)>,
+    captive_portal: Option<Ipv4Addr>,
+    idle_timeout: Option<Duration>,
+}
+
+impl fmt::Debug for HotspotConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HotspotConfig")
+            .field("ssid", &self.ssid)
+            .field("password", &self.password)
+            .field("password_flags", &self.password_flags)
+            .field("security", &self.security)
+            .field("address", &self.address)
+            .field("country", &self.country)
+            .field("band", &self.band)
+            .field("channel", &self.channel)
+            .field("subnet", &self.subnet)
+            .field("dhcp_range", &self.dhcp_range)
+            .field("dhcp_lease_time", &self.dhcp_lease_time)
+            .field("static_leases", &self.static_leases)
+            .field("captive_portal", &self.captive_portal)
+            .field("idle_timeout", &self.idle_timeout)
+            .finish()
+    }
+}
+
+impl HotspotConfig {
+    pub fn new<T>(ssid: &T) -> Result<Self>
+    where
+        T: AsSsidSlice + ?Sized,
+    {
+        let ssid = Ssid::from_bytes(verify_hotspot_ssid(ssid.as_ssid_slice()?)?.as_bytes())?;
+
+        Ok(HotspotConfig {
+            ssid,
+            password: None,
+            password_flags: SecretFlags::NONE,
+            security: None,
+            address: None,
+            country: None,
+            band: None,
+            channel: None,
+            subnet: None,
+            dhcp_range: None,
+            dhcp_lease_time: None,
+            static_leases: Vec::new(),
+            captive_portal: None,
+            idle_timeout: None,
+        })
+    }
+
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Security of the hotspot. Without one it is WPA2-Personal if a
+    /// password is set and open otherwise.
+    pub fn security(mut self, security: HotspotSecurity) -> Self {
+        self.security = Some(security);
+        self
+    }
+
+    /// Controls whether NetworkManager stores the PSK, e.g.
+    /// `SecretFlags::NOT_SAVED` keeps it in memory only.
+    pub fn password_flags(mut self, flags: SecretFlags) -> Self {
+        self.password_flags = flags;
+        self
+    }
+
+    /// Static IPv4 address of the hotspot. Without one, NetworkManager's
+    /// shared mode picks an address. No DHCP is served with a static address,
+    /// so it cannot be combined with the subnet or DHCP settings.
+    pub fn address(mut self, address: Ipv4Addr) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// Address and prefix length of the hotspot in shared mode, whose subnet
+    /// clients get DHCP leases from, instead of `10.42.0.1/24`. Unlike
+    /// `address`, NetworkManager keeps serving DHCP and NAT.
+    pub fn subnet(mut self, address: Ipv4Addr, prefix: u32) -> Result<Self> {
+        if prefix == 0 || prefix > 30 {
+            bail!(ErrorKind::NetworkManager(format!(
+                "Invalid hotspot prefix length: {}",
+                prefix
+            )));
+        }
+
+        self.subnet = Some((address, prefix));
+        Ok(self)
+    }
+
+    /// Range of addresses handed out by DHCP, which has to lie within the
+    /// subnet, `10.42.0.0/24` unless set. Requires NetworkManager 1.42 or
+    /// later.
+    pub fn dhcp_range(mut self, start: Ipv4Addr, end: Ipv4Addr) -> Result<Self> {
+        if start > end {
+            bail!(ErrorKind::NetworkManager(format!(
+                "Invalid DHCP range: {} - {}",
+                start, end
+            )));
+        }
+
+        self.dhcp_range = Some((start, end));
+        Ok(self)
+    }
+
+    /// DHCP lease time in seconds. Requires NetworkManager 1.42 or later.
+    pub fn dhcp_lease_time(mut self, seconds: u32) -> Self {
+        self.dhcp_lease_time = Some(seconds);
+        self
+    }
+
+    /// Always lease `address` to the client with the MAC address `mac`.
+    ///
+    /// NetworkManager has no setting for static leases, they are written to
+    /// a file in `/etc/NetworkManager/dnsmasq-shared.d` when the hotspot is
+    /// created. The dnsmasq instances of all shared connections read that
+    /// directory.
+    pub fn static_lease(mut self, mac: &str, address: Ipv4Addr) -> Result<Self> {
+        let valid = mac.len() == 17
+            && mac
+                .split(':')
+                .all(|octet| octet.len() == 2 && octet.chars().all(|c| c.is_ascii_hexdigit()));
+
+        if !valid {
+            bail!(ErrorKind::NetworkManager(format!(
+                "Invalid MAC address: {:?}",
+                mac
+            )));
+        }
+
+        self.static_leases.push((mac.to_ascii_lowercase(), address));
+        Ok(self)
+    }
+
+    /// Resolve every DNS name clients look up to `portal`, usually the
+    /// hotspot's own address as set with `subnet`, so that an onboarding
+    /// portal served there comes up on any page. Written to the dnsmasq
+    /// configuration like static leases.
+    ///
+    /// Redirecting traffic to literal addresses is left to the firewall,
+    /// e.g. a firewalld rich rule forwarding port 80 in the `nm-shared` zone
+    /// NetworkManager puts shared interfaces in.
+    pub fn captive_portal(mut self, portal: Ipv4Addr) -> Self {
+        self.captive_portal = Some(portal);
+        self
+    }
+
+    /// Stop a hotspot started with `WiFiDevice::start_hotspot` once no client
+    /// was associated for `timeout`, e.g. an onboarding hotspot nobody used.
+    /// Clients are listed in the background, which the iwd backend does not
+    /// support, so starting the hotspot fails with it.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// ISO 3166-1 alpha-2 country code applied to the radio before the
+    /// hotspot is started, so that only channels legal in that region are used.
+    pub fn country(mut self, country: &str) -> Result<Self> {
+        self.country = Some(verify_country_code(country)?);
+        Ok(self)
+    }
+
+    /// Band the hotspot operates on, 2.4 GHz unless set. NetworkManager has
+    /// no band value for 6 GHz, so creating a 6 GHz hotspot fails.
+    pub fn band(mut self, band: Band) -> Self {
+        self.band = Some(band);
+        self
+    }
+
+    /// Channel the hotspot operates on, checked against the band when the
+    /// hotspot is created. Without one, NetworkManager picks a channel.
+    pub fn channel(mut self, channel: u32) -> Self {
+        self.channel = Some(channel);
+        self
+    }
+
+    /// Checks the settings depending on each other, e.g. that the channel is
+    /// one of the band, whichever order they were set in.
+    pub fn verify(&self) -> Result<()> {
+        let band = self.band.unwrap_or(Band::TwoGhz);
+
+        verify_band_setting(band)?;
+
+        if let Some(channel) = self.channel {
+            verify_channel(band, channel)?;
+        }
+
+        let shared =
+            self.subnet.is_some() || self.dhcp_range.is_some() || self.dhcp_lease_time.is_some();
+
+        if self.address.is_some() && shared {
+            bail!(ErrorKind::NetworkManager(
+                "A static hotspot address cannot be combined with the subnet or DHCP settings"
+                    .into()
+            ));
+        }
+
+        if let Some((start, end)) = self.dhcp_range {
+            let (address, prefix) = self.subnet.unwrap_or(DEFAULT_SHARED_SUBNET);
+
+            if !in_subnet(start, address, prefix) || !in_subnet(end, address, prefix) {
+                bail!(ErrorKind::NetworkManager(format!(
+                    "DHCP range {} - {} is not within {}/{}",
+                    start, end, address, prefix
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn ssid(&self) -> &SsidSlice {
+        &self.ssid
+    }
+
+    pub fn get_password(&self) -> Option<&str> {
+        self.password.as_ref().map(SecretString::expose)
+    }
+
+    pub fn get_security(&self) -> HotspotSecurity {
+        match (self.security, &self.password) {
+            (Some(security), _) => security,
+            (None, Some(_)) => HotspotSecurity::WpaPsk,
+            (None, None) => HotspotSecurity::Open,
+        }
+    }
+
+    pub fn get_password_flags(&self) -> SecretFlags {
+        self.password_flags
+    }
+
+    pub fn get_address(&self) -> Option<Ipv4Addr> {
+        self.address
+    }
+
+    pub fn get_country(&self) -> Option<&str> {
+        self.country.as_ref().map(|c| c as &str)
+    }
+
+    pub fn get_subnet(&self) -> Option<(Ipv4Addr, u32)> {
+        self.subnet
+    }
+
+    pub fn get_dhcp_range(&self) -> Option<(Ipv4Addr, Ipv4Addr)> {
+        self.dhcp_range
+    }
+
+    pub fn get_dhcp_lease_time(&self) -> Option<u32> {
+        self.dhcp_lease_time
+    }
+
+    pub fn get_static_leases(&self) -> &[(String, Ipv4Addr)] {
+        &self.static_leases
+    }
+
+    pub fn get_captive_portal(&self) -> Option<Ipv4Addr> {
+        self.captive_portal
+    }
+
+    /// dnsmasq options the hotspot needs beyond what NetworkManager sets.
+    pub fn dnsmasq_options(&self) -> Vec<String> {
+        let mut options: Vec<String> = self
+            .static_leases
+            .iter()
+            .map(|&(ref mac, address)| format!("dhcp-host={},{}", mac, address))
+            .collect();
+
+        if let Some(portal) = self.captive_portal {
+            options.push(format!("address=/#/{}", portal));
+            // Captive portal URI announced to clients (RFC 8910)
+            options.push(format!("dhcp-option=114,\"http://{}/\"", portal));
+        }
+
+        options
+    }
+
+    pub fn get_idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
+    pub fn get_band(&self) -> Option<Band> {
+        self.band
+    }
+
+    pub fn get_channel(&self) -> Option<u32> {
+        self.channel
+    }
+}
+
+fn in_subnet(address: Ipv4Addr, subnet: Ipv4Addr, prefix: u32) -> bool {
+    let mask = u32::MAX << (32 - prefix);
+
+    u32::from(address) & mask == u32::from(subnet) & mask
+}
+
+/// How often the clients of a hotspot with an idle timeout are listed, more
+/// often for shorter timeouts.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A running hotspot, which gives the device back to the connection active
+/// before it was started when stopped or dropped. If there was none, the
+/// device is allowed to autoconnect again. The dnsmasq options written for
+/// static leases and the captive portal are removed as well.
+///
+/// With an idle timeout the hotspot is watched on a thread of its own, which
+/// stops it the same way once no client was associated for that long.
+///
+/// Errors while restoring on drop are only logged, use `stop` to handle them.
+pub struct Hotspot {
+    connection: Connection,
+    device: Device,
+    previous: Option<Connection>,
+    stopped: Arc<Stopped>,
+    // Dropping it ends the idle watcher
+    watcher: Option<Sender<()>>,
+}
+
+/// Whether the hotspot was torn down, by its guard or by the idle watcher.
+/// The error of the watcher restoring the previous connection is kept for
+/// `stop` to return.
+#[derive(Default)]
+struct Stopped {
+    stopped: Mutex<bool>,
+    changed: Condvar,
+    restore_error: Mutex<Option<Error>>,
+}
+
+impl Stopped {
+    fn lock(&self) -> MutexGuard<'_, bool> {
+        self.stopped.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn restore_error(&self) -> MutexGuard<'_, Option<Error>> {
+        self.restore_error.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// What the idle watcher needs to find the hotspot on a bus connection of
+/// its own.
+struct IdleWatch {
+    bus: Bus,
+    method_timeout: u64,
+    wait_interval: Duration,
+    wait_deadline: Duration,
+    connection: String,
+    device: String,
+    previous: Option<String>,
+    timeout: Duration,
+}
+
+impl Hotspot {
+    pub(crate) fn new(
+        dbus_manager: &DBusNetworkManager,
+        connection: Connection,
+        device: Device,
+        previous: Option<Connection>,
+        idle_timeout: Option<Duration>,
+    ) -> Result<Self> {
+        let stopped = Arc::new(Stopped::default());
+
+        let watcher = match idle_timeout {
+            Some(timeout) => {
+                let watch = IdleWatch {
+                    bus: dbus_manager.bus(),
+                    method_timeout: dbus_manager.method_timeout(),
+                    wait_interval: dbus_manager.wait_interval(),
+                    wait_deadline: dbus_manager.wait_deadline(),
+                    connection: connection.path().to_string(),
+                    device: device.path().to_string(),
+                    previous: previous
+                        .as_ref()
+                        .map(|previous| previous.path().to_string()),
+                    timeout,
+                };
+
+                let (sender, cancel) = mpsc::channel();
+                let stopped = Arc::clone(&stopped);
+
+                thread::Builder::new()
+                    .name(format!("nm-hotspot-{}", device.interface()))
+                    .spawn(move || watch_idle(&watch, &stopped, &cancel))
+                    .chain_err(|| {
+                        ErrorKind::NetworkManager("Unable to start the hotspot idle watcher".into())
+                    })?;
+
+                Some(sender)
+            }
+            None => None,
+        };
+
+        Ok(Hotspot {
+            connection,
+            device,
+            previous,
+            stopped,
+            watcher,
+        })
+    }
+
+    /// The connection of the access point.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// The connection restored when the hotspot is stopped.
+    pub fn previous(&self) -> Option<&Connection> {
+        self.previous.as_ref()
+    }
+
+    /// Deactivate the hotspot and restore the previous connection. If the idle
+    /// timeout already stopped it, returns the error of restoring then.
+    pub fn stop(mut self) -> Result<()> {
+        self.restore()
+    }
+
+    /// Whether the idle timeout of the hotspot stopped it.
+    pub fn is_stopped(&self) -> bool {
+        *self.stopped.lock()
+    }
+
+    /// Blocks until the idle timeout of the hotspot stopped it. Returns at
+    /// once if its config has no idle timeout.
+    pub fn wait_idle(&self) {
+        if self.watcher.is_none() {
+            return;
+        }
+
+        let mut stopped = self.stopped.lock();
+
+        while !*stopped {
+            stopped = self
+                .stopped
+                .changed
+                .wait(stopped)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn restore(&mut self) -> Result<()> {
+        self.watcher = None;
+
+        let mut stopped = self.stopped.lock();
+
+        if *stopped {
+            return match self.stopped.restore_error().take() {
+                Some(e) => Err(e),
+                None => Ok(()),
+            };
+        }
+
+        restore_hotspot(
+            &self.connection,
+            &self.device,
+            self.previous.as_ref(),
+            &mut stopped,
+        )
+    }
+}
+
+impl Drop for Hotspot {
+    fn drop(&mut self) {
+        if let Err(e) = self.restore() {
+            warn!(
+                "Unable to restore {} after hotspot: {}",
+                self.device.interface(),
+                e
+            );
+        }
+    }
+}
+
+/// Deactivates the hotspot, marking it `stopped` once it is down, and gives
+/// the device back to `previous`.
+fn restore_hotspot(
+    connection: &Connection,
+    device: &Device,
+    previous: Option<&Connection>,
+    stopped: &mut bool,
+) -> Result<()> {
+    connection.deactivate()?;
+
+    *stopped = true;
+
+    let removed = remove_dnsmasq_config(device.interface());
+
+    match previous {
+        Some(previous) => {
+            debug!("Restoring {:?} after hotspot", previous.settings().id);
+            previous.activate()?;
+        }
+        None => device.set_autoconnect(true)?,
+    }
+
+    removed
+}
+
+/// Tracks since when a hotspot had no clients.
+struct IdleTimer {
+    timeout: Duration,
+    last_client: Instant,
+}
+
+impl IdleTimer {
+    fn new(timeout: Duration, now: Instant) -> Self {
+        IdleTimer {
+            timeout,
+            last_client: now,
+        }
+    }
+
+    /// Records whether clients were associated at `now`, returning whether
+    /// there were none for the timeout.
+    fn record(&mut self, has_clients: bool, now: Instant) -> bool {
+        if has_clients {
+            self.last_client = now;
+        }
+
+        now.duration_since(self.last_client) >= self.timeout
+    }
+}
+
+fn idle_poll_interval(timeout: Duration) -> Duration {
+    (timeout / 4)
+        .max(Duration::from_secs(1))
+        .min(IDLE_POLL_INTERVAL)
+}
+
+/// Lists the clients of the hotspot until there were none for its timeout,
+/// then stops it. Failing to list them is retried, so is failing to stop
+/// the hotspot, until the `Hotspot` is dropped.
+fn watch_idle(watch: &IdleWatch, stopped: &Stopped, cancel: &Receiver<()>) {
+    let result = DBusNetworkManager::with_bus(watch.bus, Some(watch.method_timeout)).and_then(
+        |dbus_manager| {
+            dbus_manager.set_wait_interval(watch.wait_interval);
+            dbus_manager.set_wait_deadline(watch.wait_deadline);
+
+            let dbus_manager = Rc::new(dbus_manager);
+
+            let previous = match watch.previous {
+                Some(ref previous) => Some(get_connection_by_path(&dbus_manager, previous)?),
+                None => None,
+            };
+
+            Ok((
+                get_connection_by_path(&dbus_manager, &watch.connection)?,
+                get_device_by_path(&dbus_manager, &watch.device)?,
+                previous,
+            ))
+        },
+    );
+
+    let (connection, device, previous) = match result {
+        Ok(objects) => objects,
+        Err(e) => {
+            warn!("Unable to watch the hotspot for idleness: {}", e);
+            return;
+        }
+    };
+
+    let interval = idle_poll_interval(watch.timeout);
+    let mut timer = IdleTimer::new(watch.timeout, Instant::now());
+
+    while let Err(RecvTimeoutError::Timeout) = cancel.recv_timeout(interval) {
+        let clients = match device.as_wifi_device() {
+            Some(wifi_device) => wifi_device.get_hotspot_clients(),
+            None => return,
+        };
+
+        let has_clients = match clients {
+            Ok(clients) => !clients.is_empty(),
+            Err(e) => {
+                debug!(
+                    "Unable to list clients of the hotspot on {}: {}",
+                    device.interface(),
+                    e
+                );
+                continue;
+            }
+        };
+
+        if !timer.record(has_clients, Instant::now()) {
+            continue;
+        }
+
+        let mut guard = stopped.lock();
+
+        if *guard {
+            return;
+        }
+
+        debug!(
+            "Stopping hotspot on {}, no clients for {:?}",
+            device.interface(),
+            watch.timeout
+        );
+
+        let result = restore_hotspot(&connection, &device, previous.as_ref(), &mut guard);
+
+        if *guard {
+            stopped.changed.notify_all();
+        }
+
+        match result {
+            Ok(()) => return,
+            Err(e) if *guard => {
+                debug!(
+                    "Unable to restore {} after idle hotspot: {}",
+                    device.interface(),
+                    e
+                );
+                *stopped.restore_error() = Some(e);
+                return;
+            }
+            Err(e) => debug!(
+                "Unable to stop idle hotspot on {}: {}",
+                device.interface(),
+                e
+            ),
+        }
+    }
+}
+
+/// A station associated with a hotspot. Counters are taken from the access
+/// point's point of view and `None` where the backend does not report them.
+/// wpa_supplicant does not report the signal strength of stations.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct HotspotClient {
+    pub mac: String,
+    /// Address and hostname from the DHCP lease of the client, if any.
+    pub address: Option<Ipv4Addr>,
+    pub hostname: Option<String>,
+    /// Bytes and packets received from the client.
+    pub rx_bytes: Option<u64>,
+    pub rx_packets: Option<u64>,
+    /// Bytes and packets sent to the client.
+    pub tx_bytes: Option<u64>,
+    pub tx_packets: Option<u64>,
+}
+
+/// Fills in address and hostname of `clients` from the DHCP leases of the
+/// hotspot on `interface`. A missing leases file is not an error, clients
+/// may not have requested a lease yet.
+pub fn add_dnsmasq_leases(interface: &str, clients: &mut [HotspotClient]) -> Result<()> {
+    let path = PathBuf::from(DNSMASQ_LEASES_DIR).join(format!("dnsmasq-{}.leases", interface));
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => {
+            return Err(e).chain_err(|| {
+                ErrorKind::NetworkManager(format!("Unable to read {}", path.display()))
+            })
+        }
+    };
+
+    for (mac, address, hostname) in parse_dnsmasq_leases(&contents) {
+        for client in clients.iter_mut().filter(|client| client.mac == mac) {
+            client.address = Some(address);
+            client.hostname = hostname.clone();
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses lines of the form `<expiry> <mac> <address> <hostname> <client id>`,
+/// with `*` for an unknown hostname.
+fn parse_dnsmasq_leases(contents: &str) -> Vec<(String, Ipv4Addr, Option<String>)> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+
+            let mac = fields.get(1)?.to_ascii_lowercase();
+            let address = fields.get(2)?.parse().ok()?;
+            let hostname = fields
+                .get(3)
+                .filter(|&&hostname| hostname != "*")
+                .map(|hostname| hostname.to_string());
+
+            Some((mac, address, hostname))
+        })
+        .collect()
+}
+
+/// Writes the dnsmasq options of the hotspot on `interface`, or removes the
+/// file of an earlier hotspot if there are none.
+pub fn write_dnsmasq_config(interface: &str, options: &[String]) -> Result<()> {
+    let path =
+        PathBuf::from(DNSMASQ_SHARED_DIR).join(format!("network-manager-{}.conf", interface));
+
+    let result = if options.is_empty() {
+        match fs::remove_file(&path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    } else {
+        let mut contents = format!("# Hotspot on {}, managed by network-manager\n", interface);
+        for option in options {
+            contents.push_str(option);
+            contents.push('\n');
+        }
+
+        fs::create_dir_all(DNSMASQ_SHARED_DIR).and_then(|_| fs::write(&path, contents))
+    };
+
+    result.chain_err(|| ErrorKind::NetworkManager(format!("Unable to write {}", path.display())))
+}
+
+/// Removes the dnsmasq options written for the hotspot on `interface`. The
+/// dnsmasq instances of all shared connections read them, so they must not
+/// outlive the hotspot.
+pub fn remove_dnsmasq_config(interface: &str) -> Result<()> {
+    write_dnsmasq_config(interface, &[])
+}
+
+/// Checks that clients can display the SSID of a hotspot: not empty, valid
+/// UTF-8 and free of control characters.
+fn verify_hotspot_ssid(ssid: &SsidSlice) -> Result<&str> {
+    let name = match ssid.as_str() {
+        Ok(name) if !name.is_empty() => name,
+        Ok(_) => bail!(ErrorKind::SSID("Hotspot SSID should not be empty".into())),
+        Err(_) => bail!(ErrorKind::SSID(format!(
+            "Hotspot SSID should be valid UTF-8: {}",
+            ssid.to_hex()
+        ))),
+    };
+
+    if name.chars().any(char::is_control) {
+        bail!(ErrorKind::SSID(format!(
+            "Hotspot SSID should not contain control characters: {:?}",
+            name
+        )))
+    }
+
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_channel_in_band() {
+        let config = HotspotConfig::new("hotspot").unwrap();
+
+        assert!(config.clone().channel(6).verify().is_ok());
+        assert!(config.clone().channel(36).verify().is_err());
+        assert!(config
+            .clone()
+            .channel(36)
+            .band(Band::FiveGhz)
+            .verify()
+            .is_ok());
+        assert!(config.band(Band::FiveGhz).channel(6).verify().is_err());
+    }
+
+    #[test]
+    fn test_verify_addressing() {
+        let config = HotspotConfig::new("hotspot").unwrap();
+        let address = |last| Ipv4Addr::new(10, 42, 0, last);
+
+        let ranged = config
+            .clone()
+            .dhcp_range(address(10), address(100))
+            .unwrap();
+        assert!(ranged.verify().is_ok());
+        assert!(ranged.clone().address(address(1)).verify().is_err());
+        assert!(config
+            .clone()
+            .address(address(1))
+            .dhcp_lease_time(3600)
+            .verify()
+            .is_err());
+        assert!(config.clone().address(address(1)).verify().is_ok());
+
+        let outside = config
+            .clone()
+            .dhcp_range(address(10), Ipv4Addr::new(10, 42, 1, 10))
+            .unwrap();
+        assert!(outside.verify().is_err());
+
+        let subnet = outside.subnet(Ipv4Addr::new(10, 42, 0, 1), 16).unwrap();
+        assert!(subnet.verify().is_ok());
+        assert!(subnet
+            .dhcp_range(
+                Ipv4Addr::new(192, 168, 0, 10),
+                Ipv4Addr::new(192, 168, 0, 20)
+            )
+            .unwrap()
+            .verify()
+            .is_err());
+    }
+
+    #[test]
+    fn test_static_leases() {
+        let config = HotspotConfig::new("hotspot")
+            .unwrap()
+            .static_lease("AA:BB:CC:00:11:22", Ipv4Addr::new(192, 168, 50, 10))
+            .unwrap();
+
+        assert_eq!(
+            config.dnsmasq_options(),
+            vec!["dhcp-host=aa:bb:cc:00:11:22,192.168.50.10"]
+        );
+
+        let portal = config
+            .clone()
+            .captive_portal(Ipv4Addr::new(192, 168, 50, 1));
+        assert_eq!(portal.dnsmasq_options()[1], "address=/#/192.168.50.1");
+
+        assert!(config
+            .clone()
+            .static_lease("aa:bb:cc:00:11", Ipv4Addr::new(192, 168, 50, 11))
+            .is_err());
+    }
+
+    #[test]
+    fn test_hotspot_ssid() {
+        assert!(HotspotConfig::new("café").is_ok());
+        assert!(HotspotConfig::new("").is_err());
+        assert!(HotspotConfig::new("bad\nname").is_err());
+        assert!(HotspotConfig::new(&b"\xff\xfe"[..]).is_err());
+        assert!(HotspotConfig::new("a".repeat(33).as_str()).is_err());
+    }
+
+    #[test]
+    fn test_parse_dnsmasq_leases() {
+        let leases = parse_dnsmasq_leases(
+            "1700000000 aa:bb:cc:00:11:22 10.42.0.23 phone 01:aa:bb:cc:00:11:22\n\
+             1700000100 AA:BB:CC:00:11:33 10.42.0.57 * *\n\
+             1700000200 fe80::1 *\n",
+        );
+
+        assert_eq!(
+            leases,
+            vec![
+                (
+                    "aa:bb:cc:00:11:22".to_string(),
+                    Ipv4Addr::new(10, 42, 0, 23),
+                    Some("phone".to_string())
+                ),
+                (
+                    "aa:bb:cc:00:11:33".to_string(),
+                    Ipv4Addr::new(10, 42, 0, 57),
+                    None
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_idle_timer() {
+        let start = Instant::now();
+        let mut timer = IdleTimer::new(Duration::from_secs(60), start);
+
+        assert!(!timer.record(false, start + Duration::from_secs(30)));
+        assert!(!timer.record(true, start + Duration::from_secs(45)));
+        assert!(!timer.record(false, start + Duration::from_secs(90)));
+        assert!(timer.record(false, start + Duration::from_secs(105)));
+    }
+
+    #[test]
+    fn test_idle_poll_interval() {
+        assert_eq!(
+            idle_poll_interval(Duration::from_secs(2)),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            idle_poll_interval(Duration::from_secs(20)),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            idle_poll_interval(Duration::from_secs(600)),
+            IDLE_POLL_INTERVAL
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! Virtual Wi-Fi radios for tests, enabled with the `test-support` feature.
+//!
+//! Radios are created by loading the `mac80211_hwsim` kernel module and test
+//! access points are run by hostapd, so tests need root, both binaries and a
+//! running NetworkManager. Everything is torn down again on drop.
+//!
+//! ```no_run
+//! # use network_manager::hwsim::TestBed;
+//! # use network_manager::NetworkManager;
+//! let manager = NetworkManager::new();
+//! let bed = TestBed::new(&manager, "test-network", Some("test-psk")).unwrap();
+//!
+//! let device = manager.get_device_by_interface(bed.client_interface()).unwrap();
+//! ```
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use errors::*;
+
+use device::Device;
+use manager::NetworkManager;
+
+const MODULE: &str = "mac80211_hwsim";
+const NET_CLASS_DIR: &str = "/sys/class/net";
+
+/// How long to wait for the radios or the access point to come up.
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Radios created by loading `mac80211_hwsim`, removed again on drop.
+///
+/// The module is only loaded if it is not loaded already, so that radios of
+/// another user are never removed.
+pub struct Radios {
+    interfaces: Vec<String>,
+}
+
+impl Radios {
+    pub fn create(count: u32) -> Result<Self> {
+        if Path::new("/sys/module").join(MODULE).exists() {
+            bail!(ErrorKind::NetworkManager(format!(
+                "{} is already loaded",
+                MODULE
+            )));
+        }
+
+        run("modprobe", &[MODULE, &format!("radios={}", count)])?;
+
+        // Unloads the module again if the interfaces do not show up
+        let mut radios = Radios {
+            interfaces: Vec::new(),
+        };
+
+        let deadline = Instant::now() + START_TIMEOUT;
+
+        loop {
+            radios.interfaces = find_interfaces()?;
+
+            if radios.interfaces.len() >= count as usize {
+                return Ok(radios);
+            }
+
+            if Instant::now() >= deadline {
+                bail!(ErrorKind::NetworkManager(format!(
+                    "Only {} of {} radios showed up",
+                    radios.interfaces.len(),
+                    count
+                )));
+            }
+
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    /// Network interfaces of the radios, sorted by name.
+    pub fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+}
+
+impl Drop for Radios {
+    fn drop(&mut self) {
+        if let Err(e) = run("modprobe", &["-r", MODULE]) {
+            error!("Removing radios failed: {}", e);
+        }
+    }
+}
+
+/// A hostapd access point on one of the radios, stopped on drop.
+pub struct TestAccessPoint {
+    device: Device,
+    hostapd: Child,
+    config: PathBuf,
+}
+
+impl TestAccessPoint {
+    /// Starts a WPA2 access point, or an open one without `psk`, on
+    /// `interface`. The interface is set to unmanaged while the access point
+    /// runs, so that NetworkManager leaves it to hostapd.
+    pub fn start(
+        manager: &NetworkManager,
+        interface: &str,
+        ssid: &str,
+        psk: Option<&str>,
+    ) -> Result<Self> {
+        let device = manager.wait_for_device(interface, START_TIMEOUT)?;
+        device.set_managed(false)?;
+
+        let config = env::temp_dir().join(format!("nm-hwsim-{}-{}.conf", process::id(), interface));
+
+        fs::write(&config, hostapd_config(interface, ssid, psk)).chain_err(|| {
+            ErrorKind::NetworkManager(format!("Unable to write {}", config.display()))
+        })?;
+
+        let mut hostapd = match Command::new("hostapd")
+            .arg(&config)
+            .stdout(Stdio::piped())
+            .spawn()
+        {
+            Ok(hostapd) => hostapd,
+            Err(e) => {
+                let _ = fs::remove_file(&config);
+                let _ = device.set_managed(true);
+                return Err(e)
+                    .chain_err(|| ErrorKind::NetworkManager("Unable to run hostapd".into()));
+            }
+        };
+
+        let lines = read_lines(&mut hostapd);
+
+        let access_point = TestAccessPoint {
+            device,
+            hostapd,
+            config,
+        };
+
+        let deadline = Instant::now() + START_TIMEOUT;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            match lines.recv_timeout(remaining) {
+                Ok(line) => {
+                    debug!("hostapd: {}", line);
+
+                    if line.contains("AP-ENABLED") {
+                        return Ok(access_point);
+                    }
+                }
+                Err(_) => bail!(ErrorKind::NetworkManager(format!(
+                    "hostapd did not enable {} within {:?}",
+                    interface, START_TIMEOUT
+                ))),
+            }
+        }
+    }
+
+    pub fn interface(&self) -> &str {
+        self.device.interface()
+    }
+}
+
+impl Drop for TestAccessPoint {
+    fn drop(&mut self) {
+        let _ = self.hostapd.kill();
+        let _ = self.hostapd.wait();
+        let _ = fs::remove_file(&self.config);
+
+        if let Err(e) = self.device.set_managed(true) {
+            error!("Managing {} again failed: {}", self.device.interface(), e);
+        }
+    }
+}
+
+/// Two radios, one running a test access point and one left to
+/// NetworkManager to connect with.
+pub struct TestBed {
+    // Dropped in declaration order, hostapd has to go before its radio
+    access_point: TestAccessPoint,
+    radios: Radios,
+}
+
+impl TestBed {
+    pub fn new(manager: &NetworkManager, ssid: &str, psk: Option<&str>) -> Result<Self> {
+        let radios = Radios::create(2)?;
+
+        let access_point = TestAccessPoint::start(manager, &radios.interfaces()[0], ssid, psk)?;
+
+        Ok(TestBed {
+            access_point,
+            radios,
+        })
+    }
+
+    pub fn access_point(&self) -> &TestAccessPoint {
+        &self.access_point
+    }
+
+    /// The interface managed by NetworkManager.
+    pub fn client_interface(&self) -> &str {
+        &self.radios.interfaces()[1]
+    }
+}
+
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .chain_err(|| ErrorKind::NetworkManager(format!("Unable to run {}", program)))?;
+
+    if !status.success() {
+        bail!(ErrorKind::NetworkManager(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            status
+        )));
+    }
+
+    Ok(())
+}
+
+/// Interfaces whose device belongs to `mac80211_hwsim`.
+fn find_interfaces() -> Result<Vec<String>> {
+    let entries = fs::read_dir(NET_CLASS_DIR)
+        .chain_err(|| ErrorKind::NetworkManager(format!("Unable to read {}", NET_CLASS_DIR)))?;
+
+    let mut interfaces = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            fs::canonicalize(entry.path().join("device"))
+                .map(|device| device.to_string_lossy().contains(MODULE))
+                .unwrap_or(false)
+        })
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+
+    interfaces.sort();
+
+    Ok(interfaces)
+}
+
+/// Forwards the output of hostapd line by line. The output keeps being read
+/// after startup, so that hostapd never blocks on a full pipe.
+fn read_lines(hostapd: &mut Child) -> mpsc::Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+
+    if let Some(stdout) = hostapd.stdout.take() {
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(|line| line.ok()) {
+                let _ = sender.send(line);
+            }
+        });
+    }
+
+    receiver
+}
+
+fn hostapd_config(interface: &str, ssid: &str, psk: Option<&str>) -> String {
+    let mut config = format!(
+        "interface={}\ndriver=nl80211\nssid={}\nhw_mode=g\nchannel=6\n",
+        interface, ssid
+    );
+
+    if let Some(psk) = psk {
+        config.push_str(&format!(
+            "wpa=2\nwpa_key_mgmt=WPA-PSK\nrsn_pairwise=CCMP\nwpa_passphrase={}\n",
+            psk
+        ));
+    }
+
+    config
+}
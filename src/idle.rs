@@ -0,0 +1,148 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use errors::*;
+
+use device::{Device, PathGetter, StatisticsGuard};
+
+/// Refresh rate enabled for devices whose traffic counters are off.
+const STATISTICS_REFRESH_RATE_MS: u32 = 1000;
+
+/// Tells when a device has been quiet long enough for disruptive maintenance,
+/// such as a channel change, based on NetworkManager's traffic counters.
+///
+/// NetworkManager only counts bytes, so the threshold is a combined transmit
+/// and receive rate in bytes per second. Counters the monitor had to turn on
+/// are turned off again when it is dropped.
+#[derive(Debug)]
+pub struct IdleMonitor {
+    threshold: u64,
+    period: Duration,
+    last_sample: Option<(Instant, u64)>,
+    idle_since: Option<Instant>,
+    statistics: Option<StatisticsGuard>,
+}
+
+impl IdleMonitor {
+    /// Considers the device idle once its traffic stayed at or below
+    /// `threshold` bytes per second for `period`.
+    pub fn new(threshold: u64, period: Duration) -> Self {
+        IdleMonitor {
+            threshold,
+            period,
+            last_sample: None,
+            idle_since: None,
+            statistics: None,
+        }
+    }
+
+    /// Samples the counters of `device` and returns whether it has been idle
+    /// for the whole period. Sampling has to happen regularly, at least once
+    /// per statistics refresh. Counters are turned on if they are disabled.
+    pub fn poll(&mut self, device: &Device) -> Result<bool> {
+        let monitored = match self.statistics {
+            Some(ref statistics) => statistics.device().path() == device.path(),
+            None => false,
+        };
+
+        if !monitored {
+            self.statistics = Some(device.enable_statistics(STATISTICS_REFRESH_RATE_MS)?);
+        }
+
+        let statistics = device.get_statistics()?;
+
+        Ok(self.sample(statistics.tx_bytes + statistics.rx_bytes, Instant::now()))
+    }
+
+    fn sample(&mut self, total: u64, now: Instant) -> bool {
+        if let Some((sampled, previous)) = self.last_sample {
+            let elapsed = now.duration_since(sampled).as_secs_f64();
+
+            if elapsed > 0.0 {
+                let rate = total.saturating_sub(previous) as f64 / elapsed;
+
+                if rate <= self.threshold as f64 {
+                    self.idle_since.get_or_insert(sampled);
+                } else {
+                    self.idle_since = None;
+                }
+            }
+        }
+
+        self.last_sample = Some((now, total));
+
+        match self.idle_since {
+            Some(idle_since) => now.duration_since(idle_since) >= self.period,
+            None => false,
+        }
+    }
+
+    /// Polls `device` every `interval` until it is idle, returning `false` if
+    /// it did not become idle within `timeout`.
+    pub fn wait(&mut self, device: &Device, interval: Duration, timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if self.poll(device)? {
+                return Ok(true);
+            }
+
+            if Instant::now() + interval > deadline {
+                return Ok(false);
+            }
+
+            thread::sleep(interval);
+        }
+    }
+
+    /// Forgets earlier samples, e.g. after a maintenance operation.
+    pub fn reset(&mut self) {
+        self.last_sample = None;
+        self.idle_since = None;
+    }
+
+    /// Turns the counters off again if the monitor turned them on, reporting
+    /// failures that are only logged when the monitor is dropped. Polling
+    /// again turns them back on.
+    pub fn release(&mut self) -> Result<()> {
+        match self.statistics.take() {
+            Some(statistics) => statistics.release(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_after_period() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut monitor = IdleMonitor::new(100, Duration::from_secs(2));
+
+        assert!(!monitor.sample(1000, start));
+        assert!(!monitor.sample(1050, start + second));
+        assert!(monitor.sample(1100, start + 2 * second));
+
+        // a burst starts the period over
+        assert!(!monitor.sample(5000, start + 3 * second));
+        assert!(!monitor.sample(5000, start + 4 * second));
+        assert!(monitor.sample(5000, start + 5 * second));
+    }
+
+    #[test]
+    fn test_reset() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut monitor = IdleMonitor::new(0, Duration::from_secs(1));
+
+        assert!(!monitor.sample(0, start));
+        assert!(monitor.sample(0, start + second));
+
+        monitor.reset();
+        assert!(!monitor.sample(0, start + 2 * second));
+        assert!(monitor.release().is_ok());
+    }
+}
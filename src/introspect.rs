@@ -0,0 +1,348 @@
+use errors::*;
+
+/// Interfaces and child objects of a D-Bus object, as reported by
+/// `org.freedesktop.DBus.Introspectable`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Introspection {
+    pub interfaces: Vec<InterfaceInfo>,
+    /// Names of the child objects, relative to the introspected path.
+    pub nodes: Vec<String>,
+}
+
+impl Introspection {
+    pub fn interface(&self, name: &str) -> Option<&InterfaceInfo> {
+        self.interfaces
+            .iter()
+            .find(|interface| interface.name == name)
+    }
+
+    pub fn has_interface(&self, name: &str) -> bool {
+        self.interface(name).is_some()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct InterfaceInfo {
+    pub name: String,
+    pub methods: Vec<MethodInfo>,
+    pub signals: Vec<SignalInfo>,
+    pub properties: Vec<PropertyInfo>,
+}
+
+impl InterfaceInfo {
+    pub fn has_method(&self, name: &str) -> bool {
+        self.methods.iter().any(|method| method.name == name)
+    }
+
+    pub fn has_signal(&self, name: &str) -> bool {
+        self.signals.iter().any(|signal| signal.name == name)
+    }
+
+    pub fn property(&self, name: &str) -> Option<&PropertyInfo> {
+        self.properties
+            .iter()
+            .find(|property| property.name == name)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct MethodInfo {
+    pub name: String,
+    pub in_args: Vec<ArgInfo>,
+    pub out_args: Vec<ArgInfo>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct SignalInfo {
+    pub name: String,
+    pub args: Vec<ArgInfo>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ArgInfo {
+    pub name: Option<String>,
+    /// D-Bus type signature, e.g. `a{sv}`.
+    pub signature: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct PropertyInfo {
+    pub name: String,
+    /// D-Bus type signature, e.g. `a{sv}`.
+    pub signature: String,
+    pub access: PropertyAccess,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum PropertyAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl PropertyAccess {
+    pub fn is_readable(self) -> bool {
+        self != PropertyAccess::Write
+    }
+
+    pub fn is_writable(self) -> bool {
+        self != PropertyAccess::Read
+    }
+}
+
+struct Tag {
+    name: String,
+    attributes: Vec<(String, String)>,
+    closing: bool,
+    empty: bool,
+}
+
+impl Tag {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|&(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn required(&self, name: &str) -> Result<String> {
+        match self.attribute(name) {
+            Some(value) => Ok(value.to_string()),
+            None => bail!(invalid(&format!("<{}> without {}", self.name, name))),
+        }
+    }
+
+    fn arg(&self) -> Result<ArgInfo> {
+        Ok(ArgInfo {
+            name: self.attribute("name").map(|name| name.to_string()),
+            signature: self.required("type")?,
+        })
+    }
+}
+
+/// Parses introspection XML. Only the interfaces of the root node are
+/// collected, annotations are skipped.
+pub fn parse_introspection(xml: &str) -> Result<Introspection> {
+    let mut result = Introspection::default();
+    let mut stack: Vec<String> = Vec::new();
+
+    for tag in tags(xml)? {
+        if tag.closing {
+            if stack.pop().as_ref() != Some(&tag.name) {
+                bail!(invalid(&format!("unexpected </{}>", tag.name)));
+            }
+            continue;
+        }
+
+        let parent: Vec<&str> = stack.iter().map(|name| name.as_str()).collect();
+
+        match (&parent[..], tag.name.as_str()) {
+            (&["node"], "node") => result.nodes.push(tag.required("name")?),
+            (&["node"], "interface") => result.interfaces.push(InterfaceInfo {
+                name: tag.required("name")?,
+                ..Default::default()
+            }),
+            (&["node", "interface"], member) => {
+                let interface = result.interfaces.last_mut().unwrap();
+                match member {
+                    "method" => interface.methods.push(MethodInfo {
+                        name: tag.required("name")?,
+                        ..Default::default()
+                    }),
+                    "signal" => interface.signals.push(SignalInfo {
+                        name: tag.required("name")?,
+                        ..Default::default()
+                    }),
+                    "property" => interface.properties.push(PropertyInfo {
+                        name: tag.required("name")?,
+                        signature: tag.required("type")?,
+                        access: match tag.attribute("access") {
+                            Some("read") => PropertyAccess::Read,
+                            Some("write") => PropertyAccess::Write,
+                            Some("readwrite") => PropertyAccess::ReadWrite,
+                            _ => bail!(invalid("property without valid access")),
+                        },
+                    }),
+                    _ => {}
+                }
+            }
+            (&["node", "interface", "method"], "arg") => {
+                let interface = result.interfaces.last_mut().unwrap();
+                let method = interface.methods.last_mut().unwrap();
+                // Method arguments are inputs unless stated otherwise
+                if tag.attribute("direction") == Some("out") {
+                    method.out_args.push(tag.arg()?);
+                } else {
+                    method.in_args.push(tag.arg()?);
+                }
+            }
+            (&["node", "interface", "signal"], "arg") => {
+                let interface = result.interfaces.last_mut().unwrap();
+                interface.signals.last_mut().unwrap().args.push(tag.arg()?);
+            }
+            (&[], "node") => {}
+            (&[], name) => bail!(invalid(&format!("unexpected root <{}>", name))),
+            _ => {}
+        }
+
+        if !tag.empty {
+            stack.push(tag.name);
+        }
+    }
+
+    if !stack.is_empty() {
+        bail!(invalid("unterminated <node>"));
+    }
+
+    Ok(result)
+}
+
+fn invalid(details: &str) -> ErrorKind {
+    ErrorKind::DBusAPI(format!("Invalid introspection data: {}", details))
+}
+
+/// Splits the document into element tags, dropping text, comments, the XML
+/// declaration and the doctype.
+fn tags(xml: &str) -> Result<Vec<Tag>> {
+    let mut tags = Vec::new();
+    let mut rest = xml;
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+
+        if rest.starts_with("<!--") {
+            let end = rest
+                .find("-->")
+                .ok_or_else(|| invalid("unterminated comment"))?;
+            rest = &rest[end + 3..];
+            continue;
+        }
+
+        let end = rest.find('>').ok_or_else(|| invalid("unterminated tag"))?;
+        let content = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        if content.starts_with('?') || content.starts_with('!') {
+            continue;
+        }
+
+        tags.push(tag(content)?);
+    }
+
+    Ok(tags)
+}
+
+fn tag(content: &str) -> Result<Tag> {
+    let closing = content.starts_with('/');
+    let empty = content.ends_with('/');
+
+    let content = content.trim_start_matches('/').trim_end_matches('/');
+
+    let name_end = content.find(char::is_whitespace).unwrap_or(content.len());
+    let name = content[..name_end].to_string();
+
+    let mut attributes = Vec::new();
+    let mut rest = content[name_end..].trim_start();
+
+    while !rest.is_empty() {
+        let eq = rest
+            .find('=')
+            .ok_or_else(|| invalid(&format!("malformed attribute in <{}>", name)))?;
+        let key = rest[..eq].trim().to_string();
+        rest = rest[eq + 1..].trim_start();
+
+        let quote = match rest.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => quote,
+            _ => bail!(invalid(&format!("unquoted attribute in <{}>", name))),
+        };
+        let value_end = rest[1..]
+            .find(quote)
+            .ok_or_else(|| invalid(&format!("unterminated attribute in <{}>", name)))?;
+
+        attributes.push((key, unescape(&rest[1..=value_end])));
+        rest = rest[value_end + 2..].trim_start();
+    }
+
+    Ok(Tag {
+        name,
+        attributes,
+        closing,
+        empty,
+    })
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_introspection() {
+        let xml = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+"http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<!-- GDBus 2.56.4 -->
+<node>
+  <interface name="org.freedesktop.NetworkManager">
+    <method name="CheckpointCreate">
+      <arg type="ao" name="devices" direction="in"/>
+      <arg type="u" name="rollback_timeout" direction="in"/>
+      <arg type="u" name="flags" direction="in"/>
+      <arg type="o" name="checkpoint" direction="out"/>
+    </method>
+    <signal name="DeviceAdded">
+      <arg type="o" name="device_path"/>
+    </signal>
+    <property type="b" name="WirelessEnabled" access="readwrite">
+      <annotation name="org.freedesktop.DBus.Property.EmitsChangedSignal" value="true"/>
+    </property>
+    <property type="a{sv}" name="GlobalDnsConfiguration" access="readwrite"/>
+    <property type="s" name="Version" access="read"/>
+  </interface>
+  <node name="AgentManager"/>
+  <node name="Devices"/>
+</node>
+"#;
+
+        let introspection = parse_introspection(xml).unwrap();
+
+        assert_eq!(introspection.nodes, vec!["AgentManager", "Devices"]);
+        assert!(!introspection.has_interface("org.freedesktop.NetworkManager.WifiP2P"));
+
+        let interface = introspection
+            .interface("org.freedesktop.NetworkManager")
+            .unwrap();
+
+        assert!(interface.has_method("CheckpointCreate"));
+        assert!(interface.has_signal("DeviceAdded"));
+        assert_eq!(interface.methods[0].in_args.len(), 3);
+        assert_eq!(
+            interface.methods[0].out_args,
+            vec![ArgInfo {
+                name: Some("checkpoint".into()),
+                signature: "o".into(),
+            }]
+        );
+
+        let property = interface.property("GlobalDnsConfiguration").unwrap();
+        assert_eq!(property.signature, "a{sv}");
+        assert!(property.access.is_writable());
+        assert!(!interface.property("Version").unwrap().access.is_writable());
+
+        assert!(parse_introspection("<node><interface name=\"a\"></node>").is_err());
+    }
+}
@@ -0,0 +1,17 @@
+use std::net::IpAddr;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct IpAddress {
+    pub address: IpAddr,
+    pub prefix: u32,
+}
+
+/// IPv4 or IPv6 configuration currently applied to a device.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct IpConfig {
+    pub addresses: Vec<IpAddress>,
+    pub gateway: Option<IpAddr>,
+    pub nameservers: Vec<IpAddr>,
+}
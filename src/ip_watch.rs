@@ -0,0 +1,293 @@
+use std::mem;
+use std::rc::Rc;
+
+use dbus::arg::{Dict, Iter, Variant};
+use dbus::{Connection as DBusConnection, ConnectionItem, Message};
+
+use dbus_api::open_connection;
+use dbus_nm::DBusNetworkManager;
+use errors::*;
+
+use ip_config::{IpAddress, IpConfig};
+use signal_wait::properties_changed_rule;
+
+const NM_DEVICE_INTERFACE: &str = "org.freedesktop.NetworkManager.Device";
+const NM_IP4_CONFIG_INTERFACE: &str = "org.freedesktop.NetworkManager.IP4Config";
+const NM_IP6_CONFIG_INTERFACE: &str = "org.freedesktop.NetworkManager.IP6Config";
+
+/// A change of the IPv4 or IPv6 configuration of a device, e.g. a new DHCP
+/// lease or an address that expired.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct IpConfigEvent {
+    pub ipv6: bool,
+    pub previous: Option<IpConfig>,
+    pub current: Option<IpConfig>,
+}
+
+impl IpConfigEvent {
+    /// Addresses present in the current configuration only.
+    pub fn added_addresses(&self) -> Vec<&IpAddress> {
+        difference(&self.current, &self.previous)
+    }
+
+    /// Addresses present in the previous configuration only.
+    pub fn removed_addresses(&self) -> Vec<&IpAddress> {
+        difference(&self.previous, &self.current)
+    }
+}
+
+fn difference<'a>(config: &'a Option<IpConfig>, other: &Option<IpConfig>) -> Vec<&'a IpAddress> {
+    let other: &[IpAddress] = match *other {
+        Some(ref other) => &other.addresses,
+        None => &[],
+    };
+
+    match *config {
+        Some(ref config) => config
+            .addresses
+            .iter()
+            .filter(|address| !other.contains(address))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// The IPv4 or IPv6 configuration of the watched device.
+struct WatchedConfig {
+    ipv6: bool,
+    /// The configuration object listened to, `None` while the device has none.
+    path: Option<String>,
+    config: Option<IpConfig>,
+    /// Whether the configuration has to be read again. It stays set after a
+    /// failed read, so that the read is retried.
+    stale: bool,
+}
+
+impl WatchedConfig {
+    fn interface(&self) -> &'static str {
+        if self.ipv6 {
+            NM_IP6_CONFIG_INTERFACE
+        } else {
+            NM_IP4_CONFIG_INTERFACE
+        }
+    }
+
+    /// The property of the device holding the path of the configuration.
+    fn property(&self) -> &'static str {
+        if self.ipv6 {
+            "Ip6Config"
+        } else {
+            "Ip4Config"
+        }
+    }
+}
+
+/// Blocks watching the IP configuration of the device at `path`, invoking
+/// `callback` with each change until it returns `false`.
+///
+/// NetworkManager replaces the configuration objects of a device on some
+/// changes and updates them in place on others, so both the device and its
+/// current configuration objects are listened to. Only the configuration
+/// whose object changed or was replaced is read again and compared with the
+/// last one seen. A read failing, e.g. as the object is being replaced, is
+/// retried on the next signal or within a second.
+pub fn watch_ip_config<F>(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    path: &str,
+    mut callback: F,
+) -> Result<()>
+where
+    F: FnMut(IpConfigEvent) -> bool,
+{
+    let connection = open_connection(dbus_manager.bus())?;
+
+    connection.add_match(&object_rule(NM_DEVICE_INTERFACE, path))?;
+
+    let mut watched = Vec::new();
+
+    for &ipv6 in &[false, true] {
+        let mut config = WatchedConfig {
+            ipv6,
+            path: None,
+            config: None,
+            stale: false,
+        };
+
+        config.path = dbus_manager.get_device_ip_config_path(path, ipv6);
+
+        if let Some(ref config_path) = config.path {
+            connection.add_match(&object_rule(config.interface(), config_path))?;
+            config.config = Some(get_ip_config(dbus_manager, config_path, ipv6)?);
+        }
+
+        watched.push(config);
+    }
+
+    for item in connection.iter(1000) {
+        if let ConnectionItem::Signal(ref message) = item {
+            mark_stale(message, path, &mut watched);
+        }
+
+        for config in watched.iter_mut().filter(|config| config.stale) {
+            let event = match refresh(dbus_manager, &connection, path, config) {
+                Ok(Some(event)) => event,
+                Ok(None) => continue,
+                Err(e) => {
+                    debug!("Unable to read IP configuration of {}: {}", path, e);
+                    continue;
+                }
+            };
+
+            if !callback(event) {
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Matches property changes of the object at `path`.
+fn object_rule(interface: &str, path: &str) -> String {
+    format!("{},path='{}'", properties_changed_rule(interface), path)
+}
+
+/// Flags the configurations a `PropertiesChanged` signal concerns: those whose
+/// object changed, or whose object the device replaced.
+fn mark_stale(message: &Message, device_path: &str, watched: &mut [WatchedConfig]) {
+    let path = message.path();
+    let path = path.as_deref();
+
+    if path == Some(device_path) {
+        let changed: Vec<&str> = match message.get2::<&str, Dict<&str, Variant<Iter>, _>>() {
+            (Some(_), Some(properties)) => properties.map(|(name, _)| name).collect(),
+            _ => return,
+        };
+
+        for config in watched {
+            if changed.contains(&config.property()) {
+                config.stale = true;
+            }
+        }
+    } else {
+        for config in watched {
+            if path.is_some() && config.path.as_deref() == path {
+                config.stale = true;
+            }
+        }
+    }
+}
+
+/// Reads a stale configuration again, following its object if the device
+/// replaced it, and returns the change if there was one.
+fn refresh(
+    dbus_manager: &DBusNetworkManager,
+    connection: &DBusConnection,
+    device_path: &str,
+    watched: &mut WatchedConfig,
+) -> Result<Option<IpConfigEvent>> {
+    let path = dbus_manager.get_device_ip_config_path(device_path, watched.ipv6);
+
+    if path != watched.path {
+        if let Some(ref previous) = watched.path {
+            if let Err(e) = connection.remove_match(&object_rule(watched.interface(), previous)) {
+                debug!("Unable to remove match rule for {}: {}", previous, e);
+            }
+        }
+
+        if let Some(ref path) = path {
+            connection.add_match(&object_rule(watched.interface(), path))?;
+        }
+
+        watched.path = path;
+    }
+
+    let current = match watched.path {
+        Some(ref path) => Some(get_ip_config(dbus_manager, path, watched.ipv6)?),
+        None => None,
+    };
+
+    watched.stale = false;
+
+    if current == watched.config {
+        return Ok(None);
+    }
+
+    debug!("IP configuration of {} changed: {:?}", device_path, current);
+
+    Ok(Some(IpConfigEvent {
+        ipv6: watched.ipv6,
+        previous: mem::replace(&mut watched.config, current.clone()),
+        current,
+    }))
+}
+
+fn get_ip_config(dbus_manager: &DBusNetworkManager, path: &str, ipv6: bool) -> Result<IpConfig> {
+    if ipv6 {
+        dbus_manager.get_ip6_config(path)
+    } else {
+        dbus_manager.get_ip4_config(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use dbus::Path;
+
+    fn watched() -> Vec<WatchedConfig> {
+        [false, true]
+            .iter()
+            .map(|&ipv6| WatchedConfig {
+                ipv6,
+                path: Some(format!(
+                    "/org/freedesktop/NetworkManager/IP{}Config/1",
+                    if ipv6 { 6 } else { 4 }
+                )),
+                config: None,
+                stale: false,
+            })
+            .collect()
+    }
+
+    fn properties_changed(path: &str, interface: &str, property: &str) -> Message {
+        let root = Path::new("/").unwrap();
+
+        Message::new_signal(path, "org.freedesktop.DBus.Properties", "PropertiesChanged")
+            .unwrap()
+            .append3(
+                interface,
+                Dict::new(vec![(property, Variant(root))]),
+                Vec::<String>::new(),
+            )
+    }
+
+    fn stale(watched: &[WatchedConfig]) -> Vec<bool> {
+        watched.iter().map(|config| config.stale).collect()
+    }
+
+    #[test]
+    fn test_mark_stale() {
+        let device = "/org/freedesktop/NetworkManager/Devices/1";
+
+        let mut configs = watched();
+        let message = properties_changed(device, NM_DEVICE_INTERFACE, "State");
+        mark_stale(&message, device, &mut configs);
+        assert_eq!(stale(&configs), [false, false]);
+
+        let message = properties_changed(device, NM_DEVICE_INTERFACE, "Ip6Config");
+        mark_stale(&message, device, &mut configs);
+        assert_eq!(stale(&configs), [false, true]);
+
+        let mut configs = watched();
+        let message = properties_changed(
+            "/org/freedesktop/NetworkManager/IP4Config/1",
+            NM_IP4_CONFIG_INTERFACE,
+            "AddressData",
+        );
+        mark_stale(&message, device, &mut configs);
+        assert_eq!(stale(&configs), [true, false]);
+    }
+}
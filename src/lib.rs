@@ -6,29 +6,189 @@
 #[macro_use]
 extern crate error_chain;
 
+// With the `tracing` feature the `debug!`, `info!`, ... macros used
+// throughout the crate come from `tracing` instead of `log`
+#[cfg(not(feature = "tracing"))]
 #[macro_use]
 extern crate log;
 
+#[cfg(feature = "tracing")]
+#[macro_use]
+extern crate tracing;
+
 #[macro_use]
 extern crate bitflags;
 
 extern crate dbus;
 
+#[cfg(feature = "probe")]
+extern crate libc;
+
 extern crate ascii;
 
+extern crate network_manager_types;
+
+extern crate zeroize;
+
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+
+#[cfg(feature = "json")]
+extern crate serde_json;
+
+#[cfg(feature = "psk")]
+extern crate pbkdf2;
+
+#[cfg(feature = "psk")]
+extern crate sha1;
+
+/// Enters an info level span until the end of the enclosing block. Without
+/// the `tracing` feature this expands to nothing.
+#[cfg(feature = "tracing")]
+macro_rules! enter_span {
+    ($($arg:tt)*) => {
+        let _span = info_span!($($arg)*).entered();
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! enter_span {
+    ($($arg:tt)*) => {};
+}
+
+pub mod connectivity;
 pub mod errors;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+#[cfg(feature = "test-support")]
+pub mod hwsim;
+#[cfg(feature = "json")]
+pub mod report;
 
+mod apply;
+mod backend;
+mod batch;
+#[cfg(feature = "events")]
+mod carrier;
 mod connection;
+#[cfg(feature = "events")]
+mod daemon;
 mod dbus_api;
+#[cfg(feature = "wwan")]
+mod dbus_mm;
 mod dbus_nm;
+#[cfg(feature = "wifi")]
+mod dbus_supplicant;
 mod device;
+mod device_watch;
+mod diagnostics;
+#[cfg(feature = "wifi")]
+mod enterprise;
+#[cfg(feature = "probe")]
+mod failover;
+#[cfg(feature = "hotspot")]
+mod hotspot;
+mod idle;
+mod introspect;
+mod ip_config;
+#[cfg(feature = "events")]
+mod ip_watch;
+mod lldp;
 mod manager;
+mod preflight;
+mod rate_limit;
+mod redact;
+mod restore;
+#[cfg(feature = "wifi")]
+mod roam;
+#[cfg(feature = "wifi")]
+mod scan;
+mod secret;
+#[cfg(feature = "secret-store")]
+mod secret_store;
 mod service;
-mod ssid;
+mod settings;
+mod signal_wait;
+#[cfg(feature = "sleep")]
+mod sleep;
+#[cfg(feature = "events")]
+mod state_cache;
+#[cfg(feature = "events")]
+mod state_log;
+mod status;
+mod template;
+mod usage;
+#[cfg(feature = "wifi")]
 mod wifi;
+#[cfg(feature = "wwan")]
+mod wwan;
 
-pub use connection::{Connection, ConnectionSettings, ConnectionState};
-pub use device::{Device, DeviceState, DeviceType};
-pub use manager::{Connectivity, NetworkManager};
+pub use apply::{ConfigPlan, PlannedChange};
+pub use backend::WifiBackend;
+#[cfg(feature = "events")]
+pub use carrier::CarrierEvent;
+pub use connection::{
+    Connection, ConnectionFlags, ConnectionSettings, ConnectionState, EnsureReport,
+    GroupActivation, OnFailure,
+};
+#[cfg(feature = "events")]
+pub use daemon::DaemonEvent;
+pub use dbus_api::{Bus, CallInfo, CallObserver};
+pub use device::{
+    AutoconnectInhibitor, Availability, Device, DeviceState, DeviceStatistics, DriverInfo,
+    StatisticsGuard, UnavailableReason,
+};
+pub use diagnostics::{ActiveConnectionDiagnostics, DeviceDiagnostics, Diagnostics};
+#[cfg(feature = "wifi")]
+pub use enterprise::{EnterpriseInputs, EnterpriseTemplate};
+#[cfg(feature = "probe")]
+pub use failover::{FailoverAction, FailoverManager};
+#[cfg(feature = "hotspot")]
+pub use hotspot::{Hotspot, HotspotClient, HotspotConfig, HotspotSecurity};
+pub use idle::IdleMonitor;
+pub use introspect::{
+    ArgInfo, InterfaceInfo, Introspection, MethodInfo, PropertyAccess, PropertyInfo, SignalInfo,
+};
+pub use ip_config::{IpAddress, IpConfig};
+#[cfg(feature = "events")]
+pub use ip_watch::IpConfigEvent;
+pub use lldp::{LldpNeighbor, LldpVlan};
+pub use manager::{Connectivity, NetworkManager, NetworkManagerState};
+pub use network_manager_types::{
+    ApSecurity, AsSsidSlice, DeviceType, EthtoolConfig, IntoSsid, NM80211ApFlags,
+    NM80211ApSecurityFlags, SecretExport, SecretFlags, Security, Setting, SriovConfig, SriovVf,
+    Ssid, SsidError, SsidSlice, Value,
+};
+pub use preflight::ConflictingService;
+pub use rate_limit::{ActivationLimitEvent, ActivationLimiter};
+#[cfg(feature = "wifi")]
+pub use roam::{RoamAction, RoamPolicy};
+#[cfg(feature = "wifi")]
+pub use scan::{BackgroundScanInhibitor, ScanScheduler};
+pub use secret::SecretString;
+#[cfg(feature = "secret-store")]
+pub use secret_store::SecretStore;
 pub use service::ServiceState;
-pub use wifi::{AccessPoint, AccessPointCredentials, Security};
+pub use settings::{Certificate, ConnectionProfile, Ieee8021xConfig};
+#[cfg(feature = "sleep")]
+pub use sleep::SleepEvent;
+#[cfg(feature = "events")]
+pub use state_cache::{StateCache, StateSnapshot};
+#[cfg(feature = "events")]
+pub use state_log::{StateChange, StateEvent};
+pub use status::{DeviceStatus, FastStatus, Status};
+pub use template::ProfileTemplate;
+pub use usage::{
+    usage_month, FileUsageStore, MemoryUsageStore, UsageAccountant, UsageRecord, UsageStore,
+};
+#[cfg(feature = "hotspot")]
+pub use wifi::HotspotPreflight;
+#[cfg(feature = "wifi")]
+pub use wifi::{
+    channels, debug, group_networks, power, psk, AccessPoint, AccessPointCredentials, Band,
+    Network, SupplicantLogLevel, WiFiDevice, WifiPowerSave, WpsMethod,
+};
+#[cfg(feature = "wwan")]
+pub use wwan::{apn, ApnDatabase, ApnSettings, ModemDevice, SimLock, SimLockStatus, SimSlot};
@@ -0,0 +1,23 @@
+/// A neighbor discovered through LLDP on a wired device, e.g. the switch
+/// port the device is plugged into.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct LldpNeighbor {
+    pub chassis_id: String,
+    pub chassis_id_type: u32,
+    pub port_id: String,
+    pub port_id_type: u32,
+    pub port_description: Option<String>,
+    pub system_name: Option<String>,
+    pub system_description: Option<String>,
+    /// Port VLAN ID advertised by the neighbor.
+    pub pvid: Option<u32>,
+    pub vlans: Vec<LldpVlan>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct LldpVlan {
+    pub vid: u32,
+    pub name: String,
+}
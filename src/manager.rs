@@ -1,11 +1,45 @@
+use std::collections::HashMap;
 use std::rc::Rc;
+use std::time::Duration;
 
+use dbus_api::{shared_connection, Bus, CallObserver};
 use dbus_nm::DBusNetworkManager;
 use errors::*;
+#[cfg(feature = "wwan")]
+use network_manager_types::DeviceType;
+use network_manager_types::Value;
 
-use connection::{get_active_connections, get_connections, Connection};
-use device::{get_device_by_interface, get_devices, Device};
-use service::{get_service_state, start_service, stop_service, ServiceState};
+use apply::{apply_config, plan_config, ConfigPlan};
+#[cfg(feature = "wifi")]
+use connection::rotate_psk;
+use connection::{
+    activate_group, activate_many, add_connection, create_connection, create_connection_with,
+    create_virtual_device, delete_connections, ensure_connection, get_active_connections,
+    get_connections, get_connections_without, get_recently_used_networks, import_many,
+    switch_connection, Connection, ConnectionFlags, ConnectionState, EnsureReport, GroupActivation,
+    OnFailure,
+};
+#[cfg(feature = "events")]
+use daemon::{watch_daemon, DaemonEvent};
+use device::{
+    get_device_by_ifindex, get_device_by_interface, get_devices, wait_for_device, Device,
+    PathGetter,
+};
+use diagnostics::{get_diagnostics, Diagnostics};
+use introspect::Introspection;
+#[cfg(feature = "wifi")]
+use network_manager_types::AsSsidSlice;
+use preflight::{find_conflicting_services, ConflictingService};
+use rate_limit::ActivationLimiter;
+use redact::set_redaction;
+use service::{enable_service, get_service_state, is_service_enabled, ServiceState};
+#[cfg(feature = "service")]
+use service::{restart_service, start_service, stop_service};
+use settings::ConnectionProfile;
+#[cfg(feature = "sleep")]
+use sleep::{watch_sleep, SleepEvent};
+use status::{get_fast_status, get_status, FastStatus, Status};
+use usage::{watch_usage, UsageAccountant};
 
 pub struct NetworkManager {
     dbus_manager: Rc<DBusNetworkManager>,
@@ -24,19 +58,215 @@ impl NetworkManager {
         }
     }
 
-    /// Starts the Network Manager service.
+    /// Like `new`, but connecting to NetworkManager on the given bus, e.g. the
+    /// session bus.
+    pub fn with_bus(bus: Bus, method_timeout: Option<u64>) -> Result<Self> {
+        Ok(NetworkManager {
+            dbus_manager: Rc::new(DBusNetworkManager::with_bus(bus, method_timeout)?),
+        })
+    }
+
+    /// Like `new`, but without changing anything, e.g. for a `--dry-run`
+    /// option. Reads work as usual, while the D-Bus calls that would change
+    /// the state of NetworkManager, wpa_supplicant or ModemManager are only
+    /// recorded, see `planned_operations`, and waits for state changes return
+    /// at once. An operation that needs the result of a skipped call to go
+    /// on, e.g. the path of an added profile, stops there with
+    /// `ErrorKind::DryRun` listing the calls planned so far.
+    pub fn read_only() -> Self {
+        NetworkManager {
+            dbus_manager: Rc::new(DBusNetworkManager::read_only(None)),
+        }
+    }
+
+    /// Sets how often operations waiting for a connection or device state,
+    /// such as `Connection::activate`, check the state again when no signal
+    /// arrived in between. One second by default and at least 10 ms. Shorter
+    /// intervals suit tests, longer ones save wakeups on battery powered
+    /// devices.
+    pub fn with_wait_interval(self, interval: Duration) -> Self {
+        self.dbus_manager.set_wait_interval(interval);
+        self
+    }
+
+    /// Sets how long operations wait for a connection or device to reach a
+    /// state before returning the current one. Defaults to the method
+    /// timeout.
+    pub fn with_wait_deadline(self, deadline: Duration) -> Self {
+        self.dbus_manager.set_wait_deadline(deadline);
+        self
+    }
+
+    /// Limits how often activation of the same connection on the same device
+    /// is attempted. Attempts made too soon fail with
+    /// `ErrorKind::ActivationThrottled`. Unlimited by default.
+    pub fn with_activation_limiter(self, limiter: ActivationLimiter) -> Self {
+        self.dbus_manager.set_activation_limiter(Some(limiter));
+        self
+    }
+
+    /// Forgets earlier activation attempts, e.g. once the caller knows the
+    /// network is back, so that the next attempts are not throttled.
+    pub fn reset_activation_limiter(&self) {
+        self.dbus_manager.reset_activation_limiter()
+    }
+
+    /// Sets how many operations of `delete_connections`, `activate_many`
+    /// and `import_many` run at the same time, each on a bus connection of
+    /// its own. Long lists of profiles and access points are read the same
+    /// way. Four by default, one runs them one after another.
+    ///
+    /// The threads and their connections are kept for later batches until
+    /// the manager is dropped.
+    pub fn with_batch_concurrency(self, concurrency: usize) -> Self {
+        self.dbus_manager.set_batch_concurrency(concurrency);
+        self
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.dbus_manager.is_read_only()
+    }
+
+    /// Descriptions of the D-Bus calls skipped in read-only mode since the
+    /// last call, in order, e.g. `call org.freedesktop.NetworkManager.Device.
+    /// Disconnect on /org/freedesktop/NetworkManager/Devices/3`.
+    pub fn planned_operations(&self) -> Vec<String> {
+        self.dbus_manager.take_planned()
+    }
+
+    /// Like `new`, but multiplexing one system bus connection between all
+    /// managers created with `shared` or `shared_with_method_timeout` on the
+    /// current thread. The connection is closed once the last of them is
+    /// dropped.
+    pub fn shared() -> Result<Self> {
+        NetworkManager::shared_with_bus(Bus::System, None)
+    }
+
+    pub fn shared_with_method_timeout(timeout: u64) -> Result<Self> {
+        NetworkManager::shared_with_bus(Bus::System, Some(timeout))
+    }
+
+    /// Like `shared`, but on the given bus, sharing the connection with the
+    /// other managers of the current thread on that bus.
+    pub fn shared_with_bus(bus: Bus, method_timeout: Option<u64>) -> Result<Self> {
+        Ok(NetworkManager {
+            dbus_manager: Rc::new(DBusNetworkManager::with_connection(
+                shared_connection(bus)?,
+                bus,
+                method_timeout,
+            )),
+        })
+    }
+
+    /// Install hooks invoked around every D-Bus call made to NetworkManager,
+    /// replacing any previous observer. `None` removes the observer.
+    pub fn set_call_observer(&self, observer: Option<Rc<dyn CallObserver>>) {
+        self.dbus_manager.set_call_observer(observer)
+    }
+
+    /// Block watching the NetworkManager daemon for stops and restarts,
+    /// invoking `callback` until it returns `false`.
+    ///
+    /// After a restart calls on previously obtained objects fail with
+    /// `ErrorKind::DaemonRestarted`. Fetch them again and call `resync`.
+    #[cfg(feature = "events")]
+    pub fn watch_daemon<F>(callback: F) -> Result<()>
+    where
+        F: FnMut(DaemonEvent) -> bool,
+    {
+        watch_daemon(Bus::System, callback)
+    }
+
+    /// Like `watch_daemon`, but for NetworkManager on the given bus.
+    #[cfg(feature = "events")]
+    pub fn watch_daemon_on<F>(bus: Bus, callback: F) -> Result<()>
+    where
+        F: FnMut(DaemonEvent) -> bool,
+    {
+        watch_daemon(bus, callback)
+    }
+
+    /// Block watching system suspend and resume, invoking `callback` until
+    /// it returns `false`, so that connections dropped by a suspend can be
+    /// told apart from outages.
+    #[cfg(feature = "sleep")]
+    pub fn watch_sleep<F>(&self, callback: F) -> Result<()>
+    where
+        F: FnMut(SleepEvent) -> bool,
+    {
+        watch_sleep(&self.dbus_manager, callback)
+    }
+
+    /// Block accounting the traffic of activated connections with
+    /// `accountant`, invoking `callback` every `interval` until it returns
+    /// `false`, see `UsageAccountant`.
+    pub fn watch_usage<F>(
+        &self,
+        accountant: &mut UsageAccountant,
+        interval: Duration,
+        callback: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&UsageAccountant) -> bool,
+    {
+        watch_usage(&self.dbus_manager, accountant, interval, callback)
+    }
+
+    /// Acknowledge a daemon restart once objects have been fetched again, so
+    /// that failing calls are no longer attributed to the restart.
+    pub fn resync(&self) -> Result<()> {
+        self.dbus_manager.resync()
+    }
+
+    /// Turn masking of secrets such as PSKs and passwords in log output and
+    /// `Debug` formatting on or off, e.g. to debug locally. On by default.
+    pub fn set_log_redaction(enabled: bool) {
+        set_redaction(enabled)
+    }
+
+    #[cfg(feature = "service")]
+    /// Starts the Network Manager service. Like the other service functions
+    /// it talks to systemd on the system bus.
     pub fn start_service(timeout: u64) -> Result<ServiceState> {
-        start_service(timeout)
+        start_service(Bus::System, timeout)
     }
 
+    #[cfg(feature = "service")]
     /// Stops the Network Manager service.
     pub fn stop_service(timeout: u64) -> Result<ServiceState> {
-        stop_service(timeout)
+        stop_service(Bus::System, timeout)
+    }
+
+    #[cfg(feature = "service")]
+    /// Restarts the Network Manager service.
+    pub fn restart_service(timeout: u64) -> Result<ServiceState> {
+        restart_service(Bus::System, timeout)
+    }
+
+    /// Enables the Network Manager service to start on boot.
+    pub fn enable_service() -> Result<()> {
+        enable_service(Bus::System)
+    }
+
+    /// Checks whether the Network Manager service starts on boot.
+    pub fn is_service_enabled() -> Result<bool> {
+        is_service_enabled(Bus::System)
+    }
+
+    /// Checks whether the Network Manager service is running.
+    pub fn is_service_running() -> Result<bool> {
+        Ok(get_service_state(Bus::System)? == ServiceState::Active)
+    }
+
+    /// Look for other network daemons that may be holding `interface`, such
+    /// as a standalone wpa_supplicant, iwd, ConnMan or systemd-networkd.
+    pub fn find_conflicting_services(interface: &str) -> Result<Vec<ConflictingService>> {
+        find_conflicting_services(Bus::System, interface)
     }
 
     /// Gets the state of the Network Manager service.
     pub fn get_service_state() -> Result<ServiceState> {
-        get_service_state()
+        get_service_state(Bus::System)
     }
 
     /// Get a list of Network Manager connections sorted by path.
@@ -44,19 +274,240 @@ impl NetworkManager {
         get_connections(&self.dbus_manager)
     }
 
+    /// Get the connections having none of `flags`, sorted by path.
+    pub fn get_connections_without(&self, flags: ConnectionFlags) -> Result<Vec<Connection>> {
+        get_connections_without(&self.dbus_manager, flags)
+    }
+
+    /// Get the connections created by users or applications, leaving out the
+    /// profiles NetworkManager generated itself, such as
+    /// "Wired connection 1", and those representing external configuration.
+    pub fn get_user_connections(&self) -> Result<Vec<Connection>> {
+        get_connections_without(
+            &self.dbus_manager,
+            ConnectionFlags::NM_GENERATED | ConnectionFlags::EXTERNAL,
+        )
+    }
+
     pub fn get_active_connections(&self) -> Result<Vec<Connection>> {
         get_active_connections(&self.dbus_manager)
     }
 
+    /// Get up to `count` Wi-Fi connections that were connected before, most
+    /// recently used first.
+    pub fn recently_used_networks(&self, count: usize) -> Result<Vec<Connection>> {
+        get_recently_used_networks(&self.dbus_manager, count)
+    }
+
+    /// Add a connection profile to disk without activating it, e.g. to stage
+    /// the profiles of several sites and activate one of them later with
+    /// `Connection::activate`. `connection.autoconnect` is turned off unless
+    /// the profile sets it, so NetworkManager does not bring the profile up
+    /// by itself either.
+    pub fn add_connection(&self, profile: &ConnectionProfile) -> Result<Connection> {
+        add_connection(&self.dbus_manager, profile, true)
+    }
+
+    /// Like `add_connection`, but the profile is kept in memory only and lost
+    /// when NetworkManager restarts.
+    pub fn add_connection_unsaved(&self, profile: &ConnectionProfile) -> Result<Connection> {
+        add_connection(&self.dbus_manager, profile, false)
+    }
+
+    /// Add a connection profile and activate it, optionally on a specific
+    /// device.
+    pub fn create_connection(
+        &self,
+        profile: &ConnectionProfile,
+        device: Option<&Device>,
+    ) -> Result<(Connection, ConnectionState)> {
+        create_connection(&self.dbus_manager, profile, device.map(Device::path))
+    }
+
+    /// Add the profile of a virtual device, e.g. a bridge, VLAN or WireGuard
+    /// interface, activate it and return the device NetworkManager created
+    /// for it, failing if it does not show up within `timeout`. The device is
+    /// named by the `connection.interface-name` of the profile.
+    pub fn create_virtual_device(
+        &self,
+        profile: &ConnectionProfile,
+        timeout: Duration,
+    ) -> Result<(Connection, Device)> {
+        create_virtual_device(&self.dbus_manager, profile, timeout)
+    }
+
+    /// Add a connection profile and activate it like `create_connection`,
+    /// failing if it is not activated within the method timeout. What is left
+    /// behind on failure is decided by `on_failure`.
+    pub fn create_connection_with(
+        &self,
+        profile: &ConnectionProfile,
+        device: Option<&Device>,
+        on_failure: &OnFailure,
+    ) -> Result<Connection> {
+        create_connection_with(
+            &self.dbus_manager,
+            profile,
+            device.map(Device::path),
+            on_failure,
+        )
+    }
+
+    /// Make sure a profile matching `profile` exists and is active.
+    ///
+    /// The existing profile is looked up by UUID, or by ID if `profile` has no
+    /// UUID. Keys given in `profile` are created or updated, other keys of the
+    /// existing profile are left alone. Calling this repeatedly with the same
+    /// profile performs no changes once it has converged.
+    pub fn ensure(&self, profile: &ConnectionProfile) -> Result<EnsureReport> {
+        ensure_connection(&self.dbus_manager, profile)
+    }
+
+    /// Switch from one connection to another, e.g. to a profile with rotated
+    /// credentials. The old connection is only deactivated, and deleted with
+    /// `delete_old`, once the new one is activated. On failure the new
+    /// profile is kept and the old connection is brought back up, by
+    /// restoring a checkpoint of its devices taken before the switch with
+    /// `OnFailure::Rollback` and by activating it again otherwise. The
+    /// activation error is returned, with a failure to restore as its cause.
+    pub fn switch_connection(
+        &self,
+        from: &Connection,
+        to: &Connection,
+        delete_old: bool,
+        on_failure: &OnFailure,
+    ) -> Result<()> {
+        switch_connection(from, to, delete_old, on_failure)
+    }
+
+    /// Change the pre-shared key of the stored client connections for `ssid`,
+    /// e.g. after the key of a fleet's network was rotated. Active connections
+    /// are reactivated with the new key and have to reach full connectivity,
+    /// or `Unknown` if NetworkManager does not check it. If one fails, all
+    /// connections changed so far get their old key back and the error is
+    /// returned, with a failure to roll back as its cause.
+    #[cfg(feature = "wifi")]
+    pub fn rotate_psk<T>(&self, ssid: &T, new_psk: &str) -> Result<Vec<Connection>>
+    where
+        T: AsSsidSlice + ?Sized,
+    {
+        rotate_psk(&self.dbus_manager, ssid, new_psk)
+    }
+
+    /// Delete the profiles with the given UUIDs. One result per UUID is
+    /// returned, in the same order, so a failing profile does not stop the
+    /// others.
+    pub fn delete_connections(&self, uuids: &[&str]) -> Vec<Result<()>> {
+        delete_connections(&self.dbus_manager, uuids)
+    }
+
+    /// Activate several connections, returning one result per connection in
+    /// the same order.
+    pub fn activate_many(&self, connections: &[Connection]) -> Vec<Result<ConnectionState>> {
+        activate_many(&self.dbus_manager, connections)
+    }
+
+    /// Add and save several profiles without activating them, returning one
+    /// result per profile in the same order.
+    pub fn import_many(&self, profiles: &[ConnectionProfile]) -> Vec<Result<Connection>> {
+        import_many(&self.dbus_manager, profiles)
+    }
+
+    /// Activate a master connection, e.g. a bridge or bond, followed by its
+    /// slaves once the master device is up. A failing master fails the whole
+    /// group, failing slaves are reported in the result.
+    pub fn activate_group(
+        &self,
+        master: &Connection,
+        slaves: &[Connection],
+    ) -> Result<GroupActivation> {
+        activate_group(master, slaves)
+    }
+
+    /// Compare the full set of desired profiles against NetworkManager's and
+    /// plan the changes needed to converge. With `prune` set, profiles not
+    /// in `desired` are deleted as well, including ones NetworkManager
+    /// generated itself.
+    ///
+    /// Profiles can be loaded from a configuration document with the `serde`
+    /// feature, `desired` being e.g. a deserialized JSON array.
+    pub fn plan(&self, desired: &[ConnectionProfile], prune: bool) -> Result<ConfigPlan> {
+        plan_config(&self.dbus_manager, desired, prune)
+    }
+
+    /// Carry out a plan. Created and updated profiles are not activated,
+    /// that is left to their `autoconnect` setting or to `ensure`.
+    pub fn apply(&self, plan: &ConfigPlan) -> Result<()> {
+        apply_config(&self.dbus_manager, plan)
+    }
+
     /// Get a list of Network Manager devices.
     pub fn get_devices(&self) -> Result<Vec<Device>> {
         get_devices(&self.dbus_manager)
     }
 
+    /// The mobile broadband modems, each to be used through
+    /// `Device::as_modem_device`.
+    #[cfg(feature = "wwan")]
+    pub fn get_modem_devices(&self) -> Result<Vec<Device>> {
+        Ok(get_devices(&self.dbus_manager)?
+            .into_iter()
+            .filter(|device| *device.device_type() == DeviceType::Modem)
+            .collect())
+    }
+
     pub fn get_device_by_interface(&self, interface: &str) -> Result<Device> {
         get_device_by_interface(&self.dbus_manager, interface)
     }
 
+    /// Wait until NetworkManager manages the device for `interface`, e.g. at
+    /// boot when the application may start before the driver finished probing
+    /// or udev finished renaming the interface. Fails once `timeout` elapsed.
+    pub fn wait_for_device(&self, interface: &str, timeout: Duration) -> Result<Device> {
+        wait_for_device(&self.dbus_manager, interface, true, timeout)
+    }
+
+    /// Look up a device by its kernel interface index, e.g. one reported by
+    /// netlink.
+    pub fn get_device_by_ifindex(&self, ifindex: u32) -> Result<Device> {
+        get_device_by_ifindex(&self.dbus_manager, ifindex)
+    }
+
+    /// Collect a report of the daemon, device and connection state for
+    /// troubleshooting.
+    pub fn diagnostics(&self) -> Result<Diagnostics> {
+        get_diagnostics(&self.dbus_manager)
+    }
+
+    /// Introspect the NetworkManager object at `path`, e.g. to check whether
+    /// optional interfaces such as `org.freedesktop.NetworkManager.Device.WifiP2P`
+    /// are available.
+    pub fn introspect(&self, path: &str) -> Result<Introspection> {
+        self.dbus_manager.introspect(path)
+    }
+
+    /// Checks whether NetworkManager was built with checkpoint support,
+    /// which `OnFailure::Rollback` relies on.
+    pub fn supports_checkpoints(&self) -> Result<bool> {
+        self.dbus_manager.supports_checkpoints()
+    }
+
+    /// Get the global state, primary connection, SSID and address in a few
+    /// D-Bus calls, cheap enough to poll every second.
+    pub fn fast_status(&self) -> Result<FastStatus> {
+        get_fast_status(&self.dbus_manager)
+    }
+
+    /// Get the global state, primary connection and the state, addresses and
+    /// Wi-Fi signal of every device in one call.
+    pub fn status(&self) -> Result<Status> {
+        get_status(&self.dbus_manager)
+    }
+
+    pub fn get_version(&self) -> Result<String> {
+        self.dbus_manager.get_version()
+    }
+
     pub fn get_state(&self) -> Result<NetworkManagerState> {
         self.dbus_manager.get_state()
     }
@@ -72,6 +523,17 @@ impl NetworkManager {
     pub fn is_wireless_enabled(&self) -> Result<bool> {
         self.dbus_manager.is_wireless_enabled()
     }
+
+    /// Get the global DNS configuration overriding per-connection DNS, empty
+    /// if none is set.
+    pub fn get_global_dns_configuration(&self) -> Result<HashMap<String, Value>> {
+        self.dbus_manager.get_global_dns_configuration()
+    }
+
+    /// Turn the Wi-Fi radio on or off.
+    pub fn set_wireless_enabled(&self, enabled: bool) -> Result<()> {
+        self.dbus_manager.set_wireless_enabled(enabled)
+    }
 }
 
 impl Default for NetworkManager {
@@ -81,6 +543,7 @@ impl Default for NetworkManager {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum NetworkManagerState {
     Unknown,
     Asleep,
@@ -112,6 +575,7 @@ impl From<u32> for NetworkManagerState {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum Connectivity {
     Unknown,
     None,
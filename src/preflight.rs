@@ -0,0 +1,164 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use backend::{get_wifi_backend, WifiBackend};
+use dbus_api::Bus;
+use errors::*;
+use service::{get_unit_state, ServiceState};
+
+const NETWORKD_CONFIG_DIRS: &[&str] = &[
+    "/etc/systemd/network",
+    "/run/systemd/network",
+    "/usr/lib/systemd/network",
+];
+
+/// Another network daemon that may be holding an interface, which usually
+/// shows up as activations or hotspots failing for no apparent reason.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum ConflictingService {
+    /// A standalone wpa_supplicant instance bound to the interface.
+    WpaSupplicant {
+        unit: String,
+    },
+    /// iwd running while NetworkManager uses wpa_supplicant.
+    Iwd,
+    ConnMan,
+    /// systemd-networkd is running with a `.network` file matching the
+    /// interface.
+    SystemdNetworkd {
+        config: PathBuf,
+    },
+}
+
+pub fn find_conflicting_services(bus: Bus, interface: &str) -> Result<Vec<ConflictingService>> {
+    let mut conflicts = Vec::new();
+
+    for template in &[
+        "wpa_supplicant",
+        "wpa_supplicant-nl80211",
+        "wpa_supplicant-wired",
+    ] {
+        let unit = format!("{}@{}.service", template, interface);
+
+        if is_running(bus, &unit)? {
+            conflicts.push(ConflictingService::WpaSupplicant { unit });
+        }
+    }
+
+    // iwd is only in the way when NetworkManager is not using it as backend
+    if is_running(bus, "iwd.service")? && get_wifi_backend(bus)? != WifiBackend::Iwd {
+        conflicts.push(ConflictingService::Iwd);
+    }
+
+    if is_running(bus, "connman.service")? {
+        conflicts.push(ConflictingService::ConnMan);
+    }
+
+    if is_running(bus, "systemd-networkd.service")? {
+        if let Some(config) = find_networkd_config(interface) {
+            conflicts.push(ConflictingService::SystemdNetworkd { config });
+        }
+    }
+
+    Ok(conflicts)
+}
+
+fn is_running(bus: Bus, unit: &str) -> Result<bool> {
+    let state = get_unit_state(bus, unit)?;
+
+    Ok(state == ServiceState::Active || state == ServiceState::Activating)
+}
+
+fn find_networkd_config(interface: &str) -> Option<PathBuf> {
+    for dir in NETWORKD_CONFIG_DIRS {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+
+            if path.extension().and_then(|e| e.to_str()) != Some("network") {
+                continue;
+            }
+
+            if config_matches(&path, interface) {
+                return Some(path);
+            }
+        }
+    }
+
+    None
+}
+
+fn config_matches(path: &Path, interface: &str) -> bool {
+    match fs::read_to_string(path) {
+        Ok(config) => match_names(&config)
+            .iter()
+            .any(|pattern| glob_match(pattern, interface)),
+        Err(_) => false,
+    }
+}
+
+/// Collects the `Name=` patterns of the `[Match]` section of a `.network` file.
+fn match_names(config: &str) -> Vec<String> {
+    let mut in_match = false;
+    let mut names = Vec::new();
+
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_match = line == "[Match]";
+        } else if in_match && line.starts_with("Name=") {
+            names.extend(line["Name=".len()..].split_whitespace().map(String::from));
+        }
+    }
+
+    names
+}
+
+/// Shell style matching supporting `*` and `?`, as used by `Name=`.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let name = name.as_bytes();
+
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == b'?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == b'*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_names() {
+        let config = "[Match]\nName=wl* eth0\n\n[Network]\nName=ignored\nDHCP=yes\n";
+
+        let names = match_names(config);
+
+        assert_eq!(names, vec!["wl*", "eth0"]);
+        assert!(glob_match(&names[0], "wlan0"));
+        assert!(!glob_match(&names[0], "eth1"));
+        assert!(glob_match("e?h*", "eth0"));
+        assert!(!glob_match("eth", "eth0"));
+    }
+}
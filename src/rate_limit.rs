@@ -0,0 +1,217 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use errors::*;
+
+const DEFAULT_MIN_INTERVAL: Duration = Duration::from_secs(2);
+const DEFAULT_MAX_INTERVAL: Duration = Duration::from_secs(300);
+
+type ActivationObserver = Rc<dyn Fn(&ActivationLimitEvent)>;
+
+/// An activation attempt seen by an `ActivationLimiter`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum ActivationLimitEvent {
+    /// The attempt was let through. The next one on the same connection, on
+    /// whichever device, has to wait for `backoff`.
+    Allowed {
+        connection: String,
+        device: String,
+        backoff: Duration,
+    },
+    /// The attempt was refused with `ErrorKind::ActivationThrottled`.
+    Throttled {
+        connection: String,
+        device: String,
+        retry_after: Duration,
+    },
+}
+
+/// Limits how often activation of the same connection is attempted, protecting
+/// NetworkManager and wpa_supplicant from callers retrying in a tight loop.
+///
+/// After an attempt the next one has to wait `min_interval`, doubling with
+/// every further attempt up to `max_interval`. Once no attempt was made for
+/// the current backoff plus `max_interval`, the backoff starts over.
+///
+/// Attempts are counted per connection, whichever way it is activated: by
+/// the UUID of its profile, by the SSID of a Wi-Fi network joined or shared
+/// without a saved profile, or by the device path when a device is connected
+/// without naming a connection. A profile added with neither a UUID nor an ID
+/// is not limited. The device an attempt is made on is only reported to the
+/// observer. A successful activation starts the backoff over.
+pub struct ActivationLimiter {
+    min_interval: Duration,
+    max_interval: Duration,
+    observer: Option<ActivationObserver>,
+    attempts: RefCell<HashMap<String, Attempt>>,
+}
+
+struct Attempt {
+    at: Instant,
+    backoff: Duration,
+}
+
+impl ActivationLimiter {
+    pub fn new() -> Self {
+        ActivationLimiter {
+            min_interval: DEFAULT_MIN_INTERVAL,
+            max_interval: DEFAULT_MAX_INTERVAL,
+            observer: None,
+            attempts: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_min_interval(mut self, interval: Duration) -> Self {
+        self.min_interval = interval;
+        self
+    }
+
+    pub fn with_max_interval(mut self, interval: Duration) -> Self {
+        self.max_interval = interval;
+        self
+    }
+
+    /// Invokes `observer` with every attempt, allowed or throttled.
+    pub fn with_observer<F>(mut self, observer: F) -> Self
+    where
+        F: Fn(&ActivationLimitEvent) + 'static,
+    {
+        self.observer = Some(Rc::new(observer));
+        self
+    }
+
+    /// Forgets all attempts, e.g. after the network came back.
+    pub fn reset(&self) {
+        self.attempts.borrow_mut().clear();
+    }
+
+    /// Records an attempt to activate `connection` on `device`, failing if it
+    /// comes before the backoff of the previous one elapsed.
+    pub fn check(&self, connection: &str, device: &str) -> Result<()> {
+        // The observer is invoked once the attempts are no longer borrowed,
+        // so that it may use the limiter itself
+        let event = self.record(connection, device);
+
+        self.notify(&event);
+
+        match event {
+            ActivationLimitEvent::Throttled { retry_after, .. } => bail!(
+                ErrorKind::ActivationThrottled(connection.to_string(), retry_after)
+            ),
+            ActivationLimitEvent::Allowed { .. } => Ok(()),
+        }
+    }
+
+    /// Forgets the attempts on `connection` once it activated, so that a
+    /// later failure starts over with `min_interval`.
+    pub fn succeeded(&self, connection: &str) {
+        self.attempts.borrow_mut().remove(connection);
+    }
+
+    fn record(&self, connection: &str, device: &str) -> ActivationLimitEvent {
+        let now = Instant::now();
+        let mut attempts = self.attempts.borrow_mut();
+
+        let max_interval = self.max_interval;
+        attempts
+            .retain(|_, attempt| now.duration_since(attempt.at) < attempt.backoff + max_interval);
+
+        let backoff = match attempts.get(connection) {
+            Some(attempt) => {
+                let elapsed = now.duration_since(attempt.at);
+
+                if elapsed < attempt.backoff {
+                    return ActivationLimitEvent::Throttled {
+                        connection: connection.to_string(),
+                        device: device.to_string(),
+                        retry_after: attempt.backoff - elapsed,
+                    };
+                }
+
+                (attempt.backoff * 2).min(self.max_interval)
+            }
+            None => self.min_interval,
+        };
+
+        attempts.insert(connection.to_string(), Attempt { at: now, backoff });
+
+        ActivationLimitEvent::Allowed {
+            connection: connection.to_string(),
+            device: device.to_string(),
+            backoff,
+        }
+    }
+
+    fn notify(&self, event: &ActivationLimitEvent) {
+        debug!("Activation limit: {:?}", event);
+
+        if let Some(ref observer) = self.observer {
+            observer(event);
+        }
+    }
+}
+
+impl Default for ActivationLimiter {
+    fn default() -> Self {
+        ActivationLimiter::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_activation_backoff() {
+        let limiter = ActivationLimiter::new().with_min_interval(Duration::from_secs(60));
+
+        assert!(limiter.check("uuid-1", "/dev/1").is_ok());
+        assert!(limiter.check("uuid-1", "/dev/1").is_err());
+
+        // The same connection is throttled whichever device it is tried on
+        assert!(limiter.check("uuid-1", "/").is_err());
+        assert!(limiter.check("uuid-2", "/dev/1").is_ok());
+
+        limiter.reset();
+        assert!(limiter.check("uuid-1", "/dev/1").is_ok());
+    }
+
+    #[test]
+    fn test_activation_success_resets_backoff() {
+        let limiter = ActivationLimiter::new().with_min_interval(Duration::from_secs(60));
+
+        assert!(limiter.check("uuid-1", "/dev/1").is_ok());
+        assert!(limiter.check("uuid-1", "/dev/1").is_err());
+
+        limiter.succeeded("uuid-1");
+        assert!(limiter.check("uuid-1", "/dev/1").is_ok());
+    }
+
+    #[test]
+    fn test_observer_may_use_limiter() {
+        let limiter = Rc::new(RefCell::new(None::<Rc<ActivationLimiter>>));
+        let events = Rc::new(RefCell::new(Vec::new()));
+
+        let observed = Rc::clone(&limiter);
+        let recorded = Rc::clone(&events);
+        let created = Rc::new(ActivationLimiter::new().with_observer(move |event| {
+            // Would panic with a borrow error if the attempts were still held
+            if let ActivationLimitEvent::Throttled { ref connection, .. } = *event {
+                observed.borrow().as_ref().unwrap().succeeded(connection);
+            }
+            recorded.borrow_mut().push(event.clone());
+        }));
+        *limiter.borrow_mut() = Some(Rc::clone(&created));
+
+        assert!(created.check("uuid-1", "/dev/1").is_ok());
+        assert!(created.check("uuid-1", "/dev/1").is_err());
+        assert!(created.check("uuid-1", "/dev/1").is_ok());
+        assert_eq!(events.borrow().len(), 3);
+
+        // Break the cycle between the limiter and its observer
+        limiter.borrow_mut().take();
+    }
+}
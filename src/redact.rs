@@ -0,0 +1,100 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REDACTION: AtomicBool = AtomicBool::new(true);
+
+pub const MASK: &str = "<redacted>";
+
+/// Turns masking of secrets in log output and `Debug` formatting on or off.
+/// On by default.
+pub fn set_redaction(enabled: bool) {
+    REDACTION.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_redaction_enabled() -> bool {
+    REDACTION.load(Ordering::Relaxed)
+}
+
+/// Whether a setting key or property name holds a secret, e.g. `psk`,
+/// `password`, `wep-key0`, `private-key` or the `secrets` dictionary of VPN
+/// profiles. The matching `-flags` and `-type` keys are not secrets.
+pub fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+
+    if key.ends_with("-flags") || key.ends_with("-type") {
+        return false;
+    }
+
+    key == "psk"
+        || key == "secrets"
+        || key == "pin"
+        || key == "puk"
+        || key.ends_with("password")
+        || key.ends_with("private-key")
+        || key.ends_with("preshared-key")
+        || (key.starts_with("wep-key") && key[7..].chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Formats the wrapped value with `Debug`, or as `<redacted>` while redaction
+/// is enabled.
+pub struct Secret<'a, T: fmt::Debug + ?Sized + 'a>(pub &'a T);
+
+impl<'a, T: fmt::Debug + ?Sized> fmt::Debug for Secret<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if is_redaction_enabled() {
+            f.write_str(MASK)
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+/// Like `Secret`, but only masks the value if `key` names a secret.
+pub struct Keyed<'a, T: fmt::Debug + ?Sized + 'a>(pub &'a str, pub &'a T);
+
+impl<'a, T: fmt::Debug + ?Sized> fmt::Debug for Keyed<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if is_secret_key(self.0) {
+            Secret(self.1).fmt(f)
+        } else {
+            self.1.fmt(f)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_secret_key() {
+        for key in &[
+            "psk",
+            "password",
+            "private-key-password",
+            "phase2-private-key",
+            "wep-key0",
+            "wep-key3",
+            "leap-password",
+            "preshared-key",
+            "pin",
+            "secrets",
+            "Psk",
+        ] {
+            assert!(is_secret_key(key), "{}", key);
+        }
+
+        for key in &[
+            "psk-flags",
+            "password-flags",
+            "wep-key-type",
+            "wep-key-flags",
+            "wep-tx-keyidx",
+            "key-mgmt",
+            "identity",
+            "ssid",
+        ] {
+            assert!(!is_secret_key(key), "{}", key);
+        }
+    }
+}
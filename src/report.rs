@@ -0,0 +1,131 @@
+//! Flat, stable JSON documents for scripts and fleet agents, enabled with the
+//! `json` feature.
+//!
+//! Field names are fixed and all enumerations are rendered as strings, so the
+//! output does not change shape when the underlying types gain fields.
+
+use serde::Serialize;
+
+#[cfg(feature = "wifi")]
+use network_manager_types::Security;
+
+use connection::Connection;
+use device::Device;
+use errors::*;
+use manager::NetworkManager;
+#[cfg(feature = "wifi")]
+use wifi::AccessPoint;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DeviceReport {
+    pub interface: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub state: String,
+}
+
+impl DeviceReport {
+    pub fn from_device(device: &Device) -> Result<Self> {
+        Ok(DeviceReport {
+            interface: device.interface().to_string(),
+            kind: format!("{:?}", device.device_type()),
+            state: format!("{:?}", device.get_state()?),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[cfg(feature = "wifi")]
+pub struct AccessPointReport {
+    pub ssid: String,
+    pub bssid: String,
+    pub strength: u32,
+    pub security: Vec<&'static str>,
+}
+
+#[cfg(feature = "wifi")]
+impl<'a> From<&'a AccessPoint> for AccessPointReport {
+    fn from(access_point: &'a AccessPoint) -> Self {
+        let names = [
+            (Security::WEP, "wep"),
+            (Security::WPA, "wpa"),
+            (Security::WPA2, "wpa2"),
+            (Security::ENTERPRISE, "enterprise"),
+        ];
+
+        AccessPointReport {
+            ssid: access_point.ssid().to_string(),
+            bssid: access_point.bssid.clone(),
+            strength: access_point.strength,
+            security: names
+                .iter()
+                .filter(|&&(flag, _)| access_point.security_summary().contains(flag))
+                .map(|&(_, name)| name)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ConnectionReport {
+    pub id: String,
+    pub uuid: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub state: String,
+    pub devices: Vec<String>,
+}
+
+impl ConnectionReport {
+    pub fn from_connection(connection: &Connection) -> Result<Self> {
+        let settings = connection.settings();
+
+        Ok(ConnectionReport {
+            id: settings.id.clone(),
+            uuid: settings.uuid.clone(),
+            kind: settings.kind.clone(),
+            state: format!("{:?}", connection.get_state()?),
+            devices: connection
+                .get_devices()?
+                .iter()
+                .map(|device| device.interface().to_string())
+                .collect(),
+        })
+    }
+}
+
+pub fn devices(manager: &NetworkManager) -> Result<Vec<DeviceReport>> {
+    manager
+        .get_devices()?
+        .iter()
+        .map(DeviceReport::from_device)
+        .collect()
+}
+
+#[cfg(feature = "wifi")]
+pub fn access_points(access_points: &[AccessPoint]) -> Vec<AccessPointReport> {
+    access_points.iter().map(AccessPointReport::from).collect()
+}
+
+pub fn active_connections(manager: &NetworkManager) -> Result<Vec<ConnectionReport>> {
+    manager
+        .get_active_connections()?
+        .iter()
+        .map(ConnectionReport::from_connection)
+        .collect()
+}
+
+/// Serializes a report, or any other serializable value, as JSON.
+pub trait ToJson {
+    fn to_json(&self) -> Result<String>;
+}
+
+impl<T> ToJson for T
+where
+    T: Serialize + ?Sized,
+{
+    fn to_json(&self) -> Result<String> {
+        ::serde_json::to_string(self)
+            .chain_err(|| ErrorKind::NetworkManager("JSON serialization failed".into()))
+    }
+}
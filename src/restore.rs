@@ -0,0 +1,98 @@
+//! Temporary changes, e.g. of a device setting, that are undone once their
+//! guard is released or dropped.
+
+use errors::*;
+
+/// A change that can be undone.
+pub trait Undo {
+    /// What undoing does, e.g. `restore autoconnect of eth0`, for the message
+    /// logged when it fails on drop.
+    fn description(&self) -> String;
+
+    fn undo(self) -> Result<()>;
+}
+
+/// Undoes a change once released or dropped, whichever comes first. Failures
+/// are reported by `release` and only logged on drop.
+#[derive(Debug)]
+pub struct Restore<U: Undo> {
+    change: Option<U>,
+}
+
+impl<U: Undo> Restore<U> {
+    pub fn new(change: U) -> Self {
+        Restore {
+            change: Some(change),
+        }
+    }
+
+    /// Nothing was changed, e.g. as the setting already had the value.
+    pub fn unchanged() -> Self {
+        Restore { change: None }
+    }
+
+    pub fn release(mut self) -> Result<()> {
+        match self.change.take() {
+            Some(change) => change.undo(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<U: Undo> Drop for Restore<U> {
+    fn drop(&mut self) {
+        if let Some(change) = self.change.take() {
+            let description = change.description();
+
+            if let Err(e) = change.undo() {
+                debug!("Unable to {}: {}", description, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    struct Recorded {
+        undone: Rc<RefCell<Vec<&'static str>>>,
+        name: &'static str,
+        fails: bool,
+    }
+
+    impl Undo for Recorded {
+        fn description(&self) -> String {
+            format!("undo {}", self.name)
+        }
+
+        fn undo(self) -> Result<()> {
+            self.undone.borrow_mut().push(self.name);
+
+            match self.fails {
+                true => bail!("Device removed"),
+                false => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_undone_once() {
+        let undone = Rc::new(RefCell::new(Vec::new()));
+        let change = |name, fails| Recorded {
+            undone: Rc::clone(&undone),
+            name,
+            fails,
+        };
+
+        drop(Restore::new(change("dropped", true)));
+        assert!(Restore::new(change("released", false)).release().is_ok());
+        assert!(Restore::new(change("failed", true)).release().is_err());
+        assert!(Restore::<Recorded>::unchanged().release().is_ok());
+
+        assert_eq!(*undone.borrow(), vec!["dropped", "released", "failed"]);
+    }
+}
@@ -0,0 +1,193 @@
+use std::thread;
+use std::time::Duration;
+
+use errors::*;
+
+use wifi::{AccessPoint, WiFiDevice};
+
+const DEFAULT_MIN_IMPROVEMENT: u32 = 10;
+const DEFAULT_SCAN_WAIT: u64 = 3;
+
+/// Roams between access points of the same network based on signal strength.
+///
+/// When the strength of the active access point drops below `threshold`, a
+/// rescan is requested and the device is reassociated to the strongest BSSID
+/// of the same SSID, provided it is at least `min_improvement` better.
+#[derive(Clone, Debug)]
+pub struct RoamPolicy {
+    threshold: u32,
+    min_improvement: u32,
+    scan_wait: u64,
+}
+
+#[derive(Debug)]
+pub enum RoamAction {
+    /// Not associated with any access point.
+    NotAssociated,
+    /// The active access point is at or above the threshold.
+    Stay(AccessPoint),
+    /// Below the threshold, but no better access point of the same SSID found.
+    NoCandidate(AccessPoint),
+    /// Reassociated from the first access point to the second.
+    Roamed(AccessPoint, AccessPoint),
+}
+
+impl RoamPolicy {
+    pub fn new(threshold: u32) -> Self {
+        RoamPolicy {
+            threshold,
+            min_improvement: DEFAULT_MIN_IMPROVEMENT,
+            scan_wait: DEFAULT_SCAN_WAIT,
+        }
+    }
+
+    /// Minimum strength gain required before roaming, to avoid flapping
+    /// between access points of similar strength.
+    pub fn with_min_improvement(mut self, min_improvement: u32) -> Self {
+        self.min_improvement = min_improvement;
+        self
+    }
+
+    /// Seconds to wait for scan results after requesting a rescan.
+    pub fn with_scan_wait(mut self, scan_wait: u64) -> Self {
+        self.scan_wait = scan_wait;
+        self
+    }
+
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    /// Runs a single roaming evaluation on the device.
+    pub fn evaluate(&self, device: &WiFiDevice) -> Result<RoamAction> {
+        let current = match device.get_active_access_point()? {
+            Some(current) => current,
+            None => return Ok(RoamAction::NotAssociated),
+        };
+
+        if current.strength >= self.threshold {
+            return Ok(RoamAction::Stay(current));
+        }
+
+        debug!(
+            "Active access point {} of {:?} below roaming threshold: {} < {}",
+            current.bssid,
+            current.ssid(),
+            current.strength,
+            self.threshold
+        );
+
+        device.request_scan()?;
+
+        thread::sleep(Duration::from_secs(self.scan_wait));
+
+        let candidate = self.find_candidate(&current, device.get_access_points()?);
+
+        match candidate {
+            Some(candidate) => {
+                info!(
+                    "Roaming {:?} from {} ({}) to {} ({})",
+                    current.ssid(),
+                    current.bssid,
+                    current.strength,
+                    candidate.bssid,
+                    candidate.strength
+                );
+
+                device.reassociate(&candidate)?;
+
+                Ok(RoamAction::Roamed(current, candidate))
+            }
+            None => Ok(RoamAction::NoCandidate(current)),
+        }
+    }
+
+    /// Evaluates the device every `interval` until `callback` returns `false`.
+    pub fn watch<F>(&self, device: &WiFiDevice, interval: Duration, mut callback: F) -> Result<()>
+    where
+        F: FnMut(&RoamAction) -> bool,
+    {
+        loop {
+            let action = self.evaluate(device)?;
+
+            if !callback(&action) {
+                return Ok(());
+            }
+
+            thread::sleep(interval);
+        }
+    }
+
+    fn find_candidate(
+        &self,
+        current: &AccessPoint,
+        access_points: Vec<AccessPoint>,
+    ) -> Option<AccessPoint> {
+        access_points
+            .into_iter()
+            .filter(|ap| ap.ssid == current.ssid && ap.bssid != current.bssid)
+            .filter(|ap| ap.strength >= current.strength.saturating_add(self.min_improvement))
+            .max_by_key(|ap| ap.strength)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use network_manager_types::{ApSecurity, NM80211ApFlags, NM80211ApSecurityFlags};
+
+    use network_manager_types::Ssid;
+
+    fn access_point(ssid: &str, bssid: &str, strength: u32) -> AccessPoint {
+        AccessPoint {
+            path: String::new(),
+            ssid: Ssid::from_bytes(ssid.as_bytes().to_vec()).unwrap(),
+            bssid: bssid.to_string(),
+            strength,
+            security: ApSecurity::new(
+                NM80211ApFlags::AP_FLAGS_NONE,
+                NM80211ApSecurityFlags::AP_SEC_NONE,
+                NM80211ApSecurityFlags::AP_SEC_NONE,
+            ),
+            flags: NM80211ApFlags::AP_FLAGS_NONE,
+            frequency: 2412,
+            last_seen: None,
+        }
+    }
+
+    fn candidate(policy: &RoamPolicy, current: &AccessPoint) -> Option<String> {
+        let access_points = vec![
+            current.clone(),
+            access_point("home", "00:00:00:00:00:02", 45),
+            access_point("home", "00:00:00:00:00:03", 60),
+            access_point("cafe", "00:00:00:00:00:04", 90),
+        ];
+
+        policy
+            .find_candidate(current, access_points)
+            .map(|ap| ap.bssid)
+    }
+
+    #[test]
+    fn test_find_candidate() {
+        let current = access_point("home", "00:00:00:00:00:01", 40);
+
+        let policy = RoamPolicy::new(50);
+        assert_eq!(
+            candidate(&policy, &current),
+            Some("00:00:00:00:00:03".to_string())
+        );
+
+        let policy = RoamPolicy::new(50).with_min_improvement(30);
+        assert_eq!(candidate(&policy, &current), None);
+    }
+
+    #[test]
+    fn test_find_candidate_does_not_overflow() {
+        let current = access_point("home", "00:00:00:00:00:01", 40);
+
+        let policy = RoamPolicy::new(50).with_min_improvement(u32::MAX);
+        assert_eq!(candidate(&policy, &current), None);
+    }
+}
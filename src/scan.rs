@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use dbus_api::{open_connection, Bus};
+use dbus_supplicant::DBusSupplicant;
+use errors::*;
+use restore::{Restore, Undo};
+
+use device::DeviceState;
+use wifi::{AccessPoint, WiFiDevice};
+
+const POLL_INTERVAL: u64 = 1;
+
+/// Scans after which an access point no longer found is dropped, unless set
+/// with `ScanScheduler::with_max_age`.
+const MAX_AGE_SCANS: u32 = 3;
+
+/// Periodically requests access point scans while a Wi-Fi device is idle.
+///
+/// Scans are only requested when the device is disconnected (unless
+/// `with_scan_while_connected` is set), so that an active connection is not
+/// disrupted. Results of all scans are merged by BSSID, and access points not
+/// found for `max_age` are dropped again.
+pub struct ScanScheduler {
+    interval: Duration,
+    scan_while_connected: bool,
+    max_age: Duration,
+    last_scan: Option<Instant>,
+    access_points: HashMap<String, (AccessPoint, Instant)>,
+}
+
+impl ScanScheduler {
+    pub fn new(interval: Duration) -> Self {
+        ScanScheduler {
+            interval,
+            scan_while_connected: false,
+            max_age: interval * MAX_AGE_SCANS,
+            last_scan: None,
+            access_points: HashMap::new(),
+        }
+    }
+
+    pub fn with_scan_while_connected(mut self, scan_while_connected: bool) -> Self {
+        self.scan_while_connected = scan_while_connected;
+        self
+    }
+
+    /// How long an access point stays in the merged results after it was
+    /// last found, three scan intervals by default.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Requests a scan if one is due and the device is idle, then merges the
+    /// device's current access point list. Returns whether a scan was requested.
+    pub fn poll(&mut self, device: &WiFiDevice) -> Result<bool> {
+        let due = match self.last_scan {
+            Some(last_scan) => last_scan.elapsed() >= self.interval,
+            None => true,
+        };
+
+        if !due {
+            return Ok(false);
+        }
+
+        if !self.is_idle(device)? {
+            debug!(
+                "Skipping scheduled scan on busy device {}",
+                device.device().interface()
+            );
+            return Ok(false);
+        }
+
+        device.request_scan()?;
+
+        let now = Instant::now();
+
+        self.last_scan = Some(now);
+
+        self.merge(device.get_access_points()?, now);
+
+        Ok(true)
+    }
+
+    /// Polls the device until `callback` returns `false`. The callback is
+    /// invoked with the merged results after every scan.
+    pub fn run<F>(&mut self, device: &WiFiDevice, mut callback: F) -> Result<()>
+    where
+        F: FnMut(&[AccessPoint]) -> bool,
+    {
+        loop {
+            if self.poll(device)? && !callback(&self.access_points()) {
+                return Ok(());
+            }
+
+            thread::sleep(Duration::from_secs(POLL_INTERVAL));
+        }
+    }
+
+    /// Merged scan results sorted by descending strength.
+    pub fn access_points(&self) -> Vec<AccessPoint> {
+        let mut access_points: Vec<AccessPoint> = self
+            .access_points
+            .values()
+            .map(|(access_point, _)| access_point.clone())
+            .collect();
+
+        access_points.sort_by_key(|ap| ap.strength);
+        access_points.reverse();
+
+        access_points
+    }
+
+    pub fn clear(&mut self) {
+        self.access_points.clear();
+    }
+
+    fn is_idle(&self, device: &WiFiDevice) -> Result<bool> {
+        let state = device.device().get_state()?;
+
+        Ok(state == DeviceState::Disconnected
+            || (self.scan_while_connected && state == DeviceState::Activated))
+    }
+
+    fn merge(&mut self, access_points: Vec<AccessPoint>, now: Instant) {
+        for access_point in access_points {
+            self.access_points
+                .insert(access_point.bssid.clone(), (access_point, now));
+        }
+
+        let max_age = self.max_age;
+
+        self.access_points
+            .retain(|_, &mut (_, seen)| now.saturating_duration_since(seen) <= max_age);
+    }
+}
+
+/// Keeps wpa_supplicant from scanning in the background while connected,
+/// e.g. during a call, by stretching the `bgscan` intervals of the current
+/// network to `duration`. The previous setting is restored once `duration`
+/// elapsed or the inhibitor is dropped, whichever comes first.
+///
+/// Networks without a `bgscan` setting of their own, which NetworkManager
+/// adds to every network it configures, follow the global setting of
+/// wpa_supplicant and are left alone.
+///
+/// wpa_supplicant versions that only read `bgscan` on association keep
+/// scanning as before until the next roam or reconnect.
+pub struct BackgroundScanInhibitor {
+    restore: Restore<BackgroundScanChange>,
+}
+
+/// The network whose `bgscan` setting was changed, with its previous value.
+struct Inhibited {
+    network: String,
+    bgscan: String,
+}
+
+impl BackgroundScanInhibitor {
+    pub fn new(supplicant: DBusSupplicant, interface: &str, duration: Duration) -> Result<Self> {
+        let network = match supplicant.get_current_network(interface)? {
+            Some(network) => network,
+            None => bail!(ErrorKind::NetworkManager(format!(
+                "{} is not associated with a network",
+                interface
+            ))),
+        };
+
+        let bgscan = match supplicant.get_network_setting(&network, "bgscan")? {
+            Some(bgscan) => bgscan,
+            None => {
+                debug!(
+                    "{} has no background scan setting of its own, leaving it alone",
+                    interface
+                );
+
+                return Ok(BackgroundScanInhibitor {
+                    restore: Restore::unchanged(),
+                });
+            }
+        };
+
+        supplicant.set_network_setting(&network, "bgscan", &inhibited_bgscan(duration))?;
+
+        debug!(
+            "Background scans on {} inhibited for {:?}, was {:?}",
+            interface, duration, bgscan
+        );
+
+        let inhibited = Arc::new(Mutex::new(Some(Inhibited { network, bgscan })));
+
+        let (sender, cancel) = mpsc::channel();
+        let bus = supplicant.bus();
+        let shared = Arc::clone(&inhibited);
+
+        let started = thread::Builder::new()
+            .name("nm-bgscan-inhibit".into())
+            .spawn(move || restore_at_deadline(bus, duration, &shared, &cancel));
+
+        let restore = Restore::new(BackgroundScanChange {
+            supplicant,
+            inhibited,
+            deadline: sender,
+        });
+
+        if let Err(e) = started {
+            let e = Error::with_chain(
+                e,
+                ErrorKind::NetworkManager("Unable to start the background scan deadline".into()),
+            );
+
+            return Err(match restore.release() {
+                Ok(()) => e,
+                Err(restore_error) => e.with_cleanup_error(restore_error),
+            });
+        }
+
+        Ok(BackgroundScanInhibitor { restore })
+    }
+
+    /// Restores the previous `bgscan` setting, reporting failures that are
+    /// only logged when the inhibitor is dropped.
+    pub fn release(self) -> Result<()> {
+        self.restore.release()
+    }
+}
+
+/// A `bgscan` setting changed until `deadline`, unless restored before.
+struct BackgroundScanChange {
+    supplicant: DBusSupplicant,
+    inhibited: Arc<Mutex<Option<Inhibited>>>,
+    // Dropping it ends the thread restoring the setting at the deadline
+    deadline: Sender<()>,
+}
+
+impl Undo for BackgroundScanChange {
+    fn description(&self) -> String {
+        "restore background scans".into()
+    }
+
+    fn undo(self) -> Result<()> {
+        drop(self.deadline);
+
+        match take_inhibited(&self.inhibited) {
+            Some(inhibited) => restore_bgscan(&self.supplicant, &inhibited),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A `bgscan` setting scanning at most once per `duration`. No signal falls
+/// below the threshold, which selects the long interval.
+fn inhibited_bgscan(duration: Duration) -> String {
+    format!("simple:{0}:-100:{0}", duration.as_secs().max(1))
+}
+
+fn take_inhibited(inhibited: &Mutex<Option<Inhibited>>) -> Option<Inhibited> {
+    inhibited.lock().unwrap_or_else(|e| e.into_inner()).take()
+}
+
+fn restore_bgscan(supplicant: &DBusSupplicant, inhibited: &Inhibited) -> Result<()> {
+    supplicant.set_network_setting(&inhibited.network, "bgscan", &inhibited.bgscan)
+}
+
+/// Restores the setting on a bus connection of its own once `duration`
+/// elapsed, unless the inhibitor was dropped before.
+fn restore_at_deadline(
+    bus: Bus,
+    duration: Duration,
+    inhibited: &Mutex<Option<Inhibited>>,
+    cancel: &Receiver<()>,
+) {
+    if cancel.recv_timeout(duration) != Err(RecvTimeoutError::Timeout) {
+        return;
+    }
+
+    let inhibited = match take_inhibited(inhibited) {
+        Some(inhibited) => inhibited,
+        None => return,
+    };
+
+    let result = open_connection(bus).and_then(|connection| {
+        let supplicant = DBusSupplicant::with_connection(Rc::new(connection), bus, None);
+        restore_bgscan(&supplicant, &inhibited)
+    });
+
+    match result {
+        Ok(()) => debug!("Background scans resumed after {:?}", duration),
+        Err(e) => warn!("Unable to restore background scans: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use network_manager_types::{ApSecurity, NM80211ApFlags, NM80211ApSecurityFlags};
+
+    use network_manager_types::Ssid;
+
+    fn access_point(bssid: &str, strength: u32) -> AccessPoint {
+        AccessPoint {
+            path: String::new(),
+            ssid: Ssid::from_bytes("home").unwrap(),
+            bssid: bssid.to_string(),
+            strength,
+            security: ApSecurity::new(
+                NM80211ApFlags::AP_FLAGS_NONE,
+                NM80211ApSecurityFlags::AP_SEC_NONE,
+                NM80211ApSecurityFlags::AP_SEC_NONE,
+            ),
+            flags: NM80211ApFlags::AP_FLAGS_NONE,
+            frequency: 2412,
+            last_seen: None,
+        }
+    }
+
+    fn bssids(scheduler: &ScanScheduler) -> Vec<String> {
+        scheduler
+            .access_points()
+            .into_iter()
+            .map(|ap| ap.bssid)
+            .collect()
+    }
+
+    #[test]
+    fn test_merged_access_points_age_out() {
+        let mut scheduler = ScanScheduler::new(Duration::from_secs(10));
+
+        let start = Instant::now();
+
+        scheduler.merge(
+            vec![
+                access_point("00:00:00:00:00:01", 40),
+                access_point("00:00:00:00:00:02", 80),
+            ],
+            start,
+        );
+        assert_eq!(
+            bssids(&scheduler),
+            vec!["00:00:00:00:00:02", "00:00:00:00:00:01"]
+        );
+
+        // the first one is found again, updating its strength
+        scheduler.merge(
+            vec![access_point("00:00:00:00:00:01", 90)],
+            start + Duration::from_secs(20),
+        );
+        assert_eq!(
+            bssids(&scheduler),
+            vec!["00:00:00:00:00:01", "00:00:00:00:00:02"]
+        );
+
+        scheduler.merge(
+            vec![access_point("00:00:00:00:00:01", 90)],
+            start + Duration::from_secs(40),
+        );
+        assert_eq!(bssids(&scheduler), vec!["00:00:00:00:00:01"]);
+    }
+
+    #[test]
+    fn test_max_age() {
+        let mut scheduler =
+            ScanScheduler::new(Duration::from_secs(10)).with_max_age(Duration::from_secs(0));
+
+        let start = Instant::now();
+
+        scheduler.merge(vec![access_point("00:00:00:00:00:01", 40)], start);
+        scheduler.merge(
+            vec![access_point("00:00:00:00:00:02", 40)],
+            start + Duration::from_secs(1),
+        );
+        assert_eq!(bssids(&scheduler), vec!["00:00:00:00:00:02"]);
+    }
+
+    #[test]
+    fn test_inhibited_bgscan() {
+        assert_eq!(
+            inhibited_bgscan(Duration::from_secs(120)),
+            "simple:120:-100:120"
+        );
+        assert_eq!(
+            inhibited_bgscan(Duration::from_millis(300)),
+            "simple:1:-100:1"
+        );
+    }
+
+    #[test]
+    fn test_inhibited_network_is_restored_once() {
+        let inhibited = Mutex::new(Some(Inhibited {
+            network: "/fi/w1/wpa_supplicant1/Interfaces/0/Networks/0".into(),
+            bgscan: "simple:30:-70:86400".into(),
+        }));
+
+        let taken = take_inhibited(&inhibited).unwrap();
+        assert_eq!(taken.bgscan, "simple:30:-70:86400");
+
+        assert!(take_inhibited(&inhibited).is_none());
+    }
+}
@@ -0,0 +1,72 @@
+use std::fmt;
+
+use zeroize::Zeroize;
+
+use redact::MASK;
+
+/// A secret such as a PSK or password. The memory holding it is overwritten
+/// with zeros when dropped, and `Debug` never shows it, regardless of
+/// `set_log_redaction`.
+///
+/// Copies handed to NetworkManager, e.g. in D-Bus messages, are out of reach
+/// and not zeroized.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn new<S>(secret: S) -> Self
+    where
+        S: Into<String>,
+    {
+        SecretString(secret.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(MASK)
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(secret: String) -> Self {
+        SecretString(secret)
+    }
+}
+
+impl<'a> From<&'a str> for SecretString {
+    fn from(secret: &'a str) -> Self {
+        SecretString(secret.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_string_debug() {
+        let secret = SecretString::from("hunter22");
+
+        assert_eq!(format!("{:?}", secret), "<redacted>");
+        assert_eq!(secret.expose(), "hunter22");
+    }
+}
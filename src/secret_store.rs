@@ -0,0 +1,221 @@
+//! Wi-Fi credentials kept in the user's keyring through the freedesktop
+//! Secret Service, as provided by GNOME Keyring or KWallet, so applications
+//! do not have to persist passwords in their own configuration.
+
+use std::collections::HashMap;
+
+use dbus::arg::{RefArg, Variant};
+use dbus::Path;
+use zeroize::Zeroize;
+
+use dbus_api::{Bus, DBusApi};
+use errors::*;
+
+use network_manager_types::AsSsidSlice;
+use secret::SecretString;
+
+const SECRETS_SERVICE: &str = "org.freedesktop.secrets";
+
+const SECRETS_PATH: &str = "/org/freedesktop/secrets";
+const DEFAULT_COLLECTION_PATH: &str = "/org/freedesktop/secrets/aliases/default";
+
+const SECRETS_INTERFACE: &str = "org.freedesktop.Secret.Service";
+const SECRETS_COLLECTION_INTERFACE: &str = "org.freedesktop.Secret.Collection";
+const SECRETS_ITEM_INTERFACE: &str = "org.freedesktop.Secret.Item";
+
+const SCHEMA: &str = "org.freedesktop.NetworkManager.rs.WiFi";
+
+const METHOD_RETRY_ERROR_NAMES: &[&str; 0] = &[];
+
+/// The secret of an item: session, parameters, value and content type.
+type Secret = (Path<'static>, Vec<u8>, Vec<u8>, String);
+
+/// Client of the Secret Service on the session bus.
+///
+/// Secrets are transferred unencrypted over the session bus, which only the
+/// user can connect to. Items in a locked collection are not unlocked, as
+/// that needs the user to answer a prompt of the keyring, and reading them
+/// fails instead.
+pub struct SecretStore {
+    dbus: DBusApi,
+    session: Path<'static>,
+}
+
+impl SecretStore {
+    pub fn new() -> Result<Self> {
+        let dbus = DBusApi::with_bus(
+            Bus::Session,
+            SECRETS_SERVICE,
+            METHOD_RETRY_ERROR_NAMES,
+            None,
+        )?;
+
+        let response = dbus.call_with_args(
+            SECRETS_PATH,
+            SECRETS_INTERFACE,
+            "OpenSession",
+            &[
+                &"plain".to_string() as &dyn RefArg,
+                &Variant(String::new()) as &dyn RefArg,
+            ],
+        )?;
+
+        let (_, session): (Variant<Box<dyn RefArg>>, Path) = dbus.extract_two(&response)?;
+
+        Ok(SecretStore {
+            dbus,
+            session: session.into_static(),
+        })
+    }
+
+    /// The password stored for `ssid`, if any.
+    pub fn get_wifi_secret<T>(&self, ssid: &T) -> Result<Option<SecretString>>
+    where
+        T: AsSsidSlice + ?Sized,
+    {
+        let item = match self.find_item(ssid)? {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+
+        let response = self.dbus.call_with_args(
+            &item,
+            SECRETS_ITEM_INTERFACE,
+            "GetSecret",
+            &[&self.session as &dyn RefArg],
+        )?;
+
+        let (_, _, value, _): Secret = self.dbus.extract(&response)?;
+
+        match String::from_utf8(value) {
+            Ok(secret) => Ok(Some(SecretString::from(secret))),
+            Err(e) => {
+                e.into_bytes().zeroize();
+                bail!(ErrorKind::NetworkManager(format!(
+                    "Stored secret of {} is not valid UTF-8",
+                    item
+                )))
+            }
+        }
+    }
+
+    /// Stores `secret` as the password of `ssid` in the default collection,
+    /// replacing a previously stored one.
+    pub fn store_wifi_secret<T>(&self, ssid: &T, secret: &SecretString) -> Result<()>
+    where
+        T: AsSsidSlice + ?Sized,
+    {
+        let label = format!(
+            "Wi-Fi password for {}",
+            ssid.as_ssid_slice()?.to_string_lossy()
+        );
+
+        let mut properties: HashMap<String, Variant<Box<dyn RefArg>>> = HashMap::new();
+        properties.insert(
+            format!("{}.Label", SECRETS_ITEM_INTERFACE),
+            Variant(Box::new(label)),
+        );
+        properties.insert(
+            format!("{}.Attributes", SECRETS_ITEM_INTERFACE),
+            Variant(Box::new(wifi_attributes(ssid)?)),
+        );
+
+        let mut value: Secret = (
+            self.session.clone(),
+            Vec::new(),
+            secret.expose().as_bytes().to_vec(),
+            "text/plain".to_string(),
+        );
+
+        let result = self.dbus.call_with_args(
+            DEFAULT_COLLECTION_PATH,
+            SECRETS_COLLECTION_INTERFACE,
+            "CreateItem",
+            &[
+                &properties as &dyn RefArg,
+                &value as &dyn RefArg,
+                &true as &dyn RefArg,
+            ],
+        );
+
+        value.2.zeroize();
+
+        let response = result?;
+        let (_, prompt): (Path, Path) = self.dbus.extract_two(&response)?;
+
+        if &*prompt != "/" {
+            bail!(ErrorKind::NetworkManager(
+                "Default keyring collection is locked".into()
+            ))
+        }
+
+        Ok(())
+    }
+
+    /// Removes the password stored for `ssid`. Returns whether there was one.
+    pub fn delete_wifi_secret<T>(&self, ssid: &T) -> Result<bool>
+    where
+        T: AsSsidSlice + ?Sized,
+    {
+        let item = match self.find_item(ssid)? {
+            Some(item) => item,
+            None => return Ok(false),
+        };
+
+        self.dbus
+            .call(&item, SECRETS_ITEM_INTERFACE, "Delete")
+            .map(|_| true)
+    }
+
+    fn find_item<T>(&self, ssid: &T) -> Result<Option<String>>
+    where
+        T: AsSsidSlice + ?Sized,
+    {
+        let response = self.dbus.call_with_args(
+            SECRETS_PATH,
+            SECRETS_INTERFACE,
+            "SearchItems",
+            &[&wifi_attributes(ssid)? as &dyn RefArg],
+        )?;
+
+        let (unlocked, locked): (Vec<Path>, Vec<Path>) = self.dbus.extract_two(&response)?;
+
+        if let Some(item) = unlocked.first() {
+            return Ok(Some(item.to_string()));
+        }
+
+        if !locked.is_empty() {
+            bail!(ErrorKind::NetworkManager(
+                "Keyring holding the Wi-Fi secret is locked".into()
+            ))
+        }
+
+        Ok(None)
+    }
+}
+
+/// Attributes items are looked up by. The SSID is hex encoded, as it need not
+/// be valid UTF-8.
+fn wifi_attributes<T>(ssid: &T) -> Result<HashMap<String, String>>
+where
+    T: AsSsidSlice + ?Sized,
+{
+    let mut attributes = HashMap::new();
+    attributes.insert("xdg:schema".to_string(), SCHEMA.to_string());
+    attributes.insert("ssid".to_string(), ssid.as_ssid_slice()?.to_hex());
+
+    Ok(attributes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wifi_attributes() {
+        let attributes = wifi_attributes("home").unwrap();
+
+        assert_eq!(attributes["xdg:schema"], SCHEMA);
+        assert_eq!(attributes["ssid"], "686f6d65");
+    }
+}
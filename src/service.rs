@@ -1,15 +1,25 @@
 extern crate dbus;
+#[cfg(feature = "service")]
 extern crate futures;
+#[cfg(feature = "service")]
 extern crate futures_cpupool;
+#[cfg(feature = "service")]
 extern crate tokio_timer;
 
+#[cfg(feature = "service")]
 use self::dbus::arg::{Dict, Iter, Variant};
-use self::dbus::{BusType, Connection, ConnectionItem, Interface, Member, Message, Path, Props};
+#[cfg(feature = "service")]
+use self::dbus::{ConnectionItem, Interface, Member};
+use self::dbus::{Message, Path, Props};
+#[cfg(feature = "service")]
 use self::futures::future::Future;
+#[cfg(feature = "service")]
 use self::futures_cpupool::CpuPool;
 use std::str::FromStr;
+#[cfg(feature = "service")]
 use std::time::Duration;
 
+use dbus_api::{open_connection, Bus};
 use errors::*;
 
 pub const SD_SERVICE_MANAGER: &str = "org.freedesktop.systemd1";
@@ -17,11 +27,14 @@ pub const SD_SERVICE_PATH: &str = "/org/freedesktop/systemd1";
 pub const SD_MANAGER_INTERFACE: &str = "org.freedesktop.systemd1.Manager";
 pub const SD_UNIT_INTERFACE: &str = "org.freedesktop.systemd1.Unit";
 
-pub fn start_service(timeout: u64) -> Result<ServiceState> {
-    let state = get_service_state()?;
+const NM_UNIT: &str = "NetworkManager.service";
+
+#[cfg(feature = "service")]
+pub fn start_service(bus: Bus, timeout: u64) -> Result<ServiceState> {
+    let state = get_service_state(bus)?;
     match state {
         ServiceState::Active => Ok(state),
-        ServiceState::Activating => handler(timeout, ServiceState::Active),
+        ServiceState::Activating => handler(bus, timeout, ServiceState::Active),
         ServiceState::Failed => bail!(ErrorKind::Service),
         _ => {
             let message = Message::new_method_call(
@@ -31,25 +44,25 @@ pub fn start_service(timeout: u64) -> Result<ServiceState> {
                 "StartUnit",
             )
             .map_err(|_| ErrorKind::Service)?
-            .append2("NetworkManager.service", "fail");
+            .append2(NM_UNIT, "fail");
 
-            let connection =
-                Connection::get_private(BusType::System).map_err(|_| ErrorKind::Service)?;
+            let connection = open_connection(bus).map_err(|_| ErrorKind::Service)?;
 
             connection
                 .send_with_reply_and_block(message, 2000)
                 .map_err(|_| ErrorKind::Service)?;
 
-            handler(timeout, ServiceState::Active)
+            handler(bus, timeout, ServiceState::Active)
         }
     }
 }
 
-pub fn stop_service(timeout: u64) -> Result<ServiceState> {
-    let state = get_service_state()?;
+#[cfg(feature = "service")]
+pub fn stop_service(bus: Bus, timeout: u64) -> Result<ServiceState> {
+    let state = get_service_state(bus)?;
     match state {
         ServiceState::Inactive => Ok(state),
-        ServiceState::Deactivating => handler(timeout, ServiceState::Inactive),
+        ServiceState::Deactivating => handler(bus, timeout, ServiceState::Inactive),
         ServiceState::Failed => bail!(ErrorKind::Service),
         _ => {
             let message = Message::new_method_call(
@@ -59,31 +72,113 @@ pub fn stop_service(timeout: u64) -> Result<ServiceState> {
                 "StopUnit",
             )
             .map_err(|_| ErrorKind::Service)?
-            .append2("NetworkManager.service", "fail");
+            .append2(NM_UNIT, "fail");
 
-            let connection =
-                Connection::get_private(BusType::System).map_err(|_| ErrorKind::Service)?;
+            let connection = open_connection(bus).map_err(|_| ErrorKind::Service)?;
 
             connection
                 .send_with_reply_and_block(message, 2000)
                 .map_err(|_| ErrorKind::Service)?;
 
-            handler(timeout, ServiceState::Inactive)
+            handler(bus, timeout, ServiceState::Inactive)
         }
     }
 }
 
-pub fn get_service_state() -> Result<ServiceState> {
+/// Restarts the service, also recovering it from the failed state.
+#[cfg(feature = "service")]
+pub fn restart_service(bus: Bus, timeout: u64) -> Result<ServiceState> {
+    let message = Message::new_method_call(
+        SD_SERVICE_MANAGER,
+        SD_SERVICE_PATH,
+        SD_MANAGER_INTERFACE,
+        "RestartUnit",
+    )
+    .map_err(|_| ErrorKind::Service)?
+    .append2(NM_UNIT, "replace");
+
+    let connection = open_connection(bus).map_err(|_| ErrorKind::Service)?;
+
+    connection
+        .send_with_reply_and_block(message, 2000)
+        .map_err(|_| ErrorKind::Service)?;
+
+    handler(bus, timeout, ServiceState::Active)
+}
+
+/// Enables the service to start on boot. Does not start it.
+pub fn enable_service(bus: Bus) -> Result<()> {
+    let message = Message::new_method_call(
+        SD_SERVICE_MANAGER,
+        SD_SERVICE_PATH,
+        SD_MANAGER_INTERFACE,
+        "EnableUnitFiles",
+    )
+    .map_err(|_| ErrorKind::Service)?
+    .append3(vec![NM_UNIT], false, false);
+
+    let connection = open_connection(bus).map_err(|_| ErrorKind::Service)?;
+
+    connection
+        .send_with_reply_and_block(message, 2000)
+        .map_err(|_| ErrorKind::Service)?;
+
+    let message = Message::new_method_call(
+        SD_SERVICE_MANAGER,
+        SD_SERVICE_PATH,
+        SD_MANAGER_INTERFACE,
+        "Reload",
+    )
+    .map_err(|_| ErrorKind::Service)?;
+
+    connection
+        .send_with_reply_and_block(message, 2000)
+        .map_err(|_| ErrorKind::Service)?;
+
+    Ok(())
+}
+
+/// Whether the service is enabled to start on boot.
+pub fn is_service_enabled(bus: Bus) -> Result<bool> {
+    let message = Message::new_method_call(
+        SD_SERVICE_MANAGER,
+        SD_SERVICE_PATH,
+        SD_MANAGER_INTERFACE,
+        "GetUnitFileState",
+    )
+    .map_err(|_| ErrorKind::Service)?
+    .append1(NM_UNIT);
+
+    let connection = open_connection(bus).map_err(|_| ErrorKind::Service)?;
+
+    let response = connection
+        .send_with_reply_and_block(message, 2000)
+        .map_err(|_| ErrorKind::Service)?;
+
+    let state = response.get1::<&str>().ok_or(ErrorKind::Service)?;
+
+    Ok(state == "enabled" || state == "enabled-runtime")
+}
+
+pub fn get_service_state(bus: Bus) -> Result<ServiceState> {
+    get_unit_state(bus, NM_UNIT)
+}
+
+/// Gets the state of any systemd unit. Units that do not exist are reported
+/// as inactive.
+pub fn get_unit_state(bus: Bus, unit: &str) -> Result<ServiceState> {
+    // LoadUnit unlike GetUnit also works when the unit was never started and
+    // is not loaded yet, e.g. on first boot
     let message = Message::new_method_call(
         SD_SERVICE_MANAGER,
         SD_SERVICE_PATH,
         SD_MANAGER_INTERFACE,
-        "GetUnit",
+        "LoadUnit",
     )
     .map_err(|_| ErrorKind::Service)?
-    .append1("NetworkManager.service");
+    .append1(unit);
 
-    let connection = Connection::get_private(BusType::System).map_err(|_| ErrorKind::Service)?;
+    let connection = open_connection(bus).map_err(|_| ErrorKind::Service)?;
 
     let response = connection
         .send_with_reply_and_block(message, 2000)
@@ -108,17 +203,17 @@ pub fn get_service_state() -> Result<ServiceState> {
         .parse()
 }
 
-fn handler(timeout: u64, target_state: ServiceState) -> Result<ServiceState> {
+#[cfg(feature = "service")]
+fn handler(bus: Bus, timeout: u64, target_state: ServiceState) -> Result<ServiceState> {
     if timeout == 0 {
-        return get_service_state();
+        return get_service_state(bus);
     }
 
     let timer =
         self::tokio_timer::sleep(Duration::from_secs(timeout)).then(|_| bail!(ErrorKind::Service));
 
-    let process = CpuPool::new_num_cpus().spawn_fn(|| {
-        let connection =
-            Connection::get_private(BusType::System).map_err(|_| ErrorKind::Service)?;
+    let process = CpuPool::new_num_cpus().spawn_fn(move || {
+        let connection = open_connection(bus).map_err(|_| ErrorKind::Service)?;
         connection
             .add_match(
                 "type='signal', sender='org.freedesktop.systemd1', \
@@ -128,7 +223,7 @@ fn handler(timeout: u64, target_state: ServiceState) -> Result<ServiceState> {
             )
             .map_err(|_| ErrorKind::Service)?;
 
-        if get_service_state()? == target_state {
+        if get_service_state(bus)? == target_state {
             return Ok(target_state);
         }
 
@@ -0,0 +1,1041 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+
+use network_manager_types::{
+    EthtoolConfig, SecretExport, SecretFlags, Setting, SriovConfig, Value,
+};
+
+use errors::*;
+use redact::{is_secret_key, Keyed, MASK};
+use secret::SecretString;
+#[cfg(feature = "wifi")]
+use wifi::channels::{verify_band_setting, Band};
+#[cfg(feature = "wifi")]
+use wifi::power::WifiPowerSave;
+
+/// Returns a new random UUID on every read.
+const UUID_PATH: &str = "/proc/sys/kernel/random/uuid";
+
+/// The full settings of a NetworkManager connection profile, organized as
+/// setting name to key-value pairs, mirroring NetworkManager's `a{sa{sv}}`.
+#[derive(Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct ConnectionProfile {
+    settings: BTreeMap<String, Setting>,
+}
+
+impl ConnectionProfile {
+    /// Creates a profile of the given connection type, e.g. `802-3-ethernet`.
+    pub fn new(kind: &str, id: &str) -> Self {
+        ConnectionProfile::default()
+            .with("connection", "type", kind)
+            .with("connection", "id", id)
+    }
+
+    pub fn ethernet(id: &str, interface: &str) -> Self {
+        ConnectionProfile::new("802-3-ethernet", id)
+            .with("connection", "interface-name", interface)
+            .with_setting("802-3-ethernet")
+    }
+
+    /// Creates a profile of a virtual device type, e.g. `bridge` or
+    /// `wireguard`. NetworkManager creates the device, named `interface`,
+    /// when the profile is activated.
+    pub fn virtual_device(kind: &str, id: &str, interface: &str) -> Self {
+        ConnectionProfile::new(kind, id)
+            .with("connection", "interface-name", interface)
+            .with_setting(kind)
+    }
+
+    /// Creates a profile of VLAN `vlan_id` on top of `parent`, given as
+    /// interface name or connection UUID.
+    pub fn vlan(id: &str, interface: &str, parent: &str, vlan_id: u32) -> Self {
+        ConnectionProfile::virtual_device("vlan", id, interface)
+            .with("vlan", "parent", parent)
+            .with("vlan", "id", vlan_id)
+    }
+
+    pub fn with<V>(mut self, setting: &str, key: &str, value: V) -> Self
+    where
+        V: Into<Value>,
+    {
+        self.set(setting, key, value);
+        self
+    }
+
+    /// Adds an empty setting, which NetworkManager populates with defaults.
+    pub fn with_setting(mut self, setting: &str) -> Self {
+        self.settings.entry(setting.to_string()).or_default();
+        self
+    }
+
+    pub fn set<V>(&mut self, setting: &str, key: &str, value: V) -> &mut Self
+    where
+        V: Into<Value>,
+    {
+        self.settings
+            .entry(setting.to_string())
+            .or_default()
+            .insert(key.to_string(), value.into());
+        self
+    }
+
+    pub fn get(&self, setting: &str, key: &str) -> Option<&Value> {
+        self.settings.get(setting).and_then(|s| s.get(key))
+    }
+
+    pub fn remove(&mut self, setting: &str, key: &str) -> Option<Value> {
+        self.settings.get_mut(setting).and_then(|s| s.remove(key))
+    }
+
+    pub fn setting(&self, setting: &str) -> Option<&Setting> {
+        self.settings.get(setting)
+    }
+
+    pub fn remove_setting(&mut self, setting: &str) -> Option<Setting> {
+        self.settings.remove(setting)
+    }
+
+    pub fn settings(&self) -> &BTreeMap<String, Setting> {
+        &self.settings
+    }
+
+    /// Lists the `setting.key` entries of `desired` that differ from this
+    /// profile. Keys only present in this profile are not reported, as
+    /// NetworkManager fills in defaults for everything left out.
+    pub fn changes(&self, desired: &ConnectionProfile) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        for (name, setting) in &desired.settings {
+            let current = match self.settings.get(name) {
+                Some(current) => current,
+                None => {
+                    changes.push(name.clone());
+                    continue;
+                }
+            };
+
+            for (key, value) in setting {
+                if current.get(key) != Some(value) {
+                    changes.push(format!("{}.{}", name, key));
+                }
+            }
+        }
+
+        changes
+    }
+
+    /// Overlays all settings of `other` onto this profile.
+    pub fn merge(&mut self, other: &ConnectionProfile) {
+        for (name, setting) in &other.settings {
+            let current = self.settings.entry(name.clone()).or_default();
+
+            for (key, value) in setting {
+                current.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.get("connection", "id").and_then(Value::as_str)
+    }
+
+    pub fn uuid(&self) -> Option<&str> {
+        self.get("connection", "uuid").and_then(Value::as_str)
+    }
+
+    pub fn interface_name(&self) -> Option<&str> {
+        self.get("connection", "interface-name")
+            .and_then(Value::as_str)
+    }
+
+    pub fn kind(&self) -> Option<&str> {
+        self.get("connection", "type").and_then(Value::as_str)
+    }
+
+    /// Replaces the `sriov` setting.
+    pub fn set_sriov(&mut self, sriov: &SriovConfig) -> &mut Self {
+        self.settings
+            .insert("sriov".to_string(), sriov.to_setting());
+        self
+    }
+
+    /// Sets `connection.stable-id`, the identifier NetworkManager derives
+    /// `stable` cloned MAC addresses, DHCP client IDs and IPv6 stable-privacy
+    /// addresses from. Profiles sharing a stable ID look like the same
+    /// client to the network. It may contain the placeholders `${CONNECTION}`,
+    /// `${BOOT}`, `${DEVICE}` and `${RANDOM}`.
+    pub fn set_stable_id(&mut self, stable_id: &str) -> &mut Self {
+        self.set("connection", "stable-id", stable_id)
+    }
+
+    /// Sets `connection.auth-retries`, how many times secrets are requested
+    /// again after an authentication failure before the activation fails.
+    /// `-1` uses the global default, `0` retries forever.
+    ///
+    /// Each failed activation also counts against `autoconnect-retries`, so
+    /// on authentication-flaky networks both usually need raising together.
+    pub fn set_auth_retries(&mut self, retries: i32) -> &mut Self {
+        self.set("connection", "auth-retries", retries)
+    }
+
+    /// Sets `connection.autoconnect`, whether NetworkManager activates the
+    /// profile on its own when a suitable device is available.
+    pub fn set_autoconnect(&mut self, autoconnect: bool) -> &mut Self {
+        self.set("connection", "autoconnect", autoconnect)
+    }
+
+    /// Sets `connection.autoconnect-retries`, how many failed
+    /// auto-activations are tolerated before the profile is blocked from
+    /// autoconnecting. `-1` uses the global default, `0` retries forever.
+    pub fn set_autoconnect_retries(&mut self, retries: i32) -> &mut Self {
+        self.set("connection", "autoconnect-retries", retries)
+    }
+
+    /// Sets `connection.zone`, the firewalld zone the interface is placed in
+    /// on activation. Dispatcher scripts run after the zone is applied and
+    /// can read it back from the profile via `CONNECTION_UUID`.
+    pub fn set_zone(&mut self, zone: &str) -> &mut Self {
+        self.set("connection", "zone", zone)
+    }
+
+    /// Sets `gsm.device-id`, restricting a mobile broadband profile to the
+    /// modem with that `ModemDevice::get_device_id`.
+    #[cfg(feature = "wwan")]
+    pub fn set_gsm_device_id(&mut self, device_id: &str) -> &mut Self {
+        self.set("gsm", "device-id", device_id)
+    }
+
+    /// Sets `gsm.sim-id`, restricting a mobile broadband profile to the SIM
+    /// with that ICCID.
+    #[cfg(feature = "wwan")]
+    pub fn set_gsm_sim_id(&mut self, sim_id: &str) -> &mut Self {
+        self.set("gsm", "sim-id", sim_id)
+    }
+
+    /// Sets `802-11-wireless.band`, restricting a Wi-Fi profile to one band.
+    /// Fails for 6 GHz, which NetworkManager has no band value for.
+    #[cfg(feature = "wifi")]
+    pub fn set_band(&mut self, band: Band) -> Result<&mut Self> {
+        let setting = verify_band_setting(band)?;
+
+        Ok(self.set("802-11-wireless", "band", setting))
+    }
+
+    /// Sets `802-11-wireless.powersave`, applied when the connection is
+    /// activated.
+    #[cfg(feature = "wifi")]
+    pub fn set_wifi_powersave(&mut self, powersave: WifiPowerSave) -> &mut Self {
+        self.set("802-11-wireless", "powersave", u32::from(powersave))
+    }
+
+    #[cfg(feature = "wifi")]
+    pub fn wifi_powersave(&self) -> Option<WifiPowerSave> {
+        self.get("802-11-wireless", "powersave")
+            .and_then(Value::as_u32)
+            .and_then(WifiPowerSave::from_u32)
+    }
+
+    /// Sets the storage flags of a secret, e.g.
+    /// `set_secret_flags("802-11-wireless-security", "psk", SecretFlags::AGENT_OWNED)`.
+    pub fn set_secret_flags(
+        &mut self,
+        setting: &str,
+        secret: &str,
+        flags: SecretFlags,
+    ) -> &mut Self {
+        self.set(setting, &format!("{}-flags", secret), flags.bits())
+    }
+
+    /// Replaces the `802-1x` setting. On Ethernet profiles this enables
+    /// wired port authentication; Wi-Fi profiles additionally need a
+    /// matching `key-mgmt` in `802-11-wireless-security`.
+    ///
+    /// Fails if `config` does not validate the server certificate, unless
+    /// it opted out with `without_server_validation`.
+    pub fn set_8021x(&mut self, config: &Ieee8021xConfig) -> Result<&mut Self> {
+        let setting = config.to_setting()?;
+
+        self.settings.insert("802-1x".to_string(), setting);
+        Ok(self)
+    }
+
+    /// Replaces the `ethtool` setting.
+    pub fn set_ethtool(&mut self, ethtool: &EthtoolConfig) -> &mut Self {
+        self.settings
+            .insert("ethtool".to_string(), ethtool.to_setting());
+        self
+    }
+
+    /// Removes secrets such as `psk`, or replaces them with a placeholder,
+    /// so that the profile can be included in backups and bug reports. The
+    /// `<secret>-flags` keys are kept. Secrets nested in dictionaries, such
+    /// as `vpn.secrets` or the `preshared-key` of WireGuard peers, are
+    /// treated alike.
+    pub fn export_secrets(&mut self, secrets: SecretExport) -> &mut Self {
+        for setting in self.settings.values_mut() {
+            export_setting_secrets(setting, secrets);
+        }
+
+        self
+    }
+
+    /// Whether a secret of the profile is the placeholder written by
+    /// `export_secrets`, which must not be applied in place of the real one.
+    pub fn has_secret_placeholders(&self) -> bool {
+        self.settings.values().any(has_placeholders)
+    }
+}
+
+fn export_setting_secrets(setting: &mut Setting, secrets: SecretExport) {
+    match secrets {
+        SecretExport::Include => return,
+        SecretExport::Strip => setting.retain(|key, _| !is_secret_key(key)),
+        SecretExport::Placeholder => {
+            for (key, value) in setting.iter_mut() {
+                if is_secret_key(key) {
+                    mask_secret(value);
+                }
+            }
+        }
+    }
+
+    for value in setting.values_mut() {
+        match *value {
+            Value::Dict(ref mut dict) => export_setting_secrets(dict, secrets),
+            Value::Dicts(ref mut dicts) => {
+                for dict in dicts {
+                    export_setting_secrets(dict, secrets);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Masks a secret, keeping the keys of a dictionary of secrets like
+/// `vpn.secrets` so that it still shows which were set.
+fn mask_secret(value: &mut Value) {
+    match *value {
+        Value::Dict(ref mut dict) => {
+            for secret in dict.values_mut() {
+                *secret = Value::String(MASK.into());
+            }
+        }
+        _ => *value = Value::String(MASK.into()),
+    }
+}
+
+fn has_placeholders(setting: &Setting) -> bool {
+    let mask = Value::String(MASK.into());
+
+    setting.iter().any(|(key, value)| match *value {
+        Value::Dict(ref dict) => {
+            (is_secret_key(key) && dict.values().any(|secret| *secret == mask))
+                || has_placeholders(dict)
+        }
+        Value::Dicts(ref dicts) => dicts.iter().any(has_placeholders),
+        _ => is_secret_key(key) && *value == mask,
+    })
+}
+
+/// A random UUID for `connection.uuid`, which NetworkManager requires
+/// profiles to be added with.
+pub fn new_uuid() -> Result<String> {
+    match fs::read_to_string(UUID_PATH) {
+        Ok(uuid) => Ok(uuid.trim().to_string()),
+        Err(e) => bail!(ErrorKind::NetworkManager(format!(
+            "Unable to generate a UUID: {}",
+            e
+        ))),
+    }
+}
+
+/// Check that `interface` can be used as a kernel interface name: at most 15
+/// bytes, without whitespace, `/` or `:`.
+pub fn verify_interface_name(interface: &str) -> Result<()> {
+    let valid = !interface.is_empty()
+        && interface.len() <= 15
+        && interface != "."
+        && interface != ".."
+        && !interface
+            .chars()
+            .any(|c| c.is_whitespace() || c == '/' || c == ':');
+
+    if valid {
+        Ok(())
+    } else {
+        bail!(ErrorKind::NetworkManager(format!(
+            "Invalid interface name: {:?}",
+            interface
+        )))
+    }
+}
+
+/// Values of secret keys such as `psk` are masked unless redaction was
+/// turned off.
+impl fmt::Debug for ConnectionProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        struct SettingDebug<'a>(&'a Setting);
+
+        impl<'a> fmt::Debug for SettingDebug<'a> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.debug_map()
+                    .entries(self.0.iter().map(|(key, value)| (key, Keyed(key, value))))
+                    .finish()
+            }
+        }
+
+        f.debug_map()
+            .entries(
+                self.settings
+                    .iter()
+                    .map(|(name, setting)| (name, SettingDebug(setting))),
+            )
+            .finish()
+    }
+}
+
+impl From<BTreeMap<String, Setting>> for ConnectionProfile {
+    fn from(settings: BTreeMap<String, Setting>) -> Self {
+        ConnectionProfile { settings }
+    }
+}
+
+/// IEEE 802.1X (EAP) authentication (the `802-1x` setting).
+#[derive(Clone, Default, PartialEq)]
+pub struct Ieee8021xConfig {
+    eap: Vec<String>,
+    identity: Option<String>,
+    anonymous_identity: Option<String>,
+    password: Option<SecretString>,
+    password_flags: SecretFlags,
+    phase2_auth: Option<String>,
+    ca_cert: Option<Certificate>,
+    system_ca_certs: bool,
+    domain_suffix_match: Option<String>,
+    domain_match: Option<String>,
+    altsubject_matches: Vec<String>,
+    skip_server_validation: bool,
+    client_cert: Option<Certificate>,
+    private_key: Option<Certificate>,
+    private_key_password: Option<SecretString>,
+    private_key_password_flags: SecretFlags,
+}
+
+impl Ieee8021xConfig {
+    /// PEAP with MSCHAPv2 inner authentication.
+    pub fn peap(identity: &str, password: &str) -> Self {
+        Ieee8021xConfig {
+            eap: vec!["peap".into()],
+            identity: Some(identity.into()),
+            password: Some(password.into()),
+            phase2_auth: Some("mschapv2".into()),
+            ..Default::default()
+        }
+    }
+
+    /// TTLS with the given inner authentication, e.g. `pap` or `mschapv2`.
+    pub fn ttls(identity: &str, password: &str, phase2_auth: &str) -> Self {
+        Ieee8021xConfig {
+            eap: vec!["ttls".into()],
+            identity: Some(identity.into()),
+            password: Some(password.into()),
+            phase2_auth: Some(phase2_auth.into()),
+            ..Default::default()
+        }
+    }
+
+    /// EAP-TLS with a client certificate and private key, given either as
+    /// file paths or in-memory blobs.
+    pub fn tls<C, K>(identity: &str, client_cert: C, private_key: K) -> Self
+    where
+        C: Into<Certificate>,
+        K: Into<Certificate>,
+    {
+        Ieee8021xConfig {
+            eap: vec!["tls".into()],
+            identity: Some(identity.into()),
+            client_cert: Some(client_cert.into()),
+            private_key: Some(private_key.into()),
+            ..Default::default()
+        }
+    }
+
+    /// EAP-TLS with a PKCS#12 bundle holding both the client certificate and
+    /// the private key.
+    pub fn tls_pkcs12<B>(identity: &str, bundle: B, passphrase: &str) -> Self
+    where
+        B: Into<Certificate>,
+    {
+        let bundle = bundle.into();
+
+        Ieee8021xConfig {
+            eap: vec!["tls".into()],
+            identity: Some(identity.into()),
+            client_cert: Some(bundle.clone()),
+            private_key: Some(bundle),
+            private_key_password: Some(passphrase.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn anonymous_identity(mut self, identity: &str) -> Self {
+        self.anonymous_identity = Some(identity.into());
+        self
+    }
+
+    /// CA certificate used to verify the authentication server.
+    pub fn ca_cert<C>(mut self, cert: C) -> Self
+    where
+        C: Into<Certificate>,
+    {
+        self.ca_cert = Some(cert.into());
+        self
+    }
+
+    /// Verifies the authentication server against the CA certificates of
+    /// the system, e.g. for servers with publicly trusted certificates.
+    pub fn system_ca_certs(mut self, enabled: bool) -> Self {
+        self.system_ca_certs = enabled;
+        self
+    }
+
+    /// Requires the server certificate to be issued for `domain` or one of
+    /// its subdomains.
+    pub fn domain_suffix_match(mut self, domain: &str) -> Self {
+        self.domain_suffix_match = Some(domain.into());
+        self
+    }
+
+    /// Pins the server certificate to `domain`: it has to be issued for
+    /// exactly that name, not a subdomain. Requires NetworkManager 1.24.
+    pub fn domain_match(mut self, domain: &str) -> Self {
+        self.domain_match = Some(domain.into());
+        self
+    }
+
+    /// Requires the server certificate to have one of the given subject
+    /// alternative names, e.g. `DNS:radius.example.org`.
+    pub fn altsubject_matches<S>(mut self, matches: &[S]) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.altsubject_matches = matches.iter().map(|m| m.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Allows the configuration without verifying the authentication server,
+    /// which lets any access point or switch posing as the network collect
+    /// the credentials. Only meant for tests and legacy networks.
+    pub fn without_server_validation(mut self) -> Self {
+        self.skip_server_validation = true;
+        self
+    }
+
+    pub fn private_key_password(mut self, password: &str) -> Self {
+        self.private_key_password = Some(password.into());
+        self
+    }
+
+    pub fn password_flags(mut self, flags: SecretFlags) -> Self {
+        self.password_flags = flags;
+        self
+    }
+
+    pub fn private_key_password_flags(mut self, flags: SecretFlags) -> Self {
+        self.private_key_password_flags = flags;
+        self
+    }
+
+    /// Checks that the configuration has an identity and validates the
+    /// server certificate, unless it opted out with
+    /// `without_server_validation`.
+    pub fn verify(&self) -> Result<()> {
+        match self.identity {
+            Some(ref identity) if !identity.is_empty() => {}
+            _ => bail!(ErrorKind::NetworkManager(
+                "802.1X credentials require an identity".into()
+            )),
+        }
+
+        if self.ca_cert.is_some() || self.system_ca_certs || self.skip_server_validation {
+            return Ok(());
+        }
+
+        bail!(ErrorKind::NetworkManager(
+            "802.1X configuration does not validate the server certificate, set a CA \
+             certificate, enable system CA certificates or opt out with \
+             without_server_validation"
+                .into()
+        ))
+    }
+
+    /// The `802-1x` setting, after checking the configuration with `verify`.
+    pub fn to_setting(&self) -> Result<Setting> {
+        self.verify()?;
+
+        let mut setting = Setting::new();
+
+        setting.insert("eap".into(), Value::Strings(self.eap.clone()));
+
+        if self.system_ca_certs {
+            setting.insert("system-ca-certs".into(), Value::Bool(true));
+        }
+        if !self.altsubject_matches.is_empty() {
+            setting.insert(
+                "altsubject-matches".into(),
+                Value::Strings(self.altsubject_matches.clone()),
+            );
+        }
+
+        if !self.password_flags.is_empty() {
+            setting.insert(
+                "password-flags".into(),
+                Value::UInt32(self.password_flags.bits()),
+            );
+        }
+        if !self.private_key_password_flags.is_empty() {
+            setting.insert(
+                "private-key-password-flags".into(),
+                Value::UInt32(self.private_key_password_flags.bits()),
+            );
+        }
+
+        let strings = [
+            ("identity", &self.identity),
+            ("anonymous-identity", &self.anonymous_identity),
+            ("phase2-auth", &self.phase2_auth),
+            ("domain-suffix-match", &self.domain_suffix_match),
+            ("domain-match", &self.domain_match),
+        ];
+        for &(key, value) in &strings {
+            if let Some(ref value) = *value {
+                setting.insert(key.into(), Value::String(value.clone()));
+            }
+        }
+
+        let secrets = [
+            ("password", &self.password),
+            ("private-key-password", &self.private_key_password),
+        ];
+        for &(key, secret) in &secrets {
+            if let Some(ref secret) = *secret {
+                setting.insert(key.into(), Value::String(secret.expose().to_string()));
+            }
+        }
+
+        let certs = [
+            ("ca-cert", &self.ca_cert),
+            ("client-cert", &self.client_cert),
+            ("private-key", &self.private_key),
+        ];
+        for &(key, cert) in &certs {
+            if let Some(ref cert) = *cert {
+                setting.insert(key.into(), cert.to_value());
+            }
+        }
+
+        Ok(setting)
+    }
+}
+
+/// A certificate, private key or PKCS#12 bundle for EAP authentication.
+///
+/// Blobs are handed to NetworkManager in memory, so applications never need
+/// to write credentials to disk.
+#[derive(Clone, PartialEq)]
+pub enum Certificate {
+    /// A file readable by NetworkManager.
+    Path(String),
+    /// PEM or DER encoded data, or a PKCS#12 bundle.
+    Blob(Vec<u8>),
+}
+
+impl Certificate {
+    fn to_value(&self) -> Value {
+        match *self {
+            Certificate::Path(ref path) => {
+                // NetworkManager takes paths as NUL-terminated `file://` URIs
+                let mut bytes = format!("file://{}", path).into_bytes();
+                bytes.push(0);
+                Value::Bytes(bytes)
+            }
+            Certificate::Blob(ref data) => Value::Bytes(data.clone()),
+        }
+    }
+}
+
+impl fmt::Debug for Ieee8021xConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Ieee8021xConfig")
+            .field("eap", &self.eap)
+            .field("identity", &self.identity)
+            .field("anonymous_identity", &self.anonymous_identity)
+            .field("password", &self.password)
+            .field("password_flags", &self.password_flags)
+            .field("phase2_auth", &self.phase2_auth)
+            .field("ca_cert", &self.ca_cert)
+            .field("system_ca_certs", &self.system_ca_certs)
+            .field("domain_suffix_match", &self.domain_suffix_match)
+            .field("domain_match", &self.domain_match)
+            .field("altsubject_matches", &self.altsubject_matches)
+            .field("skip_server_validation", &self.skip_server_validation)
+            .field("client_cert", &self.client_cert)
+            .field("private_key", &self.private_key)
+            .field("private_key_password", &self.private_key_password)
+            .field(
+                "private_key_password_flags",
+                &self.private_key_password_flags,
+            )
+            .finish()
+    }
+}
+
+impl fmt::Debug for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Certificate::Path(ref path) => write!(f, "Path({:?})", path),
+            Certificate::Blob(ref data) => write!(f, "Blob({} bytes)", data.len()),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Certificate {
+    fn from(path: &'a str) -> Self {
+        Certificate::Path(path.to_string())
+    }
+}
+
+impl From<Vec<u8>> for Certificate {
+    fn from(data: Vec<u8>) -> Self {
+        Certificate::Blob(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use network_manager_types::SriovVf;
+
+    #[test]
+    fn test_profile_set_get() {
+        let mut profile = ConnectionProfile::ethernet("uplink", "eth0");
+        profile.set("ipv4", "method", "auto");
+
+        assert_eq!(profile.kind(), Some("802-3-ethernet"));
+        assert_eq!(profile.id(), Some("uplink"));
+        assert_eq!(profile.uuid(), None);
+        assert_eq!(
+            profile.get("ipv4", "method"),
+            Some(&Value::String("auto".into()))
+        );
+        assert!(profile.setting("802-3-ethernet").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_export_secrets() {
+        let profile = ConnectionProfile::new("802-11-wireless", "home")
+            .with("802-11-wireless-security", "key-mgmt", "wpa-psk")
+            .with("802-11-wireless-security", "psk", "password")
+            .with("802-11-wireless-security", "psk-flags", 0u32);
+
+        let mut stripped = profile.clone();
+        stripped.export_secrets(SecretExport::Strip);
+        assert_eq!(stripped.get("802-11-wireless-security", "psk"), None);
+        assert!(stripped
+            .get("802-11-wireless-security", "psk-flags")
+            .is_some());
+
+        let mut masked = profile.clone();
+        masked.export_secrets(SecretExport::Placeholder);
+        assert_eq!(
+            masked.get("802-11-wireless-security", "psk"),
+            Some(&Value::String("<redacted>".into()))
+        );
+        assert!(masked.has_secret_placeholders());
+        assert!(!profile.has_secret_placeholders());
+    }
+
+    #[test]
+    fn test_export_vpn_secrets() {
+        let mut secrets = BTreeMap::new();
+        secrets.insert("password".to_string(), Value::from("hunter2"));
+        secrets.insert("cert-pass".to_string(), Value::from("swordfish"));
+
+        let profile = ConnectionProfile::new("vpn", "office")
+            .with(
+                "vpn",
+                "service-type",
+                "org.freedesktop.NetworkManager.openvpn",
+            )
+            .with("vpn", "secrets", Value::Dict(secrets));
+
+        let mut stripped = profile.clone();
+        stripped.export_secrets(SecretExport::Strip);
+        assert_eq!(stripped.get("vpn", "secrets"), None);
+        assert!(stripped.get("vpn", "service-type").is_some());
+
+        let mut masked = profile.clone();
+        masked.export_secrets(SecretExport::Placeholder);
+        match masked.get("vpn", "secrets") {
+            Some(Value::Dict(secrets)) => {
+                assert_eq!(secrets.len(), 2);
+                assert!(secrets.values().all(|secret| *secret == Value::from(MASK)));
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(masked.has_secret_placeholders());
+        assert!(!profile.has_secret_placeholders());
+    }
+
+    #[test]
+    fn test_export_wireguard_peer_secrets() {
+        let mut peer = BTreeMap::new();
+        peer.insert("public-key".to_string(), Value::from("cGVlcg=="));
+        peer.insert("preshared-key".to_string(), Value::from("c2VjcmV0"));
+        peer.insert("preshared-key-flags".to_string(), Value::UInt32(0));
+
+        let profile = ConnectionProfile::new("wireguard", "tunnel")
+            .with("wireguard", "private-key", "a2V5")
+            .with("wireguard", "peers", Value::Dicts(vec![peer]));
+
+        let mut stripped = profile.clone();
+        stripped.export_secrets(SecretExport::Strip);
+        assert_eq!(stripped.get("wireguard", "private-key"), None);
+        match stripped.get("wireguard", "peers") {
+            Some(Value::Dicts(peers)) => {
+                assert!(!peers[0].contains_key("preshared-key"));
+                assert!(peers[0].contains_key("preshared-key-flags"));
+                assert!(peers[0].contains_key("public-key"));
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let mut masked = profile.clone();
+        masked.export_secrets(SecretExport::Placeholder);
+        match masked.get("wireguard", "peers") {
+            Some(Value::Dicts(peers)) => {
+                assert_eq!(peers[0].get("preshared-key"), Some(&Value::from(MASK)));
+                assert_eq!(peers[0].get("public-key"), Some(&Value::from("cGVlcg==")));
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(masked.has_secret_placeholders());
+    }
+
+    #[test]
+    fn test_ethtool_setting_keys() {
+        let mut profile = ConnectionProfile::ethernet("uplink", "eth0");
+        profile.set_ethtool(
+            &EthtoolConfig::new()
+                .feature("tso", false)
+                .coalesce("rx-usecs", 50)
+                .ring("rx", 4096),
+        );
+
+        assert_eq!(
+            profile.get("ethtool", "feature-tso"),
+            Some(&Value::Bool(false))
+        );
+        assert_eq!(
+            profile.get("ethtool", "coalesce-rx-usecs"),
+            Some(&Value::UInt32(50))
+        );
+        assert_eq!(
+            profile.get("ethtool", "ring-rx"),
+            Some(&Value::UInt32(4096))
+        );
+    }
+
+    #[test]
+    fn test_wired_8021x_setting() {
+        let mut profile = ConnectionProfile::ethernet("office", "eth0");
+        profile
+            .set_8021x(&Ieee8021xConfig::peap("alice", "secret").ca_cert("/etc/ca.pem"))
+            .unwrap();
+
+        assert_eq!(
+            profile.get("802-1x", "eap"),
+            Some(&Value::Strings(vec!["peap".into()]))
+        );
+        assert_eq!(
+            profile.get("802-1x", "ca-cert"),
+            Some(&Value::Bytes(b"file:///etc/ca.pem\0".to_vec()))
+        );
+        assert_eq!(profile.get("802-1x", "client-cert"), None);
+    }
+
+    #[test]
+    fn test_8021x_server_validation() {
+        let mut profile = ConnectionProfile::ethernet("office", "eth0");
+
+        assert!(profile
+            .set_8021x(&Ieee8021xConfig::ttls("alice", "secret", "pap"))
+            .is_err());
+        assert_eq!(profile.setting("802-1x"), None);
+
+        profile
+            .set_8021x(
+                &Ieee8021xConfig::ttls("alice", "secret", "pap")
+                    .system_ca_certs(true)
+                    .domain_suffix_match("example.org")
+                    .altsubject_matches(&["DNS:radius.example.org"]),
+            )
+            .unwrap();
+
+        assert_eq!(
+            profile.get("802-1x", "system-ca-certs"),
+            Some(&Value::Bool(true))
+        );
+        assert_eq!(
+            profile.get("802-1x", "domain-suffix-match"),
+            Some(&Value::String("example.org".into()))
+        );
+        assert_eq!(
+            profile.get("802-1x", "altsubject-matches"),
+            Some(&Value::Strings(vec!["DNS:radius.example.org".into()]))
+        );
+
+        profile
+            .set_8021x(&Ieee8021xConfig::ttls("alice", "secret", "pap").without_server_validation())
+            .unwrap();
+
+        assert_eq!(profile.get("802-1x", "system-ca-certs"), None);
+    }
+
+    #[test]
+    fn test_changes_and_merge() {
+        let mut current = ConnectionProfile::ethernet("uplink", "eth0")
+            .with("connection", "uuid", "8c1b4b6e-0000-0000-0000-000000000000")
+            .with("ipv4", "method", "auto");
+        let desired = ConnectionProfile::ethernet("uplink", "eth0")
+            .with("ipv4", "method", "manual")
+            .with("ipv6", "method", "ignore");
+
+        assert_eq!(current.changes(&desired), vec!["ipv4.method", "ipv6"]);
+
+        current.merge(&desired);
+
+        assert!(current.changes(&desired).is_empty());
+        assert!(current.uuid().is_some());
+    }
+
+    #[test]
+    fn test_connection_metadata() {
+        let mut profile = ConnectionProfile::ethernet("uplink", "eth0");
+        profile
+            .set_stable_id("${CONNECTION}/${BOOT}")
+            .set_auth_retries(0)
+            .set_zone("internal");
+
+        assert_eq!(
+            profile.get("connection", "stable-id"),
+            Some(&Value::String("${CONNECTION}/${BOOT}".into()))
+        );
+        assert_eq!(
+            profile.get("connection", "auth-retries"),
+            Some(&Value::Int32(0))
+        );
+        assert_eq!(
+            profile.get("connection", "zone"),
+            Some(&Value::String("internal".into()))
+        );
+    }
+
+    #[test]
+    fn test_secret_flags() {
+        let mut profile = ConnectionProfile::ethernet("office", "eth0");
+        profile
+            .set_8021x(
+                &Ieee8021xConfig::peap("alice", "secret")
+                    .system_ca_certs(true)
+                    .password_flags(SecretFlags::NOT_SAVED),
+            )
+            .unwrap();
+        profile.set_secret_flags("802-1x", "pin", SecretFlags::NOT_REQUIRED);
+
+        assert_eq!(
+            profile.get("802-1x", "password-flags"),
+            Some(&Value::UInt32(2))
+        );
+        assert_eq!(profile.get("802-1x", "pin-flags"), Some(&Value::UInt32(4)));
+        assert_eq!(profile.get("802-1x", "private-key-password-flags"), None);
+    }
+
+    #[test]
+    fn test_pkcs12_blob() {
+        let bundle = vec![0x30, 0x82, 0x01];
+        let mut profile = ConnectionProfile::ethernet("office", "eth0");
+        profile
+            .set_8021x(
+                &Ieee8021xConfig::tls_pkcs12("alice", bundle.clone(), "pass").system_ca_certs(true),
+            )
+            .unwrap();
+
+        assert_eq!(
+            profile.get("802-1x", "private-key"),
+            Some(&Value::Bytes(bundle.clone()))
+        );
+        assert_eq!(
+            profile.get("802-1x", "client-cert"),
+            Some(&Value::Bytes(bundle))
+        );
+        assert_eq!(
+            profile.get("802-1x", "private-key-password"),
+            Some(&Value::String("pass".into()))
+        );
+    }
+
+    #[test]
+    fn test_sriov_setting() {
+        let mut profile = ConnectionProfile::ethernet("uplink", "eth0");
+        profile.set_sriov(&SriovConfig {
+            total_vfs: 4,
+            autoprobe_drivers: Some(false),
+            vfs: vec![SriovVf {
+                index: 0,
+                trust: Some(true),
+                ..Default::default()
+            }],
+        });
+
+        assert_eq!(profile.get("sriov", "total-vfs"), Some(&Value::UInt32(4)));
+        assert_eq!(
+            profile.get("sriov", "autoprobe-drivers"),
+            Some(&Value::Int32(0))
+        );
+        match profile.get("sriov", "vfs") {
+            Some(Value::Dicts(vfs)) => {
+                assert_eq!(vfs[0].get("trust"), Some(&Value::Bool(true)));
+                assert_eq!(vfs[0].get("mac"), None);
+            }
+            other => panic!("unexpected vfs value: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_profile_debug_redacts_secrets() {
+        let mut profile = ConnectionProfile::new("802-11-wireless", "home");
+        profile.set("802-11-wireless-security", "psk", "hunter22");
+        profile.set("802-11-wireless-security", "psk-flags", 0u32);
+
+        let debug = format!("{:?}", profile);
+
+        assert!(!debug.contains("hunter22"));
+        assert!(debug.contains("<redacted>"));
+        assert!(debug.contains("\"psk-flags\": UInt32(0)"));
+    }
+
+    #[test]
+    fn test_verify_interface_name() {
+        assert!(verify_interface_name("br0").is_ok());
+        assert!(verify_interface_name("enp0s31f6.100").is_ok());
+        assert!(verify_interface_name("").is_err());
+        assert!(verify_interface_name("a-very-long-name0").is_err());
+        assert!(verify_interface_name("wg 0").is_err());
+        assert!(verify_interface_name("..").is_err());
+    }
+}
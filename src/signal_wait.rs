@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+use dbus::Connection as DBusConnection;
+
+use dbus_api::{shared_connection, Bus};
+use errors::*;
+
+const NM_BUS_NAME: &str = "org.freedesktop.NetworkManager";
+
+/// Calls `check` until it returns a value, blocking on the signals matching
+/// `rules` in between. Returns `None` once `timeout` elapsed.
+///
+/// Signals are subscribed to before the first check, so that a change in
+/// between is not missed. Any signal triggers a check, at most every
+/// `interval` otherwise, covering changes that are not signalled, such as
+/// udev renaming an interface.
+///
+/// The wait runs on the connection shared by the current thread, see
+/// `shared_connection`, and unsubscribes again once done.
+pub fn wait_for_signal<T, F>(
+    bus: Bus,
+    rules: &[String],
+    timeout: Duration,
+    interval: Duration,
+    mut check: F,
+) -> Result<Option<T>>
+where
+    F: FnMut() -> Result<Option<T>>,
+{
+    let connection = shared_connection(bus)?;
+
+    let mut matches = Matches {
+        connection: &connection,
+        rules: Vec::new(),
+    };
+
+    for rule in rules {
+        connection.add_match(rule)?;
+        matches.rules.push(rule);
+    }
+
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        if let Some(value) = check()? {
+            return Ok(Some(value));
+        }
+
+        let remaining = match deadline.checked_duration_since(Instant::now()) {
+            Some(remaining) if remaining > Duration::from_millis(0) => remaining,
+            _ => return Ok(None),
+        };
+
+        let timeout_ms = remaining.min(interval).as_millis() as i32;
+
+        connection.iter(timeout_ms).next();
+    }
+}
+
+/// Match rules added to a connection that outlives the wait, removed again on
+/// drop so that the connection does not keep receiving the signals.
+struct Matches<'a> {
+    connection: &'a DBusConnection,
+    rules: Vec<&'a String>,
+}
+
+impl<'a> Drop for Matches<'a> {
+    fn drop(&mut self) {
+        for rule in &self.rules {
+            if let Err(e) = self.connection.remove_match(rule) {
+                debug!("Unable to remove match rule {}: {}", rule, e);
+            }
+        }
+    }
+}
+
+/// Matches a signal of NetworkManager, on any object unless `path` is given.
+pub fn signal_rule(interface: &str, member: &str, path: Option<&str>) -> String {
+    let mut rule = format!(
+        "type='signal',sender='{}',interface='{}',member='{}'",
+        NM_BUS_NAME, interface, member
+    );
+
+    if let Some(path) = path {
+        rule.push_str(&format!(",path='{}'", path));
+    }
+
+    rule
+}
+
+/// Matches property changes of NetworkManager objects implementing
+/// `interface`.
+pub fn properties_changed_rule(interface: &str) -> String {
+    format!(
+        "type='signal',sender='{}',interface='org.freedesktop.DBus.Properties',\
+         member='PropertiesChanged',arg0='{}'",
+        NM_BUS_NAME, interface
+    )
+}
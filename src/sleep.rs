@@ -0,0 +1,242 @@
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+use dbus::{BusType, Connection as DBusConnection, ConnectionItem, Message, OwnedFd};
+
+use dbus_nm::DBusNetworkManager;
+use errors::*;
+
+use manager::{Connectivity, NetworkManagerState};
+
+const LOGIN_BUS_NAME: &str = "org.freedesktop.login1";
+const LOGIN_PATH: &str = "/org/freedesktop/login1";
+const LOGIN_MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+
+/// System suspend and resume, as announced by logind, with the state of
+/// NetworkManager at that moment.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum SleepEvent {
+    /// The system is about to suspend or hibernate. Connections dropping
+    /// from now on are due to the sleep rather than an outage.
+    Suspending { state: NetworkManagerState },
+    /// The system woke up. NetworkManager usually reconnects within a few
+    /// seconds, so a `connectivity` short of `Full` is not yet an outage.
+    Resumed {
+        /// The state once the connectivity check finished.
+        state: NetworkManagerState,
+        /// The result of a connectivity check started on resume, `Unknown`
+        /// if checking is disabled or failed.
+        connectivity: Connectivity,
+        /// How long the system slept, `None` if the suspend was not seen.
+        slept: Option<Duration>,
+    },
+}
+
+/// Blocks watching logind's `PrepareForSleep`, invoking `callback` with each
+/// event until it returns `false`.
+///
+/// A delay inhibitor lock is held while watching, so that logind suspends
+/// only once `callback` handled `Suspending`, or its `InhibitDelayMaxSec`
+/// passed. Without permission to take the lock, `Suspending` may only be
+/// handled after the system resumed.
+pub fn watch_sleep<F>(dbus_manager: &Rc<DBusNetworkManager>, mut callback: F) -> Result<()>
+where
+    F: FnMut(SleepEvent) -> bool,
+{
+    // logind is on the system bus whichever bus NetworkManager is reached on
+    let connection = DBusConnection::get_private(BusType::System)?;
+
+    connection.add_match(&format!(
+        "type='signal',sender='{}',interface='{}',member='PrepareForSleep'",
+        LOGIN_BUS_NAME, LOGIN_MANAGER_INTERFACE
+    ))?;
+
+    let mut lock = take_delay_lock(&connection);
+
+    let mut suspended = None;
+
+    for item in connection.iter(1000) {
+        let message = match item {
+            ConnectionItem::Signal(message) => message,
+            _ => continue,
+        };
+
+        let start = match message.get1::<bool>() {
+            Some(start) => start,
+            None => continue,
+        };
+
+        if !start && lock.is_none() {
+            lock = take_delay_lock(&connection);
+        }
+
+        let event = sleep_event(
+            start,
+            &mut suspended,
+            || {
+                dbus_manager
+                    .get_state()
+                    .unwrap_or(NetworkManagerState::Unknown)
+            },
+            || {
+                dbus_manager.check_connectivity().unwrap_or_else(|e| {
+                    debug!("Connectivity check after resume failed: {}", e);
+                    Connectivity::Unknown
+                })
+            },
+        );
+
+        let watching = callback(event);
+
+        // Lets the system go to sleep
+        if start {
+            lock.take();
+        }
+
+        if !watching {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+/// The event of `PrepareForSleep(start)`. On resume the state is read after
+/// the connectivity check, which may take seconds.
+fn sleep_event<S, C>(
+    start: bool,
+    suspended: &mut Option<SystemTime>,
+    state: S,
+    connectivity: C,
+) -> SleepEvent
+where
+    S: FnOnce() -> NetworkManagerState,
+    C: FnOnce() -> Connectivity,
+{
+    if start {
+        let state = state();
+
+        debug!("System suspending, NetworkManager {:?}", state);
+
+        *suspended = Some(SystemTime::now());
+
+        SleepEvent::Suspending { state }
+    } else {
+        let slept = suspended.take().and_then(|since| since.elapsed().ok());
+
+        let connectivity = connectivity();
+        let state = state();
+
+        debug!(
+            "System resumed after {:?}, NetworkManager {:?}, connectivity {:?}",
+            slept, state, connectivity
+        );
+
+        SleepEvent::Resumed {
+            state,
+            connectivity,
+            slept,
+        }
+    }
+}
+
+/// Takes a logind delay inhibitor lock on sleep, which is released by
+/// dropping it. `None` if logind refused it, e.g. for lack of permission.
+fn take_delay_lock(connection: &DBusConnection) -> Option<OwnedFd> {
+    match inhibit_sleep(connection) {
+        Ok(lock) => Some(lock),
+        Err(e) => {
+            debug!("Unable to delay sleep: {}", e);
+            None
+        }
+    }
+}
+
+fn inhibit_sleep(connection: &DBusConnection) -> Result<OwnedFd> {
+    let message = Message::new_method_call(
+        LOGIN_BUS_NAME,
+        LOGIN_PATH,
+        LOGIN_MANAGER_INTERFACE,
+        "Inhibit",
+    )
+    .map_err(ErrorKind::DBusAPI)?
+    .append3("sleep", "network-manager", "Reporting the network state")
+    .append1("delay");
+
+    let response = connection.send_with_reply_and_block(message, 2000)?;
+
+    match response.get1::<OwnedFd>() {
+        Some(lock) => Ok(lock),
+        None => bail!(ErrorKind::DBusAPI("Inhibit returned no lock".into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[test]
+    fn test_sleep_event() {
+        let mut suspended = None;
+
+        let event = sleep_event(
+            true,
+            &mut suspended,
+            || NetworkManagerState::ConnectedGlobal,
+            || unreachable!(),
+        );
+        assert_eq!(
+            event,
+            SleepEvent::Suspending {
+                state: NetworkManagerState::ConnectedGlobal
+            }
+        );
+        assert!(suspended.is_some());
+
+        let checked = Cell::new(false);
+
+        let event = sleep_event(
+            false,
+            &mut suspended,
+            || match checked.get() {
+                true => NetworkManagerState::ConnectedGlobal,
+                false => NetworkManagerState::Connecting,
+            },
+            || {
+                checked.set(true);
+                Connectivity::Full
+            },
+        );
+        match event {
+            SleepEvent::Resumed {
+                state,
+                connectivity,
+                slept,
+            } => {
+                assert_eq!(state, NetworkManagerState::ConnectedGlobal);
+                assert_eq!(connectivity, Connectivity::Full);
+                assert!(slept.is_some());
+            }
+            event => panic!("Unexpected {:?}", event),
+        }
+        assert!(suspended.is_none());
+
+        let event = sleep_event(
+            false,
+            &mut suspended,
+            || NetworkManagerState::ConnectedGlobal,
+            || Connectivity::Full,
+        );
+        assert_eq!(
+            event,
+            SleepEvent::Resumed {
+                state: NetworkManagerState::ConnectedGlobal,
+                connectivity: Connectivity::Full,
+                slept: None,
+            }
+        );
+    }
+}
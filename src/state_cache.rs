@@ -0,0 +1,731 @@
+#[cfg(feature = "wifi")]
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use dbus::arg::{Dict, Iter, Variant};
+use dbus::{ConnectionItem, Message, Path as DBusPath};
+
+use dbus_api::{open_connection, Bus};
+use dbus_nm::DBusNetworkManager;
+use errors::*;
+#[cfg(feature = "wifi")]
+use network_manager_types::DeviceType;
+
+use connection::{get_connections, ConnectionSettings};
+use device::get_device_by_path;
+#[cfg(feature = "wifi")]
+use device::get_devices;
+use manager::{Connectivity, NetworkManagerState};
+use state_log::{diff_snapshots, ChangeLog, StateChange};
+use status::{
+    get_active_connection_id, get_device_status, get_manager_status, DeviceStatus, Status,
+};
+#[cfg(feature = "wifi")]
+use wifi::AccessPoint;
+
+const NM_BUS_NAME: &str = "org.freedesktop.NetworkManager";
+const NM_INTERFACE: &str = "org.freedesktop.NetworkManager";
+const NM_DEVICE_INTERFACE: &str = "org.freedesktop.NetworkManager.Device";
+const NM_IP_CONFIG_INTERFACE: &str = "org.freedesktop.NetworkManager.IP";
+const NM_SETTINGS_INTERFACE: &str = "org.freedesktop.NetworkManager.Settings";
+#[cfg(feature = "wifi")]
+const NM_WIRELESS_INTERFACE: &str = "org.freedesktop.NetworkManager.Device.Wireless";
+#[cfg(feature = "wifi")]
+const NM_ACCESS_POINT_INTERFACE: &str = "org.freedesktop.NetworkManager.AccessPoint";
+
+/// How often the cache thread checks whether it was dropped.
+const POLL_MS: i32 = 1000;
+
+/// How long signals are collected before the model is refreshed, so that a
+/// burst, e.g. of an activation, results in one refresh.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// How many changes are kept for `StateCache::changes_since`.
+const LOG_CAPACITY: usize = 1024;
+
+/// The cached model of NetworkManager at one point in time.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct StateSnapshot {
+    /// Increases by one whenever the model changes.
+    pub generation: u64,
+    /// Sequence number of the last change the snapshot includes, to be
+    /// passed to `StateCache::changes_since`.
+    pub sequence: u64,
+    pub status: Status,
+    /// Settings of all connection profiles.
+    pub connections: Vec<ConnectionSettings>,
+    /// The most recent scan results of each Wi-Fi device, by interface.
+    #[cfg(feature = "wifi")]
+    pub access_points: BTreeMap<String, Vec<AccessPoint>>,
+}
+
+/// An in-memory model of devices, connections and access points, kept up to
+/// date by a background thread from NetworkManager's signals.
+///
+/// Taking a snapshot does not touch the bus, so it can be done as often as
+/// needed and from any thread. The thread stops when the cache is dropped.
+///
+/// Consumers forwarding the state elsewhere, e.g. to a web dashboard, can
+/// send one snapshot followed by the changes since its `sequence`.
+pub struct StateCache {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<CacheState>,
+    changed: Condvar,
+    stopped: AtomicBool,
+}
+
+struct CacheState {
+    snapshot: Arc<StateSnapshot>,
+    log: ChangeLog,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl StateCache {
+    /// Starts caching the state of NetworkManager on the system bus. Fails if
+    /// the initial state cannot be read.
+    pub fn new() -> Result<Self> {
+        StateCache::with_bus(Bus::System)
+    }
+
+    /// Like `new`, but for NetworkManager on the given bus.
+    pub fn with_bus(bus: Bus) -> Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("nm-state-cache".into())
+            .spawn(move || run_cache(bus, &sender))
+            .chain_err(|| ErrorKind::NetworkManager("Unable to start the state cache".into()))?;
+
+        let shared = match receiver.recv() {
+            Ok(result) => result?,
+            Err(_) => bail!(ErrorKind::NetworkManager(
+                "State cache thread exited".into()
+            )),
+        };
+
+        Ok(StateCache { shared })
+    }
+
+    /// The current model. Consecutive snapshots with the same generation are
+    /// the same.
+    pub fn snapshot(&self) -> Arc<StateSnapshot> {
+        Arc::clone(&self.shared.lock().snapshot)
+    }
+
+    /// The changes after `sequence`, oldest first. `None` if some of them are
+    /// no longer kept, in which case a new snapshot has to be taken.
+    pub fn changes_since(&self, sequence: u64) -> Option<Vec<StateChange>> {
+        self.shared.lock().log.since(sequence)
+    }
+
+    /// Like `changes_since`, but blocks until there is a change after
+    /// `sequence` or `timeout` elapsed.
+    pub fn wait_for_changes(&self, sequence: u64, timeout: Duration) -> Option<Vec<StateChange>> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.lock();
+
+        loop {
+            if state.log.last_sequence() != sequence {
+                return state.log.since(sequence);
+            }
+
+            let remaining = match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) => remaining,
+                None => return Some(Vec::new()),
+            };
+
+            state = match self.shared.changed.wait_timeout(state, remaining) {
+                Ok((state, _)) => state,
+                Err(poisoned) => poisoned.into_inner().0,
+            };
+        }
+    }
+}
+
+impl Drop for StateCache {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// What a burst of signals changed. Manager properties carried by the
+/// signals are applied as they are, everything else is read again, but only
+/// for the objects the signals were about.
+#[derive(Clone, Default)]
+struct Changes {
+    /// Everything is read again, e.g. once NetworkManager restarted.
+    all: bool,
+    state: Option<u32>,
+    connectivity: Option<u32>,
+    /// Object path of the new primary connection, "/" if there is none.
+    primary_connection: Option<String>,
+    /// Devices came or went.
+    device_list: bool,
+    /// Object paths of the devices whose status is read again.
+    devices: BTreeSet<String>,
+    /// Addresses changed on IP configurations, which do not tell their
+    /// device, so all devices are read again.
+    all_devices: bool,
+    /// Access points changed, one of which may be the one a Wi-Fi device is
+    /// associated with.
+    #[cfg(feature = "wifi")]
+    wifi_devices: bool,
+    connections: bool,
+    #[cfg(feature = "wifi")]
+    access_points: bool,
+}
+
+impl Changes {
+    fn all() -> Self {
+        Changes {
+            all: true,
+            ..Changes::default()
+        }
+    }
+
+    fn add(&mut self, message: &Message) {
+        let interface = message.interface();
+        let member = message.member();
+        let path = message.path().map(|path| path.to_string());
+
+        match member.as_deref() {
+            Some("NameOwnerChanged") => *self = Changes::all(),
+            Some("PropertiesChanged") => {
+                if let (Some(changed), Some(properties)) =
+                    message.get2::<&str, Dict<&str, Variant<Iter>, _>>()
+                {
+                    self.add_properties(changed, path, properties);
+                }
+            }
+            Some(member) => {
+                if let Some(ref interface) = interface {
+                    self.add_signal(interface, member, path, message);
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn add_properties<'a, I>(&mut self, interface: &str, path: Option<String>, properties: I)
+    where
+        I: Iterator<Item = (&'a str, Variant<Iter<'a>>)>,
+    {
+        if interface == NM_INTERFACE {
+            for (name, mut value) in properties {
+                match name {
+                    "State" => self.state = value.0.get::<u32>().or(self.state),
+                    "Connectivity" => {
+                        self.connectivity = value.0.get::<u32>().or(self.connectivity)
+                    }
+                    "PrimaryConnection" => {
+                        self.primary_connection =
+                            value.0.get::<DBusPath>().map(|path| path.to_string());
+                    }
+                    "Devices" | "AllDevices" => self.device_list = true,
+                    _ => {}
+                }
+            }
+        } else if interface.starts_with(NM_DEVICE_INTERFACE) {
+            self.devices.extend(path);
+
+            #[cfg(feature = "wifi")]
+            if interface == NM_WIRELESS_INTERFACE {
+                self.access_points = true;
+            }
+        } else if interface.starts_with(NM_IP_CONFIG_INTERFACE) {
+            self.all_devices = true;
+        } else if interface.starts_with(NM_SETTINGS_INTERFACE) {
+            self.connections = true;
+        }
+
+        #[cfg(feature = "wifi")]
+        if interface == NM_ACCESS_POINT_INTERFACE {
+            self.access_points = true;
+            self.wifi_devices = true;
+        }
+    }
+
+    fn add_signal(
+        &mut self,
+        interface: &str,
+        member: &str,
+        path: Option<String>,
+        message: &Message,
+    ) {
+        if interface == NM_INTERFACE {
+            match member {
+                "StateChanged" => self.state = message.get1::<u32>(),
+                "DeviceAdded" | "DeviceRemoved" => self.device_list = true,
+                _ => {}
+            }
+        } else if interface.starts_with(NM_DEVICE_INTERFACE) {
+            if member == "StateChanged" {
+                self.devices.extend(path);
+            }
+
+            #[cfg(feature = "wifi")]
+            if interface == NM_WIRELESS_INTERFACE {
+                self.access_points = true;
+            }
+        } else if interface.starts_with(NM_SETTINGS_INTERFACE) {
+            self.connections = true;
+        }
+    }
+
+    /// Whether the status of the device at `path` has to be read again.
+    fn reads_device(&self, path: &str, device: &DeviceStatus) -> bool {
+        #[cfg(feature = "wifi")]
+        if self.wifi_devices && device.device_type == DeviceType::WiFi {
+            return true;
+        }
+
+        #[cfg(not(feature = "wifi"))]
+        let _ = device;
+
+        self.all_devices || self.devices.contains(path)
+    }
+
+    /// Applies the manager properties carried by the signals to `status`.
+    fn apply(&self, status: &mut Status) {
+        if let Some(state) = self.state {
+            status.state = NetworkManagerState::from(state);
+        }
+
+        if let Some(connectivity) = self.connectivity {
+            status.connectivity = Connectivity::from(connectivity);
+        }
+    }
+}
+
+fn run_cache(bus: Bus, ready: &mpsc::Sender<Result<Arc<Shared>>>) {
+    let (dbus_manager, connection, shared, mut device_paths) = match start_cache(bus) {
+        Ok(started) => started,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+
+    let _ = ready.send(Ok(Arc::clone(&shared)));
+
+    let mut pending: Option<Changes> = None;
+
+    while !shared.stopped.load(Ordering::Relaxed) {
+        let mut changes = match connection.iter(POLL_MS).next() {
+            Some(ConnectionItem::Signal(message)) => {
+                let mut changes = pending.take().unwrap_or_default();
+                changes.add(&message);
+                changes
+            }
+            _ => match pending.take() {
+                Some(changes) => changes,
+                None => continue,
+            },
+        };
+
+        let deadline = Instant::now() + DEBOUNCE;
+
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            match connection.iter(remaining.as_millis() as i32).next() {
+                Some(ConnectionItem::Signal(message)) => changes.add(&message),
+                Some(ConnectionItem::Nothing) | None => break,
+                Some(_) => {}
+            }
+        }
+
+        let previous = Arc::clone(&shared.lock().snapshot);
+
+        match refresh(&dbus_manager, &previous, &mut device_paths, &changes) {
+            Ok(mut snapshot) => {
+                if snapshot.generation == previous.generation {
+                    continue;
+                }
+
+                let mut state = shared.lock();
+
+                for event in diff_snapshots(&previous, &snapshot) {
+                    state.log.push(event);
+                }
+
+                snapshot.sequence = state.log.last_sequence();
+                state.snapshot = Arc::new(snapshot);
+
+                shared.changed.notify_all();
+            }
+            Err(e) => {
+                debug!("Refreshing the state cache failed: {}", e);
+                pending = Some(Changes::all());
+            }
+        }
+    }
+}
+
+type Started = (
+    Rc<DBusNetworkManager>,
+    ::dbus::Connection,
+    Arc<Shared>,
+    Vec<String>,
+);
+
+fn start_cache(bus: Bus) -> Result<Started> {
+    let dbus_manager = Rc::new(DBusNetworkManager::with_bus(bus, None)?);
+
+    let connection = open_connection(bus)?;
+
+    connection.add_match(&format!("type='signal',sender='{}'", NM_BUS_NAME))?;
+    connection.add_match(&format!(
+        "type='signal',sender='org.freedesktop.DBus',interface='org.freedesktop.DBus',\
+         member='NameOwnerChanged',arg0='{}'",
+        NM_BUS_NAME
+    ))?;
+
+    let mut device_paths = Vec::new();
+
+    let snapshot = load(&dbus_manager, None, &mut device_paths, &Changes::all())?;
+
+    let shared = Arc::new(Shared {
+        state: Mutex::new(CacheState {
+            snapshot: Arc::new(snapshot),
+            log: ChangeLog::new(LOG_CAPACITY),
+        }),
+        changed: Condvar::new(),
+        stopped: AtomicBool::new(false),
+    });
+
+    Ok((dbus_manager, connection, shared, device_paths))
+}
+
+/// Reads what changed, returning `previous` with its generation unchanged
+/// if nothing did.
+fn refresh(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    previous: &StateSnapshot,
+    device_paths: &mut Vec<String>,
+    changes: &Changes,
+) -> Result<StateSnapshot> {
+    let mut snapshot = load(dbus_manager, Some(previous), device_paths, changes)?;
+
+    if snapshot != *previous {
+        snapshot.generation += 1;
+    }
+
+    Ok(snapshot)
+}
+
+/// Reads the model, or with `previous` only what `changes` requires. The
+/// object paths of the devices, in the order of `status.devices`, are kept
+/// in `device_paths`.
+fn load(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    previous: Option<&StateSnapshot>,
+    device_paths: &mut Vec<String>,
+    changes: &Changes,
+) -> Result<StateSnapshot> {
+    let previous = match previous {
+        Some(previous) if !changes.all => previous,
+        _ => return load_all(dbus_manager, previous, device_paths),
+    };
+
+    let status = update_status(dbus_manager, &previous.status, device_paths, changes)?;
+
+    let connections = if changes.connections {
+        get_connection_settings(dbus_manager)?
+    } else {
+        previous.connections.clone()
+    };
+
+    // Devices that came or went have their access points added or removed
+    #[cfg(feature = "wifi")]
+    let access_points = if changes.access_points || devices_changed(&status, previous) {
+        get_access_points(dbus_manager)?
+    } else {
+        previous.access_points.clone()
+    };
+
+    Ok(StateSnapshot {
+        generation: previous.generation,
+        sequence: previous.sequence,
+        status,
+        connections,
+        #[cfg(feature = "wifi")]
+        access_points,
+    })
+}
+
+fn load_all(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    previous: Option<&StateSnapshot>,
+    device_paths: &mut Vec<String>,
+) -> Result<StateSnapshot> {
+    let status = get_manager_status(dbus_manager)?;
+
+    device_paths.clear();
+
+    let changes = Changes {
+        device_list: true,
+        ..Changes::default()
+    };
+
+    let status = update_status(dbus_manager, &status, device_paths, &changes)?;
+
+    Ok(StateSnapshot {
+        generation: previous.map_or(0, |previous| previous.generation),
+        sequence: previous.map_or(0, |previous| previous.sequence),
+        status,
+        connections: get_connection_settings(dbus_manager)?,
+        #[cfg(feature = "wifi")]
+        access_points: get_access_points(dbus_manager)?,
+    })
+}
+
+/// Applies `changes` to `previous`, reading the primary connection and the
+/// devices the signals were about.
+fn update_status(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    previous: &Status,
+    device_paths: &mut Vec<String>,
+    changes: &Changes,
+) -> Result<Status> {
+    let mut status = previous.clone();
+
+    changes.apply(&mut status);
+
+    if let Some(ref primary) = changes.primary_connection {
+        status.primary_connection = get_active_connection_id(dbus_manager, primary)?;
+    }
+
+    if changes.device_list {
+        let paths = dbus_manager.get_devices()?;
+        let mut devices = Vec::with_capacity(paths.len());
+
+        for path in &paths {
+            let known = device_paths.iter().position(|known| known == path);
+
+            match known.map(|index| &previous.devices[index]) {
+                Some(device) if !changes.reads_device(path, device) => devices.push(device.clone()),
+                _ => devices.push(read_device_status(dbus_manager, path)?),
+            }
+        }
+
+        status.devices = devices;
+        *device_paths = paths;
+    } else {
+        for (path, device) in device_paths.iter().zip(status.devices.iter_mut()) {
+            if changes.reads_device(path, device) {
+                *device = read_device_status(dbus_manager, path)?;
+            }
+        }
+    }
+
+    Ok(status)
+}
+
+fn read_device_status(dbus_manager: &Rc<DBusNetworkManager>, path: &str) -> Result<DeviceStatus> {
+    get_device_status(dbus_manager, &get_device_by_path(dbus_manager, path)?)
+}
+
+#[cfg(feature = "wifi")]
+fn devices_changed(status: &Status, previous: &StateSnapshot) -> bool {
+    status.devices.len() != previous.status.devices.len()
+        || status
+            .devices
+            .iter()
+            .zip(&previous.status.devices)
+            .any(|(device, previous)| device.interface != previous.interface)
+}
+
+fn get_connection_settings(
+    dbus_manager: &Rc<DBusNetworkManager>,
+) -> Result<Vec<ConnectionSettings>> {
+    Ok(get_connections(dbus_manager)?
+        .iter()
+        .map(|connection| connection.settings().clone())
+        .collect())
+}
+
+#[cfg(feature = "wifi")]
+fn get_access_points(
+    dbus_manager: &Rc<DBusNetworkManager>,
+) -> Result<BTreeMap<String, Vec<AccessPoint>>> {
+    let mut access_points = BTreeMap::new();
+
+    for device in get_devices(dbus_manager)? {
+        if let Some(wifi_device) = device.as_wifi_device() {
+            access_points.insert(
+                device.interface().to_string(),
+                wifi_device.get_access_points()?,
+            );
+        }
+    }
+
+    Ok(access_points)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    use device::DeviceState;
+    use network_manager_types::DeviceType;
+
+    const DEVICE: &str = "/org/freedesktop/NetworkManager/Devices/1";
+    const OTHER_DEVICE: &str = "/org/freedesktop/NetworkManager/Devices/2";
+
+    fn signal(interface: &str, member: &str) -> Message {
+        signal_at("/org/freedesktop/NetworkManager", interface, member)
+    }
+
+    fn signal_at(path: &str, interface: &str, member: &str) -> Message {
+        Message::signal(&path.into(), &interface.into(), &member.into())
+    }
+
+    fn properties_changed<V>(path: &str, interface: &str, properties: Vec<(&str, V)>) -> Message
+    where
+        V: ::dbus::arg::Arg + ::dbus::arg::Append,
+    {
+        let properties = properties
+            .into_iter()
+            .map(|(name, value)| (name, Variant(value)))
+            .collect::<HashMap<_, _>>();
+
+        signal_at(path, "org.freedesktop.DBus.Properties", "PropertiesChanged")
+            .append2(interface, properties)
+    }
+
+    fn device(device_type: DeviceType) -> DeviceStatus {
+        DeviceStatus {
+            interface: "eth0".into(),
+            device_type,
+            state: DeviceState::Activated,
+            addresses: Vec::new(),
+            ssid: None,
+            strength: None,
+        }
+    }
+
+    #[test]
+    fn test_changes_from_signals() {
+        let mut changes = Changes::default();
+        changes.add(&signal(NM_SETTINGS_INTERFACE, "NewConnection"));
+        assert!(changes.connections);
+
+        let mut changes = Changes::default();
+        changes.add(&properties_changed(
+            DEVICE,
+            "org.freedesktop.NetworkManager.Device",
+            vec![("State", 100u32)],
+        ));
+        assert!(!changes.connections);
+    }
+
+    #[test]
+    fn test_manager_properties_are_applied() {
+        let mut changes = Changes::default();
+        changes.add(&properties_changed(
+            "/org/freedesktop/NetworkManager",
+            NM_INTERFACE,
+            vec![("State", 70u32), ("Connectivity", 4u32)],
+        ));
+
+        assert_eq!(changes.state, Some(70));
+        assert_eq!(changes.connectivity, Some(4));
+        assert!(!changes.device_list);
+        assert!(!changes.reads_device(DEVICE, &device(DeviceType::Ethernet)));
+
+        let mut status = Status {
+            state: NetworkManagerState::Disconnected,
+            connectivity: Connectivity::None,
+            primary_connection: None,
+            devices: vec![device(DeviceType::Ethernet)],
+        };
+
+        changes.apply(&mut status);
+
+        assert_eq!(status.state, NetworkManagerState::ConnectedGlobal);
+        assert_eq!(status.connectivity, Connectivity::Full);
+        assert_eq!(status.devices, vec![device(DeviceType::Ethernet)]);
+    }
+
+    #[test]
+    fn test_primary_connection_from_payload() {
+        let mut changes = Changes::default();
+        changes.add(&properties_changed(
+            "/org/freedesktop/NetworkManager",
+            NM_INTERFACE,
+            vec![(
+                "PrimaryConnection",
+                DBusPath::from("/org/freedesktop/NetworkManager/ActiveConnection/3"),
+            )],
+        ));
+
+        assert_eq!(
+            changes.primary_connection.as_deref(),
+            Some("/org/freedesktop/NetworkManager/ActiveConnection/3")
+        );
+        assert_eq!(changes.state, None);
+    }
+
+    #[test]
+    fn test_device_signals_read_that_device() {
+        let mut changes = Changes::default();
+        changes.add(&signal_at(DEVICE, NM_DEVICE_INTERFACE, "StateChanged"));
+
+        assert!(changes.reads_device(DEVICE, &device(DeviceType::Ethernet)));
+        assert!(!changes.reads_device(OTHER_DEVICE, &device(DeviceType::Ethernet)));
+        assert!(!changes.device_list);
+
+        let mut changes = Changes::default();
+        changes.add(&signal(NM_INTERFACE, "DeviceAdded"));
+        assert!(changes.device_list);
+        assert!(!changes.reads_device(DEVICE, &device(DeviceType::Ethernet)));
+
+        let mut changes = Changes::default();
+        changes.add(&properties_changed(
+            "/org/freedesktop/NetworkManager/IP4Config/5",
+            "org.freedesktop.NetworkManager.IP4Config",
+            vec![("Gateway", "192.168.1.1")],
+        ));
+        assert!(changes.reads_device(OTHER_DEVICE, &device(DeviceType::Ethernet)));
+    }
+
+    #[cfg(feature = "wifi")]
+    #[test]
+    fn test_access_point_signals_read_wifi_devices() {
+        let mut changes = Changes::default();
+        changes.add(&properties_changed(
+            "/org/freedesktop/NetworkManager/AccessPoint/7",
+            NM_ACCESS_POINT_INTERFACE,
+            vec![("Strength", 42u8)],
+        ));
+
+        assert!(changes.access_points);
+        assert!(changes.reads_device(DEVICE, &device(DeviceType::WiFi)));
+        assert!(!changes.reads_device(DEVICE, &device(DeviceType::Ethernet)));
+    }
+
+    #[test]
+    fn test_name_owner_change_reads_everything() {
+        let mut changes = Changes::default();
+        changes.add(&signal_at(DEVICE, NM_DEVICE_INTERFACE, "StateChanged"));
+        changes.add(&signal("org.freedesktop.DBus", "NameOwnerChanged"));
+
+        assert!(changes.all);
+    }
+}
@@ -0,0 +1,470 @@
+use std::collections::VecDeque;
+
+use connection::ConnectionSettings;
+use device::DeviceState;
+use ip_config::IpAddress;
+use manager::{Connectivity, NetworkManagerState};
+use network_manager_types::Ssid;
+use state_cache::StateSnapshot;
+use status::DeviceStatus;
+#[cfg(feature = "wifi")]
+use wifi::AccessPoint;
+
+/// A change between two consecutive snapshots of the `StateCache`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum StateEvent {
+    StateChanged(NetworkManagerState),
+    ConnectivityChanged(Connectivity),
+    PrimaryConnectionChanged(Option<String>),
+    DeviceAdded(DeviceStatus),
+    DeviceRemoved {
+        interface: String,
+    },
+    DeviceStateChanged {
+        interface: String,
+        state: DeviceState,
+    },
+    IpChanged {
+        interface: String,
+        addresses: Vec<IpAddress>,
+    },
+    /// The access point a Wi-Fi device is associated with, or its signal,
+    /// changed.
+    SignalChanged {
+        interface: String,
+        ssid: Option<Ssid>,
+        strength: Option<u32>,
+    },
+    ConnectionAdded(ConnectionSettings),
+    ConnectionUpdated(ConnectionSettings),
+    ConnectionRemoved {
+        uuid: String,
+    },
+    #[cfg(feature = "wifi")]
+    ApAdded {
+        interface: String,
+        access_point: AccessPoint,
+    },
+    #[cfg(feature = "wifi")]
+    ApRemoved {
+        interface: String,
+        bssid: String,
+    },
+    #[cfg(feature = "wifi")]
+    ApStrengthChanged {
+        interface: String,
+        bssid: String,
+        strength: u32,
+    },
+}
+
+/// A `StateEvent` numbered in the order it happened, starting at 1.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct StateChange {
+    pub sequence: u64,
+    pub event: StateEvent,
+}
+
+/// The most recent changes, dropping the oldest ones beyond its capacity.
+pub struct ChangeLog {
+    changes: VecDeque<StateChange>,
+    capacity: usize,
+    last_sequence: u64,
+}
+
+impl ChangeLog {
+    pub fn new(capacity: usize) -> Self {
+        ChangeLog {
+            changes: VecDeque::with_capacity(capacity),
+            capacity,
+            last_sequence: 0,
+        }
+    }
+
+    pub fn last_sequence(&self) -> u64 {
+        self.last_sequence
+    }
+
+    pub fn push(&mut self, event: StateEvent) {
+        if self.changes.len() == self.capacity {
+            self.changes.pop_front();
+        }
+
+        self.last_sequence += 1;
+
+        self.changes.push_back(StateChange {
+            sequence: self.last_sequence,
+            event,
+        });
+    }
+
+    /// The changes after `sequence`, or `None` if some of them were already
+    /// dropped or `sequence` is unknown.
+    pub fn since(&self, sequence: u64) -> Option<Vec<StateChange>> {
+        if sequence > self.last_sequence {
+            return None;
+        }
+
+        let first = self.last_sequence - self.changes.len() as u64;
+
+        if sequence < first {
+            return None;
+        }
+
+        Some(
+            self.changes
+                .iter()
+                .skip((sequence - first) as usize)
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+/// The events leading from `previous` to `current`. Changes without an event,
+/// such as access points being seen by another scan, are left out.
+pub fn diff_snapshots(previous: &StateSnapshot, current: &StateSnapshot) -> Vec<StateEvent> {
+    let mut events = Vec::new();
+
+    let (previous_status, status) = (&previous.status, &current.status);
+
+    if status.state != previous_status.state {
+        events.push(StateEvent::StateChanged(status.state.clone()));
+    }
+
+    if status.connectivity != previous_status.connectivity {
+        events.push(StateEvent::ConnectivityChanged(status.connectivity.clone()));
+    }
+
+    if status.primary_connection != previous_status.primary_connection {
+        events.push(StateEvent::PrimaryConnectionChanged(
+            status.primary_connection.clone(),
+        ));
+    }
+
+    for device in &previous_status.devices {
+        if !status
+            .devices
+            .iter()
+            .any(|current| current.interface == device.interface)
+        {
+            events.push(StateEvent::DeviceRemoved {
+                interface: device.interface.clone(),
+            });
+        }
+    }
+
+    for device in &status.devices {
+        match previous_status
+            .devices
+            .iter()
+            .find(|previous| previous.interface == device.interface)
+        {
+            Some(previous) => diff_devices(previous, device, &mut events),
+            None => events.push(StateEvent::DeviceAdded(device.clone())),
+        }
+    }
+
+    diff_connections(&previous.connections, &current.connections, &mut events);
+
+    #[cfg(feature = "wifi")]
+    for (interface, access_points) in &current.access_points {
+        let previous = previous
+            .access_points
+            .get(interface)
+            .map_or(&[][..], |previous| &previous[..]);
+
+        diff_access_points(interface, previous, access_points, &mut events);
+    }
+
+    #[cfg(feature = "wifi")]
+    for (interface, previous) in &previous.access_points {
+        if !current.access_points.contains_key(interface) {
+            diff_access_points(interface, previous, &[], &mut events);
+        }
+    }
+
+    events
+}
+
+fn diff_devices(previous: &DeviceStatus, device: &DeviceStatus, events: &mut Vec<StateEvent>) {
+    let interface = &device.interface;
+
+    if device.state != previous.state {
+        events.push(StateEvent::DeviceStateChanged {
+            interface: interface.clone(),
+            state: device.state.clone(),
+        });
+    }
+
+    if device.addresses != previous.addresses {
+        events.push(StateEvent::IpChanged {
+            interface: interface.clone(),
+            addresses: device.addresses.clone(),
+        });
+    }
+
+    if device.ssid != previous.ssid || device.strength != previous.strength {
+        events.push(StateEvent::SignalChanged {
+            interface: interface.clone(),
+            ssid: device.ssid.clone(),
+            strength: device.strength,
+        });
+    }
+}
+
+fn diff_connections(
+    previous: &[ConnectionSettings],
+    connections: &[ConnectionSettings],
+    events: &mut Vec<StateEvent>,
+) {
+    for connection in previous {
+        if !connections.iter().any(|c| c.uuid == connection.uuid) {
+            events.push(StateEvent::ConnectionRemoved {
+                uuid: connection.uuid.clone(),
+            });
+        }
+    }
+
+    for connection in connections {
+        match previous.iter().find(|c| c.uuid == connection.uuid) {
+            Some(previous) if previous != connection => {
+                events.push(StateEvent::ConnectionUpdated(connection.clone()))
+            }
+            Some(_) => {}
+            None => events.push(StateEvent::ConnectionAdded(connection.clone())),
+        }
+    }
+}
+
+#[cfg(feature = "wifi")]
+fn diff_access_points(
+    interface: &str,
+    previous: &[AccessPoint],
+    access_points: &[AccessPoint],
+    events: &mut Vec<StateEvent>,
+) {
+    for access_point in previous {
+        if !access_points
+            .iter()
+            .any(|ap| ap.bssid == access_point.bssid)
+        {
+            events.push(StateEvent::ApRemoved {
+                interface: interface.to_string(),
+                bssid: access_point.bssid.clone(),
+            });
+        }
+    }
+
+    for access_point in access_points {
+        match previous.iter().find(|ap| ap.bssid == access_point.bssid) {
+            Some(previous) if previous.strength != access_point.strength => {
+                events.push(StateEvent::ApStrengthChanged {
+                    interface: interface.to_string(),
+                    bssid: access_point.bssid.clone(),
+                    strength: access_point.strength,
+                })
+            }
+            Some(_) => {}
+            None => events.push(StateEvent::ApAdded {
+                interface: interface.to_string(),
+                access_point: access_point.clone(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "wifi")]
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    use network_manager_types::DeviceType;
+    #[cfg(feature = "wifi")]
+    use network_manager_types::{ApSecurity, NM80211ApFlags, NM80211ApSecurityFlags};
+    use status::Status;
+
+    fn device(interface: &str, state: DeviceState) -> DeviceStatus {
+        DeviceStatus {
+            interface: interface.into(),
+            device_type: DeviceType::WiFi,
+            state,
+            addresses: Vec::new(),
+            ssid: None,
+            strength: None,
+        }
+    }
+
+    fn connection(uuid: &str, id: &str) -> ConnectionSettings {
+        ConnectionSettings {
+            uuid: uuid.into(),
+            id: id.into(),
+            ..Default::default()
+        }
+    }
+
+    fn snapshot(devices: Vec<DeviceStatus>, connections: Vec<ConnectionSettings>) -> StateSnapshot {
+        StateSnapshot {
+            generation: 0,
+            sequence: 0,
+            status: Status {
+                state: NetworkManagerState::ConnectedGlobal,
+                connectivity: Connectivity::Full,
+                primary_connection: None,
+                devices,
+            },
+            connections,
+            #[cfg(feature = "wifi")]
+            access_points: BTreeMap::new(),
+        }
+    }
+
+    #[cfg(feature = "wifi")]
+    fn access_point(bssid: &str, strength: u32) -> AccessPoint {
+        AccessPoint {
+            path: String::new(),
+            ssid: Ssid::new(),
+            bssid: bssid.into(),
+            strength,
+            security: ApSecurity::new(
+                NM80211ApFlags::AP_FLAGS_NONE,
+                NM80211ApSecurityFlags::AP_SEC_NONE,
+                NM80211ApSecurityFlags::AP_SEC_NONE,
+            ),
+            flags: NM80211ApFlags::AP_FLAGS_NONE,
+            frequency: 2412,
+            last_seen: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_devices_and_connections() {
+        let previous = snapshot(
+            vec![
+                device("wlan0", DeviceState::Disconnected),
+                device("eth0", DeviceState::Activated),
+            ],
+            vec![connection("1", "Home"), connection("2", "Office")],
+        );
+
+        let mut current = snapshot(
+            vec![
+                device("wlan0", DeviceState::Activated),
+                device("usb0", DeviceState::Disconnected),
+            ],
+            vec![connection("1", "Home 5G"), connection("3", "Cafe")],
+        );
+        current.status.primary_connection = Some("Home 5G".into());
+
+        assert_eq!(
+            diff_snapshots(&previous, &current),
+            vec![
+                StateEvent::PrimaryConnectionChanged(Some("Home 5G".into())),
+                StateEvent::DeviceRemoved {
+                    interface: "eth0".into(),
+                },
+                StateEvent::DeviceStateChanged {
+                    interface: "wlan0".into(),
+                    state: DeviceState::Activated,
+                },
+                StateEvent::DeviceAdded(device("usb0", DeviceState::Disconnected)),
+                StateEvent::ConnectionRemoved { uuid: "2".into() },
+                StateEvent::ConnectionUpdated(connection("1", "Home 5G")),
+                StateEvent::ConnectionAdded(connection("3", "Cafe")),
+            ]
+        );
+
+        assert_eq!(diff_snapshots(&current, &current), vec![]);
+    }
+
+    #[cfg(feature = "wifi")]
+    #[test]
+    fn test_diff_access_points() {
+        let mut previous = snapshot(vec![device("wlan0", DeviceState::Activated)], vec![]);
+        previous.access_points.insert(
+            "wlan0".into(),
+            vec![
+                access_point("00:00:00:00:00:01", 50),
+                access_point("00:00:00:00:00:02", 60),
+            ],
+        );
+
+        let mut current = previous.clone();
+        current.access_points.insert(
+            "wlan0".into(),
+            vec![
+                access_point("00:00:00:00:00:02", 70),
+                access_point("00:00:00:00:00:03", 40),
+            ],
+        );
+
+        assert_eq!(
+            diff_snapshots(&previous, &current),
+            vec![
+                StateEvent::ApRemoved {
+                    interface: "wlan0".into(),
+                    bssid: "00:00:00:00:00:01".into(),
+                },
+                StateEvent::ApStrengthChanged {
+                    interface: "wlan0".into(),
+                    bssid: "00:00:00:00:00:02".into(),
+                    strength: 70,
+                },
+                StateEvent::ApAdded {
+                    interface: "wlan0".into(),
+                    access_point: access_point("00:00:00:00:00:03", 40),
+                },
+            ]
+        );
+    }
+
+    #[cfg(feature = "wifi")]
+    #[test]
+    fn test_diff_removed_device_access_points() {
+        let mut previous = snapshot(vec![device("wlan0", DeviceState::Activated)], vec![]);
+        previous
+            .access_points
+            .insert("wlan0".into(), vec![access_point("00:00:00:00:00:01", 50)]);
+
+        let current = snapshot(vec![], vec![]);
+
+        assert_eq!(
+            diff_snapshots(&previous, &current),
+            vec![
+                StateEvent::DeviceRemoved {
+                    interface: "wlan0".into(),
+                },
+                StateEvent::ApRemoved {
+                    interface: "wlan0".into(),
+                    bssid: "00:00:00:00:00:01".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_change_log_since() {
+        let mut log = ChangeLog::new(2);
+
+        for _ in 0..3 {
+            log.push(StateEvent::PrimaryConnectionChanged(None));
+        }
+
+        assert_eq!(log.last_sequence(), 3);
+        assert_eq!(log.since(3), Some(vec![]));
+        assert_eq!(
+            log.since(1)
+                .unwrap()
+                .iter()
+                .map(|change| change.sequence)
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(log.since(0), None);
+        assert_eq!(log.since(4), None);
+    }
+}
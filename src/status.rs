@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::rc::Rc;
+
+use dbus_nm::DBusNetworkManager;
+use errors::*;
+use network_manager_types::{DeviceType, Value};
+
+#[cfg(feature = "wifi")]
+use device::PathGetter;
+use device::{get_device_by_path, Device, DeviceState};
+use ip_config::IpAddress;
+use manager::{Connectivity, NetworkManagerState};
+use network_manager_types::Ssid;
+
+/// A cheap answer to "am I online, on which network and with what address",
+/// fetched in at most four D-Bus round trips. Intended for dashboards polling
+/// every second or so.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct FastStatus {
+    pub state: NetworkManagerState,
+    /// The result of the last connectivity check. No new check is requested.
+    pub connectivity: Connectivity,
+    /// ID of the primary connection, the one holding the default route.
+    pub connection: Option<String>,
+    /// Type of the primary connection, e.g. `802-11-wireless`.
+    pub connection_type: Option<String>,
+    /// SSID of the access point when the primary connection is Wi-Fi.
+    pub ssid: Option<Ssid>,
+    /// First IPv4 address of the primary connection, or its first IPv6
+    /// address if it has no IPv4 one.
+    pub address: Option<IpAddr>,
+}
+
+impl FastStatus {
+    pub fn is_online(&self) -> bool {
+        self.connectivity == Connectivity::Full
+    }
+}
+
+pub fn get_fast_status(dbus_manager: &Rc<DBusNetworkManager>) -> Result<FastStatus> {
+    let manager = dbus_manager.get_manager_properties()?;
+
+    let mut status = FastStatus {
+        state: NetworkManagerState::from(u32_value(&manager, "State")),
+        connectivity: Connectivity::from(u32_value(&manager, "Connectivity")),
+        connection: None,
+        connection_type: None,
+        ssid: None,
+        address: None,
+    };
+
+    let primary = match object_path(&manager, "PrimaryConnection") {
+        Some(primary) => primary,
+        None => return Ok(status),
+    };
+
+    let active = dbus_manager.get_active_connection_properties(primary)?;
+
+    status.connection = string_value(&active, "Id");
+    status.connection_type = string_value(&active, "Type");
+
+    #[cfg(feature = "wifi")]
+    if status.connection_type.as_deref() == Some("802-11-wireless") {
+        if let Some(access_point) = object_path(&active, "SpecificObject") {
+            status.ssid = dbus_manager.get_access_point_ssid(access_point);
+        }
+    }
+
+    for &(property, ipv6) in &[("Ip4Config", false), ("Ip6Config", true)] {
+        if let Some(config) = object_path(&active, property) {
+            let addresses = dbus_manager.get_ip_config_addresses(config, ipv6)?;
+
+            if let Some(address) = addresses.first() {
+                status.address = Some(address.address);
+                break;
+            }
+        }
+    }
+
+    Ok(status)
+}
+
+/// The global state together with the state of every device, as shown by a
+/// status bar or sent as a fleet heartbeat.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Status {
+    pub state: NetworkManagerState,
+    /// The result of the last connectivity check. No new check is requested.
+    pub connectivity: Connectivity,
+    /// ID of the primary connection, the one holding the default route.
+    pub primary_connection: Option<String>,
+    pub devices: Vec<DeviceStatus>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DeviceStatus {
+    pub interface: String,
+    pub device_type: DeviceType,
+    pub state: DeviceState,
+    /// IPv4 addresses followed by IPv6 addresses.
+    pub addresses: Vec<IpAddress>,
+    /// SSID of the access point a Wi-Fi device is associated with.
+    pub ssid: Option<Ssid>,
+    /// Signal strength of that access point in percent.
+    pub strength: Option<u32>,
+}
+
+pub fn get_status(dbus_manager: &Rc<DBusNetworkManager>) -> Result<Status> {
+    let mut status = get_manager_status(dbus_manager)?;
+
+    status.devices = read_devices(&dbus_manager.get_devices()?, |path| {
+        let device = get_device_by_path(dbus_manager, path)?;
+
+        get_device_status(dbus_manager, &device)
+    });
+
+    Ok(status)
+}
+
+/// Reads the device at each of `paths`, leaving out those that fail to be
+/// read, e.g. as they were removed while being enumerated.
+fn read_devices<T, F>(paths: &[String], mut read: F) -> Vec<T>
+where
+    F: FnMut(&str) -> Result<T>,
+{
+    paths
+        .iter()
+        .filter_map(|path| match read(path) {
+            Ok(device) => Some(device),
+            Err(e) => {
+                debug!("Device {} left out of the status: {}", path, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// The global part of `Status`, without reading any device.
+pub fn get_manager_status(dbus_manager: &Rc<DBusNetworkManager>) -> Result<Status> {
+    let manager = dbus_manager.get_manager_properties()?;
+
+    Ok(Status {
+        state: NetworkManagerState::from(u32_value(&manager, "State")),
+        connectivity: Connectivity::from(u32_value(&manager, "Connectivity")),
+        primary_connection: match object_path(&manager, "PrimaryConnection") {
+            Some(primary) => get_active_connection_id(dbus_manager, primary)?,
+            None => None,
+        },
+        devices: Vec::new(),
+    })
+}
+
+/// ID of the active connection at object path `active`, `None` for "/" as
+/// NetworkManager reports no primary connection.
+pub fn get_active_connection_id(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    active: &str,
+) -> Result<Option<String>> {
+    if active == "/" {
+        return Ok(None);
+    }
+
+    let properties = dbus_manager.get_active_connection_properties(active)?;
+
+    Ok(string_value(&properties, "Id"))
+}
+
+pub fn get_device_status(
+    dbus_manager: &DBusNetworkManager,
+    device: &Device,
+) -> Result<DeviceStatus> {
+    let mut addresses = Vec::new();
+
+    for config in &[device.get_ip4_config()?, device.get_ip6_config()?] {
+        if let Some(ref config) = *config {
+            addresses.extend(config.addresses.iter().cloned());
+        }
+    }
+
+    let (ssid, strength) = get_access_point_signal(dbus_manager, device);
+
+    Ok(DeviceStatus {
+        interface: device.interface().to_string(),
+        device_type: device.device_type().clone(),
+        state: device.get_state()?,
+        addresses,
+        ssid,
+        strength,
+    })
+}
+
+#[cfg(feature = "wifi")]
+fn get_access_point_signal(
+    dbus_manager: &DBusNetworkManager,
+    device: &Device,
+) -> (Option<Ssid>, Option<u32>) {
+    if *device.device_type() != DeviceType::WiFi {
+        return (None, None);
+    }
+
+    match dbus_manager.get_device_active_access_point(device.path()) {
+        Some(access_point) => (
+            dbus_manager.get_access_point_ssid(&access_point),
+            dbus_manager.get_access_point_strength(&access_point).ok(),
+        ),
+        None => (None, None),
+    }
+}
+
+#[cfg(not(feature = "wifi"))]
+fn get_access_point_signal(_: &DBusNetworkManager, _: &Device) -> (Option<Ssid>, Option<u32>) {
+    (None, None)
+}
+
+fn u32_value(properties: &HashMap<String, Value>, name: &str) -> u32 {
+    properties.get(name).and_then(Value::as_u32).unwrap_or(0)
+}
+
+fn string_value(properties: &HashMap<String, Value>, name: &str) -> Option<String> {
+    properties
+        .get(name)
+        .and_then(Value::as_str)
+        .map(|value| value.to_string())
+}
+
+fn object_path<'a>(properties: &'a HashMap<String, Value>, name: &str) -> Option<&'a str> {
+    match properties.get(name).and_then(Value::as_str) {
+        Some("/") | None => None,
+        Some(path) => Some(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_devices_skips_vanished() {
+        let paths = vec![
+            "/org/freedesktop/NetworkManager/Devices/1".to_string(),
+            "/org/freedesktop/NetworkManager/Devices/2".to_string(),
+            "/org/freedesktop/NetworkManager/Devices/3".to_string(),
+        ];
+
+        let devices = read_devices(&paths, |path| {
+            if path.ends_with('2') {
+                bail!(ErrorKind::NetworkManager("Object does not exist".into()))
+            }
+
+            Ok(path.to_string())
+        });
+
+        assert_eq!(devices, vec![paths[0].clone(), paths[2].clone()]);
+
+        assert!(read_devices(&paths, |_| -> Result<()> { bail!("Removed") }).is_empty());
+    }
+}
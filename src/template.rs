@@ -0,0 +1,295 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use network_manager_types::Value;
+
+use errors::*;
+
+use network_manager_types::Ssid;
+use settings::{new_uuid, ConnectionProfile};
+#[cfg(feature = "wifi")]
+use wifi::psk::verify_psk;
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+
+/// A connection profile with `{{name}}` placeholders in its values, e.g. the
+/// SSID, PSK or octets of static addresses, from which profiles of many
+/// devices or sites are created.
+///
+/// Placeholders can appear in strings, lists of strings, nested addresses
+/// and routes, and in byte values holding text such as the SSID. Every
+/// instance gets a new UUID unless the template sets `connection.uuid`.
+///
+/// ```
+/// # use std::collections::HashMap;
+/// # use network_manager::{ConnectionProfile, ProfileTemplate};
+/// let template = ProfileTemplate::new(
+///     ConnectionProfile::ethernet("site-{{site}}", "eth0")
+///         .with("ipv4", "method", "manual")
+///         .with("ipv4", "gateway", "10.0.{{site}}.1"),
+/// );
+///
+/// let mut values = HashMap::new();
+/// values.insert("site".to_string(), "12".to_string());
+///
+/// let profile = template.instantiate(&values).unwrap();
+/// assert_eq!(profile.id(), Some("site-12"));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProfileTemplate {
+    profile: ConnectionProfile,
+}
+
+impl ProfileTemplate {
+    pub fn new(profile: ConnectionProfile) -> Self {
+        ProfileTemplate { profile }
+    }
+
+    pub fn profile(&self) -> &ConnectionProfile {
+        &self.profile
+    }
+
+    /// The names of all placeholders, which `instantiate` needs values for.
+    pub fn variables(&self) -> Result<BTreeSet<String>> {
+        let mut variables = BTreeSet::new();
+
+        for setting in self.profile.settings().values() {
+            for value in setting.values() {
+                collect_value(value, &mut variables)?;
+            }
+        }
+
+        Ok(variables)
+    }
+
+    /// Creates a profile with the placeholders replaced by `values`, failing
+    /// if any of them has no value or the SSID or PSK of the result is
+    /// invalid.
+    pub fn instantiate(&self, values: &HashMap<String, String>) -> Result<ConnectionProfile> {
+        let missing = self
+            .variables()?
+            .into_iter()
+            .filter(|name| !values.contains_key(name))
+            .collect::<Vec<_>>();
+
+        if !missing.is_empty() {
+            bail!(ErrorKind::NetworkManager(format!(
+                "Missing template values: {}",
+                missing.join(", ")
+            )));
+        }
+
+        let mut profile = ConnectionProfile::default();
+
+        for (name, setting) in self.profile.settings() {
+            profile = profile.with_setting(name);
+
+            for (key, value) in setting {
+                profile.set(name, key, substitute_value(value, values)?);
+            }
+        }
+
+        if profile.uuid().is_none() {
+            profile.set("connection", "uuid", new_uuid()?);
+        }
+
+        verify_instance(&profile)?;
+
+        Ok(profile)
+    }
+}
+
+fn verify_instance(profile: &ConnectionProfile) -> Result<()> {
+    if let Some(ssid) = profile
+        .get("802-11-wireless", "ssid")
+        .and_then(Value::as_bytes)
+    {
+        Ssid::from_bytes(ssid)?;
+    }
+
+    #[cfg(feature = "wifi")]
+    if let Some(psk) = profile
+        .get("802-11-wireless-security", "psk")
+        .and_then(Value::as_str)
+    {
+        verify_psk(psk)?;
+    }
+
+    Ok(())
+}
+
+fn collect_value(value: &Value, variables: &mut BTreeSet<String>) -> Result<()> {
+    match *value {
+        Value::String(ref string) => collect(string, variables),
+        Value::Strings(ref strings) => strings
+            .iter()
+            .try_for_each(|string| collect(string, variables)),
+        Value::Bytes(ref bytes) => match ::std::str::from_utf8(bytes) {
+            Ok(string) => collect(string, variables),
+            Err(_) => Ok(()),
+        },
+        Value::Dict(ref dict) => dict
+            .values()
+            .try_for_each(|value| collect_value(value, variables)),
+        Value::Dicts(ref dicts) => dicts
+            .iter()
+            .flat_map(|dict| dict.values())
+            .try_for_each(|value| collect_value(value, variables)),
+        _ => Ok(()),
+    }
+}
+
+fn collect(string: &str, variables: &mut BTreeSet<String>) -> Result<()> {
+    substitute(string, |name| {
+        variables.insert(name.to_string());
+        Some(String::new())
+    })
+    .map(|_| ())
+}
+
+fn substitute_value(value: &Value, values: &HashMap<String, String>) -> Result<Value> {
+    let lookup = |name: &str| values.get(name).cloned();
+
+    let substitute_dict = |dict: &BTreeMap<String, Value>| {
+        dict.iter()
+            .map(|(key, value)| Ok((key.clone(), substitute_value(value, values)?)))
+            .collect::<Result<BTreeMap<_, _>>>()
+    };
+
+    Ok(match *value {
+        Value::String(ref string) => Value::String(substitute(string, lookup)?),
+        Value::Strings(ref strings) => Value::Strings(
+            strings
+                .iter()
+                .map(|string| substitute(string, lookup))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Bytes(ref bytes) => match ::std::str::from_utf8(bytes) {
+            Ok(string) => Value::Bytes(substitute(string, lookup)?.into_bytes()),
+            Err(_) => value.clone(),
+        },
+        Value::Dict(ref dict) => Value::Dict(substitute_dict(dict)?),
+        Value::Dicts(ref dicts) => {
+            Value::Dicts(dicts.iter().map(substitute_dict).collect::<Result<_>>()?)
+        }
+        _ => value.clone(),
+    })
+}
+
+/// Replaces the placeholders of `string` with what `lookup` returns for their
+/// names, failing on unterminated or empty placeholders and unknown names.
+fn substitute<F>(string: &str, mut lookup: F) -> Result<String>
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let mut result = String::with_capacity(string.len());
+    let mut rest = string;
+
+    while let Some(start) = rest.find(OPEN) {
+        result.push_str(&rest[..start]);
+
+        let after = &rest[start + OPEN.len()..];
+
+        let end = match after.find(CLOSE) {
+            Some(end) => end,
+            None => bail!(ErrorKind::NetworkManager(format!(
+                "Unterminated placeholder in {:?}",
+                string
+            ))),
+        };
+
+        let name = after[..end].trim();
+
+        if name.is_empty() {
+            bail!(ErrorKind::NetworkManager(format!(
+                "Empty placeholder in {:?}",
+                string
+            )));
+        }
+
+        match lookup(name) {
+            Some(value) => result.push_str(&value),
+            None => bail!(ErrorKind::NetworkManager(format!(
+                "Missing template value: {}",
+                name
+            ))),
+        }
+
+        rest = &after[end + CLOSE.len()..];
+    }
+
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template() -> ProfileTemplate {
+        let mut address = BTreeMap::new();
+        address.insert("address".to_string(), Value::from("10.1.{{site}}.{{host}}"));
+        address.insert("prefix".to_string(), Value::from(24u32));
+
+        ProfileTemplate::new(
+            ConnectionProfile::new("802-11-wireless", "{{ssid}}")
+                .with("802-11-wireless", "ssid", b"{{ssid}}".to_vec())
+                .with("802-11-wireless-security", "key-mgmt", "wpa-psk")
+                .with("802-11-wireless-security", "psk", "{{psk}}")
+                .with("ipv4", "method", "manual")
+                .with("ipv4", "address-data", Value::Dicts(vec![address])),
+        )
+    }
+
+    #[test]
+    fn test_instantiate_template() {
+        let template = template();
+
+        assert_eq!(
+            template
+                .variables()
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["host", "psk", "site", "ssid"]
+        );
+
+        let mut values = HashMap::new();
+        for &(name, value) in &[
+            ("ssid", "Site 7"),
+            ("psk", "correct horse"),
+            ("site", "7"),
+            ("host", "20"),
+        ] {
+            values.insert(name.to_string(), value.to_string());
+        }
+
+        let profile = template.instantiate(&values).unwrap();
+
+        assert_eq!(profile.id(), Some("Site 7"));
+        assert_eq!(
+            profile.get("802-11-wireless", "ssid"),
+            Some(&Value::Bytes(b"Site 7".to_vec()))
+        );
+        match profile.get("ipv4", "address-data") {
+            Some(Value::Dicts(dicts)) => {
+                assert_eq!(dicts[0]["address"], Value::from("10.1.7.20"))
+            }
+            other => panic!("unexpected address data {:?}", other),
+        }
+
+        values.remove("host");
+        assert!(template.instantiate(&values).is_err());
+    }
+
+    #[test]
+    fn test_substitute_placeholders() {
+        assert!(substitute("{{ unterminated", |_| None).is_err());
+        assert!(substitute("{{}}", |_| None).is_err());
+        assert_eq!(
+            substitute("a{{ x }}b", |_| Some("-".into())).unwrap(),
+            "a-b"
+        );
+    }
+}
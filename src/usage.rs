@@ -0,0 +1,589 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use dbus::{Connection as DBusConnection, ConnectionItem};
+
+use dbus_api::open_connection;
+use dbus_nm::DBusNetworkManager;
+use errors::*;
+
+use connection::get_device_connection;
+use device::{get_device_by_path, get_devices, Device, DeviceState, PathGetter, StatisticsGuard};
+
+const NM_BUS_NAME: &str = "org.freedesktop.NetworkManager";
+const NM_DEVICE_INTERFACE: &str = "org.freedesktop.NetworkManager.Device";
+
+/// Refresh rate enabled for devices whose traffic counters are off.
+const STATISTICS_REFRESH_RATE_MS: u32 = 1000;
+
+/// Bytes a connection profile transferred in one calendar month.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct UsageRecord {
+    /// The month in UTC, formatted `YYYY-MM`.
+    pub month: String,
+    pub uuid: String,
+    /// The profile ID when the record was last updated.
+    pub id: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+/// Where a `UsageAccountant` keeps its records across restarts.
+pub trait UsageStore {
+    fn load(&self) -> Result<Vec<UsageRecord>>;
+
+    fn save(&self, records: &[UsageRecord]) -> Result<()>;
+}
+
+/// Keeps records in memory only, for accounting within one process.
+#[derive(Default)]
+pub struct MemoryUsageStore {
+    records: Mutex<Vec<UsageRecord>>,
+}
+
+impl MemoryUsageStore {
+    pub fn new() -> Self {
+        MemoryUsageStore::default()
+    }
+}
+
+impl UsageStore for MemoryUsageStore {
+    fn load(&self) -> Result<Vec<UsageRecord>> {
+        Ok(self
+            .records
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone())
+    }
+
+    fn save(&self, records: &[UsageRecord]) -> Result<()> {
+        *self.records.lock().unwrap_or_else(|e| e.into_inner()) = records.to_vec();
+        Ok(())
+    }
+}
+
+/// Keeps records in a file of tab separated month, UUID, received bytes,
+/// transmitted bytes and profile ID. The file is replaced atomically on
+/// every save.
+pub struct FileUsageStore {
+    path: PathBuf,
+}
+
+impl FileUsageStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        FileUsageStore {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl UsageStore for FileUsageStore {
+    fn load(&self) -> Result<Vec<UsageRecord>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == ::std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => bail!(ErrorKind::NetworkManager(format!(
+                "Unable to read {}: {}",
+                self.path.display(),
+                e
+            ))),
+        };
+
+        parse_records(&contents)
+    }
+
+    fn save(&self, records: &[UsageRecord]) -> Result<()> {
+        let temporary = self.path.with_extension("tmp");
+
+        let write = || -> ::std::io::Result<()> {
+            let mut file = fs::File::create(&temporary)?;
+            file.write_all(format_records(records).as_bytes())?;
+            file.sync_all()?;
+            fs::rename(&temporary, &self.path)
+        };
+
+        write().chain_err(|| {
+            ErrorKind::NetworkManager(format!("Unable to write {}", self.path.display()))
+        })
+    }
+}
+
+/// Accounts the bytes transferred by each connection profile per month, from
+/// the traffic counters of the devices it is active on, see `watch_usage`.
+///
+/// Traffic of a device is attributed to the connection active on the device
+/// itself, so that a VPN on top of it is not counted a second time.
+pub struct UsageAccountant {
+    store: Box<dyn UsageStore>,
+    records: Vec<UsageRecord>,
+    /// Last counters of the devices being accounted, by object path.
+    samples: HashMap<String, Sample>,
+    /// Devices whose counters are kept on, by object path.
+    devices: HashMap<String, (Device, Option<StatisticsGuard>)>,
+}
+
+/// The connection a device is accounted to, with its last counters.
+struct Sample {
+    uuid: String,
+    id: String,
+    counters: (u64, u64),
+}
+
+impl UsageAccountant {
+    /// Continues accounting from the records in `store`.
+    pub fn new(store: Box<dyn UsageStore>) -> Result<Self> {
+        let records = store.load()?;
+
+        Ok(UsageAccountant {
+            store,
+            records,
+            samples: HashMap::new(),
+            devices: HashMap::new(),
+        })
+    }
+
+    /// All records, oldest month first.
+    pub fn records(&self) -> &[UsageRecord] {
+        &self.records
+    }
+
+    /// The usage of the connection with `uuid` in `month`, formatted
+    /// `YYYY-MM`.
+    pub fn monthly_usage(&self, uuid: &str, month: &str) -> Option<&UsageRecord> {
+        self.records
+            .iter()
+            .find(|record| record.uuid == uuid && record.month == month)
+    }
+
+    /// The usage of the connection with `uuid` in the current month.
+    pub fn current_usage(&self, uuid: &str) -> Option<&UsageRecord> {
+        self.monthly_usage(uuid, &usage_month(SystemTime::now()))
+    }
+
+    /// Removes the records of months before `month`, e.g. to keep a year.
+    pub fn prune_before(&mut self, month: &str) -> Result<()> {
+        let count = self.records.len();
+
+        self.records.retain(|record| &*record.month >= month);
+
+        if self.records.len() != count {
+            self.store.save(&self.records)?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts accounting the device at `path` to the connection active on
+    /// it, turning its counters on if they are off. Traffic before this
+    /// point is not accounted.
+    fn start(&mut self, dbus_manager: &Rc<DBusNetworkManager>, path: &str) -> Result<()> {
+        if self.samples.contains_key(path) {
+            return Ok(());
+        }
+
+        let connection = match get_device_connection(dbus_manager, path)? {
+            Some(connection) => connection,
+            None => return Ok(()),
+        };
+
+        let device = get_device_by_path(dbus_manager, path)?;
+
+        // Read-only managers cannot turn counters on, those already on work
+        let statistics = match device.enable_statistics(STATISTICS_REFRESH_RATE_MS) {
+            Ok(statistics) => Some(statistics),
+            Err(e) => {
+                debug!(
+                    "Unable to enable traffic counters of {}: {}",
+                    device.interface(),
+                    e
+                );
+                None
+            }
+        };
+
+        let counters = read_counters(&device)?;
+        let settings = connection.settings();
+
+        debug!("Accounting {} to {:?}", device.interface(), settings.id);
+
+        self.track(path, &settings.uuid, &settings.id, counters);
+        self.devices.insert(path.to_string(), (device, statistics));
+
+        Ok(())
+    }
+
+    /// Accounts the last interval of the device at `path`, e.g. as its
+    /// connection is deactivated, and stops accounting it.
+    fn finish(&mut self, path: &str) -> Result<bool> {
+        let mut changed = false;
+
+        if let Some((device, _statistics)) = self.devices.remove(path) {
+            match read_counters(&device) {
+                Ok(counters) => {
+                    changed = self.account(path, counters, &usage_month(SystemTime::now()))
+                }
+                Err(e) => debug!(
+                    "Unable to read the last counters of {}: {}",
+                    device.interface(),
+                    e
+                ),
+            }
+        }
+
+        self.samples.remove(path);
+
+        Ok(changed)
+    }
+
+    /// Samples the counters of every device being accounted.
+    fn sample(&mut self) -> bool {
+        let month = usage_month(SystemTime::now());
+        let mut read = Vec::new();
+
+        for (path, (device, _)) in &self.devices {
+            match read_counters(device) {
+                Ok(counters) => read.push((path.clone(), counters)),
+                Err(e) => debug!(
+                    "Unable to read the counters of {}: {}",
+                    device.interface(),
+                    e
+                ),
+            }
+        }
+
+        let mut changed = false;
+
+        for (path, counters) in read {
+            changed |= self.account(&path, counters, &month);
+        }
+
+        changed
+    }
+
+    fn track(&mut self, path: &str, uuid: &str, id: &str, counters: (u64, u64)) {
+        self.samples.insert(
+            path.to_string(),
+            Sample {
+                uuid: uuid.to_string(),
+                id: id.to_string(),
+                counters,
+            },
+        );
+    }
+
+    /// Adds the traffic of the device at `path` since its last sample to
+    /// `month`, returning whether the records changed.
+    fn account(&mut self, path: &str, counters: (u64, u64), month: &str) -> bool {
+        let (uuid, id, (rx_bytes, tx_bytes)) = match self.samples.get_mut(path) {
+            Some(sample) => {
+                let delta = counter_delta(sample.counters, counters);
+                sample.counters = counters;
+                (sample.uuid.clone(), sample.id.clone(), delta)
+            }
+            None => return false,
+        };
+
+        if rx_bytes == 0 && tx_bytes == 0 {
+            return false;
+        }
+
+        self.add(month, &uuid, &id, rx_bytes, tx_bytes);
+
+        true
+    }
+
+    fn save(&self) -> Result<()> {
+        self.store.save(&self.records)
+    }
+
+    fn add(&mut self, month: &str, uuid: &str, id: &str, rx_bytes: u64, tx_bytes: u64) {
+        match self
+            .records
+            .iter_mut()
+            .find(|record| record.uuid == uuid && record.month == month)
+        {
+            Some(record) => {
+                record.id = id.to_string();
+                record.rx_bytes += rx_bytes;
+                record.tx_bytes += tx_bytes;
+            }
+            None => self.records.push(UsageRecord {
+                month: month.to_string(),
+                uuid: uuid.to_string(),
+                id: id.to_string(),
+                rx_bytes,
+                tx_bytes,
+            }),
+        }
+    }
+}
+
+fn read_counters(device: &Device) -> Result<(u64, u64)> {
+    let statistics = device.get_statistics()?;
+    Ok((statistics.rx_bytes, statistics.tx_bytes))
+}
+
+/// Blocks accounting the traffic of the connections NetworkManager activates
+/// with `accountant`, invoking `callback` every `interval` until it returns
+/// `false`.
+///
+/// Devices are accounted from the moment they are activated until they
+/// start deactivating, when the last interval is added. In between their
+/// counters are sampled every `interval`, after which changed records are
+/// saved. Counters turned on for accounting are turned off again once a
+/// device is no longer accounted.
+pub fn watch_usage<F>(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    accountant: &mut UsageAccountant,
+    interval: Duration,
+    mut callback: F,
+) -> Result<()>
+where
+    F: FnMut(&UsageAccountant) -> bool,
+{
+    let connection = open_connection(dbus_manager.bus())?;
+
+    connection.add_match(&format!(
+        "type='signal',sender='{}',interface='{}',member='StateChanged'",
+        NM_BUS_NAME, NM_DEVICE_INTERFACE
+    ))?;
+
+    for device in get_devices(dbus_manager)? {
+        if device.get_state()? == DeviceState::Activated {
+            accountant.start(dbus_manager, device.path())?;
+        }
+    }
+
+    let result = account_signals(
+        dbus_manager,
+        &connection,
+        accountant,
+        interval,
+        &mut callback,
+    );
+
+    let paths = accountant.devices.keys().cloned().collect::<Vec<_>>();
+    let mut changed = false;
+
+    for path in paths {
+        changed |= accountant.finish(&path)?;
+    }
+
+    if changed {
+        accountant.save()?;
+    }
+
+    result
+}
+
+fn account_signals<F>(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    connection: &DBusConnection,
+    accountant: &mut UsageAccountant,
+    interval: Duration,
+    callback: &mut F,
+) -> Result<()>
+where
+    F: FnMut(&UsageAccountant) -> bool,
+{
+    let mut next_sample = Instant::now() + interval;
+
+    loop {
+        let timeout = next_sample.saturating_duration_since(Instant::now());
+
+        if let Some(ConnectionItem::Signal(message)) =
+            connection.iter(timeout.as_millis() as i32).next()
+        {
+            let path = message.path().map(|path| path.to_string());
+
+            if let (Some(path), (Some(new), Some(old))) = (path, message.get2::<u32, u32>()) {
+                let new = DeviceState::from(i64::from(new));
+                let old = DeviceState::from(i64::from(old));
+
+                if new == DeviceState::Activated {
+                    if let Err(e) = accountant.start(dbus_manager, &path) {
+                        debug!("Unable to account {}: {}", path, e);
+                    }
+                } else if old == DeviceState::Activated && accountant.finish(&path)? {
+                    accountant.save()?;
+                }
+            }
+        }
+
+        if Instant::now() < next_sample {
+            continue;
+        }
+
+        next_sample = Instant::now() + interval;
+
+        if accountant.sample() {
+            accountant.save()?;
+        }
+
+        if !callback(accountant) {
+            return Ok(());
+        }
+    }
+}
+
+/// Traffic between two samples. Counters going backwards were reset, e.g. by
+/// the interface being recreated, and count from zero.
+fn counter_delta(previous: (u64, u64), current: (u64, u64)) -> (u64, u64) {
+    let delta = |previous: u64, current: u64| {
+        if current >= previous {
+            current - previous
+        } else {
+            current
+        }
+    };
+
+    (delta(previous.0, current.0), delta(previous.1, current.1))
+}
+
+/// The UTC month of `time`, formatted `YYYY-MM`.
+pub fn usage_month(time: SystemTime) -> String {
+    let days = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs() / 86400)
+        .unwrap_or(0) as i64;
+
+    // Howard Hinnant's civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}", year, month)
+}
+
+fn format_records(records: &[UsageRecord]) -> String {
+    records
+        .iter()
+        .map(|record| {
+            format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                record.month,
+                record.uuid,
+                record.rx_bytes,
+                record.tx_bytes,
+                record.id.replace(['\t', '\n'], " ")
+            )
+        })
+        .collect()
+}
+
+fn parse_records(contents: &str) -> Result<Vec<UsageRecord>> {
+    let mut records = Vec::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.splitn(5, '\t').collect();
+
+        let record = match fields[..] {
+            [month, uuid, rx_bytes, tx_bytes, id] => match (rx_bytes.parse(), tx_bytes.parse()) {
+                (Ok(rx_bytes), Ok(tx_bytes)) => Some(UsageRecord {
+                    month: month.to_string(),
+                    uuid: uuid.to_string(),
+                    id: id.to_string(),
+                    rx_bytes,
+                    tx_bytes,
+                }),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        match record {
+            Some(record) => records.push(record),
+            None => bail!(ErrorKind::NetworkManager(format!(
+                "Invalid usage record on line {}",
+                number + 1
+            ))),
+        }
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_month() {
+        let at = |secs| usage_month(UNIX_EPOCH + Duration::from_secs(secs));
+
+        assert_eq!(at(0), "1970-01");
+        assert_eq!(at(951_782_400), "2000-02");
+        assert_eq!(at(1_709_251_199), "2024-02");
+        assert_eq!(at(1_709_251_200), "2024-03");
+    }
+
+    #[test]
+    fn test_usage_records_round_trip() {
+        let records = vec![UsageRecord {
+            month: "2024-03".into(),
+            uuid: "0e8a4d3c-2b0f-4b8e-9f2c-1d6b7c8e9a10".into(),
+            id: "LTE\tuplink".into(),
+            rx_bytes: 1 << 40,
+            tx_bytes: 42,
+        }];
+
+        let parsed = parse_records(&format_records(&records)).unwrap();
+        assert_eq!(parsed[0].id, "LTE uplink");
+        assert_eq!(parsed[0].rx_bytes, 1 << 40);
+
+        assert_eq!(counter_delta((100, 100), (150, 20)), (50, 20));
+    }
+
+    fn accountant() -> UsageAccountant {
+        UsageAccountant::new(Box::new(MemoryUsageStore::new())).unwrap()
+    }
+
+    #[test]
+    fn test_account_device_samples() {
+        let mut accountant = accountant();
+        accountant.track("/devices/1", "lte-uuid", "LTE", (1000, 500));
+
+        assert!(accountant.account("/devices/1", (1500, 700), "2024-03"));
+        assert!(!accountant.account("/devices/1", (1500, 700), "2024-03"));
+        assert!(!accountant.account("/devices/2", (9000, 9000), "2024-03"));
+
+        // The interval crossing into a new month counts towards the new one
+        assert!(accountant.account("/devices/1", (1600, 800), "2024-04"));
+
+        let march = accountant.monthly_usage("lte-uuid", "2024-03").unwrap();
+        assert_eq!((march.rx_bytes, march.tx_bytes), (500, 200));
+
+        let april = accountant.monthly_usage("lte-uuid", "2024-04").unwrap();
+        assert_eq!((april.rx_bytes, april.tx_bytes), (100, 100));
+    }
+
+    #[test]
+    fn test_finished_device_is_no_longer_accounted() {
+        let mut accountant = accountant();
+        accountant.track("/devices/1", "lte-uuid", "LTE", (1000, 1000));
+
+        assert!(!accountant.finish("/devices/1").unwrap());
+        assert!(!accountant.account("/devices/1", (2000, 2000), "2024-03"));
+        assert!(accountant.records().is_empty());
+    }
+}
@@ -1,12 +1,50 @@
+use std::cmp::Reverse;
+use std::fmt;
+use std::fs;
+#[cfg(feature = "hotspot")]
 use std::net::Ipv4Addr;
 use std::rc::Rc;
+#[cfg(feature = "hotspot")]
+use std::thread;
+use std::time::Duration;
 
 use dbus_nm::DBusNetworkManager;
 use errors::*;
+use network_manager_types::{ApSecurity, NM80211ApFlags, Security};
 
-use connection::{connect_to_access_point, create_hotspot, Connection, ConnectionState};
+use backend::{get_wifi_backend, WifiBackend};
+use batch::read_all;
+use connection::{activate_on_access_point, connect_to_access_point, Connection, ConnectionState};
+#[cfg(feature = "hotspot")]
+use connection::{create_hotspot, get_device_connection, verify_ap_mode};
 use device::{Device, PathGetter};
-use ssid::{AsSsidSlice, Ssid, SsidSlice};
+#[cfg(feature = "hotspot")]
+use hotspot::{add_dnsmasq_leases, Hotspot, HotspotClient, HotspotConfig};
+#[cfg(feature = "hotspot")]
+use network_manager_types::AsSsidSlice;
+use network_manager_types::SecretFlags;
+use network_manager_types::{Ssid, SsidSlice};
+use scan::BackgroundScanInhibitor;
+use secret::SecretString;
+use settings::Ieee8021xConfig;
+
+pub mod channels;
+pub mod debug;
+pub mod power;
+pub mod psk;
+pub mod wps;
+
+use self::channels::verify_country_code;
+pub use self::channels::Band;
+use self::debug::SupplicantDebugSession;
+pub use self::debug::SupplicantLogLevel;
+use self::power::get_tx_power;
+pub use self::power::WifiPowerSave;
+use self::psk::{verify_psk, verify_wep_key, verify_wep_passphrase};
+use self::wps::{enroll_with_pin, verify_wps_pin};
+
+const CFG80211_REGDOM_PATH: &str = "/sys/module/cfg80211/parameters/ieee80211_regdom";
+const UPTIME_PATH: &str = "/proc/uptime";
 
 pub struct WiFiDevice<'a> {
     dbus_manager: Rc<DBusNetworkManager>,
@@ -14,19 +52,24 @@ pub struct WiFiDevice<'a> {
 }
 
 impl<'a> WiFiDevice<'a> {
+    pub fn device(&self) -> &Device {
+        self.device
+    }
+
     // Get the list of access points visible to this device.
     pub fn get_access_points(&self) -> Result<Vec<AccessPoint>> {
-        let mut access_points = Vec::new();
+        enter_span!("get_access_points", device = %self.device.interface());
 
         let paths = self
             .dbus_manager
             .get_device_access_points(self.device.path())?;
 
-        for path in paths {
-            if let Some(access_point) = get_access_point(&self.dbus_manager, &path)? {
-                access_points.push(access_point);
-            }
-        }
+        let mut access_points = read_all(&self.dbus_manager, paths, |dbus_manager, path| {
+            get_access_point(dbus_manager, path)
+        })?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
 
         access_points.sort_by_key(|ap| ap.strength);
         access_points.reverse();
@@ -34,7 +77,59 @@ impl<'a> WiFiDevice<'a> {
         Ok(access_points)
     }
 
+    /// Get the visible access points grouped into networks, strongest first.
+    pub fn get_networks(&self) -> Result<Vec<Network>> {
+        Ok(group_networks(self.get_access_points()?))
+    }
+
+    /// Get the access points NetworkManager found in scan results within
+    /// `max_age`. Older entries may have gone out of range, connecting to
+    /// them is likely to fail.
+    pub fn get_access_points_fresh(&self, max_age: Duration) -> Result<Vec<AccessPoint>> {
+        let now = boot_time()?;
+
+        Ok(retain_fresh(self.get_access_points()?, now, max_age))
+    }
+
+    /// Get the access point the device is currently associated with.
+    pub fn get_active_access_point(&self) -> Result<Option<AccessPoint>> {
+        match self
+            .dbus_manager
+            .get_device_active_access_point(self.device.path())
+        {
+            Some(path) => get_access_point(&self.dbus_manager, &path),
+            None => Ok(None),
+        }
+    }
+
+    /// Re-activate the device's current connection pinned to a specific access
+    /// point, e.g. to move to a stronger BSSID of the same network.
+    pub fn reassociate(&self, access_point: &AccessPoint) -> Result<(Connection, ConnectionState)> {
+        let active_path = match self
+            .dbus_manager
+            .get_device_active_connection(self.device.path())
+        {
+            Some(active_path) => active_path,
+            None => bail!(ErrorKind::NetworkManager(format!(
+                "No active connection on {}",
+                self.device.interface()
+            ))),
+        };
+
+        let path = match self.dbus_manager.get_active_connection_path(&active_path) {
+            Some(path) => path,
+            None => bail!(ErrorKind::NetworkManager(format!(
+                "Unable to get connection of {}",
+                active_path
+            ))),
+        };
+
+        activate_on_access_point(&self.dbus_manager, &path, self.device.path(), access_point)
+    }
+
     pub fn request_scan(&self) -> Result<()> {
+        enter_span!("request_scan", device = %self.device.interface());
+
         self.dbus_manager
             .request_access_point_scan(self.device.path())?;
         Ok(())
@@ -45,6 +140,20 @@ impl<'a> WiFiDevice<'a> {
         access_point: &AccessPoint,
         credentials: &AccessPointCredentials,
     ) -> Result<(Connection, ConnectionState)> {
+        credentials.verify()?;
+
+        if let AccessPointCredentials::Wps { ref method } = *credentials {
+            if !get_wifi_backend(self.dbus_manager.bus())?.supports_wps() {
+                bail!(ErrorKind::NetworkManager(
+                    "WPS is not supported with the iwd Wi-Fi backend".into()
+                ));
+            }
+
+            if let WpsMethod::Pin(ref pin) = *method {
+                return self.connect_with_wps_pin(access_point, method, pin);
+            }
+        }
+
         connect_to_access_point(
             &self.dbus_manager,
             self.device.path(),
@@ -53,6 +162,41 @@ impl<'a> WiFiDevice<'a> {
         )
     }
 
+    /// NetworkManager cannot pass a WPS PIN on, so the network key is
+    /// obtained from wpa_supplicant first and connected with as a passphrase.
+    fn connect_with_wps_pin(
+        &self,
+        access_point: &AccessPoint,
+        method: &WpsMethod,
+        pin: &str,
+    ) -> Result<(Connection, ConnectionState)> {
+        if !access_point.supports_wps(method) {
+            bail!(ErrorKind::NetworkManager(format!(
+                "Access point {:?} does not support WPS {:?}",
+                access_point.ssid(),
+                method
+            )));
+        }
+
+        let passphrase = enroll_with_pin(
+            &self.dbus_manager,
+            self.device.interface(),
+            &access_point.bssid,
+            pin,
+        )?;
+
+        connect_to_access_point(
+            &self.dbus_manager,
+            self.device.path(),
+            access_point,
+            &AccessPointCredentials::Wpa {
+                passphrase,
+                psk_flags: SecretFlags::NONE,
+            },
+        )
+    }
+
+    #[cfg(feature = "hotspot")]
     pub fn create_hotspot<T>(
         &self,
         ssid: &T,
@@ -62,103 +206,517 @@ impl<'a> WiFiDevice<'a> {
     where
         T: AsSsidSlice + ?Sized,
     {
+        let mut config = HotspotConfig::new(ssid)?;
+
+        if let Some(password) = password {
+            config = config.password(password);
+        }
+
+        if let Some(address) = address {
+            config = config.address(address);
+        }
+
+        self.create_hotspot_with(&config)
+    }
+
+    /// Create and activate a hotspot. The dnsmasq options of static leases
+    /// and a captive portal are only removed when a hotspot started with
+    /// `start_hotspot` stops, prefer it for configs having them.
+    #[cfg(feature = "hotspot")]
+    pub fn create_hotspot_with(
+        &self,
+        config: &HotspotConfig,
+    ) -> Result<(Connection, ConnectionState)> {
         create_hotspot(
             &self.dbus_manager,
             self.device.path(),
             self.device.interface(),
-            ssid,
-            password,
-            address,
+            config,
         )
     }
+
+    /// Whether the adapter can run a hotspot. See `verify_ap_mode`.
+    #[cfg(feature = "hotspot")]
+    pub fn supports_ap_mode(&self) -> Result<bool> {
+        match verify_ap_mode(
+            &self.dbus_manager,
+            self.device.path(),
+            self.device.interface(),
+        ) {
+            Ok(()) => Ok(true),
+            Err(Error(ErrorKind::ApModeUnsupported(_), _)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Start a hotspot that restores the connection currently active on the
+    /// device once it is stopped or dropped.
+    #[cfg(feature = "hotspot")]
+    pub fn start_hotspot(&self, config: &HotspotConfig) -> Result<Hotspot> {
+        let previous = get_device_connection(&self.dbus_manager, self.device.path())?;
+
+        if config.get_idle_timeout().is_some() && !self.get_backend()?.supports_hotspot_clients() {
+            bail!(ErrorKind::NetworkManager(
+                "Hotspot idle timeouts are not supported with the iwd Wi-Fi backend".into()
+            ));
+        }
+
+        let (connection, _) = self.create_hotspot_with(config)?;
+
+        Hotspot::new(
+            &self.dbus_manager,
+            connection,
+            self.device.clone(),
+            previous,
+            config.get_idle_timeout(),
+        )
+    }
+
+    /// Scan and pick the least congested channel for a hotspot on the band of
+    /// `config`, 2.4 GHz if it has none, before bringing it up. Waits
+    /// `scan_wait` for scan results.
+    ///
+    /// Visible networks already using the SSID of the hotspot are reported
+    /// and logged, clients would otherwise mix up the two.
+    #[cfg(feature = "hotspot")]
+    pub fn hotspot_preflight(
+        &self,
+        config: &HotspotConfig,
+        scan_wait: Duration,
+    ) -> Result<HotspotPreflight> {
+        let band = config.get_band().unwrap_or(Band::TwoGhz);
+
+        self.request_scan()?;
+
+        thread::sleep(scan_wait);
+
+        let access_points = self.get_access_points()?;
+
+        let in_band = access_points
+            .iter()
+            .filter(|access_point| access_point.band() == Some(band))
+            .filter_map(|access_point| Some((access_point.channel()?, access_point.strength)))
+            .collect::<Vec<_>>();
+
+        let congestion = band
+            .hotspot_channels()
+            .into_iter()
+            .map(|channel| (channel, channels::congestion(band, channel, &in_band)))
+            .collect::<Vec<_>>();
+
+        let channel = match congestion.iter().min_by_key(|&&(_, congestion)| congestion) {
+            Some(&(channel, _)) => channel,
+            None => bail!(ErrorKind::NetworkManager(format!(
+                "No hotspot channels in the {} band",
+                band
+            ))),
+        };
+
+        let collisions = access_points
+            .into_iter()
+            .filter(|access_point| access_point.ssid() == config.ssid())
+            .collect::<Vec<_>>();
+
+        if !collisions.is_empty() {
+            warn!(
+                "SSID {:?} of the hotspot is already used by {}",
+                config.ssid(),
+                collisions
+                    .iter()
+                    .map(|access_point| access_point.bssid.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        Ok(HotspotPreflight {
+            band,
+            channel,
+            congestion,
+            collisions,
+        })
+    }
+
+    /// Get the clients of the hotspot running on the device, with their
+    /// traffic counters and DHCP leases.
+    #[cfg(feature = "hotspot")]
+    pub fn get_hotspot_clients(&self) -> Result<Vec<HotspotClient>> {
+        if !get_wifi_backend(self.dbus_manager.bus())?.supports_hotspot_clients() {
+            bail!(ErrorKind::NetworkManager(
+                "Listing hotspot clients is not supported with the iwd Wi-Fi backend".into()
+            ));
+        }
+
+        let interface = self.device.interface();
+
+        let mut clients = self.dbus_manager.supplicant().get_stations(interface)?;
+
+        add_dnsmasq_leases(interface, &mut clients)?;
+
+        Ok(clients)
+    }
+
+    /// Get the daemon NetworkManager uses to drive Wi-Fi devices.
+    pub fn get_backend(&self) -> Result<WifiBackend> {
+        get_wifi_backend(self.dbus_manager.bus())
+    }
+
+    /// Get the country code the radio currently operates under.
+    ///
+    /// The code is read from wpa_supplicant, falling back to the regulatory
+    /// domain requested from the cfg80211 kernel module.
+    pub fn get_regulatory_domain(&self) -> Result<Option<String>> {
+        match self
+            .dbus_manager
+            .supplicant()
+            .get_country(self.device.interface())
+        {
+            Ok(ref country) if !country.is_empty() => return Ok(Some(country.clone())),
+            Ok(_) => {}
+            Err(e) => debug!("Unable to get country from wpa_supplicant: {}", e),
+        }
+
+        match fs::read_to_string(CFG80211_REGDOM_PATH) {
+            Ok(ref country) if !country.trim().is_empty() => Ok(Some(country.trim().to_string())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Set the country code of the radio through wpa_supplicant. Not
+    /// available with the iwd backend.
+    pub fn set_regulatory_domain(&self, country: &str) -> Result<()> {
+        let country = verify_country_code(country)?;
+
+        if !get_wifi_backend(self.dbus_manager.bus())?.supports_country() {
+            bail!(ErrorKind::NetworkManager(
+                "Setting the regulatory domain is not supported with the iwd Wi-Fi backend".into()
+            ));
+        }
+
+        self.dbus_manager
+            .supplicant()
+            .set_country(self.device.interface(), &country)
+    }
+
+    /// Defer background scans of the current connection for `duration`, so
+    /// that real-time traffic does not suffer the latency spikes of
+    /// off-channel scans. Scans resume once `duration` elapsed or the
+    /// returned inhibitor is dropped, whichever comes first. Not available
+    /// with the iwd backend.
+    pub fn inhibit_background_scan(&self, duration: Duration) -> Result<BackgroundScanInhibitor> {
+        if get_wifi_backend(self.dbus_manager.bus())? != WifiBackend::WpaSupplicant {
+            bail!(ErrorKind::NetworkManager(
+                "Inhibiting background scans is not supported with the iwd Wi-Fi backend".into()
+            ));
+        }
+
+        BackgroundScanInhibitor::new(
+            self.dbus_manager.supplicant(),
+            self.device.interface(),
+            duration,
+        )
+    }
+
+    /// Raise the log level of wpa_supplicant to `level` until the returned
+    /// session is finished, which collects what was logged in the meantime,
+    /// e.g. around a failing association. Not available with the iwd backend.
+    pub fn debug_supplicant(&self, level: SupplicantLogLevel) -> Result<SupplicantDebugSession> {
+        if get_wifi_backend(self.dbus_manager.bus())? != WifiBackend::WpaSupplicant {
+            bail!(ErrorKind::NetworkManager(
+                "Supplicant debugging is not supported with the iwd Wi-Fi backend".into()
+            ));
+        }
+
+        SupplicantDebugSession::new(
+            self.dbus_manager.supplicant(),
+            self.device.interface(),
+            level,
+        )
+    }
+
+    /// Get the current transmit power in dBm, if the driver reports it.
+    /// Requires the `iw` tool.
+    pub fn get_tx_power(&self) -> Result<Option<f64>> {
+        get_tx_power(self.device.interface())
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct AccessPoint {
     pub path: String,
     pub ssid: Ssid,
+    pub bssid: String,
     pub strength: u32,
-    pub security: Security,
+    pub security: ApSecurity,
+    pub flags: NM80211ApFlags,
+    /// Center frequency in MHz.
+    pub frequency: u32,
+    /// When NetworkManager last found the access point in scan results, in
+    /// seconds since boot (`CLOCK_BOOTTIME`). `None` if it was never seen.
+    pub last_seen: Option<u32>,
 }
 
 impl AccessPoint {
     pub fn ssid(&self) -> &SsidSlice {
         &self.ssid
     }
+
+    /// The band the access point operates on, `None` for frequencies
+    /// outside the known Wi-Fi bands.
+    pub fn band(&self) -> Option<Band> {
+        Band::from_frequency(self.frequency)
+    }
+
+    pub fn channel(&self) -> Option<u32> {
+        channels::frequency_to_channel(self.frequency)
+    }
+
+    /// Time since NetworkManager last found the access point in scan results.
+    pub fn age(&self) -> Option<Duration> {
+        self.age_at(boot_time().ok()?)
+    }
+
+    fn age_at(&self, now: u64) -> Option<Duration> {
+        self.last_seen
+            .map(|last_seen| Duration::from_secs(now.saturating_sub(u64::from(last_seen))))
+    }
+
+    /// The simplified summary of `security`.
+    pub fn security_summary(&self) -> Security {
+        self.security.summary()
+    }
+
+    /// Whether the access point relies on deprecated WEP encryption.
+    pub fn is_wep(&self) -> bool {
+        self.security_summary().contains(Security::WEP)
+    }
+
+    /// Whether the access point advertises the given WPS enrollment method.
+    pub fn supports_wps(&self, method: &WpsMethod) -> bool {
+        match *method {
+            WpsMethod::PushButton => self.flags.contains(NM80211ApFlags::AP_FLAGS_WPS_PBC),
+            WpsMethod::Pin(_) => self.flags.contains(NM80211ApFlags::AP_FLAGS_WPS_PIN),
+        }
+    }
+}
+
+/// The outcome of a scan before starting a hotspot.
+#[cfg(feature = "hotspot")]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct HotspotPreflight {
+    pub band: Band,
+    /// The least congested of the candidate channels.
+    pub channel: u32,
+    /// The candidate channels with their congestion, a weighted sum of the
+    /// signal strengths of the access points using them.
+    pub congestion: Vec<(u32, u32)>,
+    /// Access points broadcasting the SSID of the hotspot.
+    pub collisions: Vec<AccessPoint>,
+}
+
+#[cfg(feature = "hotspot")]
+impl HotspotPreflight {
+    /// Configure the hotspot for the picked channel.
+    pub fn apply(&self, config: HotspotConfig) -> HotspotConfig {
+        config.band(self.band).channel(self.channel)
+    }
+}
+
+/// A logical network: the access points sharing an SSID and security.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Network {
+    pub ssid: Ssid,
+    pub security: Security,
+    /// Access points of the network, strongest first.
+    pub access_points: Vec<AccessPoint>,
+}
+
+impl Network {
+    pub fn ssid(&self) -> &SsidSlice {
+        &self.ssid
+    }
+
+    /// Signal strength of the strongest access point.
+    pub fn strength(&self) -> u32 {
+        self.access_points
+            .iter()
+            .map(|access_point| access_point.strength)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The strongest access point, the one to pin an activation to.
+    pub fn best_access_point(&self) -> Option<&AccessPoint> {
+        self.access_points
+            .iter()
+            .max_by_key(|access_point| access_point.strength)
+    }
+
+    pub fn bssids(&self) -> Vec<&str> {
+        self.access_points
+            .iter()
+            .map(|access_point| access_point.bssid.as_str())
+            .collect()
+    }
 }
 
-bitflags! {
-    pub struct Security: u32 {
-        const NONE         = 0b0000_0000;
-        const WEP          = 0b0000_0001;
-        const WPA          = 0b0000_0010;
-        const WPA2         = 0b0000_0100;
-        const ENTERPRISE   = 0b0000_1000;
+/// Group access points sharing an SSID and security into networks, sorted by
+/// their strongest access point. Hidden access points, which broadcast an
+/// empty SSID, are left out.
+pub fn group_networks(access_points: Vec<AccessPoint>) -> Vec<Network> {
+    let mut networks: Vec<Network> = Vec::new();
+
+    for access_point in access_points {
+        if access_point.ssid.as_bytes().is_empty() {
+            continue;
+        }
+
+        let existing = networks.iter_mut().find(|network| {
+            network.ssid == access_point.ssid && network.security == access_point.security_summary()
+        });
+
+        match existing {
+            Some(network) => network.access_points.push(access_point),
+            None => networks.push(Network {
+                ssid: access_point.ssid.clone(),
+                security: access_point.security_summary(),
+                access_points: vec![access_point],
+            }),
+        }
+    }
+
+    for network in &mut networks {
+        network
+            .access_points
+            .sort_by_key(|access_point| Reverse(access_point.strength));
     }
+
+    networks.sort_by_key(|network| Reverse(network.strength()));
+
+    networks
 }
 
-#[derive(Debug)]
+/// How to authenticate with an access point.
+///
+/// WEP, whichever variant, is deprecated and trivially breakable. It is
+/// supported only for joining legacy equipment that cannot be upgraded.
 pub enum AccessPointCredentials {
     None,
+    /// WEP with a 128-bit passphrase (`wep-key-type` 2).
     Wep {
-        passphrase: String,
+        passphrase: SecretString,
+    },
+    /// WEP with a static key given either as 5 or 13 ASCII characters, or as
+    /// 10 or 26 hex digits (`wep-key-type` 1).
+    WepKey {
+        key: SecretString,
+    },
+    /// Dynamic WEP, where the WEP keys are negotiated through 802.1X.
+    DynamicWep {
+        config: Ieee8021xConfig,
     },
     Wpa {
-        passphrase: String,
+        passphrase: SecretString,
+        /// `psk-flags`, e.g. `SecretFlags::AGENT_OWNED` to have a secret
+        /// agent store the passphrase instead of NetworkManager.
+        psk_flags: SecretFlags,
     },
+    /// WPA Enterprise, authenticating through 802.1X. The configuration
+    /// must validate the server certificate, see `Ieee8021xConfig::verify`.
     Enterprise {
-        identity: String,
-        passphrase: String,
+        config: Ieee8021xConfig,
+    },
+    /// Join a WPA network through WPS enrollment instead of a pre-shared key.
+    ///
+    /// The PSK is obtained from the access point during enrollment, so the
+    /// application never needs to handle it.
+    Wps {
+        method: WpsMethod,
     },
 }
 
-bitflags! {
-    pub struct NM80211ApFlags: u32 {
-        // access point has no special capabilities
-        const AP_FLAGS_NONE                  = 0x0000_0000;
-        // access point requires authentication and encryption (usually means WEP)
-        const AP_FLAGS_PRIVACY               = 0x0000_0001;
-        // access point supports some WPS method
-        const AP_FLAGS_WPS                   = 0x0000_0002;
-        // access point supports push-button WPS
-        const AP_FLAGS_WPS_PBC               = 0x0000_0004;
-        // access point supports PIN-based WPS
-        const AP_FLAGS_WPS_PIN               = 0x0000_0008;
-    }
-}
-
-bitflags! {
-    pub struct NM80211ApSecurityFlags: u32 {
-         // the access point has no special security requirements
-        const AP_SEC_NONE                       = 0x0000_0000;
-        // 40/64-bit WEP is supported for pairwise/unicast encryption
-        const AP_SEC_PAIR_WEP40                 = 0x0000_0001;
-        // 104/128-bit WEP is supported for pairwise/unicast encryption
-        const AP_SEC_PAIR_WEP104                = 0x0000_0002;
-        // TKIP is supported for pairwise/unicast encryption
-        const AP_SEC_PAIR_TKIP                  = 0x0000_0004;
-        // AES/CCMP is supported for pairwise/unicast encryption
-        const AP_SEC_PAIR_CCMP                  = 0x0000_0008;
-        // 40/64-bit WEP is supported for group/broadcast encryption
-        const AP_SEC_GROUP_WEP40                = 0x0000_0010;
-        // 104/128-bit WEP is supported for group/broadcast encryption
-        const AP_SEC_GROUP_WEP104               = 0x0000_0020;
-        // TKIP is supported for group/broadcast encryption
-        const AP_SEC_GROUP_TKIP                 = 0x0000_0040;
-        // AES/CCMP is supported for group/broadcast encryption
-        const AP_SEC_GROUP_CCMP                 = 0x0000_0080;
-        // WPA/RSN Pre-Shared Key encryption is supported
-        const AP_SEC_KEY_MGMT_PSK               = 0x0000_0100;
-        // 802.1x authentication and key management is supported
-        const AP_SEC_KEY_MGMT_802_1X            = 0x0000_0200;
-        // WPA/RSN Simultaneous Authentication of Equals is supported
-        const AP_SEC_KEY_MGMT_SAE               = 0x0000_0400;
-        // WPA/RSN Opportunistic Wireless Encryption is supported
-        const AP_SEC_KEY_MGMT_OWE               = 0x0000_0800;
-        // WPA/RSN Opportunistic Wireless Encryption transition mode is supported
-        const AP_SEC_KEY_MGMT_OWE_TM            = 0x0000_1000;
-        // WPA3 Enterprise Suite-B 192 bit mode is supported
-        const AP_SEC_KEY_MGMT_EAP_SUITE_B_192   = 0x0000_2000;
+impl AccessPointCredentials {
+    /// Checks the format of passphrases and keys, e.g. to reject input in a
+    /// form before attempting to connect. `connect` checks them as well.
+    pub fn verify(&self) -> Result<()> {
+        match *self {
+            AccessPointCredentials::Wep { ref passphrase } => {
+                verify_wep_passphrase(passphrase.expose())?;
+            }
+            AccessPointCredentials::WepKey { ref key } => {
+                verify_wep_key(key.expose())?;
+            }
+            AccessPointCredentials::Wpa { ref passphrase, .. } => {
+                verify_psk(passphrase.expose())?;
+            }
+            AccessPointCredentials::DynamicWep { ref config }
+            | AccessPointCredentials::Enterprise { ref config } => {
+                config.verify()?;
+            }
+            AccessPointCredentials::Wps {
+                method: WpsMethod::Pin(ref pin),
+            } => {
+                verify_wps_pin(pin)?;
+            }
+            AccessPointCredentials::None | AccessPointCredentials::Wps { .. } => {}
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for AccessPointCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AccessPointCredentials::None => f.write_str("None"),
+            AccessPointCredentials::Wep { ref passphrase } => f
+                .debug_struct("Wep")
+                .field("passphrase", passphrase)
+                .finish(),
+            AccessPointCredentials::WepKey { ref key } => {
+                f.debug_struct("WepKey").field("key", key).finish()
+            }
+            AccessPointCredentials::DynamicWep { ref config } => f
+                .debug_struct("DynamicWep")
+                .field("config", config)
+                .finish(),
+            AccessPointCredentials::Wpa {
+                ref passphrase,
+                psk_flags,
+            } => f
+                .debug_struct("Wpa")
+                .field("passphrase", passphrase)
+                .field("psk_flags", &psk_flags)
+                .finish(),
+            AccessPointCredentials::Enterprise { ref config } => f
+                .debug_struct("Enterprise")
+                .field("config", config)
+                .finish(),
+            AccessPointCredentials::Wps { ref method } => {
+                f.debug_struct("Wps").field("method", method).finish()
+            }
+        }
+    }
+}
+
+/// WPS enrollment methods, mapped to the `wps-method` setting flags.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WpsMethod {
+    PushButton,
+    /// Enrollment with a PIN of 4 digits, or 8 with a checksum digit, that
+    /// is entered on the access point, see `verify_wps_pin`.
+    Pin(String),
+}
+
+impl From<WpsMethod> for u32 {
+    fn from(method: WpsMethod) -> u32 {
+        match method {
+            WpsMethod::PushButton => 0x4,
+            WpsMethod::Pin(_) => 0x8,
+        }
     }
 }
 
@@ -172,17 +730,64 @@ pub fn new_wifi_device<'a>(
     }
 }
 
+/// Seconds since boot including time suspended, the clock NetworkManager
+/// uses for `LastSeen`.
+fn boot_time() -> Result<u64> {
+    let uptime = fs::read_to_string(UPTIME_PATH)
+        .chain_err(|| ErrorKind::NetworkManager(format!("Unable to read {}", UPTIME_PATH)))?;
+
+    match parse_uptime(&uptime) {
+        Some(secs) => Ok(secs),
+        None => bail!(ErrorKind::NetworkManager(format!(
+            "Unexpected {} contents: {}",
+            UPTIME_PATH, uptime
+        ))),
+    }
+}
+
+/// The whole seconds of `/proc/uptime`, e.g. `350735.47 234388.90`.
+fn parse_uptime(uptime: &str) -> Option<u64> {
+    uptime.split('.').next()?.parse().ok()
+}
+
+/// Drops access points last seen longer than `max_age` before `now`, or
+/// never seen, both in seconds since boot.
+fn retain_fresh(
+    mut access_points: Vec<AccessPoint>,
+    now: u64,
+    max_age: Duration,
+) -> Vec<AccessPoint> {
+    access_points.retain(|access_point| match access_point.age_at(now) {
+        Some(age) => age <= max_age,
+        None => false,
+    });
+
+    access_points
+}
+
 fn get_access_point(manager: &DBusNetworkManager, path: &str) -> Result<Option<AccessPoint>> {
     if let Some(ssid) = manager.get_access_point_ssid(path) {
+        let bssid = manager.get_access_point_hw_address(path)?;
+
         let strength = manager.get_access_point_strength(path)?;
 
-        let security = get_access_point_security(manager, path)?;
+        let flags = manager.get_access_point_flags(path)?;
+
+        let frequency = manager.get_access_point_frequency(path)?;
+
+        let last_seen = manager.get_access_point_last_seen(path);
+
+        let security = get_access_point_security(manager, path, flags)?;
 
         let access_point = AccessPoint {
             path: path.to_string(),
             ssid,
+            bssid,
             strength,
             security,
+            flags,
+            frequency,
+            last_seen,
         };
 
         Ok(Some(access_point))
@@ -191,35 +796,166 @@ fn get_access_point(manager: &DBusNetworkManager, path: &str) -> Result<Option<A
     }
 }
 
-fn get_access_point_security(manager: &DBusNetworkManager, path: &str) -> Result<Security> {
-    let flags = manager.get_access_point_flags(path)?;
-
+fn get_access_point_security(
+    manager: &DBusNetworkManager,
+    path: &str,
+    flags: NM80211ApFlags,
+) -> Result<ApSecurity> {
     let wpa_flags = manager.get_access_point_wpa_flags(path)?;
 
     let rsn_flags = manager.get_access_point_rsn_flags(path)?;
 
-    let mut security = Security::NONE;
+    Ok(ApSecurity::new(flags, wpa_flags, rsn_flags))
+}
 
-    if flags.contains(NM80211ApFlags::AP_FLAGS_PRIVACY)
-        && wpa_flags == NM80211ApSecurityFlags::AP_SEC_NONE
-        && rsn_flags == NM80211ApSecurityFlags::AP_SEC_NONE
-    {
-        security |= Security::WEP;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use network_manager_types::NM80211ApSecurityFlags;
+
+    fn security(summary: Security) -> ApSecurity {
+        let rsn = if summary.contains(Security::WPA2) {
+            NM80211ApSecurityFlags::AP_SEC_PAIR_CCMP | NM80211ApSecurityFlags::AP_SEC_KEY_MGMT_PSK
+        } else {
+            NM80211ApSecurityFlags::AP_SEC_NONE
+        };
+
+        ApSecurity::new(
+            NM80211ApFlags::AP_FLAGS_NONE,
+            NM80211ApSecurityFlags::AP_SEC_NONE,
+            rsn,
+        )
     }
 
-    if wpa_flags != NM80211ApSecurityFlags::AP_SEC_NONE {
-        security |= Security::WPA;
+    fn access_point(ssid: &str, bssid: &str, strength: u32, summary: Security) -> AccessPoint {
+        AccessPoint {
+            path: String::new(),
+            ssid: Ssid::from_bytes(ssid.as_bytes().to_vec()).unwrap(),
+            bssid: bssid.to_string(),
+            strength,
+            security: security(summary),
+            flags: NM80211ApFlags::AP_FLAGS_NONE,
+            frequency: 2412,
+            last_seen: None,
+        }
     }
 
-    if rsn_flags != NM80211ApSecurityFlags::AP_SEC_NONE {
-        security |= Security::WPA2;
+    #[test]
+    fn test_parse_uptime() {
+        assert_eq!(parse_uptime("350735.47 234388.90\n"), Some(350735));
+        assert_eq!(parse_uptime("12 5"), None);
+        assert_eq!(parse_uptime(""), None);
     }
 
-    if wpa_flags.contains(NM80211ApSecurityFlags::AP_SEC_KEY_MGMT_802_1X)
-        || rsn_flags.contains(NM80211ApSecurityFlags::AP_SEC_KEY_MGMT_802_1X)
-    {
-        security |= Security::ENTERPRISE;
+    #[test]
+    fn test_retain_fresh() {
+        let mut recent = access_point("home", "00:00:00:00:00:01", 40, Security::WPA2);
+        recent.last_seen = Some(990);
+        let mut stale = access_point("cafe", "00:00:00:00:00:02", 60, Security::NONE);
+        stale.last_seen = Some(900);
+        let never = access_point("office", "00:00:00:00:00:03", 80, Security::NONE);
+
+        assert_eq!(recent.age_at(1000), Some(Duration::from_secs(10)));
+        assert_eq!(never.age_at(1000), None);
+        // seen after `now` was read
+        assert_eq!(recent.age_at(980), Some(Duration::from_secs(0)));
+
+        let fresh = retain_fresh(vec![recent, stale, never], 1000, Duration::from_secs(30));
+        let bssids: Vec<&str> = fresh.iter().map(|ap| ap.bssid.as_str()).collect();
+        assert_eq!(bssids, ["00:00:00:00:00:01"]);
+    }
+
+    #[test]
+    fn test_group_networks() {
+        let networks = group_networks(vec![
+            access_point("home", "00:00:00:00:00:01", 40, Security::WPA2),
+            access_point("cafe", "00:00:00:00:00:02", 60, Security::NONE),
+            access_point("home", "00:00:00:00:00:03", 80, Security::WPA2),
+            access_point("home", "00:00:00:00:00:04", 90, Security::NONE),
+            access_point("", "00:00:00:00:00:05", 100, Security::WPA2),
+        ]);
+
+        let summary: Vec<(&str, u32, Vec<&str>)> = networks
+            .iter()
+            .map(|network| {
+                (
+                    network.ssid().as_str().unwrap(),
+                    network.strength(),
+                    network.bssids(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                ("home", 90, vec!["00:00:00:00:00:04"]),
+                ("home", 80, vec!["00:00:00:00:00:03", "00:00:00:00:00:01"]),
+                ("cafe", 60, vec!["00:00:00:00:00:02"]),
+            ]
+        );
     }
 
-    Ok(security)
+    #[test]
+    fn test_enterprise_requires_server_validation() {
+        let unvalidated = AccessPointCredentials::Enterprise {
+            config: Ieee8021xConfig::peap("alice", "secret"),
+        };
+        assert!(unvalidated.verify().is_err());
+
+        let dynamic_wep = AccessPointCredentials::DynamicWep {
+            config: Ieee8021xConfig::peap("alice", "secret"),
+        };
+        assert!(dynamic_wep.verify().is_err());
+
+        let validated = AccessPointCredentials::Enterprise {
+            config: Ieee8021xConfig::peap("alice", "secret").system_ca_certs(true),
+        };
+        assert!(validated.verify().is_ok());
+
+        let anonymous = AccessPointCredentials::Enterprise {
+            config: Ieee8021xConfig::peap("", "secret").system_ca_certs(true),
+        };
+        assert!(anonymous.verify().is_err());
+    }
+
+    #[test]
+    fn test_wps_pin() {
+        assert_eq!(u32::from(WpsMethod::PushButton), 0x4);
+        assert_eq!(u32::from(WpsMethod::Pin("12345670".into())), 0x8);
+
+        let mut access_point = access_point("home", "00:00:00:00:00:01", 40, Security::WPA2);
+        access_point.flags = NM80211ApFlags::AP_FLAGS_WPS_PIN;
+        assert!(access_point.supports_wps(&WpsMethod::Pin("12345670".into())));
+        assert!(!access_point.supports_wps(&WpsMethod::PushButton));
+
+        let valid = AccessPointCredentials::Wps {
+            method: WpsMethod::Pin("12345670".into()),
+        };
+        assert!(valid.verify().is_ok());
+
+        let wrong_checksum = AccessPointCredentials::Wps {
+            method: WpsMethod::Pin("12345678".into()),
+        };
+        assert!(wrong_checksum.verify().is_err());
+    }
+
+    #[test]
+    fn test_wep_key_credentials() {
+        let ascii = AccessPointCredentials::WepKey {
+            key: "abcdefghijklm".into(),
+        };
+        assert!(ascii.verify().is_ok());
+
+        let hex = AccessPointCredentials::WepKey {
+            key: "0123456789".into(),
+        };
+        assert!(hex.verify().is_ok());
+
+        let passphrase = AccessPointCredentials::WepKey {
+            key: "wep passphrase".into(),
+        };
+        assert!(passphrase.verify().is_err());
+    }
 }
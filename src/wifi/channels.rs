@@ -0,0 +1,244 @@
+//! Wi-Fi bands and the mapping between channel numbers and center
+//! frequencies.
+
+use std::fmt;
+
+use errors::*;
+
+/// A Wi-Fi frequency band.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Band {
+    /// 2.4 GHz, channels 1 to 14.
+    TwoGhz,
+    /// 5 GHz, including the 4.9 GHz channels used in Japan.
+    FiveGhz,
+    /// 6 GHz (Wi-Fi 6E).
+    SixGhz,
+}
+
+impl Band {
+    /// The band of a center frequency given in MHz.
+    pub fn from_frequency(frequency: u32) -> Option<Band> {
+        match frequency {
+            2412..=2484 => Some(Band::TwoGhz),
+            4910..=5885 => Some(Band::FiveGhz),
+            5935..=7115 => Some(Band::SixGhz),
+            _ => None,
+        }
+    }
+
+    /// The value of the `802-11-wireless.band` setting restricting a
+    /// connection to this band. NetworkManager only accepts `a` and `bg`,
+    /// so 6 GHz has none and cannot be selected through the band.
+    pub fn as_setting(self) -> Option<&'static str> {
+        match self {
+            Band::TwoGhz => Some("bg"),
+            Band::FiveGhz => Some("a"),
+            Band::SixGhz => None,
+        }
+    }
+
+    /// The 20 MHz channels of the band, in ascending order. Whether a channel
+    /// may be used depends on the regulatory domain.
+    pub fn channels(self) -> Vec<u32> {
+        match self {
+            Band::TwoGhz => (1..=14).collect(),
+            Band::FiveGhz => (36..=64)
+                .step_by(4)
+                .chain((100..=144).step_by(4))
+                .chain((149..=177).step_by(4))
+                // 4.9 GHz channels only available in Japan
+                .chain((184..=196).step_by(4))
+                .collect(),
+            Band::SixGhz => Some(2).into_iter().chain((1..=233).step_by(4)).collect(),
+        }
+    }
+
+    pub fn has_channel(self, channel: u32) -> bool {
+        self.channels().contains(&channel)
+    }
+
+    /// The channels worth considering for an access point: the three
+    /// non-overlapping 2.4 GHz channels, the 5 GHz channels that do not
+    /// require radar detection (DFS) and the 6 GHz preferred scanning
+    /// channels clients look for access points on.
+    pub fn hotspot_channels(self) -> Vec<u32> {
+        match self {
+            Band::TwoGhz => vec![1, 6, 11],
+            Band::FiveGhz => (36..=48).step_by(4).chain((149..=165).step_by(4)).collect(),
+            Band::SixGhz => (5..=229).step_by(16).collect(),
+        }
+    }
+}
+
+impl fmt::Display for Band {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Band::TwoGhz => write!(f, "2.4 GHz"),
+            Band::FiveGhz => write!(f, "5 GHz"),
+            Band::SixGhz => write!(f, "6 GHz"),
+        }
+    }
+}
+
+/// The channel number of a center frequency given in MHz.
+pub fn frequency_to_channel(frequency: u32) -> Option<u32> {
+    match Band::from_frequency(frequency)? {
+        Band::TwoGhz if frequency == 2484 => Some(14),
+        Band::TwoGhz => exact(frequency - 2407),
+        Band::FiveGhz if frequency < 5000 => exact(frequency - 4000),
+        Band::FiveGhz => exact(frequency - 5000),
+        // Channel 2 is the odd one out below the regular 20 MHz raster
+        Band::SixGhz if frequency == 5935 => Some(2),
+        Band::SixGhz if frequency < 5955 => None,
+        Band::SixGhz => exact(frequency - 5950),
+    }
+}
+
+/// The center frequency in MHz of a channel within a band, `None` if the
+/// band has no such channel.
+pub fn channel_to_frequency(band: Band, channel: u32) -> Option<u32> {
+    if !band.has_channel(channel) {
+        return None;
+    }
+
+    let frequency = match (band, channel) {
+        (Band::TwoGhz, 14) => 2484,
+        (Band::TwoGhz, _) => 2407 + channel * 5,
+        (Band::FiveGhz, 184..=196) => 4000 + channel * 5,
+        (Band::FiveGhz, _) => 5000 + channel * 5,
+        (Band::SixGhz, 2) => 5935,
+        (Band::SixGhz, _) => 5950 + channel * 5,
+    };
+
+    Some(frequency)
+}
+
+/// Check that `channel` is a channel of `band`, e.g. before configuring a
+/// hotspot with it.
+pub fn verify_channel(band: Band, channel: u32) -> Result<()> {
+    if band.has_channel(channel) {
+        Ok(())
+    } else {
+        bail!(ErrorKind::NetworkManager(format!(
+            "Invalid channel {} for the {} band",
+            channel, band
+        )))
+    }
+}
+
+/// The `802-11-wireless.band` value of `band`, failing for bands
+/// NetworkManager cannot restrict a connection to.
+pub fn verify_band_setting(band: Band) -> Result<&'static str> {
+    match band.as_setting() {
+        Some(setting) => Ok(setting),
+        None => bail!(ErrorKind::NetworkManager(format!(
+            "NetworkManager cannot restrict a connection to the {} band",
+            band
+        ))),
+    }
+}
+
+/// How congested `channel` of `band` is given the channels and signal
+/// strengths of the access points in range. Adjacent 2.4 GHz channels
+/// overlap, so access points up to four channels apart count with a weight
+/// decreasing with the distance. Channels of the other bands are 20 MHz wide
+/// and only access points on the same channel count.
+pub fn congestion(band: Band, channel: u32, access_points: &[(u32, u32)]) -> u32 {
+    access_points
+        .iter()
+        .map(|&(other, strength)| {
+            let distance = channel.abs_diff(other);
+
+            match band {
+                Band::TwoGhz if distance < 5 => strength * (5 - distance),
+                _ if distance == 0 => strength * 5,
+                _ => 0,
+            }
+        })
+        .sum()
+}
+
+fn exact(offset: u32) -> Option<u32> {
+    // Channels are spaced 5 MHz apart
+    match (offset / 5, offset % 5) {
+        (channel, 0) => Some(channel),
+        _ => None,
+    }
+}
+
+pub fn verify_country_code(country: &str) -> Result<String> {
+    let valid =
+        country.len() == 2 && (country == "00" || country.chars().all(|c| c.is_ascii_alphabetic()));
+
+    if valid {
+        Ok(country.to_ascii_uppercase())
+    } else {
+        bail!(ErrorKind::NetworkManager(format!(
+            "Invalid country code: {:?}",
+            country
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_country_code() {
+        assert_eq!(verify_country_code("de").unwrap(), "DE");
+        assert_eq!(verify_country_code("00").unwrap(), "00");
+        assert!(verify_country_code("DEU").is_err());
+        assert!(verify_country_code("1A").is_err());
+    }
+
+    #[test]
+    fn test_band_setting() {
+        assert_eq!(Band::TwoGhz.as_setting(), Some("bg"));
+        assert_eq!(Band::FiveGhz.as_setting(), Some("a"));
+        assert_eq!(Band::SixGhz.as_setting(), None);
+        assert!(verify_band_setting(Band::SixGhz).is_err());
+    }
+
+    #[test]
+    fn test_channel_mapping() {
+        let cases = [
+            (Band::TwoGhz, 1, 2412),
+            (Band::TwoGhz, 13, 2472),
+            (Band::TwoGhz, 14, 2484),
+            (Band::FiveGhz, 36, 5180),
+            (Band::FiveGhz, 165, 5825),
+            (Band::FiveGhz, 184, 4920),
+            (Band::SixGhz, 1, 5955),
+            (Band::SixGhz, 2, 5935),
+            (Band::SixGhz, 233, 7115),
+        ];
+
+        for &(band, channel, frequency) in &cases {
+            assert_eq!(Band::from_frequency(frequency), Some(band));
+            assert_eq!(frequency_to_channel(frequency), Some(channel));
+            assert_eq!(channel_to_frequency(band, channel), Some(frequency));
+        }
+
+        assert_eq!(Band::from_frequency(5900), None);
+        assert_eq!(frequency_to_channel(2413), None);
+        assert_eq!(channel_to_frequency(Band::TwoGhz, 15), None);
+        assert_eq!(channel_to_frequency(Band::FiveGhz, 38), None);
+
+        assert_eq!(Band::SixGhz.channels().len(), 60);
+        assert!(verify_channel(Band::FiveGhz, 149).is_ok());
+        assert!(verify_channel(Band::FiveGhz, 6).is_err());
+    }
+
+    #[test]
+    fn test_congestion() {
+        let access_points = [(1, 80), (3, 40), (11, 20)];
+
+        assert_eq!(congestion(Band::TwoGhz, 1, &access_points), 400 + 120);
+        assert_eq!(congestion(Band::TwoGhz, 6, &access_points), 80);
+        assert_eq!(congestion(Band::TwoGhz, 11, &access_points), 100);
+        assert_eq!(congestion(Band::FiveGhz, 36, &[(36, 50), (40, 90)]), 250);
+    }
+}
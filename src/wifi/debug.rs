@@ -0,0 +1,204 @@
+//! Temporarily verbose wpa_supplicant logging, for debugging association
+//! failures in the field.
+
+use std::process::Command;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use dbus_supplicant::DBusSupplicant;
+use errors::*;
+use restore::{Restore, Undo};
+
+/// How many journal lines are collected at most, the most recent ones.
+const MAX_JOURNAL_LINES: usize = 5000;
+
+/// The `DebugLevel` of wpa_supplicant, from most to least verbose.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum SupplicantLogLevel {
+    Excessive,
+    MsgDump,
+    Debug,
+    Info,
+    Warning,
+    Error,
+}
+
+impl SupplicantLogLevel {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            SupplicantLogLevel::Excessive => "excessive",
+            SupplicantLogLevel::MsgDump => "msgdump",
+            SupplicantLogLevel::Debug => "debug",
+            SupplicantLogLevel::Info => "info",
+            SupplicantLogLevel::Warning => "warning",
+            SupplicantLogLevel::Error => "error",
+        }
+    }
+}
+
+impl FromStr for SupplicantLogLevel {
+    type Err = Error;
+    fn from_str(level: &str) -> Result<SupplicantLogLevel> {
+        match level {
+            "excessive" => Ok(SupplicantLogLevel::Excessive),
+            "msgdump" => Ok(SupplicantLogLevel::MsgDump),
+            "debug" => Ok(SupplicantLogLevel::Debug),
+            "info" => Ok(SupplicantLogLevel::Info),
+            "warning" => Ok(SupplicantLogLevel::Warning),
+            "error" => Ok(SupplicantLogLevel::Error),
+            _ => bail!(ErrorKind::NetworkManager(format!(
+                "Unknown wpa_supplicant log level: {}",
+                level
+            ))),
+        }
+    }
+}
+
+/// What wpa_supplicant logged while a `SupplicantDebugSession` was active.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct SupplicantDiagnostics {
+    pub interface: String,
+    pub level: SupplicantLogLevel,
+    /// Seconds since the Unix epoch the session started at.
+    pub started: u64,
+    /// Journal lines of wpa_supplicant since the session started, oldest
+    /// first. Lines of other interfaces are included, as many messages do
+    /// not name one.
+    pub lines: Vec<String>,
+}
+
+/// Raises the log level of wpa_supplicant until finished or dropped, when the
+/// previous level is restored.
+///
+/// The level applies to all interfaces of wpa_supplicant, not just the
+/// device it was raised for. Keys are never logged, as `DebugShowKeys` is
+/// left alone.
+pub struct SupplicantDebugSession {
+    interface: String,
+    level: SupplicantLogLevel,
+    started: u64,
+    restore: Restore<LogLevelChange>,
+}
+
+impl SupplicantDebugSession {
+    pub fn new(
+        supplicant: DBusSupplicant,
+        interface: &str,
+        level: SupplicantLogLevel,
+    ) -> Result<Self> {
+        // Fails for unknown interfaces before the level is raised
+        supplicant.get_interface(interface)?;
+
+        let previous = supplicant.get_debug_level()?;
+
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0);
+
+        supplicant.set_debug_level(level.as_str())?;
+
+        debug!(
+            "wpa_supplicant log level raised to {} for {}, was {}",
+            level.as_str(),
+            interface,
+            previous
+        );
+
+        Ok(SupplicantDebugSession {
+            interface: interface.to_string(),
+            level,
+            started,
+            restore: Restore::new(LogLevelChange {
+                supplicant,
+                previous,
+            }),
+        })
+    }
+
+    /// Restores the previous log level and collects the journal lines
+    /// wpa_supplicant logged since the session started. Requires
+    /// `journalctl` and permission to read the system journal.
+    pub fn finish(self) -> Result<SupplicantDiagnostics> {
+        self.restore.release()?;
+
+        Ok(SupplicantDiagnostics {
+            interface: self.interface,
+            level: self.level,
+            started: self.started,
+            lines: read_journal(self.started)?,
+        })
+    }
+}
+
+struct LogLevelChange {
+    supplicant: DBusSupplicant,
+    previous: String,
+}
+
+impl Undo for LogLevelChange {
+    fn description(&self) -> String {
+        "restore the wpa_supplicant log level".into()
+    }
+
+    fn undo(self) -> Result<()> {
+        self.supplicant.set_debug_level(&self.previous)
+    }
+}
+
+fn read_journal(since: u64) -> Result<Vec<String>> {
+    let output = Command::new("journalctl")
+        .args(journal_args(since))
+        .output()
+        .chain_err(|| ErrorKind::NetworkManager("Unable to run journalctl".into()))?;
+
+    if !output.status.success() {
+        bail!(ErrorKind::NetworkManager(format!(
+            "journalctl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+fn journal_args(since: u64) -> Vec<String> {
+    vec![
+        "--no-pager".into(),
+        "--quiet".into(),
+        "--output=short-precise".into(),
+        "--identifier=wpa_supplicant".into(),
+        format!("--since=@{}", since),
+        format!("--lines={}", MAX_JOURNAL_LINES),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_level_names() {
+        for level in &[
+            SupplicantLogLevel::Excessive,
+            SupplicantLogLevel::MsgDump,
+            SupplicantLogLevel::Debug,
+            SupplicantLogLevel::Info,
+            SupplicantLogLevel::Warning,
+            SupplicantLogLevel::Error,
+        ] {
+            assert_eq!(
+                level.as_str().parse::<SupplicantLogLevel>().unwrap(),
+                *level
+            );
+        }
+
+        assert!("verbose".parse::<SupplicantLogLevel>().is_err());
+        assert!(journal_args(1700000000).contains(&"--since=@1700000000".to_string()));
+    }
+}
@@ -0,0 +1,87 @@
+//! Wi-Fi power save and transmit power.
+
+use std::process::Command;
+
+use errors::*;
+
+/// The `802-11-wireless.powersave` setting of a connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum WifiPowerSave {
+    /// Use the global default of NetworkManager's configuration.
+    Default,
+    /// Leave the current setting of the driver alone.
+    Ignore,
+    /// Keep power save off, avoiding the latency of the radio dozing.
+    Disable,
+    Enable,
+}
+
+impl From<WifiPowerSave> for u32 {
+    fn from(powersave: WifiPowerSave) -> u32 {
+        match powersave {
+            WifiPowerSave::Default => 0,
+            WifiPowerSave::Ignore => 1,
+            WifiPowerSave::Disable => 2,
+            WifiPowerSave::Enable => 3,
+        }
+    }
+}
+
+impl WifiPowerSave {
+    pub fn from_u32(value: u32) -> Option<WifiPowerSave> {
+        match value {
+            0 => Some(WifiPowerSave::Default),
+            1 => Some(WifiPowerSave::Ignore),
+            2 => Some(WifiPowerSave::Disable),
+            3 => Some(WifiPowerSave::Enable),
+            _ => None,
+        }
+    }
+}
+
+/// The current transmit power of `interface` in dBm, as reported by `iw`.
+/// `None` if `iw` is not installed or the driver does not report it.
+pub fn get_tx_power(interface: &str) -> Result<Option<f64>> {
+    let output = match Command::new("iw").args(["dev", interface, "info"]).output() {
+        Ok(output) => output,
+        Err(e) => {
+            debug!("Unable to run iw: {}", e);
+            return Ok(None);
+        }
+    };
+
+    if !output.status.success() {
+        bail!(ErrorKind::NetworkManager(format!(
+            "iw failed on {}: {}",
+            interface,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(parse_tx_power(&String::from_utf8_lossy(&output.stdout)))
+}
+
+fn parse_tx_power(info: &str) -> Option<f64> {
+    info.lines()
+        .filter_map(|line| line.trim().strip_prefix("txpower "))
+        .filter_map(|power| power.split_whitespace().next())
+        .find_map(|power| power.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tx_power() {
+        let info = "Interface wlan0\n\
+                    \tifindex 3\n\
+                    \ttype managed\n\
+                    \tchannel 36 (5180 MHz), width: 80 MHz, center1: 5210 MHz\n\
+                    \ttxpower 22.00 dBm\n";
+
+        assert_eq!(parse_tx_power(info), Some(22.0));
+        assert_eq!(parse_tx_power("Interface wlan0\n"), None);
+    }
+}
@@ -0,0 +1,184 @@
+//! Pre-shared keys: checking WPA and WEP passphrases and keys before they
+//! reach NetworkManager, which only rejects them after the association timed
+//! out, and deriving the raw WPA key from a passphrase.
+
+#[cfg(feature = "psk")]
+use pbkdf2::pbkdf2_hmac;
+#[cfg(feature = "psk")]
+use sha1::Sha1;
+#[cfg(feature = "psk")]
+use zeroize::Zeroize;
+
+use errors::*;
+
+#[cfg(feature = "psk")]
+use network_manager_types::SsidSlice;
+#[cfg(feature = "psk")]
+use secret::SecretString;
+
+/// Length of a raw PSK in hex digits.
+const RAW_PSK_LEN: usize = 64;
+
+/// PBKDF2 iterations of the passphrase-to-PSK mapping of IEEE 802.11i.
+#[cfg(feature = "psk")]
+const PSK_ITERATIONS: u32 = 4096;
+
+#[cfg(feature = "psk")]
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// Checks that `psk` is either a passphrase of 8 to 63 printable ASCII
+/// characters or a raw key of 64 hex digits.
+pub fn verify_psk(psk: &str) -> Result<&str> {
+    if psk.len() == RAW_PSK_LEN {
+        if psk.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Ok(psk);
+        }
+
+        bail!(ErrorKind::PreSharedKey(
+            "A 64 character PSK should consist of hex digits".into()
+        ))
+    }
+
+    verify_printable(psk)?;
+
+    if psk.len() < 8 {
+        bail!(ErrorKind::PreSharedKey(format!(
+            "Password length should be at least 8 characters: {} len",
+            psk.len()
+        )))
+    } else if psk.len() > 63 {
+        bail!(ErrorKind::PreSharedKey(format!(
+            "Password length should not exceed 63: {} len",
+            psk.len()
+        )))
+    }
+
+    Ok(psk)
+}
+
+/// Checks that `key` is a raw WEP key of 5 or 13 ASCII characters or 10 or
+/// 26 hex digits, for 64 and 128 bit WEP.
+pub fn verify_wep_key(key: &str) -> Result<&str> {
+    let is_hex = key.chars().all(|c| c.is_ascii_hexdigit());
+
+    match key.len() {
+        5 | 13 if key.is_ascii() => Ok(key),
+        10 | 26 if is_hex => Ok(key),
+        _ => bail!(ErrorKind::PreSharedKey(format!(
+            "WEP key should be 5 or 13 ASCII characters or 10 or 26 hex digits: {} len",
+            key.len()
+        ))),
+    }
+}
+
+/// Checks that `passphrase`, which NetworkManager hashes into a 128 bit WEP
+/// key, has 1 to 64 printable ASCII characters.
+pub fn verify_wep_passphrase(passphrase: &str) -> Result<&str> {
+    if passphrase.is_empty() || passphrase.len() > 64 {
+        bail!(ErrorKind::PreSharedKey(format!(
+            "WEP passphrase length should be 1 to 64 characters: {} len",
+            passphrase.len()
+        )))
+    }
+
+    verify_printable(passphrase)?;
+
+    Ok(passphrase)
+}
+
+/// Derives the raw key of `passphrase` on `ssid` as 64 hex digits, which
+/// can be stored instead of the passphrase, e.g. as `psk` of a profile.
+/// Raw keys are passed through unchanged.
+#[cfg(feature = "psk")]
+pub fn derive_psk(ssid: &SsidSlice, passphrase: &str) -> Result<SecretString> {
+    let passphrase = verify_psk(passphrase)?;
+
+    if passphrase.len() == RAW_PSK_LEN {
+        return Ok(passphrase.to_ascii_lowercase().into());
+    }
+
+    let mut key = [0; RAW_PSK_LEN / 2];
+    pbkdf2_hmac::<Sha1>(
+        passphrase.as_bytes(),
+        ssid.as_bytes(),
+        PSK_ITERATIONS,
+        &mut key,
+    );
+
+    // Allocated once, so that no copy of the key is left behind by growing
+    let mut psk = String::with_capacity(RAW_PSK_LEN);
+
+    for byte in &key {
+        psk.push(char::from(HEX_DIGITS[usize::from(byte >> 4)]));
+        psk.push(char::from(HEX_DIGITS[usize::from(byte & 0xf)]));
+    }
+
+    key.zeroize();
+
+    Ok(psk.into())
+}
+
+fn verify_printable(password: &str) -> Result<()> {
+    if let Some(c) = password
+        .chars()
+        .find(|&c| c != ' ' && !c.is_ascii_graphic())
+    {
+        bail!(ErrorKind::PreSharedKey(format!(
+            "Password should consist of printable ASCII characters: {:?}",
+            c
+        )))
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_psk() {
+        assert!(verify_psk("password").is_ok());
+        assert!(verify_psk("pass word with spaces").is_ok());
+        assert!(verify_psk(&"a".repeat(63)).is_ok());
+        assert!(verify_psk(&"0123456789abcdef".repeat(4)).is_ok());
+
+        assert!(verify_psk("short").is_err());
+        assert!(verify_psk("pass\tword").is_err());
+        assert!(verify_psk("pässwörd").is_err());
+        assert!(verify_psk(&"g".repeat(64)).is_err());
+        assert!(verify_psk(&"a".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn test_verify_wep() {
+        assert!(verify_wep_key("abcde").is_ok());
+        assert!(verify_wep_key("0123456789abcdef0123456789").is_ok());
+        assert!(verify_wep_key("0123456789").is_ok());
+        assert!(verify_wep_key("abcdefghijklm").is_ok());
+        assert!(verify_wep_key("abcdef").is_err());
+        assert!(verify_wep_key("012345678g").is_err());
+        assert!(verify_wep_key("0123456789abcdef012345678g").is_err());
+        // five bytes, but not five ASCII characters
+        assert!(verify_wep_key("äbcd").is_err());
+        assert!(verify_wep_key("").is_err());
+
+        assert!(verify_wep_passphrase("a").is_ok());
+        assert!(verify_wep_passphrase("").is_err());
+        assert!(verify_wep_passphrase(&"a".repeat(65)).is_err());
+    }
+
+    #[cfg(feature = "psk")]
+    #[test]
+    fn test_derive_psk() {
+        use network_manager_types::Ssid;
+
+        // IEEE 802.11i, Annex H.4 test vector
+        let ssid = Ssid::from_bytes("IEEE").unwrap();
+
+        assert_eq!(
+            derive_psk(&ssid, "password").unwrap().expose(),
+            "f42c6fc52df0ebef9ebb4b90b38a5f902e83fe1b135a70e23aed762e9710a12e"
+        );
+    }
+}
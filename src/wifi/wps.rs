@@ -0,0 +1,181 @@
+//! WPS enrollment with a PIN. NetworkManager has wpa_supplicant generate the
+//! PIN and never shows it, so the enrollment is run through wpa_supplicant
+//! directly and the network key it hands out is then connected with like a
+//! WPA passphrase.
+
+use std::time::{Duration, Instant};
+
+use dbus::arg::{Dict, Iter, Variant};
+use dbus::ConnectionItem;
+
+use dbus_api::{open_connection, variant_iter_to_vec_u8};
+use dbus_nm::DBusNetworkManager;
+use errors::*;
+use secret::SecretString;
+
+const WPAS_SERVICE: &str = "fi.w1.wpa_supplicant1";
+const WPAS_WPS_INTERFACE: &str = "fi.w1.wpa_supplicant1.Interface.WPS";
+
+/// The WPS walk time, for which the access point accepts an enrollment.
+const WPS_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Checks that `pin` is either 4 digits or 8 digits ending in a valid
+/// checksum digit, the PIN formats of the WPS specification.
+pub fn verify_wps_pin(pin: &str) -> Result<&str> {
+    if !pin.chars().all(|c| c.is_ascii_digit()) {
+        bail!(ErrorKind::PreSharedKey(
+            "WPS PIN should consist of digits".into()
+        ))
+    }
+
+    match pin.len() {
+        4 => Ok(pin),
+        8 => {
+            let checksum: u32 = pin
+                .bytes()
+                .map(|digit| u32::from(digit - b'0'))
+                .zip([3, 1].iter().cycle())
+                .map(|(digit, weight)| digit * weight)
+                .sum();
+
+            match checksum % 10 {
+                0 => Ok(pin),
+                _ => bail!(ErrorKind::PreSharedKey(
+                    "WPS PIN checksum digit is wrong".into()
+                )),
+            }
+        }
+        _ => bail!(ErrorKind::PreSharedKey(format!(
+            "WPS PIN should have 4 or 8 digits: {} len",
+            pin.len()
+        ))),
+    }
+}
+
+/// Enrolls `interface` with the access point `bssid` once `pin` was entered
+/// on the access point, and returns the network key it handed out.
+pub fn enroll_with_pin(
+    dbus_manager: &DBusNetworkManager,
+    interface: &str,
+    bssid: &str,
+    pin: &str,
+) -> Result<SecretString> {
+    let supplicant = dbus_manager.supplicant();
+
+    let path = supplicant.get_interface(interface)?;
+
+    let connection = open_connection(supplicant.bus())?;
+
+    connection.add_match(&format!(
+        "type='signal',sender='{}',path='{}',interface='{}'",
+        WPAS_SERVICE, path, WPAS_WPS_INTERFACE
+    ))?;
+
+    supplicant.start_wps_pin(&path, &parse_bssid(bssid)?, pin)?;
+
+    debug!("WPS PIN enrollment of {} with {} started", interface, bssid);
+
+    let deadline = Instant::now() + WPS_TIMEOUT;
+
+    loop {
+        let remaining = match deadline.checked_duration_since(Instant::now()) {
+            Some(remaining) if remaining > Duration::from_millis(0) => remaining,
+            _ => break,
+        };
+
+        let message = match connection.iter(remaining.as_millis() as i32).next() {
+            Some(ConnectionItem::Signal(message)) => message,
+            _ => continue,
+        };
+
+        match message.member().as_deref() {
+            Some("Credentials") => {
+                if let Some(credentials) = message.get1::<Dict<&str, Variant<Iter>, _>>() {
+                    return network_key(credentials);
+                }
+            }
+            Some("Event") if message.get1::<&str>() == Some("fail") => {
+                bail!(ErrorKind::NetworkManager(format!(
+                    "WPS enrollment with {} failed",
+                    bssid
+                )))
+            }
+            _ => {}
+        }
+    }
+
+    if let Err(e) = supplicant.cancel_wps(&path) {
+        debug!("Unable to cancel WPS enrollment of {}: {}", interface, e);
+    }
+
+    bail!(ErrorKind::NetworkManager(format!(
+        "WPS enrollment with {} timed out",
+        bssid
+    )))
+}
+
+fn network_key<'a, I>(credentials: I) -> Result<SecretString>
+where
+    I: Iterator<Item = (&'a str, Variant<Iter<'a>>)>,
+{
+    for (name, mut value) in credentials {
+        if name == "Key" {
+            let key = String::from_utf8(variant_iter_to_vec_u8(&mut value)?).map_err(|_| {
+                ErrorKind::PreSharedKey("WPS network key is not valid UTF-8".into())
+            })?;
+
+            if key.is_empty() {
+                break;
+            }
+
+            return Ok(key.into());
+        }
+    }
+
+    bail!(ErrorKind::PreSharedKey(
+        "WPS enrollment handed out no network key".into()
+    ))
+}
+
+/// Parses a BSSID as NetworkManager reports it, e.g. `00:11:22:33:44:55`.
+fn parse_bssid(bssid: &str) -> Result<Vec<u8>> {
+    let bytes = bssid
+        .split(':')
+        .map(|byte| u8::from_str_radix(byte, 16).ok())
+        .collect::<Option<Vec<u8>>>();
+
+    match bytes {
+        Some(bytes) if bytes.len() == 6 => Ok(bytes),
+        _ => bail!(ErrorKind::NetworkManager(format!(
+            "Invalid BSSID: {}",
+            bssid
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_wps_pin() {
+        assert!(verify_wps_pin("12345670").is_ok());
+        assert!(verify_wps_pin("1234").is_ok());
+
+        assert!(verify_wps_pin("12345678").is_err());
+        assert!(verify_wps_pin("123456").is_err());
+        assert!(verify_wps_pin("1234567a").is_err());
+        assert!(verify_wps_pin("").is_err());
+    }
+
+    #[test]
+    fn test_parse_bssid() {
+        assert_eq!(
+            parse_bssid("00:1A:2b:3c:4D:ff").unwrap(),
+            vec![0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0xff]
+        );
+
+        assert!(parse_bssid("00:1a:2b:3c:4d").is_err());
+        assert!(parse_bssid("00:1a:2b:3c:4d:zz").is_err());
+    }
+}
@@ -0,0 +1,445 @@
+//! Mobile broadband modems, managed by ModemManager.
+
+use std::rc::Rc;
+
+use dbus_mm::DBusModemManager;
+use dbus_nm::DBusNetworkManager;
+use errors::*;
+use network_manager_types::Value;
+
+use connection::{create_connection, Connection, ConnectionState};
+use device::{get_devices, Device, PathGetter};
+use secret::SecretString;
+use settings::ConnectionProfile;
+
+pub mod apn;
+
+pub use self::apn::{ApnDatabase, ApnSettings};
+
+/// What a modem has to be unlocked with before it can connect, ModemManager's
+/// `MMModemLock`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum SimLock {
+    Unknown,
+    None,
+    SimPin,
+    SimPin2,
+    /// The PIN was entered wrong too often, the PUK resets it.
+    SimPuk,
+    SimPuk2,
+    /// Network, provider or corporate personalization locks.
+    Other(u32),
+}
+
+impl From<u32> for SimLock {
+    fn from(lock: u32) -> Self {
+        match lock {
+            0 => SimLock::Unknown,
+            1 => SimLock::None,
+            2 => SimLock::SimPin,
+            3 => SimLock::SimPin2,
+            4 => SimLock::SimPuk,
+            5 => SimLock::SimPuk2,
+            _ => SimLock::Other(lock),
+        }
+    }
+}
+
+impl From<SimLock> for u32 {
+    fn from(lock: SimLock) -> u32 {
+        match lock {
+            SimLock::Unknown => 0,
+            SimLock::None => 1,
+            SimLock::SimPin => 2,
+            SimLock::SimPin2 => 3,
+            SimLock::SimPuk => 4,
+            SimLock::SimPuk2 => 5,
+            SimLock::Other(lock) => lock,
+        }
+    }
+}
+
+/// A SIM slot of a modem with several of them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct SimSlot {
+    /// 1-based, as passed to `ModemDevice::set_primary_sim_slot`.
+    pub slot: u32,
+    /// The ICCID of the SIM in the slot, `None` if the slot is empty.
+    pub sim_id: Option<String>,
+    /// Whether the SIM in the slot is the one the modem uses.
+    pub primary: bool,
+}
+
+/// The lock of a modem and the attempts left to unlock it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct SimLockStatus {
+    pub lock: SimLock,
+    /// `None` if the modem does not report it.
+    pub retries: Option<u32>,
+}
+
+impl SimLockStatus {
+    pub fn is_locked(&self) -> bool {
+        self.lock != SimLock::None && self.lock != SimLock::Unknown
+    }
+}
+
+pub struct ModemDevice<'a> {
+    dbus_manager: Rc<DBusNetworkManager>,
+    modem_manager: DBusModemManager,
+    device: &'a Device,
+}
+
+impl<'a> ModemDevice<'a> {
+    /// The ModemManager object of the modem.
+    fn modem(&self) -> Result<String> {
+        self.device.udi()
+    }
+
+    fn sim(&self) -> Result<String> {
+        match self.modem_manager.get_sim(&self.modem()?)? {
+            Some(sim) => Ok(sim),
+            None => bail!(ErrorKind::NetworkManager(format!(
+                "No SIM in {}",
+                self.device.interface()
+            ))),
+        }
+    }
+
+    /// The identifier profiles are bound to the modem with through
+    /// `gsm.device-id`.
+    pub fn get_device_id(&self) -> Result<String> {
+        self.dbus_manager.get_modem_device_id(self.device.path())
+    }
+
+    /// The ICCID of the active SIM, `None` if no SIM is inserted.
+    pub fn get_sim_id(&self) -> Result<Option<String>> {
+        match self.modem_manager.get_sim(&self.modem()?)? {
+            Some(sim) => Ok(Some(self.modem_manager.get_sim_identifier(&sim)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The SIM slots of the modem. Empty for modems with a single slot.
+    pub fn get_sim_slots(&self) -> Result<Vec<SimSlot>> {
+        let modem = self.modem()?;
+
+        let primary = self.modem_manager.get_primary_sim_slot(&modem)?;
+
+        let mut slots = Vec::new();
+
+        for (index, sim) in self.modem_manager.get_sim_slots(&modem)?.iter().enumerate() {
+            let slot = index as u32 + 1;
+
+            let sim_id = if &**sim == "/" {
+                None
+            } else {
+                Some(self.modem_manager.get_sim_identifier(sim)?)
+            };
+
+            slots.push(SimSlot {
+                slot,
+                sim_id,
+                primary: slot == primary,
+            });
+        }
+
+        Ok(slots)
+    }
+
+    /// Switch the modem to the SIM in `slot`. ModemManager probes the modem
+    /// again, so its NetworkManager device is removed and added back, and has
+    /// to be looked up again.
+    pub fn set_primary_sim_slot(&self, slot: u32) -> Result<()> {
+        self.modem_manager
+            .set_primary_sim_slot(&self.modem()?, slot)
+    }
+
+    /// Bind `profile` to this modem and its active SIM, so that it is not
+    /// activated on another modem or after the SIM is swapped.
+    pub fn bind_profile(&self, profile: &mut ConnectionProfile) -> Result<()> {
+        profile.set_gsm_device_id(&self.get_device_id()?);
+
+        if let Some(sim_id) = self.get_sim_id()? {
+            profile.set_gsm_sim_id(&sim_id);
+        }
+
+        Ok(())
+    }
+
+    pub fn get_sim_lock(&self) -> Result<SimLockStatus> {
+        let modem = self.modem()?;
+
+        let lock = SimLock::from(self.modem_manager.get_unlock_required(&modem)?);
+
+        let retries = self
+            .modem_manager
+            .get_unlock_retries(&modem)?
+            .get(&u32::from(lock))
+            .cloned();
+
+        Ok(SimLockStatus { lock, retries })
+    }
+
+    /// Unlock the SIM with its PIN. Every wrong PIN uses up one of the
+    /// attempts, after which the SIM requires its PUK.
+    pub fn unlock_sim(&self, pin: &SecretString) -> Result<SimLockStatus> {
+        self.modem_manager.send_pin(&self.sim()?, pin.expose())?;
+
+        self.get_sim_lock()
+    }
+
+    /// Unlock a SIM blocked by too many wrong PINs with its PUK, setting
+    /// `pin` as the new PIN.
+    pub fn unlock_sim_with_puk(
+        &self,
+        puk: &SecretString,
+        pin: &SecretString,
+    ) -> Result<SimLockStatus> {
+        self.modem_manager
+            .send_puk(&self.sim()?, puk.expose(), pin.expose())?;
+
+        self.get_sim_lock()
+    }
+
+    /// MCC and MNC of the operator that issued the SIM, e.g. `310410`.
+    /// `None` if the modem has not read it, e.g. while the SIM is locked.
+    pub fn get_operator_code(&self) -> Result<Option<String>> {
+        let code = self
+            .modem_manager
+            .get_sim_operator_identifier(&self.sim()?)?;
+
+        Ok(if code.is_empty() { None } else { Some(code) })
+    }
+
+    /// Create and activate a mobile broadband connection on the modem with
+    /// the APN `database` lists first for the operator of the SIM. The
+    /// profile is bound to the modem and SIM with `bind_profile`.
+    pub fn provision(&self, database: &ApnDatabase) -> Result<(Connection, ConnectionState)> {
+        let code = match self.get_operator_code()? {
+            Some(code) => code,
+            None => bail!(ErrorKind::NetworkManager(format!(
+                "Operator of the SIM in {} is unknown",
+                self.device.interface()
+            ))),
+        };
+
+        let settings = match database.lookup(&code).first() {
+            Some(settings) => *settings,
+            None => bail!(ErrorKind::NetworkManager(format!(
+                "No APN known for operator {}",
+                code
+            ))),
+        };
+
+        let mut profile = settings.to_profile();
+        self.bind_profile(&mut profile)?;
+
+        create_connection(&self.dbus_manager, &profile, Some(self.device.path()))
+    }
+}
+
+pub fn new_modem_device<'a>(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    device: &'a Device,
+) -> ModemDevice<'a> {
+    ModemDevice {
+        dbus_manager: Rc::clone(dbus_manager),
+        modem_manager: dbus_manager.modem_manager(),
+        device,
+    }
+}
+
+/// Fails with `ErrorKind::SimLocked` if there are modems the profile at
+/// `path` may be activated on, restricted to `device_path` if given, and all
+/// of them are locked, as activating a mobile broadband connection then fails
+/// without telling why. Modems are matched against `gsm.device-id` and
+/// `gsm.sim-id` of the profile. The check is best-effort: if ModemManager
+/// cannot be asked, NetworkManager is left to try the activation.
+pub fn check_sim_unlocked(
+    dbus_manager: &Rc<DBusNetworkManager>,
+    path: &str,
+    device_path: Option<&str>,
+) -> Result<()> {
+    let (profile, devices) = match dbus_manager
+        .get_connection_profile(path)
+        .and_then(|profile| Ok((profile, get_devices(dbus_manager)?)))
+    {
+        Ok(result) => result,
+        Err(e) => {
+            debug!("Unable to check for locked SIMs: {}", e);
+            return Ok(());
+        }
+    };
+
+    let binding = ModemBinding {
+        device_id: profile.get("gsm", "device-id").and_then(Value::as_str),
+        sim_id: profile.get("gsm", "sim-id").and_then(Value::as_str),
+    };
+
+    let mut modems = Vec::new();
+
+    for device in &devices {
+        match device_path {
+            Some(path) if path != device.path() => continue,
+            _ => {}
+        }
+
+        let modem = match device.as_modem_device() {
+            Some(modem) => modem,
+            None => continue,
+        };
+
+        let interface = device.interface().to_string();
+
+        match binding.matches(&modem) {
+            Ok(false) => {}
+            Ok(true) => match modem.get_sim_lock() {
+                Ok(status) => modems.push((interface, Some(status))),
+                Err(e) => {
+                    debug!("Unable to get SIM lock of {}: {}", interface, e);
+                    modems.push((interface, None));
+                }
+            },
+            Err(e) => {
+                debug!("Unable to match {} against the profile: {}", interface, e);
+                modems.push((interface, None));
+            }
+        }
+    }
+
+    match all_locked(modems) {
+        Some((interface, lock)) => bail!(ErrorKind::SimLocked(interface, format!("{:?}", lock))),
+        None => Ok(()),
+    }
+}
+
+/// `gsm.device-id` and `gsm.sim-id` of a profile, which NetworkManager only
+/// activates it on a matching modem and SIM with.
+struct ModemBinding<'a> {
+    device_id: Option<&'a str>,
+    sim_id: Option<&'a str>,
+}
+
+impl<'a> ModemBinding<'a> {
+    fn matches(&self, modem: &ModemDevice) -> Result<bool> {
+        let device_id = match self.device_id {
+            Some(_) => Some(modem.get_device_id()?),
+            None => None,
+        };
+
+        let sim_id = match self.sim_id {
+            Some(_) => modem.get_sim_id()?,
+            None => None,
+        };
+
+        Ok(self.matches_ids(device_id.as_deref(), sim_id.as_deref()))
+    }
+
+    fn matches_ids(&self, device_id: Option<&str>, sim_id: Option<&str>) -> bool {
+        (self.device_id.is_none() || self.device_id == device_id)
+            && (self.sim_id.is_none() || self.sim_id == sim_id)
+    }
+}
+
+/// The first of `modems` if all of them are known to be locked. A modem
+/// whose lock could not be read may be usable.
+fn all_locked(modems: Vec<(String, Option<SimLockStatus>)>) -> Option<(String, SimLock)> {
+    let mut locked = None;
+
+    for (interface, status) in modems {
+        match status {
+            Some(ref status) if status.is_locked() => {
+                if locked.is_none() {
+                    locked = Some((interface, status.lock));
+                }
+            }
+            _ => return None,
+        }
+    }
+
+    locked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sim_lock_values() {
+        for lock in 0..20 {
+            assert_eq!(u32::from(SimLock::from(lock)), lock);
+        }
+
+        let status = SimLockStatus {
+            lock: SimLock::SimPuk,
+            retries: Some(10),
+        };
+        assert!(status.is_locked());
+    }
+
+    fn locked(lock: SimLock) -> Option<SimLockStatus> {
+        Some(SimLockStatus {
+            lock,
+            retries: None,
+        })
+    }
+
+    #[test]
+    fn test_all_locked() {
+        assert_eq!(all_locked(vec![]), None);
+
+        assert_eq!(
+            all_locked(vec![
+                ("wwan0".into(), locked(SimLock::SimPin)),
+                ("wwan1".into(), locked(SimLock::SimPuk)),
+            ]),
+            Some(("wwan0".into(), SimLock::SimPin))
+        );
+
+        assert_eq!(
+            all_locked(vec![
+                ("wwan0".into(), locked(SimLock::SimPin)),
+                ("wwan1".into(), locked(SimLock::None)),
+            ]),
+            None
+        );
+
+        // a modem ModemManager failed to report on does not block
+        assert_eq!(
+            all_locked(vec![
+                ("wwan0".into(), locked(SimLock::SimPin)),
+                ("wwan1".into(), None),
+            ]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_modem_binding() {
+        let unbound = ModemBinding {
+            device_id: None,
+            sim_id: None,
+        };
+        assert!(unbound.matches_ids(None, None));
+
+        let bound = ModemBinding {
+            device_id: Some("da812de91eec16620b06cd0ca5cbc7ea25245222"),
+            sim_id: Some("8944110068256270054"),
+        };
+        assert!(bound.matches_ids(
+            Some("da812de91eec16620b06cd0ca5cbc7ea25245222"),
+            Some("8944110068256270054")
+        ));
+        assert!(!bound.matches_ids(
+            Some("da812de91eec16620b06cd0ca5cbc7ea25245222"),
+            Some("8944110068256270062")
+        ));
+        // no SIM inserted
+        assert!(!bound.matches_ids(Some("da812de91eec16620b06cd0ca5cbc7ea25245222"), None));
+        assert!(!bound.matches_ids(Some("0123"), Some("8944110068256270054")));
+    }
+}
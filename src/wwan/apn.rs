@@ -0,0 +1,145 @@
+//! Access point names of mobile network operators, for creating cellular
+//! connections without asking the user for them.
+
+use errors::*;
+
+use secret::SecretString;
+use settings::ConnectionProfile;
+
+/// A small database of common providers, with the `apn-db` feature.
+#[cfg(feature = "apn-db")]
+const BUNDLED: &str = include_str!("apns.tsv");
+
+/// How to connect to the packet data network of a provider.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ApnSettings {
+    /// MCC and MNC of the operator, e.g. `310410`.
+    pub operator_code: String,
+    pub provider: String,
+    pub apn: String,
+    pub username: Option<String>,
+    /// Left out when serialized.
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    pub password: Option<SecretString>,
+}
+
+impl ApnSettings {
+    /// A `gsm` connection profile using the APN, named after the provider.
+    pub fn to_profile(&self) -> ConnectionProfile {
+        let mut profile =
+            ConnectionProfile::new("gsm", &self.provider).with("gsm", "apn", &*self.apn);
+
+        if let Some(ref username) = self.username {
+            profile.set("gsm", "username", &**username);
+        }
+
+        if let Some(ref password) = self.password {
+            profile.set("gsm", "password", password.expose());
+        }
+
+        profile
+    }
+}
+
+/// APN settings by operator code, read from lines of tab separated operator
+/// code, provider, APN, username and password. Empty and `#` lines are
+/// skipped.
+#[derive(Clone, Debug, Default)]
+pub struct ApnDatabase {
+    entries: Vec<ApnSettings>,
+}
+
+impl ApnDatabase {
+    pub fn parse(data: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+
+        for (number, line) in data.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+
+            if fields.len() < 3 || !is_operator_code(fields[0]) || fields[2].is_empty() {
+                bail!(ErrorKind::NetworkManager(format!(
+                    "Invalid APN database entry on line {}",
+                    number + 1
+                )));
+            }
+
+            let optional = |index: usize| {
+                fields
+                    .get(index)
+                    .filter(|field| !field.is_empty())
+                    .map(|field| field.to_string())
+            };
+
+            entries.push(ApnSettings {
+                operator_code: fields[0].to_string(),
+                provider: fields[1].to_string(),
+                apn: fields[2].to_string(),
+                username: optional(3),
+                password: optional(4).map(SecretString::from),
+            });
+        }
+
+        Ok(ApnDatabase { entries })
+    }
+
+    /// The database bundled with the crate, covering a selection of common
+    /// providers only.
+    #[cfg(feature = "apn-db")]
+    pub fn bundled() -> Self {
+        ApnDatabase::parse(BUNDLED).expect("bundled APN database is valid")
+    }
+
+    /// The APNs of the operator with MCC and MNC `operator_code`, as read
+    /// from the SIM, the preferred one first.
+    pub fn lookup(&self, operator_code: &str) -> Vec<&ApnSettings> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.operator_code == operator_code)
+            .collect()
+    }
+}
+
+/// MCC and MNC, five or six digits.
+fn is_operator_code(code: &str) -> bool {
+    (code.len() == 5 || code.len() == 6) && code.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apn_lookup() {
+        let database = ApnDatabase::parse(
+            "# comment\n\
+             310410\tAT&T\tbroadband\t\t\n\
+             23430\tEE\teverywhere\teesecure\tsecure\n",
+        )
+        .unwrap();
+
+        let entries = database.lookup("23430");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].username.as_deref(), Some("eesecure"));
+        assert_eq!(
+            entries[0].password.as_ref().map(SecretString::expose),
+            Some("secure")
+        );
+        assert_eq!(database.lookup("310410")[0].password, None);
+        assert!(database.lookup("00101").is_empty());
+
+        assert!(ApnDatabase::parse("3104\tAT&T\tbroadband\n").is_err());
+    }
+
+    #[cfg(feature = "apn-db")]
+    #[test]
+    fn test_bundled_apn_database() {
+        assert!(!ApnDatabase::bundled().lookup("310410").is_empty());
+    }
+}
@@ -0,0 +1,25 @@
+#!/bin/sh
+# Runs inside the container: starts the system bus, the test access point and
+# NetworkManager, then the ignored integration tests.
+set -eu
+
+for interface in wlan0 wlan1 wlan2; do
+    timeout 30 sh -c "until ip link show $interface >/dev/null 2>&1; do sleep 1; done"
+done
+
+mkdir -p /run/dbus
+dbus-daemon --system --fork
+
+hostapd -B /etc/hostapd/integration.conf
+
+NetworkManager --no-daemon &
+
+# Let NetworkManager pick up the radios before the tests look for them
+nm-online --startup --timeout=30 || true
+
+export NM_TEST_WIFI_INTERFACE=wlan1
+export NM_TEST_HOTSPOT_INTERFACE=wlan2
+export NM_TEST_SSID=nm-integration
+export NM_TEST_PSK=integration-psk
+
+exec cargo test --test integration -- --ignored --test-threads=1 "$@"
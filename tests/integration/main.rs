@@ -0,0 +1,164 @@
+//! End-to-end tests against a live NetworkManager with virtual radios.
+//!
+//! The tests are ignored by default, as they change the network
+//! configuration of the machine they run on. `run.sh` starts them inside a
+//! privileged container with `mac80211_hwsim` radios and a hostapd access
+//! point. To run them elsewhere, set the variables read by `Env` and pass
+//! `--ignored --test-threads=1`.
+
+extern crate network_manager;
+
+use std::env;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use network_manager::{
+    AccessPoint, AccessPointCredentials, ConnectionState, Device, HotspotConfig, NetworkManager,
+    SecretFlags,
+};
+
+const DEVICE_TIMEOUT: Duration = Duration::from_secs(30);
+const SCAN_TIMEOUT: Duration = Duration::from_secs(60);
+const SCAN_INTERVAL: Duration = Duration::from_secs(5);
+
+const HOTSPOT_SSID: &str = "nm-integration-hotspot";
+const HOTSPOT_PSK: &str = "hotspot-psk";
+
+struct Env {
+    /// Radio connecting to access points, `NM_TEST_WIFI_INTERFACE`.
+    wifi_interface: String,
+    /// Radio running hotspots, `NM_TEST_HOTSPOT_INTERFACE`.
+    hotspot_interface: String,
+    /// Access point the tests connect to, `NM_TEST_SSID` and `NM_TEST_PSK`.
+    ssid: String,
+    psk: String,
+}
+
+impl Env {
+    fn get() -> Self {
+        let var = |name: &str| {
+            env::var(name).unwrap_or_else(|_| panic!("{} is not set, see run.sh", name))
+        };
+
+        Env {
+            wifi_interface: var("NM_TEST_WIFI_INTERFACE"),
+            hotspot_interface: var("NM_TEST_HOTSPOT_INTERFACE"),
+            ssid: var("NM_TEST_SSID"),
+            psk: var("NM_TEST_PSK"),
+        }
+    }
+}
+
+fn wifi_device(manager: &NetworkManager, interface: &str) -> Device {
+    let device = manager
+        .wait_for_device(interface, DEVICE_TIMEOUT)
+        .unwrap_or_else(|e| panic!("No device for {}: {}", interface, e));
+
+    assert!(
+        device.as_wifi_device().is_some(),
+        "{} is not Wi-Fi",
+        interface
+    );
+
+    device
+}
+
+/// Scans until an access point with `ssid` shows up.
+fn find_access_point(device: &Device, ssid: &str) -> AccessPoint {
+    let wifi_device = device.as_wifi_device().unwrap();
+    let deadline = Instant::now() + SCAN_TIMEOUT;
+
+    loop {
+        // Scans are refused while one is running, the results still come in
+        let _ = wifi_device.request_scan();
+
+        thread::sleep(SCAN_INTERVAL);
+
+        let found = wifi_device
+            .get_access_points()
+            .unwrap()
+            .into_iter()
+            .find(|access_point| access_point.ssid().as_str().ok() == Some(ssid));
+
+        if let Some(access_point) = found {
+            return access_point;
+        }
+
+        assert!(Instant::now() < deadline, "{} not found in scans", ssid);
+    }
+}
+
+fn connect(device: &Device, ssid: &str, psk: &str) {
+    let access_point = find_access_point(device, ssid);
+
+    let credentials = AccessPointCredentials::Wpa {
+        passphrase: psk.into(),
+        psk_flags: SecretFlags::NONE,
+    };
+
+    let (connection, state) = device
+        .as_wifi_device()
+        .unwrap()
+        .connect(&access_point, &credentials)
+        .unwrap();
+
+    assert_eq!(state, ConnectionState::Activated);
+    assert_eq!(connection.get_state().unwrap(), ConnectionState::Activated);
+
+    connection.deactivate().unwrap();
+    connection.delete().unwrap();
+}
+
+#[test]
+#[ignore]
+fn scan() {
+    let env = Env::get();
+    let manager = NetworkManager::new();
+
+    let device = wifi_device(&manager, &env.wifi_interface);
+
+    let access_point = find_access_point(&device, &env.ssid);
+
+    assert!(access_point.strength > 0);
+    assert!(access_point.band().is_some());
+}
+
+#[test]
+#[ignore]
+fn connect_to_access_point() {
+    let env = Env::get();
+    let manager = NetworkManager::new();
+
+    let device = wifi_device(&manager, &env.wifi_interface);
+
+    connect(&device, &env.ssid, &env.psk);
+}
+
+#[test]
+#[ignore]
+fn hotspot() {
+    let env = Env::get();
+    let manager = NetworkManager::new();
+
+    let hotspot_device = wifi_device(&manager, &env.hotspot_interface);
+    let wifi_device = wifi_device(&manager, &env.wifi_interface);
+
+    let config = HotspotConfig::new(HOTSPOT_SSID)
+        .unwrap()
+        .password(HOTSPOT_PSK);
+
+    let hotspot = hotspot_device
+        .as_wifi_device()
+        .unwrap()
+        .start_hotspot(&config)
+        .unwrap();
+
+    assert_eq!(
+        hotspot.connection().get_state().unwrap(),
+        ConnectionState::Activated
+    );
+
+    connect(&wifi_device, HOTSPOT_SSID, HOTSPOT_PSK);
+
+    hotspot.stop().unwrap();
+}
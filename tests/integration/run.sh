@@ -0,0 +1,34 @@
+#!/bin/sh
+# Runs the integration tests against NetworkManager in a privileged container.
+#
+# Needs root on a Linux host with the mac80211_hwsim module and docker. Three
+# virtual radios are created and moved into the container: wlan0 runs the
+# test access point, wlan1 connects to it and wlan2 runs hotspots. Arguments
+# are passed on to the test binary, e.g. `run.sh hotspot`.
+set -eu
+
+cd "$(dirname "$0")"
+SRC="$(cd ../.. && pwd)"
+IMAGE=network-manager-integration
+
+modprobe -r mac80211_hwsim 2>/dev/null || true
+modprobe mac80211_hwsim radios=3
+
+docker build --tag "$IMAGE" .
+
+CONTAINER="$(docker create --privileged --volume "$SRC:/src" "$IMAGE" "$@")"
+trap 'docker rm --force "$CONTAINER" >/dev/null; modprobe -r mac80211_hwsim' EXIT
+
+docker start "$CONTAINER" >/dev/null
+
+# Radios can only be moved into the network namespace of a running process,
+# the entrypoint waits for them to show up
+PID="$(docker inspect --format '{{.State.Pid}}' "$CONTAINER")"
+for phy in /sys/class/ieee80211/*; do
+    if readlink -f "$phy/device" | grep -q mac80211_hwsim; then
+        iw phy "$(basename "$phy")" set netns "$PID"
+    fi
+done
+
+docker logs --follow "$CONTAINER"
+exit "$(docker wait "$CONTAINER")"
@@ -0,0 +1,84 @@
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum DeviceType {
+    /// Device types this crate does not know of, `0` being NetworkManager's
+    /// own unknown type.
+    Unknown(u32),
+    Ethernet,
+    WiFi,
+    Unused1,
+    Unused2,
+    Bt,
+    OlpcMesh,
+    Wimax,
+    Modem,
+    Infiniband,
+    Bond,
+    Vlan,
+    Adsl,
+    Bridge,
+    Generic,
+    Team,
+    Tun,
+    IpTunnel,
+    Macvlan,
+    Vxlan,
+    Veth,
+    Macsec,
+    Dummy,
+    Ppp,
+    OvsInterface,
+    OvsPort,
+    OvsBridge,
+    Wpan,
+    Lowpan,
+    Wireguard,
+    WifiP2p,
+    Vrf,
+    LoopBack,
+    Hsr,
+    Ipvlan,
+}
+
+impl From<i64> for DeviceType {
+    fn from(device_type: i64) -> Self {
+        match device_type {
+            0 => DeviceType::Unknown(0),
+            1 => DeviceType::Ethernet,
+            2 => DeviceType::WiFi,
+            3 => DeviceType::Unused1,
+            4 => DeviceType::Unused2,
+            5 => DeviceType::Bt,
+            6 => DeviceType::OlpcMesh,
+            7 => DeviceType::Wimax,
+            8 => DeviceType::Modem,
+            9 => DeviceType::Infiniband,
+            10 => DeviceType::Bond,
+            11 => DeviceType::Vlan,
+            12 => DeviceType::Adsl,
+            13 => DeviceType::Bridge,
+            14 => DeviceType::Generic,
+            15 => DeviceType::Team,
+            16 => DeviceType::Tun,
+            17 => DeviceType::IpTunnel,
+            18 => DeviceType::Macvlan,
+            19 => DeviceType::Vxlan,
+            20 => DeviceType::Veth,
+            21 => DeviceType::Macsec,
+            22 => DeviceType::Dummy,
+            23 => DeviceType::Ppp,
+            24 => DeviceType::OvsInterface,
+            25 => DeviceType::OvsPort,
+            26 => DeviceType::OvsBridge,
+            27 => DeviceType::Wpan,
+            28 => DeviceType::Lowpan,
+            29 => DeviceType::Wireguard,
+            30 => DeviceType::WifiP2p,
+            31 => DeviceType::Vrf,
+            32 => DeviceType::LoopBack, // Add LoopBack device type
+            33 => DeviceType::Hsr,
+            34 => DeviceType::Ipvlan,
+            _ => DeviceType::Unknown(device_type as u32),
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! # Network Manager Types
+//!
+//! Plain data types shared with the `network-manager` crate, which re-exports
+//! them. They carry no D-Bus dependency, so services exchanging them with a
+//! device agent, e.g. a provisioning API, do not have to link libdbus.
+
+#[macro_use]
+extern crate bitflags;
+
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+
+mod device_type;
+mod security;
+mod settings;
+mod ssid;
+mod value;
+
+pub use device_type::DeviceType;
+pub use security::{ApSecurity, NM80211ApFlags, NM80211ApSecurityFlags, Security};
+pub use settings::{EthtoolConfig, SecretExport, SecretFlags, Setting, SriovConfig, SriovVf};
+pub use ssid::{AsSsidSlice, IntoSsid, Ssid, SsidError, SsidSlice};
+pub use value::Value;
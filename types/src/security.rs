@@ -0,0 +1,190 @@
+bitflags! {
+    pub struct Security: u32 {
+        const NONE         = 0b0000_0000;
+        const WEP          = 0b0000_0001;
+        const WPA          = 0b0000_0010;
+        const WPA2         = 0b0000_0100;
+        const ENTERPRISE   = 0b0000_1000;
+    }
+}
+
+bitflags! {
+    pub struct NM80211ApFlags: u32 {
+        // access point has no special capabilities
+        const AP_FLAGS_NONE                  = 0x0000_0000;
+        // access point requires authentication and encryption (usually means WEP)
+        const AP_FLAGS_PRIVACY               = 0x0000_0001;
+        // access point supports some WPS method
+        const AP_FLAGS_WPS                   = 0x0000_0002;
+        // access point supports push-button WPS
+        const AP_FLAGS_WPS_PBC               = 0x0000_0004;
+        // access point supports PIN-based WPS
+        const AP_FLAGS_WPS_PIN               = 0x0000_0008;
+    }
+}
+
+bitflags! {
+    pub struct NM80211ApSecurityFlags: u32 {
+         // the access point has no special security requirements
+        const AP_SEC_NONE                       = 0x0000_0000;
+        // 40/64-bit WEP is supported for pairwise/unicast encryption
+        const AP_SEC_PAIR_WEP40                 = 0x0000_0001;
+        // 104/128-bit WEP is supported for pairwise/unicast encryption
+        const AP_SEC_PAIR_WEP104                = 0x0000_0002;
+        // TKIP is supported for pairwise/unicast encryption
+        const AP_SEC_PAIR_TKIP                  = 0x0000_0004;
+        // AES/CCMP is supported for pairwise/unicast encryption
+        const AP_SEC_PAIR_CCMP                  = 0x0000_0008;
+        // 40/64-bit WEP is supported for group/broadcast encryption
+        const AP_SEC_GROUP_WEP40                = 0x0000_0010;
+        // 104/128-bit WEP is supported for group/broadcast encryption
+        const AP_SEC_GROUP_WEP104               = 0x0000_0020;
+        // TKIP is supported for group/broadcast encryption
+        const AP_SEC_GROUP_TKIP                 = 0x0000_0040;
+        // AES/CCMP is supported for group/broadcast encryption
+        const AP_SEC_GROUP_CCMP                 = 0x0000_0080;
+        // WPA/RSN Pre-Shared Key encryption is supported
+        const AP_SEC_KEY_MGMT_PSK               = 0x0000_0100;
+        // 802.1x authentication and key management is supported
+        const AP_SEC_KEY_MGMT_802_1X            = 0x0000_0200;
+        // WPA/RSN Simultaneous Authentication of Equals is supported
+        const AP_SEC_KEY_MGMT_SAE               = 0x0000_0400;
+        // WPA/RSN Opportunistic Wireless Encryption is supported
+        const AP_SEC_KEY_MGMT_OWE               = 0x0000_0800;
+        // WPA/RSN Opportunistic Wireless Encryption transition mode is supported
+        const AP_SEC_KEY_MGMT_OWE_TM            = 0x0000_1000;
+        // WPA3 Enterprise Suite-B 192 bit mode is supported
+        const AP_SEC_KEY_MGMT_EAP_SUITE_B_192   = 0x0000_2000;
+    }
+}
+
+/// The security an access point advertises, as decoded from its `Flags`,
+/// `WpaFlags` and `RsnFlags` properties.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ApSecurity {
+    /// Ciphers and key management of the WPA information element.
+    pub wpa: NM80211ApSecurityFlags,
+    /// Ciphers and key management of the RSN (WPA2 and WPA3) information
+    /// element.
+    pub rsn: NM80211ApSecurityFlags,
+    /// Whether the access point requires encryption at all.
+    pub privacy: bool,
+}
+
+impl ApSecurity {
+    pub fn new(
+        flags: NM80211ApFlags,
+        wpa: NM80211ApSecurityFlags,
+        rsn: NM80211ApSecurityFlags,
+    ) -> Self {
+        ApSecurity {
+            wpa,
+            rsn,
+            privacy: flags.contains(NM80211ApFlags::AP_FLAGS_PRIVACY),
+        }
+    }
+
+    /// Whether the WPA or the RSN information element contains all of
+    /// `flags`, e.g. `AP_SEC_PAIR_CCMP` or `AP_SEC_KEY_MGMT_PSK`.
+    pub fn supports(&self, flags: NM80211ApSecurityFlags) -> bool {
+        self.wpa.contains(flags) || self.rsn.contains(flags)
+    }
+
+    /// The simplified summary of the advertised security.
+    pub fn summary(&self) -> Security {
+        let mut security = Security::NONE;
+
+        if self.privacy
+            && self.wpa == NM80211ApSecurityFlags::AP_SEC_NONE
+            && self.rsn == NM80211ApSecurityFlags::AP_SEC_NONE
+        {
+            security |= Security::WEP;
+        }
+
+        // WPA-advertising APs that still negotiate WEP ciphers are legacy too
+        if self.wpa.intersects(
+            NM80211ApSecurityFlags::AP_SEC_PAIR_WEP40 | NM80211ApSecurityFlags::AP_SEC_PAIR_WEP104,
+        ) {
+            security |= Security::WEP;
+        }
+
+        if self.wpa != NM80211ApSecurityFlags::AP_SEC_NONE {
+            security |= Security::WPA;
+        }
+
+        if self.rsn != NM80211ApSecurityFlags::AP_SEC_NONE {
+            security |= Security::WPA2;
+        }
+
+        if self.supports(NM80211ApSecurityFlags::AP_SEC_KEY_MGMT_802_1X) {
+            security |= Security::ENTERPRISE;
+        }
+
+        security
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Security {
+    fn serialize<S: ::serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> ::std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.bits())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for NM80211ApFlags {
+    fn serialize<S: ::serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> ::std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.bits())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for NM80211ApSecurityFlags {
+    fn serialize<S: ::serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> ::std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.bits())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ap_security_summary() {
+        let open = ApSecurity::new(
+            NM80211ApFlags::AP_FLAGS_NONE,
+            NM80211ApSecurityFlags::AP_SEC_NONE,
+            NM80211ApSecurityFlags::AP_SEC_NONE,
+        );
+        assert_eq!(open.summary(), Security::NONE);
+
+        let wep = ApSecurity::new(
+            NM80211ApFlags::AP_FLAGS_PRIVACY,
+            NM80211ApSecurityFlags::AP_SEC_NONE,
+            NM80211ApSecurityFlags::AP_SEC_NONE,
+        );
+        assert_eq!(wep.summary(), Security::WEP);
+
+        let enterprise = ApSecurity::new(
+            NM80211ApFlags::AP_FLAGS_PRIVACY,
+            NM80211ApSecurityFlags::AP_SEC_NONE,
+            NM80211ApSecurityFlags::AP_SEC_PAIR_CCMP
+                | NM80211ApSecurityFlags::AP_SEC_GROUP_CCMP
+                | NM80211ApSecurityFlags::AP_SEC_KEY_MGMT_802_1X,
+        );
+        assert_eq!(enterprise.summary(), Security::WPA2 | Security::ENTERPRISE);
+        assert!(enterprise.supports(NM80211ApSecurityFlags::AP_SEC_PAIR_CCMP));
+        assert!(!enterprise.supports(NM80211ApSecurityFlags::AP_SEC_PAIR_TKIP));
+        assert!(!enterprise.supports(NM80211ApSecurityFlags::AP_SEC_KEY_MGMT_PSK));
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+
+use value::Value;
+
+/// The key-value pairs of a single setting, e.g. `802-3-ethernet`.
+pub type Setting = BTreeMap<String, Value>;
+
+/// How secrets are treated when exporting a profile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecretExport {
+    /// Secrets are exported as they are.
+    Include,
+    /// Secrets are left out.
+    Strip,
+    /// Secrets are replaced by `<redacted>`, showing which were set.
+    Placeholder,
+}
+
+bitflags! {
+    /// Where NetworkManager keeps a secret such as `psk` or `password`, set
+    /// through the matching `<secret>-flags` key.
+    pub struct SecretFlags: u32 {
+        // the secret is stored by NetworkManager
+        const NONE         = 0x0;
+        // a secret agent owns the secret and is asked for it on activation
+        const AGENT_OWNED  = 0x1;
+        // the secret is never persisted, it is asked for each time
+        const NOT_SAVED    = 0x2;
+        // the secret is not needed for this connection
+        const NOT_REQUIRED = 0x4;
+    }
+}
+
+impl Default for SecretFlags {
+    fn default() -> Self {
+        SecretFlags::NONE
+    }
+}
+
+/// SR-IOV virtual function provisioning (the `sriov` setting).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SriovConfig {
+    pub total_vfs: u32,
+    /// Whether VFs are probed by their kernel driver. `None` keeps the
+    /// system default.
+    pub autoprobe_drivers: Option<bool>,
+    pub vfs: Vec<SriovVf>,
+}
+
+/// Configuration of a single SR-IOV virtual function.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SriovVf {
+    pub index: u32,
+    pub mac: Option<String>,
+    pub spoof_check: Option<bool>,
+    pub trust: Option<bool>,
+    /// Minimum TX rate in Mbps.
+    pub min_tx_rate: Option<u32>,
+    /// Maximum TX rate in Mbps.
+    pub max_tx_rate: Option<u32>,
+}
+
+impl SriovConfig {
+    pub fn to_setting(&self) -> Setting {
+        let mut setting = Setting::new();
+
+        setting.insert("total-vfs".into(), Value::UInt32(self.total_vfs));
+
+        if let Some(autoprobe_drivers) = self.autoprobe_drivers {
+            let ternary = if autoprobe_drivers { 1 } else { 0 };
+            setting.insert("autoprobe-drivers".into(), Value::Int32(ternary));
+        }
+
+        if !self.vfs.is_empty() {
+            let vfs = self.vfs.iter().map(SriovVf::to_dict).collect();
+            setting.insert("vfs".into(), Value::Dicts(vfs));
+        }
+
+        setting
+    }
+}
+
+impl SriovVf {
+    fn to_dict(&self) -> BTreeMap<String, Value> {
+        let mut vf = BTreeMap::new();
+
+        vf.insert("index".into(), Value::UInt32(self.index));
+
+        if let Some(ref mac) = self.mac {
+            vf.insert("mac".into(), Value::String(mac.clone()));
+        }
+        if let Some(spoof_check) = self.spoof_check {
+            vf.insert("spoof-check".into(), Value::Bool(spoof_check));
+        }
+        if let Some(trust) = self.trust {
+            vf.insert("trust".into(), Value::Bool(trust));
+        }
+        if let Some(min_tx_rate) = self.min_tx_rate {
+            vf.insert("min-tx-rate".into(), Value::UInt32(min_tx_rate));
+        }
+        if let Some(max_tx_rate) = self.max_tx_rate {
+            vf.insert("max-tx-rate".into(), Value::UInt32(max_tx_rate));
+        }
+
+        vf
+    }
+}
+
+/// NIC offload features, interrupt coalescing and ring sizes (the `ethtool`
+/// setting). Names are as used by `ethtool`, e.g. `feature("tso", false)`,
+/// `coalesce("rx-usecs", 50)` and `ring("rx", 4096)`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EthtoolConfig {
+    features: BTreeMap<String, bool>,
+    coalesce: BTreeMap<String, u32>,
+    ring: BTreeMap<String, u32>,
+}
+
+impl EthtoolConfig {
+    pub fn new() -> Self {
+        EthtoolConfig::default()
+    }
+
+    pub fn feature(mut self, name: &str, enabled: bool) -> Self {
+        self.features.insert(name.to_string(), enabled);
+        self
+    }
+
+    pub fn coalesce(mut self, name: &str, value: u32) -> Self {
+        self.coalesce.insert(name.to_string(), value);
+        self
+    }
+
+    pub fn ring(mut self, name: &str, value: u32) -> Self {
+        self.ring.insert(name.to_string(), value);
+        self
+    }
+
+    pub fn to_setting(&self) -> Setting {
+        let mut setting = Setting::new();
+
+        for (name, enabled) in &self.features {
+            setting.insert(format!("feature-{}", name), Value::Bool(*enabled));
+        }
+        for (name, value) in &self.coalesce {
+            setting.insert(format!("coalesce-{}", name), Value::UInt32(*value));
+        }
+        for (name, value) in &self.ring {
+            setting.insert(format!("ring-{}", name), Value::UInt32(*value));
+        }
+
+        setting
+    }
+}
@@ -0,0 +1,362 @@
+use std::ascii;
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+use std::fmt::Write;
+use std::mem;
+use std::ops::Deref;
+use std::str;
+
+pub type Result<T> = ::std::result::Result<T, SsidError>;
+
+/// Why bytes or a string do not make a valid SSID.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SsidError {
+    /// The SSID is longer than 32 bytes.
+    TooLong(usize),
+    /// The digits of a hex SSID are odd in number or not hex.
+    InvalidHex(String),
+    /// The SSID is not valid UTF-8 where a string was asked for.
+    Utf8(str::Utf8Error),
+}
+
+impl fmt::Display for SsidError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SsidError::TooLong(len) => {
+                write!(f, "SSID length should not exceed 32: {} len", len)
+            }
+            SsidError::InvalidHex(ref hex) => write!(f, "Invalid hex SSID: {}", hex),
+            SsidError::Utf8(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl error::Error for SsidError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            SsidError::Utf8(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<str::Utf8Error> for SsidError {
+    fn from(e: str::Utf8Error) -> Self {
+        SsidError::Utf8(e)
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ssid {
+    vec: Vec<u8>,
+}
+
+impl Ssid {
+    pub fn new() -> Self {
+        Ssid { vec: Vec::new() }
+    }
+
+    pub fn from_bytes<B>(bytes: B) -> Result<Self>
+    where
+        B: Into<Vec<u8>> + AsRef<[u8]>,
+    {
+        match bytes.as_ref().as_ssid_slice() {
+            Ok(_) => Ok(unsafe { Ssid::from_bytes_unchecked(bytes) }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Creates an SSID from a hex string, e.g. `"f09f93b6"` or `"0xf09f93b6"`.
+    ///
+    /// Useful for networks whose names are not valid UTF-8 or contain characters
+    /// that are awkward to pass around as strings.
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let digits = if hex.starts_with("0x") || hex.starts_with("0X") {
+            &hex[2..]
+        } else {
+            hex
+        };
+
+        if digits.len() % 2 != 0 {
+            return Err(SsidError::InvalidHex(hex.to_string()));
+        }
+
+        let mut bytes = Vec::with_capacity(digits.len() / 2);
+
+        for pair in digits.as_bytes().chunks(2) {
+            match (hex_digit(pair[0]), hex_digit(pair[1])) {
+                (Some(high), Some(low)) => bytes.push(high << 4 | low),
+                _ => return Err(SsidError::InvalidHex(hex.to_string())),
+            }
+        }
+
+        Ssid::from_bytes(bytes)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.vec
+    }
+
+    unsafe fn from_bytes_unchecked<B>(bytes: B) -> Self
+    where
+        B: Into<Vec<u8>>,
+    {
+        Ssid { vec: bytes.into() }
+    }
+}
+
+pub trait IntoSsid: Sized {
+    fn into_ssid(self) -> Result<Ssid>;
+}
+
+impl IntoSsid for Ssid {
+    fn into_ssid(self) -> Result<Ssid> {
+        Ok(self)
+    }
+}
+
+impl IntoSsid for Vec<u8> {
+    fn into_ssid(self) -> Result<Ssid> {
+        Ssid::from_bytes(self)
+    }
+}
+
+impl IntoSsid for &[u8] {
+    fn into_ssid(self) -> Result<Ssid> {
+        Ssid::from_bytes(self.to_vec())
+    }
+}
+
+impl IntoSsid for String {
+    fn into_ssid(self) -> Result<Ssid> {
+        Ssid::from_bytes(self)
+    }
+}
+
+impl IntoSsid for &str {
+    fn into_ssid(self) -> Result<Ssid> {
+        Ssid::from_bytes(self.to_string())
+    }
+}
+
+impl Deref for Ssid {
+    type Target = SsidSlice;
+
+    #[inline]
+    fn deref(&self) -> &SsidSlice {
+        unsafe { mem::transmute::<&[u8], &SsidSlice>(&self.vec[..]) }
+    }
+}
+
+impl AsRef<[u8]> for Ssid {
+    fn as_ref(&self) -> &[u8] {
+        &self.vec
+    }
+}
+
+/// Serialized as the lossy, escaped `Display` form. Use `to_hex` where the
+/// exact bytes need to be preserved.
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Ssid {
+    fn serialize<S: ::serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> ::std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Debug for Ssid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self as &SsidSlice, f)
+    }
+}
+
+impl fmt::Display for Ssid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self as &SsidSlice, f)
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SsidSlice {
+    slice: [u8],
+}
+
+pub trait AsSsidSlice {
+    fn as_ssid_slice(&self) -> Result<&SsidSlice>;
+}
+
+impl SsidSlice {
+    pub fn as_str(&self) -> Result<&str> {
+        Ok(str::from_utf8(&self.slice)?)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.slice
+    }
+
+    pub fn is_utf8(&self) -> bool {
+        str::from_utf8(&self.slice).is_ok()
+    }
+
+    /// Decodes the SSID as UTF-8, replacing invalid sequences with `U+FFFD`.
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.slice)
+    }
+
+    pub fn to_hex(&self) -> String {
+        let mut hex = String::with_capacity(self.slice.len() * 2);
+        for byte in self.as_bytes() {
+            write!(hex, "{:02x}", byte).unwrap();
+        }
+        hex
+    }
+}
+
+impl fmt::Display for SsidSlice {
+    /// Lossy UTF-8 rendering with control characters escaped, so that SSIDs
+    /// containing emoji display as-is while embedded NULs or newlines do not
+    /// garble terminal or log output.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.to_string_lossy().chars() {
+            if c.is_control() {
+                for e in c.escape_default() {
+                    f.write_char(e)?;
+                }
+            } else {
+                f.write_char(c)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for SsidSlice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_char('"')?;
+        for byte in self.as_bytes() {
+            for c in ascii::escape_default(*byte) {
+                f.write_char(c as char)?;
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+impl AsSsidSlice for [u8] {
+    fn as_ssid_slice(&self) -> Result<&SsidSlice> {
+        if self.len() > 32 {
+            Err(SsidError::TooLong(self.len()))
+        } else {
+            Ok(unsafe { mem::transmute::<&[u8], &SsidSlice>(self) })
+        }
+    }
+}
+
+impl AsSsidSlice for str {
+    fn as_ssid_slice(&self) -> Result<&SsidSlice> {
+        self.as_bytes().as_ssid_slice()
+    }
+}
+
+impl AsSsidSlice for Ssid {
+    fn as_ssid_slice(&self) -> Result<&SsidSlice> {
+        Ok(self)
+    }
+}
+
+fn hex_digit(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ssid_from_bytes_as_bytes() {
+        let vec_u8 = vec![0x68_u8, 0x65_u8, 0x6c_u8, 0x6c_u8, 0x6f_u8];
+        let ssid = Ssid::from_bytes(vec_u8.clone()).unwrap();
+        let slice = &ssid as &SsidSlice;
+        let as_bytes = slice.as_bytes();
+        assert_eq!(vec_u8, as_bytes);
+    }
+
+    #[test]
+    fn test_ssid_from_bytes_as_str() {
+        let vec_u8 = vec![0x68_u8, 0x65_u8, 0x6c_u8, 0x6c_u8, 0x6f_u8];
+        let ssid = Ssid::from_bytes(vec_u8.clone()).unwrap();
+        let slice = &ssid as &SsidSlice;
+        let as_str = slice.as_str().unwrap();
+        assert_eq!(vec_u8, as_str.as_bytes());
+    }
+
+    #[test]
+    fn test_ssid_from_bytes_eq() {
+        let from_string = Ssid::from_bytes("hello".to_string()).unwrap();
+        let vec_u8 = vec![0x68_u8, 0x65_u8, 0x6c_u8, 0x6c_u8, 0x6f_u8];
+        let from_vec_u8 = Ssid::from_bytes(vec_u8).unwrap();
+        assert_eq!(from_string, from_vec_u8);
+    }
+
+    #[test]
+    fn test_as_ssid_slice() {
+        let slice_from_str = "hello".as_ssid_slice().unwrap();
+        let ssid_u8 = [0x68_u8, 0x65_u8, 0x6c_u8, 0x6c_u8, 0x6f_u8];
+        let slice_from_u8 = (&ssid_u8 as &[u8]).as_ssid_slice().unwrap();
+        assert_eq!(slice_from_str, slice_from_u8);
+    }
+
+    #[test]
+    fn test_ssid_debug() {
+        let ssid = Ssid::from_bytes(b"hello\0\x7F".to_vec()).unwrap();
+        let debug = format!("{:?}", ssid);
+        assert_eq!(debug, "\"hello\\x00\\x7f\"");
+    }
+
+    #[test]
+    fn test_ssid_slice_debug() {
+        let ssid = Ssid::from_bytes(b"hello\0\x7F".to_vec()).unwrap();
+        let slice = &ssid as &SsidSlice;
+        let debug = format!("{:?}", slice);
+        assert_eq!(debug, "\"hello\\x00\\x7f\"");
+    }
+
+    #[test]
+    fn test_ssid_display_escapes_control_chars() {
+        let ssid = Ssid::from_bytes("caf\u{e9}\n\u{1f4f6}".to_string()).unwrap();
+        assert_eq!(format!("{}", ssid), "caf\u{e9}\\n\u{1f4f6}");
+    }
+
+    #[test]
+    fn test_ssid_display_lossy() {
+        let ssid = Ssid::from_bytes(vec![0x68_u8, 0xff_u8, 0x69_u8]).unwrap();
+        assert!(!ssid.is_utf8());
+        assert_eq!(format!("{}", ssid), "h\u{fffd}i");
+    }
+
+    #[test]
+    fn test_ssid_hex_roundtrip() {
+        let ssid = Ssid::from_hex("0x68FF69").unwrap();
+        assert_eq!(ssid.as_bytes(), &[0x68_u8, 0xff_u8, 0x69_u8]);
+        assert_eq!(ssid.to_hex(), "68ff69");
+        assert!(Ssid::from_hex("abc").is_err());
+        assert!(Ssid::from_hex("zz").is_err());
+    }
+
+    #[test]
+    fn test_ssid_too_long() {
+        assert_eq!(
+            Ssid::from_bytes(vec![0x61_u8; 33]),
+            Err(SsidError::TooLong(33))
+        );
+        assert!(Ssid::from_bytes(vec![0x61_u8; 32]).is_ok());
+    }
+}
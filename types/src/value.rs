@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+
+/// An owned D-Bus value as used in connection settings and properties.
+///
+/// With the `serde` feature values are (de)serialized tagged with their D-Bus
+/// signature, e.g. `{"u": 1500}` or `{"as": ["peap"]}`, so that a
+/// configuration document maps onto settings without losing integer widths.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Value {
+    #[cfg_attr(feature = "serde", serde(rename = "b"))]
+    Bool(bool),
+    #[cfg_attr(feature = "serde", serde(rename = "y"))]
+    Byte(u8),
+    #[cfg_attr(feature = "serde", serde(rename = "i"))]
+    Int32(i32),
+    #[cfg_attr(feature = "serde", serde(rename = "u"))]
+    UInt32(u32),
+    #[cfg_attr(feature = "serde", serde(rename = "x"))]
+    Int64(i64),
+    #[cfg_attr(feature = "serde", serde(rename = "t"))]
+    UInt64(u64),
+    #[cfg_attr(feature = "serde", serde(rename = "d"))]
+    Double(f64),
+    #[cfg_attr(feature = "serde", serde(rename = "s"))]
+    String(String),
+    #[cfg_attr(feature = "serde", serde(rename = "ay"))]
+    Bytes(Vec<u8>),
+    #[cfg_attr(feature = "serde", serde(rename = "as"))]
+    Strings(Vec<String>),
+    #[cfg_attr(feature = "serde", serde(rename = "au"))]
+    UInt32s(Vec<u32>),
+    #[cfg_attr(feature = "serde", serde(rename = "aay"))]
+    ByteArrays(Vec<Vec<u8>>),
+    #[cfg_attr(feature = "serde", serde(rename = "a{sv}"))]
+    Dict(BTreeMap<String, Value>),
+    #[cfg_attr(feature = "serde", serde(rename = "aa{sv}"))]
+    Dicts(Vec<BTreeMap<String, Value>>),
+}
+
+impl Value {
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match *self {
+            Value::UInt32(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::Byte(value) => Some(i64::from(value)),
+            Value::Int32(value) => Some(i64::from(value)),
+            Value::UInt32(value) => Some(i64::from(value)),
+            Value::Int64(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            Value::Bytes(ref value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<u8> for Value {
+    fn from(value: u8) -> Self {
+        Value::Byte(value)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::Int32(value)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(value: u32) -> Self {
+        Value::UInt32(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int64(value)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Self {
+        Value::UInt64(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Double(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(value: &'a str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(value: Vec<u8>) -> Self {
+        Value::Bytes(value)
+    }
+}
+
+impl From<Vec<String>> for Value {
+    fn from(value: Vec<String>) -> Self {
+        Value::Strings(value)
+    }
+}
+
+impl From<Vec<u32>> for Value {
+    fn from(value: Vec<u32>) -> Self {
+        Value::UInt32s(value)
+    }
+}
+
+impl From<BTreeMap<String, Value>> for Value {
+    fn from(value: BTreeMap<String, Value>) -> Self {
+        Value::Dict(value)
+    }
+}
+
+impl From<Vec<BTreeMap<String, Value>>> for Value {
+    fn from(value: Vec<BTreeMap<String, Value>>) -> Self {
+        Value::Dicts(value)
+    }
+}